@@ -0,0 +1,2266 @@
+//! Canvas logic that doesn't need a window: block layout and collision, the
+//! world/screen transform, resize math and the session file format. The app in
+//! `main.rs` wires these into egui; everything here can be tested on its own.
+
+use eframe::egui::{Pos2, Rect, Vec2};
+
+/// Gap kept between blocks when pushing them apart
+pub const COLLISION_GAP: f32 = 1.0;
+/// Smallest width or height a block can be resized to
+pub const MIN_BLOCK_SIZE: f32 = 50.0;
+/// Default zoom range of the canvas
+pub const MIN_ZOOM: f32 = 0.1;
+pub const MAX_ZOOM: f32 = 5.0;
+/// Furthest the zoom range can be widened, either way
+pub const LOWEST_ZOOM: f32 = 0.01;
+pub const HIGHEST_ZOOM: f32 = 50.0;
+/// Fraction of a coasting pan's speed that is left after one second
+pub const PAN_FRICTION: f32 = 0.05;
+/// A coasting pan stops below this speed, in screen pixels per second
+pub const PAN_STOP_SPEED: f32 = 20.0;
+/// Fraction of the way to its target a gliding view still has to go after one second
+pub const VIEW_GLIDE_REMAINING: f32 = 1e-4;
+/// Texture coordinates of a whole, uncropped image
+pub const FULL_CROP: Rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+
+// --- Layout / Collision ---
+
+pub mod layout {
+    use super::COLLISION_GAP;
+    use eframe::egui::{Pos2, Rect, Vec2};
+
+    /// Upper bound on push passes in `resolve_collision`
+    pub const MAX_COLLISION_PASSES: usize = 32;
+
+    /// Overlaps shallower than this are float noise from earlier pushes
+    const OVERLAP_EPSILON: f32 = 1e-3;
+
+    /// How deep `rect` (grown by `COLLISION_GAP`) reaches into `other` on each axis, if
+    /// they overlap. Rects that only touch don't count.
+    fn penetration(rect: Rect, other: Rect) -> Option<Vec2> {
+        let overlap = rect.expand(COLLISION_GAP).intersect(other).size();
+        (overlap.x > OVERLAP_EPSILON && overlap.y > OVERLAP_EPSILON).then_some(overlap)
+    }
+
+    /// The four moves that take `rect` just clear of `other`, shortest first
+    fn exits(rect: Rect, other: Rect) -> [Vec2; 4] {
+        let mut exits = [
+            Vec2::new(other.min.x - COLLISION_GAP - rect.max.x, 0.0),
+            Vec2::new(other.max.x + COLLISION_GAP - rect.min.x, 0.0),
+            Vec2::new(0.0, other.min.y - COLLISION_GAP - rect.max.y),
+            Vec2::new(0.0, other.max.y + COLLISION_GAP - rect.min.y),
+        ];
+        exits.sort_by(|a, b| a.length_sq().total_cmp(&b.length_sq()));
+        exits
+    }
+
+    /// Push `rect` out of the `others` it overlaps, keeping `COLLISION_GAP` between
+    /// them. Returns whether `rect` moved.
+    ///
+    /// Each pass handles only the deepest overlap, so pushes from several neighbors are
+    /// never added up (which overshoots or cancels out for a block squeezed between
+    /// two). Of the four ways out of that neighbor, the shortest one that leaves `rect`
+    /// clear of all `others` is taken, which settles the block for good. When every way
+    /// out runs into another neighbor the shortest is taken anyway and the next pass
+    /// deals with the new overlap; passes stop once nothing overlaps, or after
+    /// `MAX_COLLISION_PASSES`, in which case an overlap may remain (a block boxed in
+    /// on all sides).
+    pub fn resolve_collision(rect: &mut Rect, others: &[Rect]) -> bool {
+        resolve_group_collision(std::slice::from_mut(rect), others)
+    }
+
+    /// Like `resolve_collision`, but moves all of `group` by the same offsets so the
+    /// rects keep their exact layout relative to each other. Rects within the group are
+    /// never checked against each other, only against `others`.
+    pub fn resolve_group_collision(group: &mut [Rect], others: &[Rect]) -> bool {
+        let is_clear = |group: &[Rect], offset: Vec2| {
+            group.iter().all(|r| {
+                let candidate = r.translate(offset);
+                others.iter().all(|o| penetration(candidate, *o).is_none())
+            })
+        };
+
+        let mut moved = false;
+        for _ in 0..MAX_COLLISION_PASSES {
+            let deepest = group
+                .iter()
+                .flat_map(|r| {
+                    others
+                        .iter()
+                        .filter_map(move |o| penetration(*r, *o).map(|depth| (*r, *o, depth)))
+                })
+                .max_by(|a, b| a.2.min_elem().total_cmp(&b.2.min_elem()));
+            let Some((rect, other, _)) = deepest else {
+                break;
+            };
+
+            let exits = exits(rect, other);
+            let push = exits
+                .iter()
+                .copied()
+                .find(|push| is_clear(group, *push))
+                .unwrap_or(exits[0]);
+            for r in group.iter_mut() {
+                *r = r.translate(push);
+            }
+            moved = true;
+        }
+        moved
+    }
+
+    /// Move rects out of the way of the ones at `fixed` (a dragged or resized block and
+    /// its group), which keep their place. Rects pushed aside push their own neighbors
+    /// in turn, each moving the shortest way out. Returns the indices of moved rects.
+    ///
+    /// Called every frame of a drag the pushes stay small, so neighbors appear to be
+    /// shoved along smoothly. Crowded areas can make rects push each other back and
+    /// forth, so the number of pushes is bounded and an overlap may remain in extreme
+    /// cases.
+    pub fn push_neighbors(rects: &mut [Rect], fixed: &[usize]) -> Vec<usize> {
+        let mut pushers: std::collections::VecDeque<usize> = fixed.iter().copied().collect();
+        let mut moved = Vec::new();
+        let mut budget = rects.len() * 8;
+
+        while let Some(pusher) = pushers.pop_front() {
+            for i in 0..rects.len() {
+                if i == pusher
+                    || fixed.contains(&i)
+                    || penetration(rects[i], rects[pusher]).is_none()
+                {
+                    continue;
+                }
+                if budget == 0 {
+                    return moved;
+                }
+                budget -= 1;
+
+                // Out of the pusher, without landing on any fixed rect
+                let obstacles: Vec<Rect> = fixed
+                    .iter()
+                    .chain(std::iter::once(&pusher))
+                    .map(|&f| rects[f])
+                    .collect();
+                resolve_collision(&mut rects[i], &obstacles);
+                if !moved.contains(&i) {
+                    moved.push(i);
+                }
+                pushers.push_back(i);
+            }
+        }
+        moved
+    }
+
+    /// Top-left corner near `start_pos` where a rect of `size` overlaps none of `occupied`.
+    ///
+    /// Candidates are tried in square rings of growing radius around `start_pos`, nearest
+    /// first within each ring, on a grid of half the block size so smaller gaps between
+    /// existing blocks are found too. If every ring is taken the rect goes below the
+    /// lowest occupied one, which is always free.
+    pub fn find_free_rect(occupied: &[Rect], start_pos: Vec2, size: Vec2) -> Vec2 {
+        const RINGS: i32 = 40;
+        let spacing = 20.0;
+        let step = (size + Vec2::splat(spacing)) / 2.0;
+        let is_free = |pos: Vec2| {
+            let candidate = Rect::from_min_size(pos.to_pos2(), size).expand(COLLISION_GAP);
+            !occupied.iter().any(|r| r.intersects(candidate))
+        };
+
+        if is_free(start_pos) {
+            return start_pos;
+        }
+
+        let mut ring_cells = Vec::new();
+        for ring in 1..=RINGS {
+            ring_cells.clear();
+            for i in -ring..=ring {
+                ring_cells.extend([(i, -ring), (i, ring)]);
+                if i.abs() != ring {
+                    ring_cells.extend([(-ring, i), (ring, i)]);
+                }
+            }
+            let offset = |(x, y): (i32, i32)| Vec2::new(x as f32 * step.x, y as f32 * step.y);
+            ring_cells.sort_by(|a, b| offset(*a).length_sq().total_cmp(&offset(*b).length_sq()));
+
+            if let Some(pos) = ring_cells
+                .iter()
+                .map(|cell| start_pos + offset(*cell))
+                .find(|pos| is_free(*pos))
+            {
+                return pos;
+            }
+        }
+
+        let lowest = occupied.iter().map(|r| r.max.y).fold(start_pos.y, f32::max);
+        Vec2::new(start_pos.x, lowest + spacing)
+    }
+
+    /// Result of `snap_to_edges`: how far to move the rect, and the alignment guides it
+    /// then lines up with
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Snap {
+        pub offset: Vec2,
+        /// Line segments from the snapped rect's edge or center to the matching one
+        pub guides: Vec<[Pos2; 2]>,
+    }
+
+    /// Left/center/right (or top/middle/bottom) coordinates of `rect` along an axis
+    fn snap_lines(rect: Rect, vertical: bool) -> [f32; 3] {
+        let range = if vertical {
+            rect.x_range()
+        } else {
+            rect.y_range()
+        };
+        [range.min, range.center(), range.max]
+    }
+
+    /// Smallest nudge, per axis, that lines up an edge or the center of `rect` with an
+    /// edge or center of one of `others`, ignoring those more than `threshold` away.
+    /// Opposite edges snap to `COLLISION_GAP` apart, so blocks can be butted up against
+    /// each other without being pushed on.
+    pub fn snap_to_edges(rect: Rect, others: &[Rect], threshold: f32) -> Snap {
+        // Where line `mine` of the rect goes to meet line `theirs` of another
+        let target = |mine: usize, theirs: usize, lines: [f32; 3]| match (mine, theirs) {
+            (2, 0) => lines[0] - COLLISION_GAP,
+            (0, 2) => lines[2] + COLLISION_GAP,
+            _ => lines[theirs],
+        };
+
+        let mut snap = Snap::default();
+        for vertical in [true, false] {
+            let mine = snap_lines(rect, vertical);
+            let mut best: Option<f32> = None;
+            for other in others {
+                let theirs = snap_lines(*other, vertical);
+                for (i, j) in (0..3).flat_map(|i| (0..3).map(move |j| (i, j))) {
+                    let d = target(i, j, theirs) - mine[i];
+                    if d.abs() <= threshold && best.is_none_or(|b| d.abs() < b.abs()) {
+                        best = Some(d);
+                    }
+                }
+            }
+            if vertical {
+                snap.offset.x = best.unwrap_or(0.0);
+            } else {
+                snap.offset.y = best.unwrap_or(0.0);
+            }
+        }
+
+        let snapped = rect.translate(snap.offset);
+        for vertical in [true, false] {
+            let mine = snap_lines(snapped, vertical);
+            for other in others {
+                let theirs = snap_lines(*other, vertical);
+                for (i, j) in (0..3).flat_map(|i| (0..3).map(move |j| (i, j))) {
+                    if (target(i, j, theirs) - mine[i]).abs() > OVERLAP_EPSILON {
+                        continue;
+                    }
+                    let line = theirs[j];
+                    let span = snapped.union(*other);
+                    snap.guides.push(if vertical {
+                        [Pos2::new(line, span.min.y), Pos2::new(line, span.max.y)]
+                    } else {
+                        [Pos2::new(span.min.x, line), Pos2::new(span.max.x, line)]
+                    });
+                }
+            }
+        }
+        snap
+    }
+
+    /// Largest rect with the given aspect ratio that fits inside `cell`, sharing its top-left corner.
+    pub fn fit_rect_to_aspect(cell: Rect, aspect_ratio: f32) -> Rect {
+        if aspect_ratio <= 0.0 || !aspect_ratio.is_finite() {
+            return cell;
+        }
+        let size = if cell.width() / cell.height() > aspect_ratio {
+            Vec2::new(cell.height() * aspect_ratio, cell.height())
+        } else {
+            Vec2::new(cell.width(), cell.width() / aspect_ratio)
+        };
+        Rect::from_min_size(cell.min, size)
+    }
+
+    /// Top-left corners for blocks of `sizes`, laid out in order in rows `gap` apart that
+    /// start at `origin` and wrap before getting wider than `max_width`. A block wider
+    /// than that gets a row of its own.
+    pub fn arrange_in_rows(sizes: &[Vec2], origin: Pos2, max_width: f32, gap: f32) -> Vec<Pos2> {
+        let mut positions = Vec::with_capacity(sizes.len());
+        let mut cursor = origin;
+        let mut row_height: f32 = 0.0;
+        for size in sizes {
+            if cursor.x > origin.x && cursor.x + size.x > origin.x + max_width {
+                cursor = Pos2::new(origin.x, cursor.y + row_height + gap);
+                row_height = 0.0;
+            }
+            positions.push(cursor);
+            cursor.x += size.x + gap;
+            row_height = row_height.max(size.y);
+        }
+        positions
+    }
+
+    /// Top-left corners for blocks of `sizes` stacked in columns as wide as the widest
+    /// block, as many as fit in `max_width` starting at `origin`. Each block in turn goes
+    /// to the shortest column, so blocks of mixed heights pack without row gaps.
+    pub fn masonry(sizes: &[Vec2], origin: Pos2, max_width: f32, gap: f32) -> Vec<Pos2> {
+        let column_width = sizes.iter().map(|s| s.x).fold(0.0, f32::max);
+        let columns = ((max_width + gap) / (column_width + gap)).floor().max(1.0) as usize;
+        let mut bottoms = vec![origin.y; columns];
+        sizes
+            .iter()
+            .map(|size| {
+                let (column, bottom) = bottoms
+                    .iter_mut()
+                    .enumerate()
+                    .min_by(|a, b| a.1.total_cmp(b.1))
+                    .unwrap();
+                let pos = Pos2::new(origin.x + column as f32 * (column_width + gap), *bottom);
+                *bottom += size.y + gap;
+                pos
+            })
+            .collect()
+    }
+}
+
+// --- Viewport ---
+
+/// Pan and zoom of the canvas. A world point `p` is drawn at
+/// `screen_center + (p + pan) * zoom`.
+pub struct Viewport {
+    pub pan: Vec2,
+    pub zoom: f32,
+    /// Range zooming keeps to; change it with `set_zoom_limits`
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+            min_zoom: MIN_ZOOM,
+            max_zoom: MAX_ZOOM,
+        }
+    }
+}
+
+/// A camera move, whichever input it comes from: mouse, touch or keyboard
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViewAction {
+    /// Move the view along with something dragged this many screen pixels
+    Pan(Vec2),
+    /// Multiply the zoom, keeping the screen point `anchor` over the same world point
+    Zoom { anchor: Pos2, factor: f32 },
+}
+
+impl Viewport {
+    pub fn world_to_screen(&self, screen_center: Pos2, world: Pos2) -> Pos2 {
+        screen_center + (world.to_vec2() + self.pan) * self.zoom
+    }
+
+    pub fn screen_to_world(&self, screen_center: Pos2, screen: Pos2) -> Pos2 {
+        ((screen - screen_center) / self.zoom - self.pan).to_pos2()
+    }
+
+    pub fn world_rect_to_screen(&self, screen_center: Pos2, world: Rect) -> Rect {
+        Rect::from_min_size(
+            self.world_to_screen(screen_center, world.min),
+            world.size() * self.zoom,
+        )
+    }
+
+    /// Multiply the zoom by `factor` (within `min_zoom..=max_zoom`), keeping the world
+    /// point under `anchor` in place on screen.
+    pub fn zoom_around(&mut self, screen_center: Pos2, anchor: Pos2, factor: f32) {
+        let world_anchor = self.screen_to_world(screen_center, anchor);
+        self.zoom = self.clamp_zoom(self.zoom * factor);
+        self.pan = (anchor - screen_center) / self.zoom - world_anchor.to_vec2();
+    }
+
+    /// Zoom and pan so `world` is centered in a view of `screen_size`, filling `fill` of
+    /// it (0 to 1) along its tighter side, as far as the zoom range allows
+    pub fn fit(&mut self, world: Rect, screen_size: Vec2, fill: f32) {
+        let zoom = (screen_size / world.size()).min_elem() * fill;
+        self.zoom = self.clamp_zoom(zoom);
+        self.pan = -world.center().to_vec2();
+    }
+
+    /// Let the zoom range from `min` to `max`, within `LOWEST_ZOOM..=HIGHEST_ZOOM`, and
+    /// bring the current zoom into that range. A limit that isn't a number is reset to
+    /// its default, and `max` is raised to `min` if it is below it.
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        let limit = |zoom: f32, default: f32| {
+            if zoom.is_finite() {
+                zoom.clamp(LOWEST_ZOOM, HIGHEST_ZOOM)
+            } else {
+                default
+            }
+        };
+        self.min_zoom = limit(min, MIN_ZOOM);
+        self.max_zoom = limit(max, MAX_ZOOM).max(self.min_zoom);
+        self.zoom = self.clamp_zoom(self.zoom);
+    }
+
+    /// `zoom` brought into the zoom range; a zoom that isn't a number becomes the
+    /// smallest one
+    pub fn clamp_zoom(&self, zoom: f32) -> f32 {
+        if zoom.is_nan() {
+            self.min_zoom
+        } else {
+            zoom.clamp(self.min_zoom, self.max_zoom)
+        }
+    }
+
+    /// Move part of the way toward `target` for a frame `dt` seconds long: the pan
+    /// straight there, the zoom by equal factors so both ends of the trip feel alike.
+    /// Lands on the target once the rest is too small to see; `false` when there.
+    pub fn glide_toward(&mut self, target: &Viewport, dt: f32) -> bool {
+        let remaining = VIEW_GLIDE_REMAINING.powf(dt);
+        self.pan = target.pan + (self.pan - target.pan) * remaining;
+        self.zoom = target.zoom * (self.zoom / target.zoom).powf(remaining);
+        let pan_left = (self.pan - target.pan).length() * self.zoom;
+        if pan_left < 0.5 && (self.zoom / target.zoom - 1.0).abs() < 1e-3 {
+            (self.pan, self.zoom) = (target.pan, target.zoom);
+            return false;
+        }
+        true
+    }
+
+    /// Carry out a camera move from any input
+    pub fn apply(&mut self, screen_center: Pos2, action: ViewAction) {
+        match action {
+            ViewAction::Pan(screen_delta) => self.pan_by(screen_delta),
+            ViewAction::Zoom { anchor, factor } => self.zoom_around(screen_center, anchor, factor),
+        }
+    }
+
+    /// Move the view along with a pointer that moved `screen_delta` pixels
+    pub fn pan_by(&mut self, screen_delta: Vec2) {
+        self.pan += screen_delta / self.zoom;
+    }
+
+    /// World distance between background grid lines: `base`, doubled as often as needed
+    /// to keep them at least `min_screen` pixels apart when zoomed out
+    pub fn grid_spacing(&self, base: f32, min_screen: f32) -> f32 {
+        let mut spacing = base;
+        while spacing * self.zoom < min_screen {
+            spacing *= 2.0;
+        }
+        spacing
+    }
+}
+
+/// One step of a pan that keeps going after the pointer let go at `velocity` (screen
+/// pixels per second): the distance covered in `dt` seconds, and the slower velocity to
+/// carry on with, or `None` once it is down to `PAN_STOP_SPEED`.
+pub fn coast(velocity: Vec2, dt: f32) -> (Vec2, Option<Vec2>) {
+    if velocity.length() <= PAN_STOP_SPEED {
+        return (Vec2::ZERO, None);
+    }
+    let slower = velocity * PAN_FRICTION.powf(dt);
+    (velocity * dt, Some(slower))
+}
+
+// --- Resize ---
+
+pub mod resize {
+    use super::MIN_BLOCK_SIZE;
+    use eframe::egui::{Pos2, Rect, Vec2};
+
+    /// Corner of a block that follows the pointer while resizing
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum ResizeHandle {
+        TopLeft,
+        TopRight,
+        BottomLeft,
+        BottomRight,
+    }
+
+    impl ResizeHandle {
+        /// The corner of `rect` closest to `point`
+        pub fn nearest(rect: Rect, point: Pos2) -> Self {
+            let center = rect.center();
+            match (point.x < center.x, point.y < center.y) {
+                (true, true) => ResizeHandle::TopLeft,
+                (false, true) => ResizeHandle::TopRight,
+                (true, false) => ResizeHandle::BottomLeft,
+                (false, false) => ResizeHandle::BottomRight,
+            }
+        }
+
+        fn moves_left_edge(self) -> bool {
+            matches!(self, ResizeHandle::TopLeft | ResizeHandle::BottomLeft)
+        }
+
+        fn moves_top_edge(self) -> bool {
+            matches!(self, ResizeHandle::TopLeft | ResizeHandle::TopRight)
+        }
+    }
+
+    /// `initial` with its `handle` corner dragged by `delta` (world units), no smaller
+    /// than `MIN_BLOCK_SIZE`. With an `aspect_ratio` the height follows the width.
+    pub fn resized_rect(
+        initial: Rect,
+        handle: ResizeHandle,
+        delta: Vec2,
+        aspect_ratio: Option<f32>,
+    ) -> Rect {
+        let mut rect = initial;
+        if handle.moves_left_edge() {
+            rect.min.x += delta.x;
+        } else {
+            rect.max.x += delta.x;
+        }
+        if handle.moves_top_edge() {
+            rect.min.y += delta.y;
+        } else {
+            rect.max.y += delta.y;
+        }
+
+        if rect.width() < MIN_BLOCK_SIZE {
+            if handle.moves_left_edge() {
+                rect.min.x = rect.max.x - MIN_BLOCK_SIZE;
+            } else {
+                rect.max.x = rect.min.x + MIN_BLOCK_SIZE;
+            }
+        }
+        if rect.height() < MIN_BLOCK_SIZE {
+            if handle.moves_top_edge() {
+                rect.min.y = rect.max.y - MIN_BLOCK_SIZE;
+            } else {
+                rect.max.y = rect.min.y + MIN_BLOCK_SIZE;
+            }
+        }
+
+        if let Some(aspect_ratio) = aspect_ratio {
+            rect.set_height(rect.width() / aspect_ratio);
+        }
+        rect
+    }
+}
+
+// --- Crop ---
+
+pub mod crop {
+    use eframe::egui::{Pos2, Rect};
+
+    /// Texture coordinates that show just `part` of a block at `rect` whose image is
+    /// currently cropped to `crop`. Crops nest, so cropping again refines the last one.
+    pub fn crop_to(crop: Rect, rect: Rect, part: Rect) -> Rect {
+        let to_uv = |p: Pos2| crop.lerp_inside((p - rect.min) / rect.size());
+        Rect::from_min_max(to_uv(part.min), to_uv(part.max))
+    }
+
+    /// Where the whole image of a block at `rect`, cropped to `crop`, would be drawn
+    pub fn uncropped_rect(rect: Rect, crop: Rect) -> Rect {
+        let size = rect.size() / crop.size();
+        Rect::from_min_size(rect.min - crop.min.to_vec2() * size, size)
+    }
+
+    /// Width-to-height ratio of the part of an image (with ratio `aspect_ratio`) that
+    /// `crop` leaves visible
+    pub fn cropped_aspect_ratio(aspect_ratio: f32, crop: Rect) -> f32 {
+        aspect_ratio * crop.width() / crop.height()
+    }
+
+    /// Column and row of the pixel under `point` in an image `size` pixels big, cropped
+    /// to `crop` and drawn over `rect`. `None` outside the block.
+    pub fn pixel_at(rect: Rect, crop: Rect, point: Pos2, size: [usize; 2]) -> Option<[usize; 2]> {
+        if !rect.contains(point) || size.contains(&0) {
+            return None;
+        }
+        let uv = crop.lerp_inside((point - rect.min) / rect.size());
+        let pixel = |t: f32, len: usize| ((t * len as f32) as usize).min(len - 1);
+        Some([pixel(uv.x, size[0]), pixel(uv.y, size[1])])
+    }
+}
+
+// --- Image Adjustments ---
+
+pub mod adjust {
+    use eframe::egui::{Color32, ColorImage};
+    use serde::{Deserialize, Serialize};
+
+    /// Per-block tweaks baked into an image's pixels when it is uploaded
+    #[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+    pub struct ImageAdjust {
+        /// Added to every channel, from -1 (black) to 1 (white)
+        pub brightness: f32,
+        /// Spreads channels away from (or towards, below 0) middle gray, from -1 to 1
+        pub contrast: f32,
+        pub grayscale: bool,
+    }
+
+    impl ImageAdjust {
+        pub fn is_identity(&self) -> bool {
+            *self == Self::default()
+        }
+
+        /// One pixel with the adjustments applied: grayscale first, then contrast, then
+        /// brightness. Alpha is kept.
+        pub fn apply_to_pixel(&self, color: Color32) -> Color32 {
+            let [r, g, b, a] = color.to_srgba_unmultiplied();
+            let mut rgb = [r, g, b].map(|c| c as f32 / 255.0);
+            if self.grayscale {
+                let luma = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+                rgb = [luma; 3];
+            }
+            let contrast = 1.0 + self.contrast;
+            let [r, g, b] = rgb.map(|c| {
+                let c = (c - 0.5) * contrast + 0.5 + self.brightness;
+                (c.clamp(0.0, 1.0) * 255.0).round() as u8
+            });
+            Color32::from_rgba_unmultiplied(r, g, b, a)
+        }
+
+        pub fn apply(&self, image: &mut ColorImage) {
+            if self.is_identity() {
+                return;
+            }
+            for pixel in &mut image.pixels {
+                *pixel = self.apply_to_pixel(*pixel);
+            }
+        }
+    }
+}
+
+// --- Animation Playback ---
+
+pub mod playback {
+    use serde::{Deserialize, Serialize};
+
+    /// How often an animation repeats
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+    pub enum Looping {
+        #[default]
+        Forever,
+        /// Play this many times, then stop on the last frame
+        Times(u32),
+    }
+
+    impl Looping {
+        /// Whether an animation that has played `loops_played` times should stay stopped
+        pub fn finished(&self, loops_played: u32) -> bool {
+            matches!(*self, Looping::Times(times) if loops_played >= times)
+        }
+    }
+
+    /// Order the frames of an animation are played in
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+    pub enum Direction {
+        #[default]
+        Forward,
+        Reverse,
+        /// Forward, then back to the first frame
+        PingPong,
+    }
+
+    impl Direction {
+        /// Frame a loop starts on
+        pub fn first_frame(&self, len: usize) -> usize {
+            match self {
+                Direction::Reverse => len.saturating_sub(1),
+                Direction::Forward | Direction::PingPong => 0,
+            }
+        }
+    }
+
+    /// How far a playing animation has got
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+    pub struct Progress {
+        /// Loops completed since the animation was last started
+        pub loops_played: u32,
+        /// A ping-pong animation is on its way back to the first frame
+        pub returning: bool,
+    }
+
+    /// Frame shown after `current` in an animation of `len` frames, and whether it keeps
+    /// playing. Arriving at the frame a loop ends on counts it in `progress`; the
+    /// animation stops there once `looping` has had all its loops.
+    pub fn next_frame(
+        current: usize,
+        len: usize,
+        looping: Looping,
+        direction: Direction,
+        progress: &mut Progress,
+    ) -> (usize, bool) {
+        let Some(last) = len.checked_sub(1).filter(|&last| last > 0) else {
+            return (0, false);
+        };
+        let current = current.min(last);
+        let (next, end) = match direction {
+            Direction::Forward => ((current + 1) % len, last),
+            Direction::Reverse => (current.checked_sub(1).unwrap_or(last), 0),
+            Direction::PingPong => {
+                // Turn around at either end
+                if current == last {
+                    progress.returning = true;
+                } else if current == 0 {
+                    progress.returning = false;
+                }
+                let next = if progress.returning {
+                    current - 1
+                } else {
+                    current + 1
+                };
+                (next, 0)
+            }
+        };
+        if next == end {
+            progress.loops_played += 1;
+            if looping.finished(progress.loops_played) {
+                return (next, false);
+            }
+        }
+        (next, true)
+    }
+}
+
+// --- Markdown ---
+
+pub mod markdown {
+    use std::borrow::Cow;
+    use std::ops::Range;
+    use std::path::Path;
+
+    /// Formatting the edit toolbar of a text block applies to the selected text
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Format {
+        Bold,
+        Italic,
+        Heading,
+        List,
+    }
+
+    impl Format {
+        /// Apply the formatting to the characters in `selection` (character, not byte,
+        /// indices), or take it off again if they already have it. Returns the selection
+        /// moved along with the text.
+        pub fn apply(self, text: &mut String, selection: Range<usize>) -> Range<usize> {
+            match self {
+                Format::Bold => toggle_wrap(text, selection, "**"),
+                Format::Italic => toggle_wrap(text, selection, "*"),
+                Format::Heading => toggle_line_prefix(text, selection, "# "),
+                Format::List => toggle_line_prefix(text, selection, "- "),
+            }
+        }
+    }
+
+    fn byte_index(text: &str, char_index: usize) -> usize {
+        text.char_indices()
+            .nth(char_index)
+            .map_or(text.len(), |(i, _)| i)
+    }
+
+    /// Whether the text between `before` and `after` is wrapped in `marker` itself, not
+    /// just in a longer run of its character like the `**` of bold around an italic `*`.
+    /// A run of three is both: `***` is bold and italic.
+    fn wrapped_in(before: &str, after: &str, marker: &str) -> bool {
+        if !(before.ends_with(marker) && after.starts_with(marker)) {
+            return false;
+        }
+        let Some(c) = marker.chars().next() else {
+            return false;
+        };
+        let run = before
+            .chars()
+            .rev()
+            .take_while(|&b| b == c)
+            .count()
+            .min(after.chars().take_while(|&a| a == c).count());
+        run == marker.chars().count() || (c == '*' && run == 3)
+    }
+
+    /// Put `marker` on both sides of the selection, or an empty pair at the cursor
+    fn toggle_wrap(text: &mut String, selection: Range<usize>, marker: &str) -> Range<usize> {
+        let (start, end) = (
+            byte_index(text, selection.start),
+            byte_index(text, selection.end),
+        );
+        let len = marker.chars().count();
+        if wrapped_in(&text[..start], &text[end..], marker) {
+            text.replace_range(end..end + marker.len(), "");
+            text.replace_range(start - marker.len()..start, "");
+            return selection.start - len..selection.end - len;
+        }
+        text.insert_str(end, marker);
+        text.insert_str(start, marker);
+        selection.start + len..selection.end + len
+    }
+
+    /// Start every line the selection touches with `prefix`
+    fn toggle_line_prefix(
+        text: &mut String,
+        selection: Range<usize>,
+        prefix: &str,
+    ) -> Range<usize> {
+        let (start, end) = (
+            byte_index(text, selection.start),
+            byte_index(text, selection.end),
+        );
+        let first_line = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let last_line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+        let lines: Vec<&str> = text[first_line..last_line_end].split('\n').collect();
+        let remove = lines.iter().all(|line| line.starts_with(prefix));
+        let changed: Vec<String> = lines
+            .iter()
+            .map(|line| match line.strip_prefix(prefix) {
+                Some(rest) if remove => rest.to_string(),
+                _ => format!("{prefix}{line}"),
+            })
+            .collect();
+        let line_count = changed.len();
+        text.replace_range(first_line..last_line_end, &changed.join("\n"));
+
+        let len = prefix.chars().count();
+        if remove {
+            // A cursor inside a removed prefix ends up at the start of its line
+            let line_start = text[..first_line].chars().count();
+            let start = selection.start.saturating_sub(len).max(line_start);
+            start..selection.end.saturating_sub(len * line_count).max(start)
+        } else {
+            selection.start + len..selection.end + len * line_count
+        }
+    }
+
+    /// Point the `![alt](path)` images of a text block at absolute `file://` uris, with
+    /// relative paths taken from `base`. Images that aren't `available` are replaced by
+    /// their alt text; web and `data:` uris are left alone.
+    pub fn resolve_images<'a>(
+        text: &'a str,
+        base: &Path,
+        available: impl Fn(&Path) -> bool,
+    ) -> Cow<'a, str> {
+        if !text.contains("![") {
+            return Cow::Borrowed(text);
+        }
+        let mut resolved = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(open) = rest.find("![") {
+            resolved.push_str(&rest[..open]);
+            let after = &rest[open + 2..];
+            let Some((alt, target, len)) = image_reference(after) else {
+                resolved.push_str("![");
+                rest = after;
+                continue;
+            };
+            if target.contains("://") || target.starts_with("data:") {
+                resolved.push_str(&rest[open..open + 2 + len]);
+            } else {
+                let path = base.join(target);
+                if available(&path) {
+                    resolved.push_str(&format!("![{alt}](<file://{}>)", path.display()));
+                } else {
+                    resolved.push_str(alt);
+                }
+            }
+            rest = &after[len..];
+        }
+        resolved.push_str(rest);
+        Cow::Owned(resolved)
+    }
+
+    /// Byte offset of the `[ ]` / `[x]` box of each task list item, and whether it is
+    /// checked. Lines in fenced code blocks are skipped.
+    fn task_boxes(text: &str) -> Vec<(usize, bool)> {
+        let mut boxes = Vec::new();
+        let mut in_fence = false;
+        let mut offset = 0;
+        for line in text.split('\n') {
+            let start = offset;
+            offset += line.len() + 1;
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+            let item = if let Some(rest) = trimmed.strip_prefix(['-', '*', '+']) {
+                rest
+            } else {
+                let digits = trimmed.len()
+                    - trimmed
+                        .trim_start_matches(|c: char| c.is_ascii_digit())
+                        .len();
+                match trimmed[digits..].strip_prefix(['.', ')']) {
+                    Some(rest) if digits > 0 => rest,
+                    _ => continue,
+                }
+            };
+            let Some(task) = item.strip_prefix(' ') else {
+                continue;
+            };
+            let task = task.trim_start();
+            let checked = match task.get(..3) {
+                Some("[ ]") => false,
+                Some("[x]" | "[X]") => true,
+                _ => continue,
+            };
+            if task[3..].is_empty() || task[3..].starts_with([' ', '\t']) {
+                boxes.push((start + line.len() - task.len(), checked));
+            }
+        }
+        boxes
+    }
+
+    /// Check or uncheck the task list items of `to` like the ones of `from`, item by
+    /// item, which is how a box clicked in the rendered text gets back into the block's
+    /// markdown. Returns whether anything changed.
+    pub fn copy_task_marks(from: &str, to: &mut String) -> bool {
+        let mut changed = false;
+        for ((_, checked), (at, was_checked)) in task_boxes(from).into_iter().zip(task_boxes(to)) {
+            if checked != was_checked {
+                to.replace_range(at + 1..at + 2, if checked { "x" } else { " " });
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Cut a document into sections at its top-level (`# `) headings and horizontal
+    /// rules, as the text of separate blocks. Headings start their section, rules are
+    /// dropped, and neither counts inside fenced code. Empty sections are left out.
+    pub fn split_sections(text: &str) -> Vec<String> {
+        let mut sections = vec![String::new()];
+        let mut in_fence = false;
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+            } else if !in_fence && is_rule(trimmed) {
+                sections.push(String::new());
+                continue;
+            } else if !in_fence && (line.starts_with("# ") || line == "#") {
+                sections.push(String::new());
+            }
+            let section = sections.last_mut().expect("starts with one section");
+            section.push_str(line);
+            section.push('\n');
+        }
+        sections
+            .into_iter()
+            .map(|section| section.trim().to_string())
+            .filter(|section| !section.is_empty())
+            .collect()
+    }
+
+    /// `---`, `***` or `___`, three or more of one of them with spaces allowed between
+    fn is_rule(line: &str) -> bool {
+        let marks: Vec<char> = line.chars().filter(|c| *c != ' ').collect();
+        marks.len() >= 3
+            && matches!(marks[0], '-' | '*' | '_')
+            && marks.iter().all(|c| *c == marks[0])
+    }
+
+    /// Alt text and destination of the image reference following a `![`, and how many
+    /// bytes it takes up. A title after the destination is dropped.
+    fn image_reference(text: &str) -> Option<(&str, &str, usize)> {
+        let close = text.find("](")?;
+        let alt = &text[..close];
+        if alt.contains('\n') {
+            return None;
+        }
+        let link = &text[close + 2..];
+        let end = link.find(')')?;
+        let inner = link[..end].trim();
+        let target = match inner.strip_prefix('<') {
+            Some(quoted) => quoted.split_once('>')?.0,
+            None => inner.split_whitespace().next()?,
+        };
+        Some((alt, target, close + 2 + end + 1))
+    }
+}
+
+// --- Spell Checking ---
+
+pub mod spelling {
+    use std::collections::HashSet;
+    use std::ops::Range;
+    use std::sync::OnceLock;
+
+    /// Bundled English word list, one lowercase word per line
+    const ENGLISH_WORDS: &str = include_str!("../assets/english_words.txt");
+
+    fn english() -> &'static HashSet<&'static str> {
+        static WORDS: OnceLock<HashSet<&str>> = OnceLock::new();
+        WORDS.get_or_init(|| ENGLISH_WORDS.lines().collect())
+    }
+
+    /// Whether `word` is in the English list or the session's own words, ignoring case
+    /// and a possessive `'s`
+    pub fn is_known(word: &str, custom_words: &[String]) -> bool {
+        let word = word.to_lowercase().replace('\u{2019}', "'");
+        let stem = word.strip_suffix("'s").unwrap_or(&word);
+        [word.as_str(), stem]
+            .iter()
+            .any(|w| english().contains(w) || custom_words.iter().any(|c| c.to_lowercase() == *w))
+    }
+
+    /// Byte ranges of the words in `text` that `is_known` doesn't know. Code, links,
+    /// numbers, acronyms and single letters aren't checked.
+    pub fn misspelled(text: &str, custom_words: &[String]) -> Vec<Range<usize>> {
+        words(text)
+            .into_iter()
+            .filter(|range| !is_known(&text[range.clone()], custom_words))
+            .collect()
+    }
+
+    fn words(text: &str) -> Vec<Range<usize>> {
+        let mut in_fence = false;
+        let mut in_code = false;
+        let mut offset = 0;
+        let mut ranges = Vec::new();
+        for line in text.split('\n') {
+            let line_start = offset;
+            offset += line.len() + 1;
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+            for chunk in line.split_whitespace() {
+                let was_in_code = in_code;
+                in_code ^= chunk.matches('`').count() % 2 == 1;
+                if was_in_code
+                    || chunk.contains(['`', '/', '\\', '_', '@', '<', '>', '=', '{', '}', '|'])
+                    || chunk.contains("](")
+                    || chunk.contains(|c: char| c.is_ascii_digit())
+                {
+                    continue;
+                }
+                let start = line_start + (chunk.as_ptr() as usize - line.as_ptr() as usize);
+                ranges.extend(
+                    letter_runs(chunk)
+                        .into_iter()
+                        .map(|r| start + r.start..start + r.end),
+                );
+            }
+        }
+        // Acronyms and camelCase are names more often than words
+        ranges.retain(|range| {
+            let mut chars = text[range.clone()].chars();
+            chars.next();
+            chars.clone().next().is_some() && !chars.any(char::is_uppercase)
+        });
+        ranges
+    }
+
+    /// Runs of letters in `chunk`, with apostrophes kept inside words
+    fn letter_runs(chunk: &str) -> Vec<Range<usize>> {
+        let mut runs = Vec::new();
+        let mut start = None;
+        let mut chars = chunk.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let next_is_letter = chars.peek().is_some_and(|&(_, n)| n.is_alphabetic());
+            let apostrophe = c == '\'' || c == '\u{2019}';
+            let in_word = c.is_alphabetic() || (apostrophe && start.is_some() && next_is_letter);
+            match (in_word, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    runs.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            runs.push(s..chunk.len());
+        }
+        runs
+    }
+}
+
+// --- Link Previews ---
+
+pub mod link_preview {
+    /// What a web page says about itself in its OpenGraph tags, falling back to its
+    /// `<title>` and description meta tag
+    #[derive(Clone, Default, PartialEq, Debug)]
+    pub struct PagePreview {
+        pub title: Option<String>,
+        pub description: Option<String>,
+        /// Absolute URL of the page's preview image
+        pub image: Option<String>,
+    }
+
+    /// Whether `text` is a single http(s) address, as pasted from a browser
+    pub fn is_web_url(text: &str) -> bool {
+        let rest = text
+            .strip_prefix("https://")
+            .or_else(|| text.strip_prefix("http://"));
+        rest.is_some_and(|rest| {
+            !rest.is_empty() && !rest.starts_with('/') && !rest.contains(char::is_whitespace)
+        })
+    }
+
+    /// Read the preview tags out of the HTML of the page at `page_url`
+    pub fn parse(html: &str, page_url: &str) -> PagePreview {
+        // ASCII lowercasing keeps byte offsets, so positions found in `lower` index `html`
+        let lower = html.to_ascii_lowercase();
+        let mut preview = PagePreview::default();
+        let mut description = None;
+        let mut image = None;
+        let mut from = 0;
+        while let Some(start) = lower[from..].find("<meta").map(|i| from + i) {
+            let end = lower[start..].find('>').map_or(html.len(), |i| start + i);
+            from = end;
+            let tag = &html[start..end];
+            let key = attribute(tag, "property").or_else(|| attribute(tag, "name"));
+            let (Some(key), Some(content)) = (key, attribute(tag, "content")) else {
+                continue;
+            };
+            let content = decode_entities(content.trim());
+            if content.is_empty() {
+                continue;
+            }
+            let slot = match key.to_ascii_lowercase().as_str() {
+                "og:title" => &mut preview.title,
+                "og:description" => &mut preview.description,
+                "description" => &mut description,
+                "og:image" => &mut preview.image,
+                "twitter:image" => &mut image,
+                _ => continue,
+            };
+            slot.get_or_insert(content);
+        }
+
+        if preview.title.is_none() {
+            preview.title = lower
+                .find("<title")
+                .and_then(|start| Some(start + lower[start..].find('>')? + 1))
+                .and_then(|start| Some(&html[start..start + lower[start..].find("</title")?]))
+                .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+                .map(|title| decode_entities(&title))
+                .filter(|title| !title.is_empty());
+        }
+        preview.description = preview.description.or(description);
+        preview.image = preview
+            .image
+            .or(image)
+            .map(|image| resolve_url(page_url, &image));
+        preview
+    }
+
+    /// Value of the attribute `name` in the start tag `tag`, quoted or not
+    fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+        let lower = tag.to_ascii_lowercase();
+        let mut from = 0;
+        while let Some(at) = lower[from..].find(name).map(|i| from + i) {
+            from = at + name.len();
+            if !lower[..at].ends_with(char::is_whitespace) {
+                continue;
+            }
+            let Some(value) = tag[from..].trim_start().strip_prefix('=') else {
+                continue;
+            };
+            let value = value.trim_start();
+            return Some(match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let value = &value[1..];
+                    &value[..value.find(quote).unwrap_or(value.len())]
+                }
+                // A self-closing slash right after an unquoted value isn't part of it
+                _ => match value.find(char::is_whitespace) {
+                    Some(end) => &value[..end],
+                    None => value.strip_suffix('/').unwrap_or(value),
+                },
+            });
+        }
+        None
+    }
+
+    fn decode_entities(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&#x27;", "'")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    /// `link` made absolute against the page it was found on
+    fn resolve_url(page_url: &str, link: &str) -> String {
+        if link.contains("://") {
+            return link.to_string();
+        }
+        let (scheme, rest) = page_url.split_once("://").unwrap_or(("https", page_url));
+        if let Some(host_relative) = link.strip_prefix("//") {
+            return format!("{scheme}://{host_relative}");
+        }
+        let host = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+        if link.starts_with('/') {
+            return format!("{scheme}://{host}{link}");
+        }
+        let path = &rest[host.len()..];
+        let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+        let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        let dir = if dir.is_empty() { "/" } else { dir };
+        format!("{scheme}://{host}{dir}{link}")
+    }
+}
+
+// --- Background Work ---
+
+pub mod work_queue {
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::thread;
+
+    type Job = Box<dyn FnOnce() + Send>;
+
+    /// Runs jobs in the background on at most `workers` threads, started on first use.
+    /// Jobs beyond that wait their turn, so a board with hundreds of links or images
+    /// doesn't start hundreds of threads at once.
+    pub struct WorkQueue {
+        workers: usize,
+        jobs: OnceLock<Sender<Job>>,
+    }
+
+    impl WorkQueue {
+        pub const fn new(workers: usize) -> Self {
+            WorkQueue {
+                workers,
+                jobs: OnceLock::new(),
+            }
+        }
+
+        pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+            let jobs = self.jobs.get_or_init(|| {
+                let (tx, rx) = channel::<Job>();
+                let rx = Arc::new(Mutex::new(rx));
+                for _ in 0..self.workers.max(1) {
+                    let rx = Arc::clone(&rx);
+                    thread::spawn(move || loop {
+                        // The lock is released before the job runs
+                        let next = rx.lock().map(|rx| rx.recv());
+                        match next {
+                            Ok(Ok(job)) => job(),
+                            _ => return,
+                        }
+                    });
+                }
+                tx
+            });
+            let _ = jobs.send(Box::new(job));
+        }
+    }
+}
+
+// --- Selection ---
+
+pub mod selection {
+    use eframe::egui::Pos2;
+
+    /// Whether `point` lies inside the closed `polygon` (even-odd rule, so a lasso that
+    /// crosses itself leaves holes where it overlaps)
+    pub fn point_in_polygon(point: Pos2, polygon: &[Pos2]) -> bool {
+        let mut inside = false;
+        let mut prev = match polygon.last() {
+            Some(p) => *p,
+            None => return false,
+        };
+        for &curr in polygon {
+            if (curr.y > point.y) != (prev.y > point.y) {
+                let x_at_y = curr.x + (point.y - curr.y) * (prev.x - curr.x) / (prev.y - curr.y);
+                if point.x < x_at_y {
+                    inside = !inside;
+                }
+            }
+            prev = curr;
+        }
+        inside
+    }
+}
+
+// --- Session ---
+
+pub mod session {
+    use super::adjust::ImageAdjust;
+    use super::playback::{Direction, Looping};
+    use super::{FULL_CROP, MIN_BLOCK_SIZE};
+    use eframe::egui::{Pos2, Rect, Vec2};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// A saved canvas, as written to session JSON files
+    #[derive(Serialize, Deserialize)]
+    pub struct Session {
+        pub viewport: ViewportData,
+        /// Missing from sessions saved before backgrounds were configurable
+        #[serde(default)]
+        pub background: BackgroundData,
+        /// Blocks in z-order: later entries are drawn on top and hit-tested first
+        pub blocks: Vec<BlockData>,
+        /// Words the spell checker accepts on this board besides the English list
+        #[serde(default)]
+        pub custom_words: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+    pub struct BackgroundData {
+        /// RGB fill of the canvas; `None` uses the theme's color
+        pub color: Option<[u8; 3]>,
+        pub pattern: BackgroundPattern,
+    }
+
+    /// What is drawn over the canvas fill, in world space
+    #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+    pub enum BackgroundPattern {
+        #[default]
+        Solid,
+        Dots,
+        Lines,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct ViewportData {
+        pub pan: [f32; 2],
+        pub zoom: f32,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct BlockData {
+        pub id: Uuid,
+        pub rect: [f32; 4], // min_x, min_y, max_x, max_y
+        pub content: BlockContentData,
+        pub chained: bool,
+        /// Missing from sessions saved before blocks had an opacity
+        #[serde(default = "opaque")]
+        pub opacity: u8,
+        /// `rect` is in screen pixels from the canvas' top-left corner
+        #[serde(default)]
+        pub pinned: bool,
+        /// Milliseconds since the Unix epoch; 0 in sessions saved before it was recorded
+        #[serde(default)]
+        pub created_at: u64,
+        /// Position on the presentation's slide path, if the block is on it
+        #[serde(default)]
+        pub slide: Option<u32>,
+        /// Reminder attached to the block, shown on hover
+        #[serde(default)]
+        pub note: Option<String>,
+        /// Resizing keeps the block's width/height ratio
+        #[serde(default)]
+        pub lock_aspect: bool,
+        /// Missing from sessions saved before blocks could be hidden
+        #[serde(default = "shown")]
+        pub visible: bool,
+    }
+
+    fn opaque() -> u8 {
+        u8::MAX
+    }
+
+    fn shown() -> bool {
+        true
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub enum BlockContentData {
+        Text {
+            text: String,
+        },
+        Image {
+            path: String,
+            counter: i32,
+            playing: bool,
+            /// Visible part of the image as `[min_u, min_v, max_u, max_v]` texture
+            /// coordinates; the whole image in sessions from before cropping
+            #[serde(default = "full_crop")]
+            crop: [f32; 4],
+            #[serde(default)]
+            adjust: ImageAdjust,
+            #[serde(default)]
+            looping: Looping,
+            #[serde(default)]
+            direction: Direction,
+            /// Seconds per frame replacing the file's own delays
+            #[serde(default)]
+            delay_override: Option<f64>,
+        },
+        Link {
+            url: String,
+            #[serde(default)]
+            title: String,
+            #[serde(default)]
+            description: String,
+            /// Preview image found on the page, fetched again when the session is opened
+            #[serde(default)]
+            image: Option<String>,
+        },
+        Color {
+            /// Unmultiplied sRGB and alpha
+            rgba: [u8; 4],
+            /// Whether the hex code is shown on the swatch
+            #[serde(default)]
+            label: bool,
+        },
+    }
+
+    fn full_crop() -> [f32; 4] {
+        rect_to_session(FULL_CROP)
+    }
+
+    /// Turn a saved crop back into texture coordinates, clamped to the image. Crops that
+    /// leave nothing visible (or aren't numbers) fall back to the whole image.
+    pub fn crop_from_session(coords: [f32; 4]) -> Rect {
+        let [u0, v0, u1, v1] = coords.map(|c| c.clamp(0.0, 1.0));
+        let crop = Rect::from_two_pos(Pos2::new(u0, v0), Pos2::new(u1, v1));
+        if crop.width() > 0.0 && crop.height() > 0.0 {
+            crop
+        } else {
+            FULL_CROP
+        }
+    }
+
+    /// Turn a saved `[min_x, min_y, max_x, max_y]` array into a block rect. Swapped corners
+    /// are put back in order and sizes below `MIN_BLOCK_SIZE` are grown to it, which is
+    /// reported through the returned flag. Non-finite coordinates can't be repaired.
+    pub fn rect_from_session(coords: [f32; 4]) -> Option<(Rect, bool)> {
+        if !coords.iter().all(|c| c.is_finite()) {
+            return None;
+        }
+        let [x0, y0, x1, y1] = coords;
+        let min = Pos2::new(x0.min(x1), y0.min(y1));
+        let size = Vec2::new((x1 - x0).abs(), (y1 - y0).abs());
+        let fixed_size = size.max(Vec2::splat(MIN_BLOCK_SIZE));
+        let repaired = x0 > x1 || y0 > y1 || fixed_size != size;
+        Some((Rect::from_min_size(min, fixed_size), repaired))
+    }
+
+    pub fn rect_to_session(rect: Rect) -> [f32; 4] {
+        [rect.min.x, rect.min.y, rect.max.x, rect.max.y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::layout::*;
+    use super::resize::*;
+    use super::session::*;
+    use super::*;
+    use eframe::egui::Color32;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Rect {
+        Rect::from_min_size(Pos2::new(x, y), Vec2::new(w, h))
+    }
+
+    #[test]
+    fn collision_pushes_along_the_shallower_overlap() {
+        let other = rect(0.0, 0.0, 100.0, 100.0);
+        // Overlaps 10 units horizontally and 80 vertically, from the right
+        let mut moving = rect(90.0, 10.0, 100.0, 100.0);
+
+        assert!(resolve_collision(&mut moving, &[other]));
+        assert_eq!(moving.min.y, 10.0);
+        assert!(moving.min.x >= other.max.x);
+        assert!(!moving.intersects(other));
+    }
+
+    /// Whether `a` and `b` are at least `COLLISION_GAP` apart (up to float error)
+    fn keeps_gap(a: Rect, b: Rect) -> bool {
+        !a.expand(COLLISION_GAP - 1e-3).intersects(b)
+    }
+
+    #[test]
+    fn collision_keeps_pushing_until_clear() {
+        // Each push out of one neighbor lands the block in another; this takes five
+        // passes, more than the fixed three the solver used to run
+        let others = [
+            Rect::from_min_max(Pos2::new(90.0, -50.0), Pos2::new(190.0, 50.0)),
+            Rect::from_min_max(Pos2::new(140.0, 130.0), Pos2::new(290.0, 230.0)),
+            Rect::from_min_max(Pos2::new(-40.0, -60.0), Pos2::new(10.0, 40.0)),
+            Rect::from_min_max(Pos2::new(-120.0, -30.0), Pos2::new(30.0, 120.0)),
+        ];
+        let mut moving = rect(0.0, 0.0, 100.0, 100.0);
+
+        assert!(resolve_collision(&mut moving, &others));
+        assert!(others.iter().all(|o| keeps_gap(moving, *o)), "{moving:?}");
+    }
+
+    #[test]
+    fn collision_clears_a_dense_cluster() {
+        let others = [
+            rect(0.0, 0.0, 100.0, 100.0),
+            rect(101.0, 0.0, 100.0, 100.0),
+            rect(0.0, 101.0, 100.0, 100.0),
+            rect(-40.0, -80.0, 100.0, 60.0),
+        ];
+        let mut moving = rect(50.0, 50.0, 120.0, 120.0);
+
+        resolve_collision(&mut moving, &others);
+        assert!(others.iter().all(|o| keeps_gap(moving, *o)), "{moving:?}");
+    }
+
+    #[test]
+    fn sandwiched_block_settles_next_to_its_neighbors() {
+        // Too wide for the gap between its neighbors, so pushing it sideways out of
+        // one only shoves it into the other
+        let others = [rect(0.0, 0.0, 100.0, 100.0), rect(150.0, 0.0, 100.0, 100.0)];
+        let start = rect(80.0, 10.0, 100.0, 100.0);
+        let mut moving = start;
+
+        assert!(resolve_collision(&mut moving, &others));
+        assert!(others.iter().all(|o| keeps_gap(moving, *o)), "{moving:?}");
+        // Out the short way (down), not flung past one of the neighbors
+        assert_eq!(moving.min.x, start.min.x);
+        assert!(
+            (moving.min.y - (100.0 + COLLISION_GAP)).abs() < 1e-3,
+            "{moving:?}"
+        );
+
+        // Already settled: resolving again doesn't move it
+        let settled = moving;
+        assert!(!resolve_collision(&mut moving, &others));
+        assert_eq!(moving, settled);
+    }
+
+    #[test]
+    fn overlaps_on_opposite_sides_do_not_cancel_out() {
+        // Summing the pushes from both neighbors nets to nothing, leaving the block stuck
+        let others = [rect(0.0, 0.0, 100.0, 300.0), rect(160.0, 0.0, 100.0, 300.0)];
+        let mut moving = rect(80.0, 100.0, 100.0, 100.0);
+
+        assert!(resolve_collision(&mut moving, &others));
+        assert!(others.iter().all(|o| keeps_gap(moving, *o)), "{moving:?}");
+    }
+
+    #[test]
+    fn group_collision_keeps_relative_offsets() {
+        let mut group = [rect(0.0, 0.0, 100.0, 100.0), rect(0.0, 130.0, 100.0, 100.0)];
+        let others = [
+            rect(90.0, 150.0, 100.0, 100.0),
+            rect(-300.0, 0.0, 50.0, 50.0),
+        ];
+        let offset = group[1].min - group[0].min;
+
+        assert!(resolve_group_collision(&mut group, &others));
+
+        assert_eq!(group[1].min - group[0].min, offset);
+        for r in &group {
+            assert!(others.iter().all(|o| keeps_gap(*r, *o)), "{r:?}");
+        }
+        // Only the member that overlapped decided the push: a short hop to the left
+        assert_eq!(group[0].min.y, 0.0);
+        assert!(group[0].min.x > -20.0);
+    }
+
+    #[test]
+    fn pushing_moves_neighbors_and_keeps_the_dragged_block_in_place() {
+        let dragged = rect(0.0, 0.0, 100.0, 100.0);
+        // A neighbor overlapped by the drag, and one right behind it
+        let mut rects = [
+            dragged,
+            rect(90.0, 0.0, 100.0, 100.0),
+            rect(191.0, 0.0, 100.0, 100.0),
+            rect(0.0, 500.0, 100.0, 100.0),
+        ];
+
+        let mut moved = push_neighbors(&mut rects, &[0]);
+        moved.sort();
+
+        assert_eq!(rects[0], dragged);
+        assert_eq!(moved, vec![1, 2]);
+        assert_eq!(rects[3], rect(0.0, 500.0, 100.0, 100.0));
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(keeps_gap(*a, *b), "{a:?} overlaps {b:?}");
+            }
+        }
+        // Shoved along in the drag direction rather than jumping around
+        assert!(rects[1].min.y == 0.0 && rects[2].min.y == 0.0);
+    }
+
+    #[test]
+    fn pushing_never_moves_neighbors_onto_the_group() {
+        // A chained group of two with a neighbor caught between them
+        let group = [rect(0.0, 0.0, 100.0, 100.0), rect(0.0, 130.0, 100.0, 100.0)];
+        let mut rects = [group[0], group[1], rect(20.0, 90.0, 60.0, 60.0)];
+
+        push_neighbors(&mut rects, &[0, 1]);
+
+        assert_eq!(rects[..2], group);
+        assert!(
+            group.iter().all(|g| keeps_gap(rects[2], *g)),
+            "{:?}",
+            rects[2]
+        );
+    }
+
+    #[test]
+    fn collision_leaves_separate_rects_alone() {
+        let mut moving = rect(200.0, 0.0, 100.0, 100.0);
+        assert!(!resolve_collision(
+            &mut moving,
+            &[rect(0.0, 0.0, 100.0, 100.0)]
+        ));
+        assert_eq!(moving, rect(200.0, 0.0, 100.0, 100.0));
+    }
+
+    #[test]
+    fn find_free_rect_keeps_a_free_start_position() {
+        let occupied = [rect(500.0, 500.0, 200.0, 100.0)];
+        let pos = find_free_rect(&occupied, Vec2::ZERO, Vec2::new(200.0, 100.0));
+        assert_eq!(pos, Vec2::ZERO);
+    }
+
+    #[test]
+    fn find_free_rect_finds_a_gap_on_a_crowded_canvas() {
+        // 9x9 grid of 200x100 blocks packed 10px apart around the origin, with the
+        // center cell missing but too small for the block being placed
+        let mut occupied = Vec::new();
+        for row in -4..=4 {
+            for col in -4..=4 {
+                if (row, col) != (0, 0) {
+                    occupied.push(rect(col as f32 * 210.0, row as f32 * 110.0, 200.0, 100.0));
+                }
+            }
+        }
+        let size = Vec2::new(250.0, 150.0);
+
+        let pos = find_free_rect(&occupied, Vec2::ZERO, size);
+
+        let placed = Rect::from_min_size(pos.to_pos2(), size);
+        assert!(occupied.iter().all(|r| !r.intersects(placed)));
+        // Closer than just dropping it below everything
+        assert!(pos.length() < 1000.0, "placed too far away: {pos:?}");
+    }
+
+    #[test]
+    fn find_free_rect_falls_back_below_all_blocks() {
+        // One block covering every candidate position
+        let occupied = [rect(-50_000.0, -50_000.0, 100_000.0, 100_000.0)];
+        let size = Vec2::new(200.0, 100.0);
+
+        let pos = find_free_rect(&occupied, Vec2::ZERO, size);
+
+        assert!(!occupied[0].intersects(Rect::from_min_size(pos.to_pos2(), size)));
+    }
+
+    #[test]
+    fn fit_rect_to_aspect_shrinks_the_longer_side() {
+        let cell = rect(10.0, 10.0, 300.0, 300.0);
+        assert_eq!(
+            fit_rect_to_aspect(cell, 2.0),
+            rect(10.0, 10.0, 300.0, 150.0)
+        );
+        assert_eq!(
+            fit_rect_to_aspect(cell, 0.5),
+            rect(10.0, 10.0, 150.0, 300.0)
+        );
+        assert_eq!(fit_rect_to_aspect(cell, f32::NAN), cell);
+    }
+
+    #[test]
+    fn screen_and_world_transforms_are_inverse() {
+        let viewport = Viewport {
+            pan: Vec2::new(-120.0, 40.0),
+            zoom: 2.5,
+            ..Default::default()
+        };
+        let center = Pos2::new(400.0, 300.0);
+        let world = Pos2::new(33.0, -7.0);
+
+        let screen = viewport.world_to_screen(center, world);
+        assert_eq!(
+            screen,
+            Pos2::new(400.0 + (33.0 - 120.0) * 2.5, 300.0 + 33.0 * 2.5)
+        );
+        assert!((viewport.screen_to_world(center, screen) - world).length() < 1e-4);
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place_and_clamps() {
+        let mut viewport = Viewport::default();
+        let center = Pos2::new(400.0, 300.0);
+        let anchor = Pos2::new(650.0, 120.0);
+        let world_anchor = viewport.screen_to_world(center, anchor);
+
+        viewport.zoom_around(center, anchor, 1.7);
+        assert_eq!(viewport.zoom, 1.7);
+        assert!((viewport.world_to_screen(center, world_anchor) - anchor).length() < 1e-3);
+
+        viewport.zoom_around(center, anchor, 100.0);
+        assert_eq!(viewport.zoom, MAX_ZOOM);
+        viewport.zoom_around(center, anchor, 0.0);
+        assert_eq!(viewport.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn zoom_limits_stay_ordered_and_in_bounds() {
+        let center = Pos2::new(400.0, 300.0);
+        let anchor = Pos2::new(650.0, 120.0);
+        let mut viewport = Viewport {
+            zoom: 4.0,
+            ..Default::default()
+        };
+        viewport.set_zoom_limits(0.5, 2.0);
+        assert_eq!(viewport.zoom, 2.0);
+
+        viewport.set_zoom_limits(0.0, f32::INFINITY);
+        assert_eq!(
+            (viewport.min_zoom, viewport.max_zoom),
+            (LOWEST_ZOOM, MAX_ZOOM)
+        );
+        viewport.set_zoom_limits(20.0, 8.0);
+        assert_eq!((viewport.min_zoom, viewport.max_zoom), (20.0, 20.0));
+        assert_eq!(viewport.zoom, 20.0);
+
+        // The anchor holds still even at the far ends of the widest range
+        viewport.set_zoom_limits(LOWEST_ZOOM, HIGHEST_ZOOM);
+        for factor in [1e-6, 1e6, f32::NAN] {
+            let world_anchor = viewport.screen_to_world(center, anchor);
+            viewport.zoom_around(center, anchor, factor);
+            assert!(viewport.zoom >= LOWEST_ZOOM && viewport.zoom <= HIGHEST_ZOOM);
+            let moved = viewport.world_to_screen(center, world_anchor) - anchor;
+            assert!(moved.length() < 1e-2, "{factor}: {moved:?}");
+        }
+    }
+
+    #[test]
+    fn grid_spacing_coarsens_when_zoomed_out() {
+        let mut viewport = Viewport::default();
+        assert_eq!(viewport.grid_spacing(50.0, 20.0), 50.0);
+        viewport.zoom = 0.1;
+        // 50 * 0.1 = 5px, doubled twice to reach 20px
+        assert_eq!(viewport.grid_spacing(50.0, 20.0), 200.0);
+        viewport.zoom = MAX_ZOOM;
+        assert_eq!(viewport.grid_spacing(50.0, 20.0), 50.0);
+    }
+
+    #[test]
+    fn lasso_selects_points_inside_a_concave_outline() {
+        use selection::point_in_polygon;
+        // A "C" shape open to the right
+        let lasso = [
+            Pos2::new(0.0, 0.0),
+            Pos2::new(100.0, 0.0),
+            Pos2::new(100.0, 20.0),
+            Pos2::new(20.0, 20.0),
+            Pos2::new(20.0, 80.0),
+            Pos2::new(100.0, 80.0),
+            Pos2::new(100.0, 100.0),
+            Pos2::new(0.0, 100.0),
+        ];
+        assert!(point_in_polygon(Pos2::new(10.0, 50.0), &lasso));
+        assert!(point_in_polygon(Pos2::new(90.0, 10.0), &lasso));
+        assert!(!point_in_polygon(Pos2::new(60.0, 50.0), &lasso));
+        assert!(!point_in_polygon(Pos2::new(-5.0, 50.0), &lasso));
+        assert!(!point_in_polygon(Pos2::new(10.0, 10.0), &lasso[..2]));
+        assert!(!point_in_polygon(Pos2::new(10.0, 10.0), &[]));
+    }
+
+    #[test]
+    fn snapping_aligns_nearby_edges_and_ignores_far_ones() {
+        let other = rect(0.0, 0.0, 100.0, 100.0);
+        // Left edges 3 apart, and the top edge 4 further than the gap below the other
+        let snap = snap_to_edges(rect(3.0, 105.0, 50.0, 50.0), &[other], 5.0);
+        assert_eq!(snap.offset, Vec2::new(-3.0, -4.0));
+        assert!(snap
+            .guides
+            .contains(&[Pos2::new(0.0, 0.0), Pos2::new(0.0, 151.0)]));
+        // The snapped position is already clear of the other block
+        assert!(!resolve_collision(
+            &mut rect(0.0, 101.0, 50.0, 50.0),
+            &[other]
+        ));
+
+        let far = snap_to_edges(rect(60.0, 130.0, 50.0, 50.0), &[other], 5.0);
+        assert_eq!(far, Snap::default());
+    }
+
+    #[test]
+    fn resize_moves_the_dragged_corner_only() {
+        let initial = rect(0.0, 0.0, 200.0, 100.0);
+        let handle = ResizeHandle::nearest(initial, Pos2::new(10.0, 90.0));
+        assert_eq!(handle, ResizeHandle::BottomLeft);
+
+        let resized = resized_rect(initial, handle, Vec2::new(-20.0, 30.0), None);
+        assert_eq!(
+            resized,
+            Rect::from_min_max(Pos2::new(-20.0, 0.0), Pos2::new(200.0, 130.0))
+        );
+    }
+
+    #[test]
+    fn resize_respects_min_size_and_aspect_ratio() {
+        let initial = rect(0.0, 0.0, 200.0, 100.0);
+
+        let shrunk = resized_rect(initial, ResizeHandle::TopLeft, Vec2::splat(500.0), None);
+        assert_eq!(
+            shrunk,
+            Rect::from_min_max(Pos2::new(150.0, 50.0), Pos2::new(200.0, 100.0))
+        );
+
+        let image = resized_rect(
+            initial,
+            ResizeHandle::BottomRight,
+            Vec2::new(100.0, 0.0),
+            Some(2.0),
+        );
+        assert_eq!(image, rect(0.0, 0.0, 300.0, 150.0));
+    }
+
+    #[test]
+    fn crops_nest_and_undo_back_to_the_whole_image() {
+        let image = rect(0.0, 0.0, 200.0, 100.0);
+        // Right half of the image, then the bottom half of that
+        let right = crop::crop_to(FULL_CROP, image, rect(100.0, 0.0, 100.0, 100.0));
+        assert_eq!(
+            right,
+            Rect::from_min_max(Pos2::new(0.5, 0.0), Pos2::new(1.0, 1.0))
+        );
+        let block = rect(100.0, 0.0, 100.0, 100.0);
+        let corner = crop::crop_to(right, block, rect(100.0, 50.0, 100.0, 50.0));
+        assert_eq!(
+            corner,
+            Rect::from_min_max(Pos2::new(0.5, 0.5), Pos2::new(1.0, 1.0))
+        );
+        assert_eq!(crop::cropped_aspect_ratio(2.0, corner), 2.0);
+
+        assert_eq!(
+            crop::uncropped_rect(rect(100.0, 50.0, 100.0, 50.0), corner),
+            image
+        );
+    }
+
+    #[test]
+    fn points_on_a_cropped_block_map_to_image_pixels() {
+        let block = rect(100.0, 100.0, 200.0, 100.0);
+        let size = [40, 10];
+        assert_eq!(
+            crop::pixel_at(block, FULL_CROP, Pos2::new(100.0, 100.0), size),
+            Some([0, 0])
+        );
+        // The far edge still lands on the last pixel
+        assert_eq!(
+            crop::pixel_at(block, FULL_CROP, Pos2::new(300.0, 200.0), size),
+            Some([39, 9])
+        );
+        // Only the right half of the image is shown, stretched over the whole block
+        let right = Rect::from_min_max(Pos2::new(0.5, 0.0), Pos2::new(1.0, 1.0));
+        assert_eq!(
+            crop::pixel_at(block, right, Pos2::new(150.0, 150.0), size),
+            Some([25, 5])
+        );
+        assert_eq!(
+            crop::pixel_at(block, right, Pos2::new(99.0, 150.0), size),
+            None
+        );
+        assert_eq!(crop::pixel_at(block, right, block.center(), [0, 0]), None);
+    }
+
+    #[test]
+    fn adjustments_change_color_but_keep_alpha() {
+        let color = Color32::from_rgba_unmultiplied(200, 100, 50, 128);
+        let identity = adjust::ImageAdjust::default();
+        assert_eq!(identity.apply_to_pixel(color), color);
+
+        let gray = adjust::ImageAdjust {
+            grayscale: true,
+            ..Default::default()
+        }
+        .apply_to_pixel(color)
+        .to_srgba_unmultiplied();
+        assert!(gray[0] == gray[1] && gray[1] == gray[2]);
+        assert_eq!(gray[3], 128);
+
+        let white = adjust::ImageAdjust {
+            brightness: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(white.apply_to_pixel(Color32::BLACK), Color32::WHITE);
+        let flat = adjust::ImageAdjust {
+            contrast: -1.0,
+            ..Default::default()
+        };
+        assert_eq!(flat.apply_to_pixel(Color32::WHITE), Color32::from_gray(128));
+    }
+
+    /// Frames an animation of `len` frames shows from its first frame until it stops,
+    /// at most 20 of them
+    fn play(len: usize, looping: playback::Looping, direction: playback::Direction) -> Vec<usize> {
+        let limit = 20;
+        let mut progress = playback::Progress::default();
+        let mut frame = direction.first_frame(len);
+        let mut shown = vec![frame];
+        while shown.len() < limit {
+            let (next, playing) =
+                playback::next_frame(frame, len, looping, direction, &mut progress);
+            frame = next;
+            shown.push(frame);
+            if !playing {
+                break;
+            }
+        }
+        shown
+    }
+
+    #[test]
+    fn fitting_centers_a_rect_and_fills_the_view() {
+        let mut viewport = Viewport::default();
+        let screen = Vec2::new(1000.0, 500.0);
+        let wide = rect(100.0, 100.0, 400.0, 50.0);
+        viewport.fit(wide, screen, 0.9);
+        assert_eq!(viewport.zoom, 2.25);
+        let on_screen = viewport.world_rect_to_screen(Pos2::new(500.0, 250.0), wide);
+        assert_eq!(on_screen.center(), Pos2::new(500.0, 250.0));
+        assert_eq!(on_screen.width(), 900.0);
+
+        // A tiny block only gets as close as the zoom range allows
+        viewport.fit(rect(0.0, 0.0, 1.0, 1.0), screen, 0.9);
+        assert_eq!(viewport.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn view_actions_pan_and_zoom_like_the_pointer_would() {
+        let center = Pos2::new(400.0, 300.0);
+        let mut viewport = Viewport::default();
+        viewport.apply(center, ViewAction::Pan(Vec2::new(10.0, -20.0)));
+        assert_eq!(viewport.pan, Vec2::new(10.0, -20.0));
+
+        let anchor = Pos2::new(600.0, 300.0);
+        let under_anchor = viewport.screen_to_world(center, anchor);
+        viewport.apply(
+            center,
+            ViewAction::Zoom {
+                anchor,
+                factor: 2.0,
+            },
+        );
+        assert_eq!(viewport.zoom, 2.0);
+        assert_eq!(viewport.screen_to_world(center, anchor), under_anchor);
+    }
+
+    #[test]
+    fn gliding_views_settle_on_the_target() {
+        let target = Viewport {
+            pan: Vec2::new(-500.0, 200.0),
+            zoom: 4.0,
+            ..Default::default()
+        };
+        let mut viewport = Viewport::default();
+        let mut frames = 0;
+        let mut zoom = viewport.zoom;
+        while viewport.glide_toward(&target, 1.0 / 60.0) {
+            assert!(viewport.zoom > zoom);
+            zoom = viewport.zoom;
+            frames += 1;
+        }
+        assert_eq!((viewport.pan, viewport.zoom), (target.pan, target.zoom));
+        assert!(frames > 5 && frames < 60 * 2);
+    }
+
+    #[test]
+    fn released_pans_coast_to_a_stop() {
+        assert_eq!(
+            coast(Vec2::new(PAN_STOP_SPEED, 0.0), 0.1),
+            (Vec2::ZERO, None)
+        );
+
+        let (mut velocity, mut travelled, mut frames) = (Vec2::new(-1000.0, 500.0), Vec2::ZERO, 0);
+        while let (delta, Some(slower)) = coast(velocity, 1.0 / 60.0) {
+            assert!(slower.length() < velocity.length());
+            travelled += delta;
+            velocity = slower;
+            frames += 1;
+        }
+        // Keeps its direction and comes to rest within a few seconds, before it gets as
+        // far as a second at the release speed would take it
+        assert!(travelled.x < 0.0 && travelled.y > 0.0);
+        assert!(travelled.length() < Vec2::new(-1000.0, 500.0).length());
+        assert!(frames < 60 * 5);
+    }
+
+    #[test]
+    fn limited_loops_stop_on_the_last_frame() {
+        use playback::{Direction, Looping};
+
+        assert_eq!(
+            play(3, Looping::Times(2), Direction::Forward),
+            vec![0, 1, 2, 0, 1, 2]
+        );
+        assert_eq!(play(3, Looping::Forever, Direction::Forward).len(), 20);
+        assert!(Looping::Times(2).finished(2));
+        assert!(!Looping::Forever.finished(u32::MAX));
+    }
+
+    #[test]
+    fn animations_play_backwards_and_bounce() {
+        use playback::{Direction, Looping};
+
+        assert_eq!(
+            play(3, Looping::Times(2), Direction::Reverse),
+            vec![2, 1, 0, 2, 1, 0]
+        );
+        assert_eq!(
+            play(3, Looping::Times(2), Direction::PingPong),
+            vec![0, 1, 2, 1, 0, 1, 2, 1, 0]
+        );
+        // Nothing to bounce between
+        assert_eq!(play(1, Looping::Forever, Direction::PingPong), vec![0, 0]);
+    }
+
+    #[test]
+    fn formatting_wraps_the_selection_and_comes_off_again() {
+        use markdown::Format;
+
+        let mut text = "make this bold".to_string();
+        let selection = Format::Bold.apply(&mut text, 5..9);
+        assert_eq!(text, "make **this** bold");
+        assert_eq!(selection, 7..11);
+        assert_eq!(Format::Bold.apply(&mut text, selection), 5..9);
+        assert_eq!(text, "make this bold");
+
+        // Nothing selected: an empty pair to type into; non-ASCII text counts characters
+        let mut text = "café ".to_string();
+        assert_eq!(Format::Italic.apply(&mut text, 5..5), 6..6);
+        assert_eq!(text, "café **");
+    }
+
+    #[test]
+    fn italic_inside_bold_adds_to_it_instead_of_taking_a_star_off() {
+        use markdown::Format;
+
+        let mut text = "a **word** here".to_string();
+        let selection = Format::Italic.apply(&mut text, 4..8);
+        assert_eq!(text, "a ***word*** here");
+        assert_eq!(selection, 5..9);
+
+        // Either half of bold italic comes off on its own
+        let mut bold = text.clone();
+        assert_eq!(Format::Bold.apply(&mut bold, selection.clone()), 3..7);
+        assert_eq!(bold, "a *word* here");
+        assert_eq!(Format::Italic.apply(&mut text, selection), 4..8);
+        assert_eq!(text, "a **word** here");
+    }
+
+    #[test]
+    fn line_formatting_covers_every_selected_line() {
+        use markdown::Format;
+
+        let mut text = "title\none\ntwo\nrest".to_string();
+        assert_eq!(Format::Heading.apply(&mut text, 2..2), 4..4);
+        assert_eq!(text, "# title\none\ntwo\nrest");
+
+        // From the middle of "one" to the middle of "two"
+        let selection = Format::List.apply(&mut text, 9..14);
+        assert_eq!(text, "# title\n- one\n- two\nrest");
+        assert_eq!(selection, 11..18);
+        assert_eq!(Format::List.apply(&mut text, selection), 9..14);
+        assert_eq!(text, "# title\none\ntwo\nrest");
+    }
+
+    #[test]
+    fn markdown_images_resolve_against_the_session_folder() {
+        let base = std::path::Path::new("/boards");
+        let available = |p: &std::path::Path| p.ends_with("figure.png") || p.ends_with("a b.gif");
+
+        let text = "See ![a figure](figure.png \"title\") and ![gone](old.png).";
+        assert_eq!(
+            markdown::resolve_images(text, base, available),
+            "See ![a figure](<file:///boards/figure.png>) and gone."
+        );
+        let text = "![anim](</gifs/a b.gif>) ![web](https://example.com/x.png)";
+        assert_eq!(
+            markdown::resolve_images(text, base, available),
+            "![anim](<file:///gifs/a b.gif>) ![web](https://example.com/x.png)"
+        );
+        // Anything that isn't a whole image reference stays as it is
+        assert!(matches!(
+            markdown::resolve_images("plain text", base, available),
+            std::borrow::Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            markdown::resolve_images("half ![an image](", base, available),
+            "half ![an image]("
+        );
+    }
+
+    #[test]
+    fn ticked_boxes_are_copied_into_the_markdown() {
+        let mut text = "# Todo\n- [ ] milk\n  * [x] eggs\n```\n- [ ] code\n```\n2. [ ] ![bread](b.png)\n- [ ]no".to_string();
+        let rendered =
+            "# Todo\n- [x] milk\n  * [ ] eggs\n```\n- [ ] code\n```\n2. [X] bread\n- [ ]no";
+        assert!(markdown::copy_task_marks(rendered, &mut text));
+        assert_eq!(
+            text,
+            "# Todo\n- [x] milk\n  * [ ] eggs\n```\n- [ ] code\n```\n2. [x] ![bread](b.png)\n- [ ]no"
+        );
+        assert!(!markdown::copy_task_marks(rendered, &mut text));
+    }
+
+    #[test]
+    fn documents_split_at_top_level_headings_and_rules() {
+        let text = "Intro\n\n# One\nbody\n## Still one\n\n- - -\nTwo\n```\n# not a heading\n---\n```\n***\n\n___\n#hashtag";
+        assert_eq!(
+            markdown::split_sections(text),
+            [
+                "Intro",
+                "# One\nbody\n## Still one",
+                "Two\n```\n# not a heading\n---\n```",
+                "#hashtag",
+            ]
+        );
+        assert!(markdown::split_sections("\n---\n").is_empty());
+    }
+
+    #[test]
+    fn link_previews_prefer_opengraph_tags_and_resolve_the_image() {
+        let html = r#"<html><head><title>Fallback</title>
+            <META name="description" content="Plain description">
+            <meta property="og:title" content="Tom &amp; Jerry" />
+            <meta content='Cat and mouse' property='og:description'>
+            <meta property=og:image content=/img/card.png>
+            </head></html>"#;
+        assert_eq!(
+            link_preview::parse(html, "https://example.com/shows/tom?x=1"),
+            link_preview::PagePreview {
+                title: Some("Tom & Jerry".to_string()),
+                description: Some("Cat and mouse".to_string()),
+                image: Some("https://example.com/img/card.png".to_string()),
+            }
+        );
+
+        let plain = "<title>\n  A   page\n</title><meta name=description content=\"Words\">\
+            <meta name=twitter:image content=\"thumb.jpg\">";
+        assert_eq!(
+            link_preview::parse(plain, "http://example.com/a/b.html"),
+            link_preview::PagePreview {
+                title: Some("A page".to_string()),
+                description: Some("Words".to_string()),
+                image: Some("http://example.com/a/thumb.jpg".to_string()),
+            }
+        );
+        let empty = link_preview::parse("", "https://example.com");
+        assert_eq!(empty, Default::default());
+    }
+
+    #[test]
+    fn only_single_web_addresses_count_as_urls() {
+        assert!(link_preview::is_web_url("https://example.com/a?b=c"));
+        assert!(link_preview::is_web_url("http://localhost:8080"));
+        assert!(!link_preview::is_web_url("https://"));
+        assert!(!link_preview::is_web_url("ftp://example.com"));
+        assert!(!link_preview::is_web_url("see https://example.com"));
+        assert!(!link_preview::is_web_url("https://example.com and more"));
+    }
+
+    #[test]
+    fn spelling_flags_unknown_words_only() {
+        let text = "Ths is a wrold of \"Lissajous\" curves, don\u{2019}t worry.\n\
+            Skip `inline cdoe`, NASA, camelCase, x2 and https://exmaple.com\n\
+            ```\nfn mian() {}\n```\nThe cat's wrold";
+        let flagged: Vec<&str> = spelling::misspelled(text, &[])
+            .into_iter()
+            .map(|r| &text[r])
+            .collect();
+        assert_eq!(flagged, ["Ths", "wrold", "Lissajous", "wrold"]);
+
+        // The session's own words count as known, whatever their case
+        let custom = vec!["lissajous".to_string()];
+        assert!(spelling::misspelled("Lissajous figures", &custom).is_empty());
+    }
+
+    #[test]
+    fn rows_wrap_at_the_width_and_clear_the_tallest_block() {
+        let sizes = [
+            Vec2::new(100.0, 50.0),
+            Vec2::new(100.0, 80.0),
+            Vec2::new(100.0, 50.0),
+            Vec2::new(400.0, 20.0),
+        ];
+        let positions = layout::arrange_in_rows(&sizes, Pos2::new(10.0, 10.0), 250.0, 5.0);
+        assert_eq!(
+            positions,
+            [
+                Pos2::new(10.0, 10.0),
+                Pos2::new(115.0, 10.0),
+                Pos2::new(10.0, 95.0),
+                // Too wide for any row: on its own
+                Pos2::new(10.0, 150.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn masonry_fills_the_shortest_column_first() {
+        let sizes = [
+            Vec2::new(100.0, 200.0),
+            Vec2::new(80.0, 50.0),
+            Vec2::new(100.0, 60.0),
+            Vec2::new(100.0, 10.0),
+        ];
+        let positions = layout::masonry(&sizes, Pos2::ZERO, 230.0, 10.0);
+        assert_eq!(
+            positions,
+            [
+                Pos2::new(0.0, 0.0),
+                Pos2::new(110.0, 0.0),
+                Pos2::new(110.0, 60.0),
+                Pos2::new(110.0, 130.0),
+            ]
+        );
+        // Narrower than one column still gets one
+        assert_eq!(
+            layout::masonry(&sizes[..1], Pos2::ZERO, 10.0, 10.0),
+            [Pos2::ZERO]
+        );
+    }
+
+    #[test]
+    fn session_rects_round_trip_and_get_repaired() {
+        let r = rect(-10.0, 20.0, 300.0, 80.0);
+        assert_eq!(rect_from_session(rect_to_session(r)), Some((r, false)));
+
+        let (inverted, repaired) = rect_from_session([300.0, 200.0, 100.0, 0.0]).unwrap();
+        assert!(repaired);
+        assert_eq!(
+            inverted,
+            Rect::from_min_max(Pos2::new(100.0, 0.0), Pos2::new(300.0, 200.0))
+        );
+
+        let (tiny, repaired) = rect_from_session([0.0, 0.0, 0.0, 10.0]).unwrap();
+        assert!(repaired);
+        assert_eq!(tiny.size(), Vec2::splat(MIN_BLOCK_SIZE));
+
+        assert!(rect_from_session([0.0, f32::INFINITY, 10.0, 10.0]).is_none());
+    }
+
+    #[test]
+    fn work_queue_runs_every_job_on_its_few_threads() {
+        use std::collections::HashSet;
+        use std::sync::mpsc::channel;
+        static QUEUE: work_queue::WorkQueue = work_queue::WorkQueue::new(2);
+        let (tx, rx) = channel();
+        for i in 0..10 {
+            let tx = tx.clone();
+            QUEUE.spawn(move || tx.send((i, std::thread::current().id())).unwrap());
+        }
+        let done: Vec<_> = (0..10).map(|_| rx.recv().unwrap()).collect();
+        let mut jobs: Vec<i32> = done.iter().map(|(i, _)| *i).collect();
+        jobs.sort();
+        assert_eq!(jobs, (0..10).collect::<Vec<_>>());
+        let threads: HashSet<_> = done.iter().map(|(_, thread)| thread).collect();
+        assert!(threads.len() <= 2);
+    }
+
+    #[test]
+    fn saved_crops_are_clamped_to_the_image() {
+        let crop = Rect::from_min_max(Pos2::new(0.25, 0.0), Pos2::new(0.75, 0.5));
+        assert_eq!(crop_from_session(rect_to_session(crop)), crop);
+        assert_eq!(
+            crop_from_session([-1.0, 0.5, 2.0, 0.25]),
+            Rect::from_min_max(Pos2::new(0.0, 0.25), Pos2::new(1.0, 0.5))
+        );
+        assert_eq!(crop_from_session([0.5, 0.0, 0.5, 1.0]), FULL_CROP);
+        assert_eq!(crop_from_session([f32::NAN, 0.0, 1.0, 1.0]), FULL_CROP);
+    }
+}
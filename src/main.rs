@@ -2,22 +2,136 @@ use eframe::egui;
 use egui::{Align2, Color32, Pos2, Rect, RichText, Stroke, Vec2};
 use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
 
+use ma_blocks::adjust::ImageAdjust;
+use ma_blocks::crop::{crop_to, cropped_aspect_ratio, pixel_at, uncropped_rect};
+use ma_blocks::layout::{self, fit_rect_to_aspect};
+use ma_blocks::link_preview::{self, PagePreview};
+use ma_blocks::markdown::{self, Format};
+use ma_blocks::playback::{next_frame, Direction, Looping, Progress};
+use ma_blocks::resize::{resized_rect, ResizeHandle};
+use ma_blocks::selection::point_in_polygon;
+use ma_blocks::session::{
+    crop_from_session, rect_from_session, rect_to_session, BackgroundData, BackgroundPattern,
+    BlockContentData, BlockData, Session, ViewportData,
+};
+use ma_blocks::spelling;
+use ma_blocks::work_queue::WorkQueue;
+use ma_blocks::{
+    coast, ViewAction, Viewport, FULL_CROP, HIGHEST_ZOOM, LOWEST_ZOOM, MAX_ZOOM, MIN_BLOCK_SIZE,
+    MIN_ZOOM,
+};
 use rfd::FileDialog;
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::thread;
 use uuid::Uuid;
 
-const COLLISION_GAP: f32 = 1.0;
-const MIN_BLOCK_SIZE: f32 = 50.0;
+/// Width of a newly created image block (and of a grid cell when importing several)
+const IMAGE_BLOCK_WIDTH: f32 = 300.0;
+/// Gap between imported blocks, whether laid out in a grid or a column
+const IMPORT_GRID_GAP: f32 = 20.0;
+/// Width of the text blocks a Markdown file is imported as
+const MARKDOWN_IMPORT_WIDTH: f32 = 400.0;
+/// Size a new link block starts at, room for a thumbnail, its title, description and URL
+const LINK_BLOCK_WIDTH: f32 = 360.0;
+const LINK_BLOCK_HEIGHT: f32 = 90.0;
+/// How long a new block takes to fade in, in milliseconds
+const FADE_IN_MILLIS: f32 = 150.0;
+/// Size and color of a newly added color swatch block
+const SWATCH_SIZE: f32 = 120.0;
+const DEFAULT_SWATCH_COLOR: Color32 = Color32::from_rgb(0x4a, 0x90, 0xd9);
+/// How long fetching a page or its preview image may take before the link stays plain
+const LINK_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Largest preview image downloaded for a link block
+const LINK_IMAGE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// Longest side of a link block's thumbnail, in pixels
+const LINK_THUMBNAIL_SIZE: u32 = 256;
+/// File extensions (lowercase) that can be loaded as image blocks (PDFs show a single page)
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "avif", "webp", "svg", "pdf"];
+/// Pixel width an SVG is first rasterized at
+const SVG_INITIAL_RASTER_WIDTH: u32 = 600;
+/// Upper bound for SVG re-rasterization when zooming in
+const SVG_MAX_RASTER_WIDTH: u32 = 4096;
+/// Pixel width PDF pages are rendered at
+const PDF_RASTER_WIDTH: u32 = 1200;
+/// Link previews fetched at the same time; the rest wait for one of these to finish
+const LINK_FETCH_WORKERS: usize = 4;
+/// Widest the eyedropper decodes an image to sample it, in pixels
+const EYEDROPPER_MAX_WIDTH: u32 = 4096;
+/// Seconds between clearing the markdown cache of entries left behind by edits
+const MARKDOWN_CACHE_LIFETIME: f64 = 120.0;
+/// How long a notification stays on screen, in seconds
+const NOTIFICATION_DURATION: f64 = 4.0;
+/// Seconds between rehashing the canvas to show unsaved changes in the window title
+const DIRTY_CHECK_INTERVAL: f64 = 0.5;
+/// Storage key for the `Settings`
+const SETTINGS_KEY: &str = "settings";
+const KEYMAP_KEY: &str = "keymap";
+const COUNTER_TOOL_KEY: &str = "counter_tool_active";
+const SHOW_HELP_KEY: &str = "show_help";
+const SHOW_LAYERS_KEY: &str = "show_layers";
+/// Keys each preference was stored under before they were gathered into `Settings`,
+/// only read to carry them over
+const THEME_KEY: &str = "theme";
+const EXPORT_SCALE_KEY: &str = "export_scale";
+const SCROLL_MODE_KEY: &str = "scroll_mode";
+const ZOOM_LIMITS_KEY: &str = "zoom_limits";
+const PAN_INERTIA_KEY: &str = "pan_inertia";
+const EDIT_PREVIEW_KEY: &str = "edit_preview";
+const SPELL_CHECK_KEY: &str = "spell_check";
+const ONION_SKIN_KEY: &str = "onion_skin";
+const NEGATIVE_COUNTERS_KEY: &str = "negative_counters";
+const FADE_IN_KEY: &str = "fade_in_blocks";
+/// Id of the status bar's file name search box, for the shortcut that focuses it
+const FILENAME_SEARCH_ID: &str = "filename_search";
+/// How far down and right of the block below each block of a stacked pile is placed
+const STACK_OFFSET: f32 = 24.0;
+/// How far down and right of the original a duplicate is placed, if there is room
+const DUPLICATE_OFFSET: f32 = 20.0;
+/// Longest text a screen reader announces for a text block, in characters
+const ACCESSIBLE_TEXT_CHARS: usize = 200;
+/// Frame rate offered when an animation is first given a fixed one
+const DEFAULT_FIXED_FPS: f64 = 10.0;
+/// Opacity of the neighbouring frames drawn over a paused animation with onion skin on
+const ONION_SKIN_ALPHA: f32 = 0.3;
+/// Seconds for the outline of chained blocks to fade out and back while a chain
+/// handle is hovered
+const CHAIN_PULSE_PERIOD: f64 = 1.0;
+/// Frames the diagnostics overlay graphs, one pixel wide each
+const FRAME_HISTORY: usize = 120;
+/// Frame time (seconds) that fills the diagnostics graph's height
+const FRAME_GRAPH_MAX: f32 = 0.05;
+/// Most deleted blocks the trash keeps; older ones are dropped for good
+const TRASH_LIMIT: usize = 50;
+/// How much Ctrl+arrow keys grow or shrink the selected blocks (world units)
+const KEY_RESIZE_STEP: f32 = 10.0;
+/// How fast held keys move the view, in screen pixels per second
+const KEY_PAN_SPEED: f32 = 600.0;
+/// How much held keys multiply the zoom by per second
+const KEY_ZOOM_SPEED: f32 = 2.0;
+/// Default world distance between background dots/lines at normal zoom
+const GRID_SPACING: f32 = 50.0;
+/// Grid spacings the preferences allow
+const GRID_SPACING_RANGE: std::ops::RangeInclusive<f32> = 10.0..=500.0;
+/// Columns a folder of images can be imported in
+const IMPORT_COLUMNS_RANGE: std::ops::RangeInclusive<usize> = 1..=12;
+/// Closest the background dots/lines get on screen before the grid coarsens, in pixels
+const GRID_MIN_SCREEN_SPACING: f32 = 16.0;
+/// Share of the view a block fills when it is fitted to it
+const FIT_BLOCK_FILL: f32 = 0.9;
+/// How close a dragged block's edge gets to another's before snapping to it, in pixels
+const SNAP_DISTANCE: f32 = 6.0;
 
 // --- Image Decoder Module ---
 
 mod image_decoder {
+    use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+    use std::path::Path;
+    use std::sync::{Arc, OnceLock};
 
     /// Image format enum
     #[derive(Clone, Copy)]
@@ -25,6 +139,7 @@ mod image_decoder {
         Avif,
         Gif,
         Webp,
+        Apng,
     }
 
     /// Decoded AVIF frame with RGBA pixels
@@ -48,6 +163,16 @@ mod image_decoder {
         pub frame_durations: Vec<f64>,
     }
 
+    /// Logs a failed libavif call and turns its result into an `Option` for `?`
+    fn avif_check(result: libavif_sys::avifResult, step: &str) -> Option<()> {
+        if result == libavif_sys::AVIF_RESULT_OK {
+            Some(())
+        } else {
+            eprintln!("AVIF decode error: {step} failed (avifResult {result})");
+            None
+        }
+    }
+
     /// RAII wrapper for avifDecoder to ensure proper cleanup
     struct DecoderGuard {
         decoder: *mut libavif_sys::avifDecoder,
@@ -57,7 +182,7 @@ mod image_decoder {
         fn new() -> Option<Self> {
             let decoder = unsafe { libavif_sys::avifDecoderCreate() };
             if decoder.is_null() {
-                return None;
+                None
             } else {
                 // Enable multi-threaded decoding using all available CPU cores
                 unsafe {
@@ -66,7 +191,7 @@ mod image_decoder {
                         .unwrap_or(4);
                     (*decoder).maxThreads = num_cpus;
                 }
-                return Some(Self { decoder });
+                Some(Self { decoder })
             }
         }
     }
@@ -91,20 +216,30 @@ mod image_decoder {
             }
         }
 
-        fn allocate(&mut self, image: *const libavif_sys::avifImage) {
-            unsafe {
+        fn allocate(&mut self, image: *const libavif_sys::avifImage) -> Option<()> {
+            let result = unsafe {
                 libavif_sys::avifRGBImageSetDefaults(&mut self.rgb, image);
                 self.rgb.format = libavif_sys::AVIF_RGB_FORMAT_RGBA;
                 self.rgb.depth = 8;
-                libavif_sys::avifRGBImageAllocatePixels(&mut self.rgb);
-                self.allocated = true;
-            }
+                libavif_sys::avifRGBImageAllocatePixels(&mut self.rgb)
+            };
+            // libavif may have allocated before failing, so free whatever is there on drop
+            self.allocated = !self.rgb.pixels.is_null();
+            avif_check(result, "avifRGBImageAllocatePixels")?;
+            self.allocated.then_some(())
         }
 
-        fn convert_from_yuv(&mut self, image: *const libavif_sys::avifImage) {
-            unsafe {
-                libavif_sys::avifImageYUVToRGB(image, &mut self.rgb);
-            }
+        fn convert_from_yuv(&mut self, image: *const libavif_sys::avifImage) -> Option<()> {
+            let result = unsafe { libavif_sys::avifImageYUVToRGB(image, &mut self.rgb) };
+            avif_check(result, "avifImageYUVToRGB")
+        }
+
+        /// Allocates, converts and copies out one decoded image as packed RGBA
+        fn decode_rgba(image: *const libavif_sys::avifImage) -> Option<Vec<u8>> {
+            let mut rgb = Self::new();
+            rgb.allocate(image)?;
+            rgb.convert_from_yuv(image)?;
+            Some(rgb.extract_pixels())
         }
 
         fn extract_pixels(&self) -> Vec<u8> {
@@ -176,6 +311,43 @@ mod image_decoder {
         pub frame_durations: Vec<f64>,
     }
 
+    /// Decoded APNG frame with RGBA pixels, already composited onto the canvas
+    pub struct ApngFrame {
+        pub pixels: Vec<u8>,
+        pub width: u32,
+        pub height: u32,
+        pub duration: f64,
+    }
+
+    /// Result of decoding an animated PNG
+    pub struct ApngDecodeResult {
+        pub frames: Vec<ApngFrame>,
+    }
+
+    /// Result of decoding just the first frame of an APNG (for preview)
+    pub struct ApngFirstFrameResult {
+        pub frame: ApngFrame,
+        pub aspect_ratio: f32,
+        pub total_frame_count: usize,
+        pub frame_durations: Vec<f64>,
+    }
+
+    /// SVG document rendered to pixels
+    pub struct SvgRaster {
+        /// Premultiplied RGBA pixels
+        pub pixels: Vec<u8>,
+        pub width: u32,
+        pub height: u32,
+        pub aspect_ratio: f32,
+    }
+
+    /// A PDF page rendered to RGBA pixels
+    pub struct PdfPageRaster {
+        pub pixels: Vec<u8>,
+        pub width: u32,
+        pub height: u32,
+    }
+
     /// Decode an AVIF file from bytes, supporting both static and animated images.
     ///
     /// Returns `None` if decoding fails at any step.
@@ -186,14 +358,10 @@ mod image_decoder {
         let result = unsafe {
             libavif_sys::avifDecoderSetIOMemory(decoder.decoder, data.as_ptr(), data.len())
         };
-        if result != libavif_sys::AVIF_RESULT_OK {
-            return None;
-        }
+        avif_check(result, "avifDecoderSetIOMemory")?;
 
         let result = unsafe { libavif_sys::avifDecoderParse(decoder.decoder) };
-        if result != libavif_sys::AVIF_RESULT_OK {
-            return None;
-        }
+        avif_check(result, "avifDecoderParse")?;
 
         let mut frames = Vec::new();
 
@@ -210,10 +378,15 @@ mod image_decoder {
 
         let mut frame_index: u32 = 0;
 
-        // Decode all frames
-        while unsafe { libavif_sys::avifDecoderNextImage(decoder.decoder) }
-            == libavif_sys::AVIF_RESULT_OK
-        {
+        // Decode all frames. A failure midway drops the whole animation rather than
+        // showing a truncated one; the guards release everything decoded so far.
+        loop {
+            let result = unsafe { libavif_sys::avifDecoderNextImage(decoder.decoder) };
+            if result == libavif_sys::AVIF_RESULT_NO_IMAGES_REMAINING {
+                break;
+            }
+            avif_check(result, "avifDecoderNextImage")?;
+
             let image = unsafe { (*decoder.decoder).image };
             if image.is_null() {
                 continue;
@@ -222,11 +395,7 @@ mod image_decoder {
             let (width, height) = unsafe { ((*image).width, (*image).height) };
 
             // Convert YUV to RGBA
-            let mut rgb = RgbImageGuard::new();
-            rgb.allocate(image);
-            rgb.convert_from_yuv(image);
-
-            let pixels = rgb.extract_pixels();
+            let pixels = RgbImageGuard::decode_rgba(image)?;
 
             // Get timing using avifDecoderNthImageTiming for more reliable results
             let duration = unsafe {
@@ -279,14 +448,10 @@ mod image_decoder {
         let result = unsafe {
             libavif_sys::avifDecoderSetIOMemory(decoder.decoder, data.as_ptr(), data.len())
         };
-        if result != libavif_sys::AVIF_RESULT_OK {
-            return None;
-        }
+        avif_check(result, "avifDecoderSetIOMemory")?;
 
         let result = unsafe { libavif_sys::avifDecoderParse(decoder.decoder) };
-        if result != libavif_sys::AVIF_RESULT_OK {
-            return None;
-        }
+        avif_check(result, "avifDecoderParse")?;
 
         let image_count = unsafe { (*decoder.decoder).imageCount } as usize;
         let total_duration = unsafe { (*decoder.decoder).duration };
@@ -318,9 +483,7 @@ mod image_decoder {
 
         // Decode only the first frame
         let result = unsafe { libavif_sys::avifDecoderNextImage(decoder.decoder) };
-        if result != libavif_sys::AVIF_RESULT_OK {
-            return None;
-        }
+        avif_check(result, "avifDecoderNextImage")?;
 
         let image = unsafe { (*decoder.decoder).image };
         if image.is_null() {
@@ -334,11 +497,7 @@ mod image_decoder {
             1.0
         };
 
-        let mut rgb = RgbImageGuard::new();
-        rgb.allocate(image);
-        rgb.convert_from_yuv(image);
-
-        let pixels = rgb.extract_pixels();
+        let pixels = RgbImageGuard::decode_rgba(image)?;
 
         Some(AvifFirstFrameResult {
             frame: AvifFrame {
@@ -400,21 +559,17 @@ mod image_decoder {
 
         let total_frame_count = frame_durations.len();
 
-        if let Some(pixels) = first_frame_pixels {
-            Some(GifFirstFrameResult {
-                frame: GifFrame {
-                    pixels,
-                    width,
-                    height,
-                    duration: frame_durations.first().copied().unwrap_or(0.1),
-                },
-                aspect_ratio,
-                total_frame_count,
-                frame_durations,
-            })
-        } else {
-            None
-        }
+        first_frame_pixels.map(|pixels| GifFirstFrameResult {
+            frame: GifFrame {
+                pixels,
+                width,
+                height,
+                duration: frame_durations.first().copied().unwrap_or(0.1),
+            },
+            aspect_ratio,
+            total_frame_count,
+            frame_durations,
+        })
     }
 
     /// Decode a WebP file from bytes, supporting both static and animated images.
@@ -491,8 +646,8 @@ mod image_decoder {
         Some(WebpFirstFrameResult {
             frame: WebpFrame {
                 pixels,
-                width: width as u32,
-                height: height as u32,
+                width,
+                height,
                 duration: frame_durations.first().copied().unwrap_or(0.1),
             },
             aspect_ratio,
@@ -500,14 +655,190 @@ mod image_decoder {
             frame_durations,
         })
     }
-}
 
-#[derive(Clone, Copy, PartialEq)]
-enum ResizeHandle {
-    TopLeft,
-    TopRight,
-    BottomLeft,
-    BottomRight,
+    /// Frame delays of an animated PNG, read from its `fcTL` chunks without decoding
+    /// any pixels. `None` for regular PNGs, which have no `acTL` chunk.
+    fn apng_frame_durations(data: &[u8]) -> Option<Vec<f64>> {
+        const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+        let mut rest = data.strip_prefix(SIGNATURE)?;
+        let mut animated = false;
+        let mut durations = Vec::new();
+
+        // Each chunk is a big-endian length, a 4 byte type, the data and a CRC
+        while rest.len() >= 12 {
+            let len = u32::from_be_bytes(rest[0..4].try_into().ok()?) as usize;
+            let kind = &rest[4..8];
+            let chunk = rest.get(8..8 + len)?;
+            match kind {
+                b"acTL" => animated = true,
+                // Delay numerator and denominator follow the sequence number,
+                // size and offset; a zero denominator means 1/100 s
+                b"fcTL" if len >= 26 => {
+                    let num = u16::from_be_bytes([chunk[20], chunk[21]]);
+                    let den = match u16::from_be_bytes([chunk[22], chunk[23]]) {
+                        0 => 100,
+                        den => den,
+                    };
+                    durations.push(num as f64 / den as f64);
+                }
+                b"IEND" => break,
+                _ => {}
+            }
+            rest = rest.get(12 + len..)?;
+        }
+
+        (animated && !durations.is_empty()).then_some(durations)
+    }
+
+    /// Decode an animated PNG from bytes. Returns `None` for regular PNGs.
+    pub fn decode_apng(data: &[u8]) -> Option<ApngDecodeResult> {
+        use image::AnimationDecoder;
+
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(data)).ok()?;
+        if !decoder.is_apng().ok()? {
+            return None;
+        }
+
+        let mut frames = Vec::new();
+        for frame in decoder.apng().ok()?.into_frames() {
+            let frame = frame.ok()?;
+            let (num, den) = frame.delay().numer_denom_ms();
+            let buffer = frame.into_buffer();
+            frames.push(ApngFrame {
+                width: buffer.width(),
+                height: buffer.height(),
+                pixels: buffer.into_raw(),
+                duration: num as f64 / den as f64 / 1000.0,
+            });
+        }
+
+        if frames.is_empty() {
+            None
+        } else {
+            Some(ApngDecodeResult { frames })
+        }
+    }
+
+    /// Decode only the first frame of an animated PNG (fast preview). Returns `None`
+    /// for regular PNGs.
+    pub fn decode_apng_first_frame(data: &[u8]) -> Option<ApngFirstFrameResult> {
+        use image::AnimationDecoder;
+
+        let frame_durations = apng_frame_durations(data)?;
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(data)).ok()?;
+        let buffer = decoder
+            .apng()
+            .ok()?
+            .into_frames()
+            .next()?
+            .ok()?
+            .into_buffer();
+        let (width, height) = buffer.dimensions();
+        let aspect_ratio = if height > 0 {
+            width as f32 / height as f32
+        } else {
+            1.0
+        };
+
+        Some(ApngFirstFrameResult {
+            frame: ApngFrame {
+                pixels: buffer.into_raw(),
+                width,
+                height,
+                duration: frame_durations.first().copied().unwrap_or(0.1),
+            },
+            aspect_ratio,
+            total_frame_count: frame_durations.len(),
+            frame_durations,
+        })
+    }
+
+    /// SVG parsing options with the system fonts loaded.
+    pub fn svg_options() -> resvg::usvg::Options<'static> {
+        // System fonts are only scanned once, the database is shared between renders
+        static FONTS: OnceLock<Arc<resvg::usvg::fontdb::Database>> = OnceLock::new();
+        let fontdb = FONTS.get_or_init(|| {
+            let mut db = resvg::usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            // egui's bundled fonts, so exported text matches the canvas even without system fonts
+            for data in egui::FontDefinitions::default().font_data.into_values() {
+                db.load_font_data(data.font.into_owned());
+            }
+            db.set_sans_serif_family("Ubuntu");
+            Arc::new(db)
+        });
+        resvg::usvg::Options {
+            fontdb: fontdb.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Render an SVG document from bytes so that it is `target_width` pixels wide.
+    ///
+    /// Returns `None` if the document can't be parsed or has no area.
+    pub fn rasterize_svg(data: &[u8], target_width: u32) -> Option<SvgRaster> {
+        let tree = resvg::usvg::Tree::from_data(data, &svg_options()).ok()?;
+        let size = tree.size();
+        if size.width() <= 0.0 || size.height() <= 0.0 {
+            return None;
+        }
+
+        let aspect_ratio = size.width() / size.height();
+        let width = target_width.max(1);
+        let height = ((width as f32 / aspect_ratio).round() as u32).max(1);
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)?;
+        let transform = resvg::tiny_skia::Transform::from_scale(
+            width as f32 / size.width(),
+            height as f32 / size.height(),
+        );
+        resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+        Some(SvgRaster {
+            pixels: pixmap.take(),
+            width,
+            height,
+            aspect_ratio,
+        })
+    }
+
+    /// Shared pdfium instance, bound on first use to a library next to the
+    /// executable or, failing that, the system one. `None` if neither is found.
+    fn pdfium() -> Option<&'static Pdfium> {
+        static PDFIUM: OnceLock<Option<Pdfium>> = OnceLock::new();
+        PDFIUM
+            .get_or_init(|| {
+                // Not the working directory, which depends on how the app was started
+                let bundled = std::env::current_exe().ok().and_then(|exe| {
+                    let dir = exe.parent()?;
+                    Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(dir)).ok()
+                });
+                bundled
+                    .map_or_else(Pdfium::bind_to_system_library, Ok)
+                    .map(Pdfium::new)
+                    .map_err(|e| eprintln!("PDF support unavailable: {e}"))
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// Number of pages in the PDF at `path`
+    pub fn pdf_page_count(path: &Path) -> Option<u16> {
+        let document = pdfium()?.load_pdf_from_file(path, None).ok()?;
+        Some(document.pages().len())
+    }
+
+    /// Render page `index` (zero-based) of the PDF at `path` so that it is `target_width` pixels wide.
+    pub fn render_pdf_page(path: &Path, index: u16, target_width: u32) -> Option<PdfPageRaster> {
+        let document = pdfium()?.load_pdf_from_file(path, None).ok()?;
+        let page = document.pages().get(index).ok()?;
+        let config = PdfRenderConfig::new().set_target_width(target_width as i32);
+        let bitmap = page.render_with_config(&config).ok()?;
+        Some(PdfPageRaster {
+            pixels: bitmap.as_rgba_bytes(),
+            width: bitmap.width() as u32,
+            height: bitmap.height() as u32,
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -533,15 +864,346 @@ enum AnimationState {
     /// Currently loading animation frames in background
     Loading { total_frame_count: usize },
     /// Animation paused due to concurrent limit - can be resumed on click
-    Paused {
-        total_frame_count: usize,
-        frame_durations: Vec<f64>,
-        aspect_ratio: f32,
-        path: String,
-        format: image_decoder::ImageFormat,
-    },
+    Paused,
+}
+
+/// What scrolling does on the canvas
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, serde::Serialize, serde::Deserialize)]
+enum ScrollMode {
+    /// Mouse wheel style: scrolling zooms
+    #[default]
+    Zoom,
+    /// Trackpad style: two-finger scrolling pans, pinching or Ctrl+scroll zooms
+    Pan,
+}
+
+/// Preferences kept between launches, edited in the Preferences window. Fields missing
+/// from what was saved, e.g. ones added since, get their default.
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct Settings {
+    /// Dark or light look of both the egui widgets and the canvas
+    theme: egui::Theme,
+    /// New blocks fade in over `FADE_IN_MILLIS` instead of appearing at once
+    fade_in_blocks: bool,
+    /// World distance between background dots/lines at normal zoom
+    grid_spacing: f32,
+    scroll_mode: ScrollMode,
+    /// Let a fast pan carry on for a moment after it is released
+    pan_inertia: bool,
+    /// Lowest and highest zoom, applied to the view with `Viewport::set_zoom_limits`
+    zoom_limits: (f32, f32),
+    /// Show the rendered markdown next to the editor of a text block
+    edit_preview: bool,
+    /// Underline words the spell checker doesn't know while editing text
+    spell_check: bool,
+    /// Ghost the previous and next frames over paused animations
+    onion_skin: bool,
+    /// Animations allowed to play at once; starting one more pauses the oldest
+    max_concurrent_animations: usize,
+    /// Let the counter tool count below zero instead of stopping there
+    negative_counters: bool,
+    /// Pixels per canvas unit in exported images, whatever the view's zoom
+    export_scale: f32,
+    /// How blocks that are dragged or resized onto others make room
+    collision_mode: CollisionMode,
+    /// Settle chained blocks as one rigid group on release, keeping their relative layout
+    rigid_chains: bool,
+    /// Snap dragged blocks to the edges and centers of nearby ones
+    snap_to_guides: bool,
+    /// Tidy up into masonry columns instead of rows
+    tidy_masonry: bool,
+    /// Tidy up chained groups as one piece
+    tidy_keep_groups: bool,
+    /// Number of columns used when importing a folder of images
+    import_columns: usize,
+    /// Import every page of a picked PDF instead of just the first one
+    pdf_all_pages: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            theme: egui::Theme::Dark,
+            fade_in_blocks: true,
+            grid_spacing: GRID_SPACING,
+            scroll_mode: ScrollMode::default(),
+            pan_inertia: true,
+            zoom_limits: (MIN_ZOOM, MAX_ZOOM),
+            edit_preview: false,
+            spell_check: false,
+            onion_skin: false,
+            max_concurrent_animations: 15,
+            negative_counters: false,
+            export_scale: 1.0,
+            collision_mode: CollisionMode::default(),
+            rigid_chains: true,
+            snap_to_guides: true,
+            tidy_masonry: false,
+            tidy_keep_groups: true,
+            import_columns: 4,
+            pdf_all_pages: false,
+        }
+    }
+}
+
+impl Settings {
+    /// What was saved on the last run. Without that, the preferences saved one key each
+    /// by older versions are carried over.
+    fn load(storage: &dyn eframe::Storage) -> Self {
+        if let Some(settings) = eframe::get_value(storage, SETTINGS_KEY) {
+            return settings;
+        }
+        let mut settings = Settings::default();
+        if let Some(theme) = eframe::get_value(storage, THEME_KEY) {
+            settings.theme = theme;
+        }
+        if let Some(fade_in) = eframe::get_value(storage, FADE_IN_KEY) {
+            settings.fade_in_blocks = fade_in;
+        }
+        if let Some(mode) = eframe::get_value(storage, SCROLL_MODE_KEY) {
+            settings.scroll_mode = mode;
+        }
+        if let Some(inertia) = eframe::get_value(storage, PAN_INERTIA_KEY) {
+            settings.pan_inertia = inertia;
+        }
+        if let Some(limits) = eframe::get_value(storage, ZOOM_LIMITS_KEY) {
+            settings.zoom_limits = limits;
+        }
+        if let Some(preview) = eframe::get_value(storage, EDIT_PREVIEW_KEY) {
+            settings.edit_preview = preview;
+        }
+        if let Some(spell_check) = eframe::get_value(storage, SPELL_CHECK_KEY) {
+            settings.spell_check = spell_check;
+        }
+        if let Some(onion_skin) = eframe::get_value(storage, ONION_SKIN_KEY) {
+            settings.onion_skin = onion_skin;
+        }
+        if let Some(negative) = eframe::get_value(storage, NEGATIVE_COUNTERS_KEY) {
+            settings.negative_counters = negative;
+        }
+        if let Some(scale) = eframe::get_value(storage, EXPORT_SCALE_KEY) {
+            settings.export_scale = scale;
+        }
+        settings
+    }
+}
+
+/// What happens when a dragged or resized block ends up over others
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, serde::Serialize, serde::Deserialize)]
+enum CollisionMode {
+    /// Neighbors are shoved aside while dragging, and once more on release
+    #[default]
+    Push,
+    /// Blocks pass over each other while dragging and are moved apart on release
+    SettleOnDrop,
+    /// Blocks stay exactly where they are put, overlapping or not
+    Overlap,
+}
+
+/// What the canvas is about to be replaced with, once unsaved changes are dealt with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Replacement {
+    /// A session file, still to be picked
+    Load,
+    /// An empty, untitled board
+    New,
+}
+
+/// Commands whose keys can be changed in the Keyboard Shortcuts window
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, serde::Serialize, serde::Deserialize,
+)]
+enum Action {
+    /// Held while dragging with the left mouse button to pan
+    PanDrag,
+    /// Leave text editing, the ruler, cropping or present mode
+    Cancel,
+    Save,
+    Duplicate,
+    Delete,
+    FitBlock,
+    Preview,
+    Present,
+    FindImage,
+    Diagnostics,
+    SelectAll,
+    InvertSelection,
+    /// Held to move the view, for `key_view_actions`
+    LookUp,
+    LookLeft,
+    LookDown,
+    LookRight,
+    ZoomOut,
+    ZoomIn,
+    /// Step along the slide path while presenting
+    NextSlide,
+    PreviousSlide,
+}
+
+impl Action {
+    const ALL: [Action; 20] = [
+        Action::PanDrag,
+        Action::Cancel,
+        Action::Save,
+        Action::Duplicate,
+        Action::Delete,
+        Action::FitBlock,
+        Action::Preview,
+        Action::Present,
+        Action::FindImage,
+        Action::Diagnostics,
+        Action::SelectAll,
+        Action::InvertSelection,
+        Action::LookUp,
+        Action::LookLeft,
+        Action::LookDown,
+        Action::LookRight,
+        Action::ZoomOut,
+        Action::ZoomIn,
+        Action::NextSlide,
+        Action::PreviousSlide,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::PanDrag => "Pan (hold while dragging)",
+            Action::Cancel => "Leave editing, ruler, crop or present mode",
+            Action::Save => "Save session",
+            Action::Duplicate => "Duplicate selection",
+            Action::Delete => "Delete selection",
+            Action::FitBlock => "Zoom to selected block",
+            Action::Preview => "Toggle text preview",
+            Action::Present => "Present",
+            Action::FindImage => "Find image by file name",
+            Action::Diagnostics => "Show frame times and memory use",
+            Action::SelectAll => "Select all blocks",
+            Action::InvertSelection => "Invert selection",
+            Action::LookUp => "Look up (hold)",
+            Action::LookLeft => "Look left (hold)",
+            Action::LookDown => "Look down (hold)",
+            Action::LookRight => "Look right (hold)",
+            Action::ZoomOut => "Zoom out (hold)",
+            Action::ZoomIn => "Zoom in (hold)",
+            Action::NextSlide => "Next slide while presenting",
+            Action::PreviousSlide => "Previous slide while presenting",
+        }
+    }
+
+    fn default_shortcut(self) -> egui::KeyboardShortcut {
+        use egui::{Key, Modifiers};
+        let (modifiers, key) = match self {
+            Action::PanDrag => (Modifiers::NONE, Key::Space),
+            Action::Cancel => (Modifiers::NONE, Key::Escape),
+            Action::Save => (Modifiers::COMMAND, Key::S),
+            Action::Duplicate => (Modifiers::COMMAND, Key::D),
+            Action::Delete => (Modifiers::NONE, Key::Delete),
+            Action::FitBlock => (Modifiers::NONE, Key::F),
+            Action::Preview => (Modifiers::COMMAND, Key::P),
+            Action::Present => (Modifiers::NONE, Key::F5),
+            Action::FindImage => (Modifiers::COMMAND, Key::F),
+            Action::Diagnostics => (Modifiers::NONE, Key::F12),
+            Action::SelectAll => (Modifiers::COMMAND, Key::A),
+            Action::InvertSelection => (Modifiers::COMMAND | Modifiers::SHIFT, Key::I),
+            Action::LookUp => (Modifiers::NONE, Key::W),
+            Action::LookLeft => (Modifiers::NONE, Key::A),
+            Action::LookDown => (Modifiers::NONE, Key::S),
+            Action::LookRight => (Modifiers::NONE, Key::D),
+            Action::ZoomOut => (Modifiers::NONE, Key::Q),
+            Action::ZoomIn => (Modifiers::NONE, Key::E),
+            Action::NextSlide => (Modifiers::NONE, Key::ArrowRight),
+            Action::PreviousSlide => (Modifiers::NONE, Key::ArrowLeft),
+        };
+        egui::KeyboardShortcut::new(modifiers, key)
+    }
+}
+
+/// Keys bound to each `Action`. Only the ones changed from `Action::default_shortcut`
+/// are kept, so later defaults still reach saved settings.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+struct Keymap {
+    changed: BTreeMap<Action, egui::KeyboardShortcut>,
+}
+
+impl Keymap {
+    fn shortcut(&self, action: Action) -> egui::KeyboardShortcut {
+        self.changed
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_shortcut())
+    }
+
+    fn bind(&mut self, action: Action, shortcut: egui::KeyboardShortcut) {
+        if shortcut == action.default_shortcut() {
+            self.changed.remove(&action);
+        } else {
+            self.changed.insert(action, shortcut);
+        }
+    }
+
+    /// Whether the action's shortcut was pressed, taking the key press so nothing
+    /// else reacts to it
+    fn consume(&self, input: &mut egui::InputState, action: Action) -> bool {
+        input.consume_shortcut(&self.shortcut(action))
+    }
+
+    /// Whether the action's shortcut was pressed, leaving it for others to see too
+    fn pressed(&self, input: &egui::InputState, action: Action) -> bool {
+        let shortcut = self.shortcut(action);
+        input.key_pressed(shortcut.logical_key)
+            && input.modifiers.matches_logically(shortcut.modifiers)
+    }
+
+    /// Whether the action's key is being held down
+    fn held(&self, input: &egui::InputState, action: Action) -> bool {
+        let shortcut = self.shortcut(action);
+        input.key_down(shortcut.logical_key)
+            && input.modifiers.matches_logically(shortcut.modifiers)
+    }
+
+    /// Other actions bound to the same shortcut as `action`
+    fn conflicts(&self, action: Action) -> Vec<Action> {
+        let shortcut = self.shortcut(action);
+        Action::ALL
+            .into_iter()
+            .filter(|&other| other != action && self.shortcut(other) == shortcut)
+            .collect()
+    }
+}
+
+/// Canvas colors that go with an egui theme
+struct Palette {
+    canvas: Color32,
+    block: Color32,
+    /// Default color of text on a block, readable on `block` whatever egui's text color is
+    block_text: Color32,
+    /// Ring around the block being edited or reached with the keyboard, standing out
+    /// from `canvas` and the selection/chain borders
+    focus: Color32,
+}
+
+impl Palette {
+    fn for_theme(theme: egui::Theme) -> Self {
+        match theme {
+            egui::Theme::Dark => Self {
+                canvas: Color32::from_rgb(30, 30, 30),
+                block: Color32::from_rgb(240, 240, 240),
+                block_text: Color32::from_gray(30),
+                focus: Color32::from_rgb(0, 200, 255),
+            },
+            egui::Theme::Light => Self {
+                canvas: Color32::from_rgb(215, 215, 215),
+                block: Color32::WHITE,
+                block_text: Color32::from_gray(30),
+                focus: Color32::from_rgb(0, 40, 160),
+            },
+        }
+    }
 }
 
+// Boards are mostly images, so boxing that variant would only add an indirection
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone)]
 enum BlockContent {
     Text {
@@ -562,6 +1224,30 @@ enum BlockContent {
         first_frame: Option<egui::ColorImage>,
         /// Time when animation started playing (for concurrent limit management)
         playing_start_time: Option<f64>,
+        /// Visible part of the image in texture coordinates; `aspect_ratio` is that of
+        /// this part
+        crop: Rect,
+        /// Brightness/contrast/grayscale baked into `frames`
+        adjust: ImageAdjust,
+        looping: Looping,
+        direction: Direction,
+        progress: Progress,
+        /// Seconds every frame is shown for, in place of the file's own `frame_delays`
+        delay_override: Option<f64>,
+    },
+    /// Bookmark card that opens `url` in the browser when clicked. The rest is filled in
+    /// from the page's preview tags once they have been fetched.
+    Link {
+        url: String,
+        title: String,
+        description: String,
+        image_url: Option<String>,
+        thumbnail: Option<egui::TextureHandle>,
+    },
+    /// Solid swatch of `color`; clicking it copies the hex code
+    Color {
+        color: Color32,
+        label: bool,
     },
 }
 
@@ -572,16 +1258,62 @@ struct Block {
     content: BlockContent,
     chained: bool,
     selected: bool,
+    /// 0 (invisible) to 255 (opaque), applied to the fill, border, image and text
+    opacity: u8,
+    /// Fixed to the screen instead of the world: `rect` is then in pixels from the
+    /// canvas' top-left corner, and the block takes no part in panning, zoom or collision
+    pinned: bool,
+    /// Milliseconds since the Unix epoch; 0 for blocks from sessions saved before this
+    /// was recorded
+    created_at: u64,
+    /// Position on the slide path that present mode follows, if the block is on it
+    slide: Option<u32>,
+    /// Reminder shown when hovering the block's speech bubble
+    note: Option<String>,
+    /// Resizing keeps the current width/height ratio, as it always does for images
+    lock_aspect: bool,
+    /// Hidden blocks are neither drawn nor hit on the canvas, but are still saved and
+    /// exported
+    visible: bool,
 }
 
+/// Where new block ids come from
 #[derive(Default)]
-struct Viewport {
-    pan: Vec2,
-    zoom: f32,
+enum IdGenerator {
+    /// Random v4 UUIDs
+    #[default]
+    Random,
+    /// Counting up from the last id handed out, so runs (and saved sessions) are
+    /// reproducible
+    Sequential(u128),
+}
+
+impl IdGenerator {
+    fn next_id(&mut self) -> Uuid {
+        match self {
+            IdGenerator::Random => Uuid::new_v4(),
+            IdGenerator::Sequential(last) => match last.checked_add(1) {
+                Some(next) => {
+                    *last = next;
+                    Uuid::from_u128(next)
+                }
+                // A loaded session already holds the largest id there is
+                None => Uuid::new_v4(),
+            },
+        }
+    }
+
+    /// Note an id that came from elsewhere (a loaded session) so it's never handed out again
+    fn observe(&mut self, id: Uuid) {
+        if let IdGenerator::Sequential(last) = self {
+            *last = (*last).max(id.as_u128());
+        }
+    }
 }
 
 struct CanvasApp {
     viewport: Viewport,
+    /// Blocks in z-order, back to front: drawn in order, hit-tested in reverse
     blocks: Vec<Block>,
     /// State for resizing (Right mouse drag)
     resizing_state: Option<InteractionState>,
@@ -598,19 +1330,144 @@ struct CanvasApp {
     /// Sender to clone for background threads
     image_tx: Sender<ImageLoadData>,
     /// Channel for receiving file paths from file dialog
-    file_dialog_rx: Receiver<Vec<PathBuf>>,
+    file_dialog_rx: Receiver<FileDialogResult>,
     /// Sender for file dialog results
-    file_dialog_tx: Sender<Vec<PathBuf>>,
+    file_dialog_tx: Sender<FileDialogResult>,
     /// Is the counter tool active?
     counter_tool_active: bool,
     /// Show help window
     show_help: bool,
-    /// Cache for markdown rendering
+    /// Preferences kept between launches
+    settings: Settings,
+    /// Show the Preferences window
+    show_settings: bool,
+    /// Cache for markdown rendering, shared by all text blocks. Its entries are keyed by
+    /// content, not by block, so they can't be dropped one block at a time: the whole
+    /// cache is replaced when text blocks are deleted or a new session is loaded, and
+    /// every `MARKDOWN_CACHE_LIFETIME` seconds for what edits leave behind.
     common_mark_cache: CommonMarkCache,
-    /// Maximum number of concurrent animations allowed
-    max_concurrent_animations: usize,
+    /// Markdown of text blocks with their images resolved, by text and session file, so
+    /// the file system isn't asked about every image on every frame. Cleared along with
+    /// `common_mark_cache`, which also picks up images added to the folder since.
+    resolved_markdown: HashMap<(String, Option<PathBuf>), String>,
+    /// Time `common_mark_cache` was last replaced
+    markdown_cache_cleared: f64,
     /// Current number of playing animations
     current_concurrent_animations: usize,
+    /// Placeholder blocks to shrink to the image's aspect ratio once it is decoded
+    pending_fit: HashSet<Uuid>,
+    /// SVG blocks currently being re-rasterized at a higher resolution
+    svg_rerender_pending: HashSet<Uuid>,
+    /// SVG blocks whose file couldn't be rasterized, not tried again at a higher
+    /// resolution until the block's image is decoded anew
+    svg_rerender_failed: HashSet<Uuid>,
+    /// Transient message shown at the bottom of the canvas, with the time it was posted
+    notification: Option<(String, f64)>,
+    /// Source of ids for new blocks
+    id_generator: IdGenerator,
+    /// Nudge the current drag has been snapped by, undone before snapping again so the
+    /// block can be pulled free
+    drag_snap: Vec2,
+    /// World-space alignment guides to draw for the current drag
+    snap_guides: Vec<[Pos2; 2]>,
+    /// Where the dragged block was picked up and how far the pointer has moved it
+    /// since, so Shift can hold it to one axis
+    drag_start: Option<(Pos2, Vec2)>,
+    /// Canvas fill and grid pattern, saved with the session
+    background: BackgroundData,
+    /// Screen area of the canvas in the last frame, for code that runs before it is laid out
+    canvas_rect: Rect,
+    /// World-space outline of a lasso selection being drawn (Alt + drag on empty canvas)
+    lasso: Option<Vec<Pos2>>,
+    /// Is the ruler tool active?
+    ruler_active: bool,
+    /// Clicking an image copies the color under the pointer
+    eyedropper_active: bool,
+    /// Still of the image the eyedropper last pointed at, decoded from its file when
+    /// needed rather than kept for every image (`None` until it arrives)
+    eyedropper_still: Option<(Uuid, Option<egui::ColorImage>)>,
+    /// World-space points the ruler measures between; the second follows the pointer
+    /// until it is clicked
+    ruler_points: Vec<Pos2>,
+    /// Image block being cropped, while crop mode is on
+    crop_target: Option<Uuid>,
+    /// Corners of the crop being drawn, in the target block's coordinates
+    crop_drag: Option<(Pos2, Pos2)>,
+    /// Image blocks from the loaded session whose files are gone, with the stored path
+    missing_images: Vec<(Uuid, String)>,
+    /// Shift exported selections so their top-left corner is at the world origin
+    export_to_origin: bool,
+    /// Write exported Markdown top to bottom, left to right instead of in z-order
+    markdown_reading_order: bool,
+    /// The view was being dragged last frame
+    was_panning: bool,
+    /// Velocity of a released pan that is still coasting, in screen pixels per second
+    pan_coast: Option<Vec2>,
+    /// Words the spell checker accepts on this board, saved with the session
+    custom_words: Vec<String>,
+    /// `content_fingerprint` when the canvas was last saved or loaded; `None` for a new
+    /// canvas that has never been
+    saved_fingerprint: Option<u64>,
+    /// Asking whether to drop unsaved changes before the canvas is replaced this way
+    confirm_replace: Option<Replacement>,
+    /// Whether the title last showed unsaved changes, and when that was worked out
+    dirty_check: (bool, f64),
+    /// Title last sent to the window
+    window_title: String,
+    /// File the canvas was last saved to or loaded from
+    session_path: Option<PathBuf>,
+    /// Position in `slide_order` while presenting full screen without any UI
+    presentation: Option<usize>,
+    /// Clicking blocks adds them to the slide path or takes them off, instead of
+    /// selecting them
+    editing_slide_path: bool,
+    /// Freeze panning and zooming while arranging blocks
+    view_locked: bool,
+    /// Block last reached with Tab, ringed until the mouse is used again
+    keyboard_focus: Option<Uuid>,
+    /// Keys for the commands that can be rebound
+    keymap: Keymap,
+    show_keymap: bool,
+    /// Action waiting for its new key in the Keyboard Shortcuts window
+    rebinding: Option<Action>,
+    /// Blocks deleted in this session, oldest first, with when they were deleted
+    /// (milliseconds since the Unix epoch)
+    trash: Vec<(Block, u64)>,
+    show_trash: bool,
+    /// Show the sidebar listing the blocks front to back
+    show_layers: bool,
+    /// Part of an image's file name to look for, typed in the status bar
+    filename_query: String,
+    /// Address typed into the add-text menu for a new link block
+    new_link_url: String,
+    show_diagnostics: bool,
+    /// Seconds between the last `FRAME_HISTORY` frames, oldest first, recorded while the
+    /// diagnostics overlay is shown
+    frame_times: VecDeque<f32>,
+}
+
+/// Result of a file dialog run on a background thread
+enum FileDialogResult {
+    /// Image files picked individually
+    Images(Vec<PathBuf>),
+    /// Supported images found in a picked folder, and how many other files were skipped
+    Folder {
+        images: Vec<PathBuf>,
+        skipped: usize,
+    },
+    /// Replacement files for blocks whose images went missing
+    Relink(Vec<(Uuid, PathBuf)>),
+    /// Contents of a picked Markdown file, to split into text blocks
+    Markdown(Result<String, String>),
+    /// Contact sheet of the blocks `replaces`, written to a picked file, and where its
+    /// block goes
+    ContactSheet {
+        file: Result<PathBuf, String>,
+        replaces: Vec<Uuid>,
+        rect: Rect,
+    },
+    /// Whether rendering the board to a picked PNG file worked
+    PngExport(Result<(), String>),
 }
 
 /// Data sent from background image loading thread
@@ -624,17 +1481,9 @@ enum ImageLoadData {
         path: Option<String>,
         target_block_id: Option<Uuid>,
     },
-    /// AVIF preview - only first frame loaded, animation available on demand
-    AvifPreview {
-        first_frame: egui::ColorImage,
-        frame_durations: Vec<f64>,
-        aspect_ratio: f32,
-        total_frame_count: usize,
-        path: Option<String>,
-        target_block_id: Option<Uuid>,
-    },
-    /// GIF preview - only first frame loaded, animation available on demand
-    GifPreview {
+    /// Animated AVIF/GIF/WebP preview - only first frame loaded, animation available on demand
+    AnimatedPreview {
+        format: image_decoder::ImageFormat,
         first_frame: egui::ColorImage,
         frame_durations: Vec<f64>,
         aspect_ratio: f32,
@@ -642,14 +1491,10 @@ enum ImageLoadData {
         path: Option<String>,
         target_block_id: Option<Uuid>,
     },
-    /// WebP preview - only first frame loaded, animation available on demand
-    WebpPreview {
-        first_frame: egui::ColorImage,
-        frame_durations: Vec<f64>,
-        aspect_ratio: f32,
-        total_frame_count: usize,
-        path: Option<String>,
-        target_block_id: Option<Uuid>,
+    /// Cached low-res preview for a block that is still waiting for its full decode
+    Thumbnail {
+        target_block_id: Uuid,
+        image: egui::ColorImage,
     },
     /// Remaining animation frames loaded (after user clicked play)
     AnimationLoaded {
@@ -657,51 +1502,566 @@ enum ImageLoadData {
         frames: Vec<egui::ColorImage>,
         frame_delays: Vec<f64>,
     },
+    /// Decoding the remaining animation frames failed; the block keeps its first frame
+    AnimationFailed { target_block_id: Uuid, path: String },
+    /// Rasterizing the SVG of a block failed; it keeps the raster it has, if any
+    SvgFailed { target_block_id: Uuid },
+    /// The file of a block could not be decoded at all
+    Failed { target_block_id: Uuid, path: String },
+    /// Preview of the page `url` that a link block pointed at, with its image if it
+    /// had one
+    LinkPreview {
+        target_block_id: Uuid,
+        url: String,
+        preview: PagePreview,
+        thumbnail: Option<egui::ColorImage>,
+    },
+    /// First frame of an image block's file, for the eyedropper to sample
+    EyedropperStill {
+        target_block_id: Uuid,
+        image: egui::ColorImage,
+    },
 }
 
-// --- Serialization Structs ---
+// --- Thumbnail Cache Module ---
 
-#[derive(Serialize, Deserialize)]
-struct Session {
-    viewport: ViewportData,
-    blocks: Vec<BlockData>,
-}
+/// Small PNG previews of decoded images, kept on disk so reloaded sessions can show
+/// something immediately while the full decode runs. Entries are keyed by the source's
+/// path, modification time and size, so editing the source invalidates its entry.
+mod thumbnail_cache {
+    use std::path::{Path, PathBuf};
+    use std::time::UNIX_EPOCH;
 
-#[derive(Serialize, Deserialize)]
-struct ViewportData {
-    pan: [f32; 2],
-    zoom: f32,
-}
+    /// Longest edge of a cached thumbnail, in pixels
+    const MAX_THUMBNAIL_SIZE: u32 = 256;
 
-#[derive(Serialize, Deserialize)]
-struct BlockData {
-    id: Uuid,
-    rect: [f32; 4], // min_x, min_y, max_x, max_y
-    content: BlockContentData,
-    chained: bool,
-}
+    /// Platform cache directory for thumbnails, created on demand
+    fn cache_dir() -> Option<PathBuf> {
+        let base = if cfg!(target_os = "macos") {
+            PathBuf::from(std::env::var_os("HOME")?).join("Library/Caches")
+        } else if let Some(xdg) = std::env::var_os("XDG_CACHE_HOME") {
+            PathBuf::from(xdg)
+        } else {
+            PathBuf::from(std::env::var_os("HOME")?).join(".cache")
+        };
+        let dir = base.join("ma_blocks").join("thumbnails");
+        std::fs::create_dir_all(&dir).ok()?;
+        Some(dir)
+    }
 
-#[derive(Serialize, Deserialize)]
-enum BlockContentData {
-    Text {
-        text: String,
-    },
-    Image {
-        path: String,
-        counter: i32,
-        playing: bool,
-    },
-}
+    /// 64-bit FNV-1a. Unlike `DefaultHasher`, its output is the same across Rust
+    /// releases, so entries written by one build are found by the next.
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
 
-impl Default for CanvasApp {
-    fn default() -> Self {
-        let (tx, rx) = channel();
-        let (file_tx, file_rx) = channel();
-        Self {
-            viewport: Viewport {
-                pan: Vec2::ZERO,
-                zoom: 1.0,
-            },
+    /// Cache file for a stored block path (which may carry a `#page=N` suffix) in the
+    /// source file's current state
+    fn entry_path(path: &Path) -> Option<PathBuf> {
+        let (source, _) = super::split_pdf_page(path);
+        let metadata = std::fs::metadata(source).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        let mut key = path.as_os_str().as_encoded_bytes().to_vec();
+        key.extend_from_slice(&modified.as_nanos().to_le_bytes());
+        key.extend_from_slice(&metadata.len().to_le_bytes());
+        Some(cache_dir()?.join(format!("{:016x}.png", fnv1a(&key))))
+    }
+
+    /// Cached thumbnail for `path`, if there is an up-to-date one
+    pub fn load(path: &Path) -> Option<egui::ColorImage> {
+        let entry = entry_path(path)?;
+        let buffer = image::open(&entry).ok()?.to_rgba8();
+        let size = [buffer.width() as usize, buffer.height() as usize];
+        Some(egui::ColorImage::from_rgba_unmultiplied(
+            size,
+            buffer.as_raw(),
+        ))
+    }
+
+    /// Write a downscaled copy of `frame` for `path` unless an up-to-date one exists
+    pub fn store(path: &Path, frame: &egui::ColorImage) {
+        let Some(entry) = entry_path(path) else {
+            return;
+        };
+        if entry.exists() {
+            return;
+        }
+
+        let pixels: Vec<u8> = frame
+            .pixels
+            .iter()
+            .flat_map(|c| c.to_srgba_unmultiplied())
+            .collect();
+        let Some(buffer) =
+            image::RgbaImage::from_raw(frame.width() as u32, frame.height() as u32, pixels)
+        else {
+            return;
+        };
+        let thumbnail = image::DynamicImage::ImageRgba8(buffer)
+            .thumbnail(MAX_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+
+        // Write next to the entry and rename so readers never see a partial file
+        let tmp = entry.with_extension("png.tmp");
+        if thumbnail
+            .save_with_format(&tmp, image::ImageFormat::Png)
+            .is_ok()
+        {
+            let _ = std::fs::rename(&tmp, &entry);
+        } else {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+}
+
+// --- Markdown Images ---
+
+/// Image loader for the `file://` uris that `markdown::resolve_images` gives inline
+/// images in text blocks. Decodes with the same code as exports, so text blocks show
+/// every format image blocks do (the first frame of animations).
+mod markdown_images {
+    use super::export;
+    use eframe::egui::load::{ImageLoadResult, ImageLoader, ImagePoll, LoadError, SizeHint};
+    use eframe::egui::{ColorImage, Context};
+    use ma_blocks::work_queue::WorkQueue;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+    /// Inline images are decoded at most this many pixels wide
+    const MAX_WIDTH: u32 = 1024;
+    /// Inline images decoded at the same time; the rest wait for one of these to finish
+    const DECODE_WORKERS: usize = 2;
+    static DECODES: WorkQueue = WorkQueue::new(DECODE_WORKERS);
+
+    /// Decoded image or error per uri; `None` while the decode is still running
+    type Cache = HashMap<String, Option<Result<Arc<ColorImage>, String>>>;
+
+    /// The cache, even if a thread panicked while holding it: entries only ever go in or
+    /// out whole, so it can't be left half-updated
+    fn lock(cache: &Mutex<Cache>) -> MutexGuard<'_, Cache> {
+        cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[derive(Default)]
+    pub struct Loader {
+        cache: Arc<Mutex<Cache>>,
+    }
+
+    impl ImageLoader for Loader {
+        fn id(&self) -> &str {
+            concat!(module_path!(), "::Loader")
+        }
+
+        fn load(&self, ctx: &Context, uri: &str, _: SizeHint) -> ImageLoadResult {
+            let Some(path) = uri.strip_prefix("file://") else {
+                return Err(LoadError::NotSupported);
+            };
+            let mut cache = lock(&self.cache);
+            match cache.get(uri) {
+                Some(Some(Ok(image))) => Ok(ImagePoll::Ready {
+                    image: image.clone(),
+                }),
+                Some(Some(Err(e))) => Err(LoadError::Loading(e.clone())),
+                Some(None) => Ok(ImagePoll::Pending { size: None }),
+                None => {
+                    cache.insert(uri.to_owned(), None);
+                    let (cache, ctx, uri) = (self.cache.clone(), ctx.clone(), uri.to_owned());
+                    let path = PathBuf::from(path);
+                    DECODES.spawn(move || {
+                        let result = export::decode_still(&path, MAX_WIDTH)
+                            .map(|image| {
+                                let size = [image.width() as usize, image.height() as usize];
+                                Arc::new(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+                            })
+                            .ok_or_else(|| format!("Could not read {}", path.display()));
+                        lock(&cache).insert(uri, Some(result));
+                        ctx.request_repaint();
+                    });
+                    Ok(ImagePoll::Pending { size: None })
+                }
+            }
+        }
+
+        fn forget(&self, uri: &str) {
+            lock(&self.cache).remove(uri);
+        }
+
+        fn forget_all(&self) {
+            lock(&self.cache).clear();
+        }
+
+        fn byte_size(&self) -> usize {
+            lock(&self.cache)
+                .values()
+                .flatten()
+                .flatten()
+                .map(|image| image.pixels.len() * 4)
+                .sum()
+        }
+    }
+}
+
+// --- Export Module ---
+
+/// Renders a session to an SVG document, and from there to PNG, without a window or
+/// egui context so boards can also be exported from scripts (`--render`). Markdown in
+/// text blocks is drawn as plain text.
+mod export {
+    use super::{
+        color_hex, contrasting_text, crop_from_session, image_decoder, is_pdf, is_svg,
+        link_caption, split_pdf_page, BlockContentData, BlockData, ImageAdjust, Session, FULL_CROP,
+        PDF_RASTER_WIDTH, SVG_MAX_RASTER_WIDTH,
+    };
+    use base64::Engine;
+    use eframe::egui::{Color32, ColorImage, Rect};
+    use std::fmt::Write;
+    use std::path::Path;
+
+    /// Space around the blocks' bounding box, in canvas units
+    const MARGIN: f32 = 20.0;
+    /// Longest edge of a rendered PNG; larger boards are scaled down to fit
+    const MAX_PNG_SIZE: u32 = 16384;
+    /// Embedded images are kept at up to this multiple of their block size
+    const IMAGE_OVERSAMPLE: f32 = 2.0;
+    const FONT_SIZE: f32 = 16.0;
+    const LINE_HEIGHT: f32 = 1.3;
+    /// Side of the square cells of a contact sheet, and the space between them, in pixels
+    const SHEET_CELL: u32 = 256;
+    const SHEET_GAP: u32 = 8;
+
+    /// Blocks that are part of the board; pinned ones only overlay the window
+    fn board_blocks(session: &Session) -> impl Iterator<Item = &BlockData> {
+        session.blocks.iter().filter(|b| !b.pinned)
+    }
+
+    /// Canvas-space bounds of all blocks as `[min_x, min_y, max_x, max_y]`
+    fn bounds(session: &Session) -> Option<[f32; 4]> {
+        board_blocks(session).map(|b| b.rect).reduce(|a, b| {
+            [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]
+        })
+    }
+
+    fn escape_xml(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// First frame (or page) of an image block's file, at most `max_width` pixels wide
+    pub fn decode_still(path: &Path, max_width: u32) -> Option<image::RgbaImage> {
+        let (file, page) = split_pdf_page(path);
+        let image = if is_pdf(&file) {
+            let raster = image_decoder::render_pdf_page(&file, page, max_width)?;
+            image::RgbaImage::from_raw(raster.width, raster.height, raster.pixels)?
+        } else if is_svg(&file) {
+            let raster = image_decoder::rasterize_svg(&std::fs::read(&file).ok()?, max_width)?;
+            let size = [raster.width as usize, raster.height as usize];
+            let pixels = ColorImage::from_rgba_premultiplied(size, &raster.pixels)
+                .pixels
+                .iter()
+                .flat_map(|c| c.to_srgba_unmultiplied())
+                .collect();
+            image::RgbaImage::from_raw(raster.width, raster.height, pixels)?
+        } else {
+            let data = std::fs::read(&file).ok()?;
+            let extension = file
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(str::to_lowercase);
+            let frame = match extension.as_deref() {
+                Some("gif") => image_decoder::decode_gif_first_frame(&data)
+                    .map(|p| (p.frame.pixels, p.frame.width, p.frame.height)),
+                Some("avif") => image_decoder::decode_avif_first_frame(&data)
+                    .map(|p| (p.frame.pixels, p.frame.width, p.frame.height)),
+                Some("webp") => image_decoder::decode_webp_first_frame(&data)
+                    .map(|p| (p.frame.pixels, p.frame.width, p.frame.height)),
+                _ => None,
+            };
+            match frame {
+                Some((pixels, width, height)) => image::RgbaImage::from_raw(width, height, pixels)?,
+                None => image::load_from_memory(&data).ok()?.to_rgba8(),
+            }
+        };
+        if image.width() > max_width {
+            let height = (image.height() as f32 * max_width as f32 / image.width() as f32) as u32;
+            Some(image::imageops::thumbnail(&image, max_width, height.max(1)))
+        } else {
+            Some(image)
+        }
+    }
+
+    /// `wanted` pixels as a width to decode `path` at, kept within what SVGs and PDF pages
+    /// are ever rasterized at: small crops ask for huge rasters otherwise
+    fn raster_width(path: &Path, wanted: f32) -> u32 {
+        let (file, _) = split_pdf_page(path);
+        let limit = if is_pdf(&file) {
+            PDF_RASTER_WIDTH
+        } else if is_svg(&file) {
+            SVG_MAX_RASTER_WIDTH
+        } else {
+            // Other images are only ever scaled down to it
+            u32::MAX
+        };
+        (wanted.ceil().max(1.0) as u32).min(limit)
+    }
+
+    /// The `crop` part of `still` with `adjust` applied, as the canvas shows it
+    fn crop_and_adjust(
+        still: &image::RgbaImage,
+        crop: Rect,
+        adjust: &ImageAdjust,
+    ) -> image::RgbaImage {
+        let (w, h) = (still.width() as f32, still.height() as f32);
+        let mut part = image::imageops::crop_imm(
+            still,
+            (crop.min.x * w) as u32,
+            (crop.min.y * h) as u32,
+            ((crop.width() * w) as u32).max(1),
+            ((crop.height() * h) as u32).max(1),
+        )
+        .to_image();
+        if !adjust.is_identity() {
+            for pixel in part.pixels_mut() {
+                let [r, g, b, a] = pixel.0;
+                let color = Color32::from_rgba_unmultiplied(r, g, b, a);
+                pixel.0 = adjust.apply_to_pixel(color).to_srgba_unmultiplied();
+            }
+        }
+        part
+    }
+
+    /// `data:` URI with the image for a block `width` pixels wide once exported, cropped
+    /// and adjusted like on the canvas. Untouched SVGs are embedded as-is so they stay
+    /// sharp at any export scale.
+    fn image_data_uri(path: &str, width: f32, crop: Rect, adjust: &ImageAdjust) -> Option<String> {
+        let path = Path::new(path);
+        let engine = base64::engine::general_purpose::STANDARD;
+        if is_svg(path) && crop == FULL_CROP && adjust.is_identity() {
+            let data = std::fs::read(path).ok()?;
+            return Some(format!("data:image/svg+xml;base64,{}", engine.encode(data)));
+        }
+        // Enough pixels for the cropped part to still fill the block
+        let max_width = raster_width(path, width * IMAGE_OVERSAMPLE / crop.width());
+        let image = crop_and_adjust(&decode_still(path, max_width)?, crop, adjust);
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).ok()?;
+        Some(format!(
+            "data:image/png;base64,{}",
+            engine.encode(png.into_inner())
+        ))
+    }
+
+    /// The whole board as a standalone SVG document, blocks drawn in z-order with the
+    /// same colors as the canvas. Images that fail to load are left as empty blocks.
+    /// The document is `scale` pixels per canvas unit in size.
+    pub fn session_to_svg(session: &Session, scale: f32) -> Option<String> {
+        let [min_x, min_y, max_x, max_y] = bounds(session)?;
+        let (x, y) = (min_x - MARGIN, min_y - MARGIN);
+        let (width, height) = (max_x - min_x + 2.0 * MARGIN, max_y - min_y + 2.0 * MARGIN);
+        let (pixel_width, pixel_height) = (width * scale, height * scale);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{x} {y} {width} {height}" width="{pixel_width}" height="{pixel_height}">"#
+        );
+        let _ = writeln!(
+            svg,
+            r##"<rect x="{x}" y="{y}" width="{width}" height="{height}" fill="#1e1e1e"/>"##
+        );
+
+        for (i, block) in board_blocks(session).enumerate() {
+            let [x0, y0, x1, y1] = block.rect;
+            let (w, h) = (x1 - x0, y1 - y0);
+            let border = if block.chained { "#00ff00" } else { "#000000" };
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{x0}" y="{y0}" width="{w}" height="{h}" rx="5" fill="#f0f0f0" stroke="{border}" stroke-width="2"/>"##
+            );
+
+            match &block.content {
+                BlockContentData::Text { text } => {
+                    let _ = writeln!(
+                        svg,
+                        r#"<clipPath id="clip{i}"><rect x="{x0}" y="{y0}" width="{w}" height="{h}"/></clipPath>"#
+                    );
+                    let _ = write!(
+                        svg,
+                        r#"<text clip-path="url(#clip{i})" font-family="sans-serif" font-size="{FONT_SIZE}" fill="black" xml:space="preserve">"#
+                    );
+                    for (line_idx, line) in text.lines().enumerate() {
+                        let line_y = y0 + 5.0 + FONT_SIZE * (1.0 + line_idx as f32 * LINE_HEIGHT);
+                        let _ = write!(
+                            svg,
+                            r#"<tspan x="{}" y="{line_y}">{}</tspan>"#,
+                            x0 + 5.0,
+                            escape_xml(line)
+                        );
+                    }
+                    let _ = writeln!(svg, "</text>");
+                }
+                BlockContentData::Link {
+                    url,
+                    title,
+                    description,
+                    ..
+                } => {
+                    let (x, y) = (x0 + 5.0, y0 + 5.0 + FONT_SIZE);
+                    let _ = writeln!(
+                        svg,
+                        r#"<a href="{}"><text x="{x}" y="{y}" font-family="sans-serif" font-size="{FONT_SIZE}" fill="black">🔗 {}</text></a>"#,
+                        escape_xml(url),
+                        escape_xml(link_caption(url, title))
+                    );
+                    if !description.is_empty() {
+                        let y = y + FONT_SIZE * LINE_HEIGHT;
+                        let _ = writeln!(
+                            svg,
+                            r#"<text x="{x}" y="{y}" font-family="sans-serif" font-size="{FONT_SIZE}" fill="gray">{}</text>"#,
+                            escape_xml(description)
+                        );
+                    }
+                }
+                BlockContentData::Color { rgba, label } => {
+                    let [r, g, b, a] = *rgba;
+                    let opacity = f32::from(a) / 255.0;
+                    let _ = writeln!(
+                        svg,
+                        r##"<rect x="{x0}" y="{y0}" width="{w}" height="{h}" rx="5" fill="#{r:02x}{g:02x}{b:02x}" fill-opacity="{opacity}"/>"##
+                    );
+                    if *label {
+                        let color = Color32::from_rgba_unmultiplied(r, g, b, a);
+                        let [tr, tg, tb, _] = contrasting_text(color).to_array();
+                        let (x, y) = (x0 + 8.0, y0 + h - 8.0);
+                        let _ = writeln!(
+                            svg,
+                            r##"<text x="{x}" y="{y}" font-family="monospace" font-size="{FONT_SIZE}" fill="#{tr:02x}{tg:02x}{tb:02x}">{}</text>"##,
+                            color_hex(color)
+                        );
+                    }
+                }
+                BlockContentData::Image {
+                    path,
+                    counter,
+                    crop,
+                    adjust,
+                    ..
+                } => {
+                    let crop = crop_from_session(*crop);
+                    if let Some(uri) = image_data_uri(path, w * scale, crop, adjust) {
+                        let _ = writeln!(
+                            svg,
+                            r#"<image x="{x0}" y="{y0}" width="{w}" height="{h}" preserveAspectRatio="none" href="{uri}"/>"#
+                        );
+                    }
+                    if *counter != 0 {
+                        let (cx, cy) = (x0 + 20.0, y0 + 20.0);
+                        let fill = if *counter > 0 { "#00ff00" } else { "#ffa500" };
+                        let _ = writeln!(
+                            svg,
+                            r##"<circle cx="{cx}" cy="{cy}" r="15" fill="{fill}"/><text x="{cx}" y="{cy}" font-family="sans-serif" font-size="20" text-anchor="middle" dominant-baseline="central">{counter}</text>"##
+                        );
+                    }
+                }
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        Some(svg)
+    }
+
+    /// Render the board to a PNG at `out`, `scale` pixels per canvas unit unless that
+    /// would exceed `MAX_PNG_SIZE`.
+    pub fn render_png(session: &Session, out: &Path, scale: f32) -> Result<(), String> {
+        let svg = session_to_svg(session, scale).ok_or("session has no blocks")?;
+        let tree = resvg::usvg::Tree::from_str(&svg, &image_decoder::svg_options())
+            .map_err(|e| format!("could not build the board image: {e}"))?;
+
+        let size = tree.size();
+        let scale = (MAX_PNG_SIZE as f32 / size.width().max(size.height())).min(1.0);
+        let width = (size.width() * scale).ceil() as u32;
+        let height = (size.height() * scale).ceil() as u32;
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(width.max(1), height.max(1))
+            .ok_or("board is too large to render")?;
+        resvg::render(
+            &tree,
+            resvg::tiny_skia::Transform::from_scale(scale, scale),
+            &mut pixmap.as_mut(),
+        );
+        pixmap
+            .save_png(out)
+            .map_err(|e| format!("could not write {}: {e}", out.display()))
+    }
+
+    /// Write the board to an SVG file at `out`, `scale` pixels per canvas unit in size
+    pub fn write_svg(session: &Session, out: &Path, scale: f32) -> Result<(), String> {
+        let svg = session_to_svg(session, scale).ok_or("session has no blocks")?;
+        std::fs::write(out, svg).map_err(|e| format!("could not write {}: {e}", out.display()))
+    }
+
+    /// The images at `sources` (with their crop and adjustments, as on the canvas) in a
+    /// grid of square cells, row by row, each scaled to fit its cell. Images that fail to
+    /// decode are left out; `None` if none could be.
+    pub fn contact_sheet(sources: &[(String, Rect, ImageAdjust)]) -> Option<image::RgbaImage> {
+        let stills: Vec<image::RgbaImage> = sources
+            .iter()
+            .filter_map(|(path, crop, adjust)| {
+                // Enough pixels for the cropped part to still fill a cell
+                let path = Path::new(path);
+                let max_width = raster_width(path, SHEET_CELL as f32 / crop.width());
+                let still = decode_still(path, max_width)?;
+                Some(crop_and_adjust(&still, *crop, adjust))
+            })
+            .collect();
+        if stills.is_empty() {
+            return None;
+        }
+
+        let columns = (stills.len() as f32).sqrt().ceil() as u32;
+        let rows = (stills.len() as u32).div_ceil(columns);
+        let extent = |cells: u32| cells * SHEET_CELL + (cells + 1) * SHEET_GAP;
+        let mut sheet = image::RgbaImage::from_pixel(
+            extent(columns),
+            extent(rows),
+            image::Rgba([40, 40, 40, 255]),
+        );
+        for (i, still) in stills.iter().enumerate() {
+            let (w, h) = (still.width() as f32, still.height() as f32);
+            let scale = (SHEET_CELL as f32 / w).min(SHEET_CELL as f32 / h);
+            let width = ((w * scale).round() as u32).clamp(1, SHEET_CELL);
+            let height = ((h * scale).round() as u32).clamp(1, SHEET_CELL);
+            let thumb = image::imageops::thumbnail(still, width, height);
+            let (column, row) = (i as u32 % columns, i as u32 / columns);
+            let x = SHEET_GAP + column * (SHEET_CELL + SHEET_GAP) + (SHEET_CELL - width) / 2;
+            let y = SHEET_GAP + row * (SHEET_CELL + SHEET_GAP) + (SHEET_CELL - height) / 2;
+            image::imageops::overlay(&mut sheet, &thumb, x as i64, y as i64);
+        }
+        Some(sheet)
+    }
+}
+
+impl Default for CanvasApp {
+    fn default() -> Self {
+        let (tx, rx) = channel();
+        let (file_tx, file_rx) = channel();
+        Self {
+            viewport: Viewport::default(),
             blocks: Vec::new(),
             resizing_state: None,
             editing_id: None,
@@ -714,9 +2074,54 @@ impl Default for CanvasApp {
             file_dialog_tx: file_tx,
             counter_tool_active: false,
             show_help: false,
+            settings: Settings::default(),
+            show_settings: false,
             common_mark_cache: CommonMarkCache::default(),
-            max_concurrent_animations: 15,
+            resolved_markdown: HashMap::new(),
+            markdown_cache_cleared: 0.0,
             current_concurrent_animations: 0,
+            pending_fit: HashSet::new(),
+            svg_rerender_pending: HashSet::new(),
+            svg_rerender_failed: HashSet::new(),
+            notification: None,
+            id_generator: IdGenerator::default(),
+            drag_snap: Vec2::ZERO,
+            drag_start: None,
+            snap_guides: Vec::new(),
+            background: BackgroundData::default(),
+            canvas_rect: Rect::NOTHING,
+            lasso: None,
+            ruler_active: false,
+            eyedropper_active: false,
+            eyedropper_still: None,
+            ruler_points: Vec::new(),
+            crop_target: None,
+            crop_drag: None,
+            missing_images: Vec::new(),
+            export_to_origin: true,
+            markdown_reading_order: true,
+            was_panning: false,
+            pan_coast: None,
+            custom_words: Vec::new(),
+            saved_fingerprint: None,
+            confirm_replace: None,
+            dirty_check: (false, f64::NEG_INFINITY),
+            window_title: String::new(),
+            session_path: None,
+            presentation: None,
+            editing_slide_path: false,
+            view_locked: false,
+            keyboard_focus: None,
+            keymap: Keymap::default(),
+            show_keymap: false,
+            rebinding: None,
+            trash: Vec::new(),
+            show_trash: false,
+            show_layers: false,
+            filename_query: String::new(),
+            new_link_url: String::new(),
+            show_diagnostics: false,
+            frame_times: VecDeque::new(),
         }
     }
 }
@@ -724,208 +2129,945 @@ impl Default for CanvasApp {
 // --- Physics / Collision Helpers ---
 
 impl Block {
-    fn resolve_collision(&mut self, others: &[Block]) -> bool {
-        let mut moved = false;
-        for _ in 0..3 {
-            let mut total_push = Vec2::ZERO;
-            let my_rect = self.rect.expand(COLLISION_GAP);
-
-            for other in others {
-                if self.id == other.id {
-                    continue;
-                }
-
-                if my_rect.intersects(other.rect) {
-                    let intersection = my_rect.intersect(other.rect);
-                    let dx = intersection.width();
-                    let dy = intersection.height();
-                    let center_diff = self.rect.center() - other.rect.center();
-
-                    let push = if dx < dy {
-                        Vec2::new(if center_diff.x > 0.0 { dx } else { -dx }, 0.0)
-                    } else {
-                        Vec2::new(0.0, if center_diff.y > 0.0 { dy } else { -dy })
-                    };
-                    total_push += push;
-                }
-            }
+    /// Push this block out of the blocks in `others` (id and rect) it overlaps
+    fn resolve_collision(&mut self, others: &[(Uuid, Rect)]) -> bool {
+        let other_rects: Vec<Rect> = others
+            .iter()
+            .filter(|(id, _)| *id != self.id)
+            .map(|(_, rect)| *rect)
+            .collect();
+        layout::resolve_collision(&mut self.rect, &other_rects)
+    }
 
-            if total_push != Vec2::ZERO {
-                self.rect = self.rect.translate(total_push);
-                moved = true;
+    /// Width over height that resizing keeps: an image's own, or the block's current
+    /// one while its aspect ratio is locked
+    fn kept_aspect_ratio(&self) -> Option<f32> {
+        match self.content {
+            BlockContent::Image { aspect_ratio, .. } => Some(aspect_ratio),
+            BlockContent::Text { .. } | BlockContent::Link { .. } | BlockContent::Color { .. } => {
+                self.lock_aspect
+                    .then(|| self.rect.width() / self.rect.height())
             }
         }
-        moved
     }
 }
-// --- App Implementation ---
 
-impl eframe::App for CanvasApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let mut help_toggled = false;
+impl CanvasApp {
+    /// Indices of the block at `idx` and, if it is chained, the rest of its group
+    fn group_indices(&self, idx: usize) -> Vec<usize> {
+        let mut indices = vec![idx];
+        if self.blocks[idx].chained && !self.blocks[idx].pinned {
+            indices.extend(
+                self.blocks
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, b)| *i != idx && b.chained && !b.pinned)
+                    .map(|(i, _)| i),
+            );
+        }
+        indices
+    }
 
-        let time_now = ctx.input(|i| i.time);
+    /// Move the block at `idx` to have its top-left corner at `min`, taking its chained
+    /// group along as dragging does
+    fn move_block_to(&mut self, idx: usize, min: Pos2) {
+        let delta = min - self.blocks[idx].rect.min;
+        if delta == Vec2::ZERO {
+            return;
+        }
+        for i in self.group_indices(idx) {
+            self.blocks[i].rect = self.blocks[i].rect.translate(delta);
+        }
+    }
 
-        // Poll for file dialog results
-        match self.file_dialog_rx.try_recv() {
-            Ok(paths) => {
-                for path in paths {
-                    self.load_image_file(path, ctx.clone(), None);
-                }
+    /// Drop everything the markdown renderer has cached; visible text blocks fill it again
+    /// on the next frame
+    fn clear_markdown_cache(&mut self, ctx: &egui::Context) {
+        self.common_mark_cache = CommonMarkCache::default();
+        self.resolved_markdown.clear();
+        self.markdown_cache_cleared = ctx.input(|i| i.time);
+    }
+
+    /// Move other blocks out of the way of the block at `idx` and its group, which keep
+    /// their place. Hidden blocks neither push nor get pushed, so nothing is shoved
+    /// aside by a block that can't be seen.
+    fn push_neighbors(&mut self, idx: usize) {
+        if self.blocks[idx].pinned || !self.blocks[idx].visible {
+            return;
+        }
+        let world: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned && self.blocks[i].visible)
+            .collect();
+        let fixed: Vec<usize> = self
+            .group_indices(idx)
+            .iter()
+            .filter_map(|i| world.iter().position(|w| w == i))
+            .collect();
+        let mut rects: Vec<Rect> = world.iter().map(|&i| self.blocks[i].rect).collect();
+        for j in layout::push_neighbors(&mut rects, &fixed) {
+            self.blocks[world[j]].rect = rects[j];
+        }
+    }
+
+    /// Make room around the block at `idx` once it is dropped. With `rigid_chains` its
+    /// group first moves out of the blocks around it as one piece, keeping its layout;
+    /// otherwise each of its blocks is moved out on its own when they passed over
+    /// others while dragging. Pushing then clears whatever still overlaps, leaving the
+    /// dropped block where it was let go. In overlap mode nothing moves at all.
+    fn settle_drop(&mut self, idx: usize) {
+        if self.settings.collision_mode == CollisionMode::Overlap {
+            return;
+        }
+        let group = self.group_indices(idx);
+        if self.settings.rigid_chains {
+            let others: Vec<Rect> = (0..self.blocks.len())
+                .filter(|i| !group.contains(i) && !self.blocks[*i].pinned)
+                .filter(|i| self.blocks[*i].visible)
+                .map(|i| self.blocks[i].rect)
+                .collect();
+            let mut rects: Vec<Rect> = group.iter().map(|&i| self.blocks[i].rect).collect();
+            layout::resolve_group_collision(&mut rects, &others);
+            for (&i, rect) in group.iter().zip(rects) {
+                self.blocks[i].rect = rect;
             }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => {
-                // Recreate channel if disconnected
-                let (tx, rx) = channel();
-                self.file_dialog_tx = tx;
-                self.file_dialog_rx = rx;
+        } else if self.settings.collision_mode == CollisionMode::SettleOnDrop {
+            let others: Vec<(Uuid, Rect)> = self
+                .blocks
+                .iter()
+                .filter(|b| !b.pinned && b.visible)
+                .map(|b| (b.id, b.rect))
+                .collect();
+            for &i in &group {
+                self.blocks[i].resolve_collision(&others);
             }
         }
+        if self.settings.collision_mode == CollisionMode::Push {
+            self.push_neighbors(idx);
+        }
+    }
 
-        // Poll for loaded image data
-        while let Ok(data) = self.image_rx.try_recv() {
-            match data {
-                ImageLoadData::Complete {
-                    frames,
-                    frame_delays,
-                    aspect_ratio,
-                    path,
-                    target_block_id,
-                } => {
-                    if frames.is_empty() {
-                        continue;
-                    }
+    /// Where `block` is drawn, given the canvas area of the current frame
+    fn block_screen_rect(&self, block: &Block) -> Rect {
+        if block.pinned {
+            block.rect.translate(self.canvas_rect.min.to_vec2())
+        } else {
+            self.viewport
+                .world_rect_to_screen(self.canvas_rect.center(), block.rect)
+        }
+    }
 
-                    let texture_frames: Vec<_> = frames
-                        .iter()
-                        .enumerate()
-                        .map(|(i, img)| {
-                            ctx.load_texture(
-                                format!("img-{}-{i}", Uuid::new_v4()),
-                                img.clone(),
-                                egui::TextureOptions::default(),
-                            )
-                        })
-                        .collect();
+    /// The screen point `screen` in the coordinates of `block.rect`
+    fn to_block_space(&self, block: &Block, screen: Pos2) -> Pos2 {
+        if block.pinned {
+            screen - self.canvas_rect.min.to_vec2()
+        } else {
+            self.viewport
+                .screen_to_world(self.canvas_rect.center(), screen)
+        }
+    }
 
-                    if let Some(target_id) = target_block_id {
-                        if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
-                            if let BlockContent::Image {
-                                frames: f,
-                                frame_delays: fd,
-                                aspect_ratio: ar,
-                                animation_state,
-                                ..
-                            } = &mut block.content
-                            {
-                                *f = texture_frames;
-                                *fd = frame_delays;
-                                *ar = aspect_ratio;
-                                *animation_state = AnimationState::Ready;
-                            }
-                        }
-                    } else {
-                        let id = Uuid::new_v4();
-                        let width = 300.0;
-                        let height = width / aspect_ratio;
-                        let size = Vec2::new(width, height);
-                        let center_world = -self.viewport.pan;
-                        let pos = self.find_free_rect(center_world, size);
+    /// Whether the pointer is panning the view this frame, see `is_pan_gesture`
+    fn pan_gesture(&self, input: &egui::InputState) -> bool {
+        is_pan_gesture(
+            input.pointer.middle_down(),
+            input.pointer.primary_down(),
+            self.keymap.held(input, Action::PanDrag),
+        )
+    }
 
-                        self.blocks.push(Block {
-                            id,
-                            rect: Rect::from_min_size(pos.to_pos2(), size),
-                            content: BlockContent::Image {
-                                frames: texture_frames,
-                                frame_delays,
-                                aspect_ratio,
-                                playing: frames.len() > 1,
-                                current_frame_idx: 0,
-                                last_frame_time: 0.0,
-                                counter: 0,
-                                path,
-                                animation_state: AnimationState::Ready,
-                                first_frame: frames.first().cloned(),
-                                playing_start_time: None,
-                            },
-                            chained: false,
-                            selected: false,
-                        });
-                    }
-                }
-                ImageLoadData::AvifPreview {
-                    first_frame,
-                    frame_durations,
-                    aspect_ratio,
-                    total_frame_count,
-                    path,
-                    target_block_id,
-                } => {
-                    let texture = ctx.load_texture(
-                        format!("avif-preview-{}", Uuid::new_v4()),
-                        first_frame,
-                        egui::TextureOptions::default(),
-                    );
+    /// Ruler, eyedropper or crop mode, which take over the pointer on the whole canvas
+    fn overlay_tool_active(&self) -> bool {
+        self.ruler_active || self.eyedropper_active || self.crop_target.is_some()
+    }
 
-                    let animation_state = if total_frame_count > 1 {
-                        AnimationState::NotLoaded {
-                            path: path.clone().unwrap(),
-                            format: image_decoder::ImageFormat::Avif,
-                            total_frame_count,
-                            frame_durations: frame_durations.clone(),
-                        }
-                    } else {
-                        AnimationState::Ready
-                    };
+    /// Topmost shown block under the screen point `screen`, pinned ones first
+    fn block_at(&self, screen: Pos2) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .rev()
+            .filter(|b| b.pinned)
+            .chain(self.blocks.iter().rev().filter(|b| !b.pinned))
+            .find(|b| b.visible && b.rect.contains(self.to_block_space(b, screen)))
+    }
 
-                    if let Some(target_id) = target_block_id {
-                        if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
-                             if let BlockContent::Image {
-                                 frames,
-                                 frame_delays,
-                                 aspect_ratio: ar,
-                                 animation_state: anim_state,
-                                 first_frame: ff,
-                                 ..
-                             } = &mut block.content
-                             {
-                                 *frames = vec![texture];
-                                 *frame_delays = frame_durations;
-                                 *ar = aspect_ratio;
-                                 *anim_state = animation_state;
-                                 *ff = Some(first_frame);
-                             }
-                        }
-                    } else {
-                        let id = Uuid::new_v4();
-                        let width = 300.0;
-                        let height = width / aspect_ratio;
-                        let size = Vec2::new(width, height);
-                        let center_world = -self.viewport.pan;
-                        let pos = self.find_free_rect(center_world, size);
+    /// Color of the image pixel under the screen point `screen`, if the topmost block
+    /// there is an image whose pixels are at hand: its paused first frame or the
+    /// `eyedropper_still`. Animations are sampled from their first frame.
+    fn color_at(&self, screen: Pos2) -> Option<Color32> {
+        let block = self.block_at(screen)?;
+        let BlockContent::Image {
+            first_frame,
+            crop,
+            adjust,
+            ..
+        } = &block.content
+        else {
+            return None;
+        };
+        let still = match &self.eyedropper_still {
+            Some((id, still)) if *id == block.id => still.as_ref(),
+            _ => None,
+        };
+        let image = first_frame.as_ref().or(still)?;
+        let point = self.to_block_space(block, screen);
+        let [x, y] = pixel_at(block.rect, *crop, point, image.size)?;
+        Some(adjust.apply_to_pixel(image.pixels[y * image.width() + x]))
+    }
 
-                        self.blocks.push(Block {
-                            id,
-                            rect: Rect::from_min_size(pos.to_pos2(), size),
-                            content: BlockContent::Image {
-                                frames: vec![texture],
-                                frame_delays: frame_durations,
-                                aspect_ratio,
-                                playing: false, // Don't auto-play, wait for user click
+    /// Start decoding the image under the screen point `screen` for the eyedropper, unless
+    /// its pixels are already at hand. Only one image is held at a time.
+    fn request_eyedropper_still(&mut self, screen: Pos2, ctx: &egui::Context) {
+        let Some(block) = self.block_at(screen) else {
+            return;
+        };
+        let BlockContent::Image {
+            path: Some(path),
+            first_frame: None,
+            ..
+        } = &block.content
+        else {
+            return;
+        };
+        let (id, path) = (block.id, PathBuf::from(path));
+        if matches!(&self.eyedropper_still, Some((held, _)) if *held == id) {
+            return;
+        }
+        self.eyedropper_still = Some((id, None));
+        let tx = self.image_tx.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let Some(still) = export::decode_still(&path, EYEDROPPER_MAX_WIDTH) else {
+                return;
+            };
+            let size = [still.width() as usize, still.height() as usize];
+            let image = egui::ColorImage::from_rgba_unmultiplied(size, &still);
+            let _ = tx.send(ImageLoadData::EyedropperStill {
+                target_block_id: id,
+                image,
+            });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Crop the image block at `idx` to `part` of it (in the block's coordinates),
+    /// keeping that part where it is on the canvas. Selections too small to show
+    /// anything are ignored.
+    fn crop_block(&mut self, idx: usize, part: Rect) {
+        let block = &mut self.blocks[idx];
+        let part = part.intersect(block.rect);
+        if !(part.width() >= 1.0 && part.height() >= 1.0) {
+            return;
+        }
+        let BlockContent::Image {
+            crop, aspect_ratio, ..
+        } = &mut block.content
+        else {
+            return;
+        };
+        let full_aspect_ratio = *aspect_ratio * crop.height() / crop.width();
+        *crop = crop_to(*crop, block.rect, part);
+        *aspect_ratio = cropped_aspect_ratio(full_aspect_ratio, *crop);
+        // Small crops are blown up to the smallest block size
+        let scale = (Vec2::splat(MIN_BLOCK_SIZE) / part.size())
+            .max_elem()
+            .max(1.0);
+        block.rect = Rect::from_min_size(part.min, part.size() * scale);
+        self.push_neighbors(idx);
+    }
+
+    /// Show the whole image of the block at `idx` again, at the scale of its cropped part
+    fn uncrop_block(&mut self, idx: usize) {
+        let block = &mut self.blocks[idx];
+        let BlockContent::Image {
+            crop, aspect_ratio, ..
+        } = &mut block.content
+        else {
+            return;
+        };
+        *aspect_ratio *= crop.height() / crop.width();
+        block.rect = uncropped_rect(block.rect, *crop);
+        *crop = FULL_CROP;
+        self.push_neighbors(idx);
+    }
+
+    /// Pin the block at `idx` to where it currently is on screen, or put a pinned one
+    /// back into the world under the same spot
+    fn toggle_pinned(&mut self, idx: usize) {
+        let screen_rect = self.block_screen_rect(&self.blocks[idx]);
+        let block = &mut self.blocks[idx];
+        block.pinned = !block.pinned;
+        if block.pinned {
+            block.rect = screen_rect.translate(-self.canvas_rect.min.to_vec2());
+            block.chained = false;
+        } else {
+            let min = self
+                .viewport
+                .screen_to_world(self.canvas_rect.center(), screen_rect.min);
+            let size = block.rect.size() / self.viewport.zoom;
+            block.rect = Rect::from_min_size(min, size.max(Vec2::splat(MIN_BLOCK_SIZE)));
+            self.push_neighbors(idx);
+        }
+    }
+
+    /// Hide the block at `idx`, or show it again. A hidden block can't stay selected or
+    /// keep being edited, as it can't be seen.
+    fn toggle_visible(&mut self, idx: usize) {
+        let block = &mut self.blocks[idx];
+        block.visible = !block.visible;
+        if !block.visible {
+            block.selected = false;
+            if self.editing_id == Some(block.id) {
+                self.editing_id = None;
+            }
+            if self.keyboard_focus == Some(block.id) {
+                self.keyboard_focus = None;
+            }
+        }
+    }
+}
+
+/// Whether `path` has one of the supported image extensions
+fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Send the cached thumbnail for `path` so a block waiting on a full decode shows
+/// something right away. Only used for existing blocks (e.g. after a session load).
+fn send_cached_thumbnail(
+    tx: &Sender<ImageLoadData>,
+    ctx: &egui::Context,
+    path: &Path,
+    target_block_id: Option<Uuid>,
+) {
+    let Some(target_block_id) = target_block_id else {
+        return;
+    };
+    if let Some(image) = thumbnail_cache::load(path) {
+        let _ = tx.send(ImageLoadData::Thumbnail {
+            target_block_id,
+            image,
+        });
+        ctx.request_repaint();
+    }
+}
+
+/// Whether `path` points at an SVG document, which is rasterized rather than decoded
+fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.to_string_lossy().to_lowercase() == "svg")
+}
+
+/// Split a stored PDF path of the form `file.pdf#page=N` (one-based, as in PDF open
+/// parameters) into the file path and a zero-based page index.
+fn split_pdf_page(path: &Path) -> (PathBuf, u16) {
+    let path_str = path.to_string_lossy();
+    if let Some((file, page)) = path_str.rsplit_once("#page=") {
+        if let Ok(page) = page.parse::<u16>() {
+            return (PathBuf::from(file), page.saturating_sub(1));
+        }
+    }
+    (path.to_path_buf(), 0)
+}
+
+/// The file on disk behind an image block's stored path, if it still exists
+fn existing_source_file(path: Option<&str>) -> Option<PathBuf> {
+    let (file, _) = split_pdf_page(Path::new(path?));
+    file.is_file().then_some(file)
+}
+
+/// Current time in milliseconds since the Unix epoch, for `Block::created_at`
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// How long ago something happened, `millis` milliseconds back, in rough words
+fn format_age(millis: u64) -> String {
+    match millis / 1000 {
+        0..=59 => "just now".to_string(),
+        secs @ 60..=3599 => format!("{} min ago", secs / 60),
+        secs => format!("{} h ago", secs / 3600),
+    }
+}
+
+/// Roughly how tall a text block `MARKDOWN_IMPORT_WIDTH` wide has to be to show
+/// `text` at normal zoom, counting wrapped lines at an average character width
+fn estimated_text_height(text: &str) -> f32 {
+    const LINE_HEIGHT: f32 = 22.0;
+    const CHAR_WIDTH: f32 = 8.0;
+    let per_line = (MARKDOWN_IMPORT_WIDTH / CHAR_WIDTH) as usize;
+    let lines: usize = text
+        .lines()
+        .map(|line| line.chars().count().div_ceil(per_line).max(1))
+        .sum();
+    (lines as f32 * LINE_HEIGHT + 20.0).clamp(100.0, 800.0)
+}
+
+/// Movement along its longer axis only, for dragging in a straight line
+fn constrain_to_axis(delta: Vec2) -> Vec2 {
+    if delta.x.abs() >= delta.y.abs() {
+        Vec2::new(delta.x, 0.0)
+    } else {
+        Vec2::new(0.0, delta.y)
+    }
+}
+
+/// What the counter tool's right-click leaves of `counter`: one less, but not below
+/// zero unless negative counts are allowed
+fn decremented_counter(counter: i32, allow_negative: bool) -> i32 {
+    if allow_negative {
+        counter.saturating_sub(1)
+    } else {
+        (counter - 1).max(0)
+    }
+}
+
+/// Opacity of a block created at `created_at` while it fades in, both in milliseconds
+/// since the Unix epoch. Blocks dated in the future (from a clock that ran ahead) are
+/// shown at once.
+fn fade_in(now: u64, created_at: u64) -> f32 {
+    now.checked_sub(created_at)
+        .map_or(1.0, |age| (age as f32 / FADE_IN_MILLIS).min(1.0))
+}
+
+/// Smooth 0 to 1 and back every `period` seconds, starting at 1
+fn pulse(time: f64, period: f64) -> f32 {
+    (0.5 + 0.5 * (time / period * std::f64::consts::TAU).cos()) as f32
+}
+
+/// Frames either side of `current` out of `count`, for onion skinning
+fn onion_frames(current: usize, count: usize) -> [Option<usize>; 2] {
+    let next = current + 1;
+    [current.checked_sub(1), (next < count).then_some(next)]
+}
+
+/// HTML of the web page at `url`
+fn fetch_page(url: &str) -> Result<String, String> {
+    ureq::get(url)
+        .timeout(LINK_FETCH_TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())
+}
+
+/// The image at `url` shrunk to a link card thumbnail, if it downloads and decodes
+fn fetch_thumbnail(url: &str) -> Option<egui::ColorImage> {
+    let response = ureq::get(url).timeout(LINK_FETCH_TIMEOUT).call().ok()?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(LINK_IMAGE_MAX_BYTES)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let image = image::load_from_memory(&bytes)
+        .ok()?
+        .thumbnail(LINK_THUMBNAIL_SIZE, LINK_THUMBNAIL_SIZE)
+        .to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, &image))
+}
+
+/// `#rrggbb` code of `color`, with its alpha appended when it isn't opaque
+fn color_hex(color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == u8::MAX {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+/// Black or white, whichever reads better on top of `color`
+fn contrasting_text(color: Color32) -> Color32 {
+    let [r, g, b, _] = color.to_srgba_unmultiplied();
+    let luma = 0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b);
+    if luma > 150.0 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
+/// What a link block shows as its title: the one it was given, or else its URL
+fn link_caption<'a>(url: &'a str, title: &'a str) -> &'a str {
+    if title.trim().is_empty() {
+        url
+    } else {
+        title
+    }
+}
+
+/// File size in bytes, KB or MB, whichever reads best
+fn format_file_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    match bytes {
+        0..KB => format!("{bytes} bytes"),
+        KB..MB => format!("{:.1} KB", bytes as f64 / KB as f64),
+        _ => format!("{:.1} MB", bytes as f64 / MB as f64),
+    }
+}
+
+/// Markdown of a text block with its images resolved next to the session file (or the
+/// working directory before the canvas is saved)
+fn markdown_with_images<'a>(text: &'a str, session_path: Option<&Path>) -> Cow<'a, str> {
+    let base = session_path
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    markdown::resolve_images(text, &base, |path| {
+        let (file, _) = split_pdf_page(path);
+        file.is_file() && is_supported_image(&file)
+    })
+}
+
+/// Render a text block's markdown, with its images resolved once per text and session
+/// file in `resolved`. Ticking a task list box in it updates `text`.
+fn show_markdown(
+    ui: &mut egui::Ui,
+    cache: &mut CommonMarkCache,
+    resolved: &mut HashMap<(String, Option<PathBuf>), String>,
+    text: &mut String,
+    session_path: Option<&Path>,
+) {
+    let key = (text.clone(), session_path.map(Path::to_path_buf));
+    let mut rendered = resolved
+        .entry(key)
+        .or_insert_with(|| markdown_with_images(text, session_path).into_owned())
+        .clone();
+    CommonMarkViewer::new()
+        .max_image_width(Some(ui.available_width() as usize))
+        .show_mut(ui, cache, &mut rendered);
+    markdown::copy_task_marks(&rendered, text);
+}
+
+/// Wavy underline below the characters `chars` of an edited text. Words broken across
+/// rows aren't marked.
+fn draw_squiggle(
+    painter: &egui::Painter,
+    output: &egui::text_edit::TextEditOutput,
+    chars: std::ops::Range<usize>,
+) {
+    let galley = &output.galley;
+    let from = galley.pos_from_ccursor(egui::text::CCursor::new(chars.start));
+    let to = galley.pos_from_ccursor(egui::text::CCursor::new(chars.end));
+    if from.top() != to.top() {
+        return;
+    }
+    let origin = output.galley_pos + egui::vec2(from.left(), from.bottom());
+    let (step, height) = (2.0, 1.5);
+    let steps = ((to.left() - from.left()) / step).ceil().max(1.0) as usize;
+    let points = (0..=steps)
+        .map(|k| origin + egui::vec2(k as f32 * step, if k % 2 == 0 { 0.0 } else { height }))
+        .collect();
+    let stroke = egui::Stroke::new(1.0, egui::Color32::RED);
+    painter.add(egui::Shape::line(points, stroke));
+}
+
+/// Inspector field for a block coordinate or side. A typed value is applied on Enter or
+/// when the field loses focus, so neighbors aren't pushed around by every keystroke.
+fn geometry_value<'a>(value: &'a mut f32, prefix: &str) -> egui::DragValue<'a> {
+    egui::DragValue::new(value)
+        .prefix(prefix)
+        .update_while_editing(false)
+        .custom_parser(parse_finite)
+}
+
+/// A typed number, ignoring whitespace like egui's own parser does, but rejecting
+/// `inf` and `NaN`
+fn parse_finite(text: &str) -> Option<f64> {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == '\u{2212}' { '-' } else { c })
+        .collect();
+    text.parse().ok().filter(|v: &f64| v.is_finite())
+}
+
+/// Drag values for the lowest and highest zoom, edited as percentages like the zoom
+/// shown in the status bar. Returns whether either changed.
+fn zoom_limits_edit(ui: &mut egui::Ui, (min, max): &mut (f32, f32)) -> bool {
+    let (mut min_percent, mut max_percent) = (*min * 100.0, *max * 100.0);
+    let range = LOWEST_ZOOM * 100.0..=HIGHEST_ZOOM * 100.0;
+    let min_edit = egui::DragValue::new(&mut min_percent)
+        .range(range.clone())
+        .speed(1.0)
+        .suffix("%");
+    let max_edit = egui::DragValue::new(&mut max_percent)
+        .range(range)
+        .speed(1.0)
+        .suffix("%");
+    let min_changed = ui.add(min_edit).changed();
+    ui.label("to");
+    let changed = min_changed | ui.add(max_edit).changed();
+    if changed {
+        (*min, *max) = (min_percent / 100.0, max_percent / 100.0);
+    }
+    changed
+}
+
+/// Whether the pointer is dragging the view along: with the middle button, or with the
+/// primary one while the pan key is held. Blocks under the pointer must not move then.
+fn is_pan_gesture(middle_down: bool, primary_down: bool, pan_key_held: bool) -> bool {
+    middle_down || (pan_key_held && primary_down)
+}
+
+/// Camera moves asked for this frame by the mouse wheel, trackpad and touchscreen,
+/// around `screen_center`. Dragging the view along is left to the caller, which also
+/// lets it coast.
+fn pointer_view_actions(
+    input: &egui::InputState,
+    scroll_mode: ScrollMode,
+    screen_center: Pos2,
+) -> Vec<ViewAction> {
+    let mut actions = Vec::new();
+    // Without a pointer, zoom around the middle of the window. Pinches are anchored at
+    // the pointer too, which egui keeps where the fingers are.
+    let anchor = input.pointer.hover_pos().unwrap_or(screen_center);
+    // Pinching zooms in either mode; egui reports Ctrl+scroll the same way
+    let pinch = input.zoom_delta();
+    if pinch != 1.0 {
+        actions.push(ViewAction::Zoom {
+            anchor,
+            factor: pinch,
+        });
+    }
+    match scroll_mode {
+        // Without a pinch, the wheel zooms as before
+        ScrollMode::Zoom => {
+            if pinch == 1.0 && input.raw_scroll_delta.y.abs() > 0.0 {
+                let factor = 1.0 + input.raw_scroll_delta.y * 0.001;
+                actions.push(ViewAction::Zoom { anchor, factor });
+            }
+        }
+        ScrollMode::Pan => actions.push(ViewAction::Pan(input.smooth_scroll_delta)),
+    }
+    // Fingers on a touchscreen drag the view along while pinching
+    if let Some(touch) = input.multi_touch() {
+        actions.push(ViewAction::Pan(touch.translation_delta));
+    }
+    actions
+}
+
+/// Camera moves for the look and zoom actions held down over `dt` seconds, by default
+/// all within reach of the left hand: W A S D look around, Q zooms out and E in,
+/// around `screen_center`
+fn key_view_actions(
+    held: impl Fn(Action) -> bool,
+    dt: f32,
+    screen_center: Pos2,
+) -> Vec<ViewAction> {
+    let step = KEY_PAN_SPEED * dt;
+    let pans = [
+        (Action::LookUp, Vec2::new(0.0, step)),
+        (Action::LookLeft, Vec2::new(step, 0.0)),
+        (Action::LookDown, Vec2::new(0.0, -step)),
+        (Action::LookRight, Vec2::new(-step, 0.0)),
+    ];
+    let zooms = [(Action::ZoomOut, -1.0), (Action::ZoomIn, 1.0)];
+    let pan = pans
+        .into_iter()
+        .filter(|&(action, _)| held(action))
+        .map(|(_, delta)| ViewAction::Pan(delta));
+    let zoom = zooms
+        .into_iter()
+        .filter(|&(action, _)| held(action))
+        .map(|(_, sign)| ViewAction::Zoom {
+            anchor: screen_center,
+            factor: KEY_ZOOM_SPEED.powf(sign * dt),
+        });
+    pan.chain(zoom).collect()
+}
+
+/// Copy of `image` with a block's adjustments baked in, ready to upload
+fn adjusted(image: &egui::ColorImage, adjust: ImageAdjust) -> egui::ColorImage {
+    let mut image = image.clone();
+    adjust.apply(&mut image);
+    image
+}
+
+/// Show `file` in the platform's file manager: selected where supported, otherwise by
+/// opening the folder that contains it
+fn reveal_in_file_manager(file: &Path) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut c = std::process::Command::new("open");
+        c.arg("-R").arg(file);
+        c
+    } else if cfg!(target_os = "windows") {
+        let mut c = std::process::Command::new("explorer");
+        c.arg(format!("/select,{}", file.display()));
+        c
+    } else {
+        let mut c = std::process::Command::new("xdg-open");
+        c.arg(file.parent().unwrap_or(Path::new(".")));
+        c
+    };
+    let mut child = command.spawn()?;
+    // Reap the launcher once it exits
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Whether `path` points at a PDF (optionally with a `#page=N` suffix)
+fn is_pdf(path: &Path) -> bool {
+    split_pdf_page(path)
+        .0
+        .extension()
+        .is_some_and(|e| e.to_string_lossy().to_lowercase() == "pdf")
+}
+
+/// Replace every multi-page PDF in `paths` with one `#page=N` entry per page
+fn expand_pdf_pages(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        let page_count = if is_pdf(&path) {
+            image_decoder::pdf_page_count(&path).unwrap_or(1)
+        } else {
+            1
+        };
+        if page_count > 1 {
+            let path_str = path.to_string_lossy();
+            expanded.extend(
+                (1..=page_count).map(|page| PathBuf::from(format!("{path_str}#page={page}"))),
+            );
+        } else {
+            expanded.push(path);
+        }
+    }
+    expanded
+}
+
+// --- App Implementation ---
+
+impl eframe::App for CanvasApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings);
+        eframe::set_value(storage, KEYMAP_KEY, &self.keymap);
+        eframe::set_value(storage, COUNTER_TOOL_KEY, &self.counter_tool_active);
+        eframe::set_value(storage, SHOW_HELP_KEY, &self.show_help);
+        eframe::set_value(storage, SHOW_LAYERS_KEY, &self.show_layers);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut help_toggled = false;
+
+        let time_now = ctx.input(|i| i.time);
+
+        // The real gaps between frames: `stable_dt` is smoothed for animations and would
+        // hide how long the app sat idle
+        if self.show_diagnostics {
+            self.frame_times.push_back(ctx.input(|i| i.unstable_dt));
+            if self.frame_times.len() > FRAME_HISTORY {
+                self.frame_times.pop_front();
+            }
+        }
+
+        if time_now - self.markdown_cache_cleared > MARKDOWN_CACHE_LIFETIME {
+            self.clear_markdown_cache(ctx);
+        }
+
+        // A key pressed while rebinding is taken for the new shortcut and nothing else
+        if let Some(action) = self.rebinding {
+            let shortcut = ctx.input_mut(|i| {
+                let shortcut = i.events.iter().find_map(|e| match e {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => Some(egui::KeyboardShortcut::new(*modifiers, *key)),
+                    _ => None,
+                });
+                if shortcut.is_some() {
+                    i.events.retain(|e| !matches!(e, egui::Event::Key { .. }));
+                }
+                shortcut
+            });
+            if let Some(shortcut) = shortcut {
+                // Esc backs out and keeps the old shortcut
+                let escape = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Escape);
+                if shortcut != escape {
+                    self.keymap.bind(action, shortcut);
+                }
+                self.rebinding = None;
+            }
+        }
+
+        // Poll for file dialog results
+        match self.file_dialog_rx.try_recv() {
+            Ok(FileDialogResult::Images(paths)) => {
+                if paths.len() == 1 {
+                    for path in paths {
+                        self.load_image_file(path, ctx.clone(), None);
+                    }
+                } else {
+                    let columns = (paths.len() as f32).sqrt().ceil() as usize;
+                    self.import_images_grid(paths, ctx, columns);
+                }
+            }
+            Ok(FileDialogResult::Folder { images, skipped }) => {
+                let added = images.len();
+                self.import_images_grid(images, ctx, self.settings.import_columns);
+                let mut message = format!("Imported {added} images from folder");
+                if skipped > 0 {
+                    message += &format!(" ({skipped} unsupported files skipped)");
+                }
+                self.notify(ctx, message);
+            }
+            Ok(FileDialogResult::Relink(replacements)) => {
+                let wanted = replacements.len();
+                let relinked = replacements
+                    .into_iter()
+                    .filter(|(id, file)| self.relink_image(*id, file, ctx))
+                    .count();
+                if wanted > 1 || relinked == 0 {
+                    self.notify(
+                        ctx,
+                        format!("Relinked {relinked} of {wanted} missing images"),
+                    );
+                }
+            }
+            Ok(FileDialogResult::Markdown(text)) => match text {
+                Ok(text) => {
+                    let added = self.add_markdown_sections(&text);
+                    self.notify(ctx, format!("Imported {added} text blocks"));
+                }
+                Err(e) => self.notify(ctx, format!("Could not import the file: {e}")),
+            },
+            Ok(FileDialogResult::ContactSheet {
+                file,
+                replaces,
+                rect,
+            }) => match file {
+                Ok(file) => self.place_contact_sheet(file, &replaces, rect, ctx),
+                Err(e) => self.notify(ctx, format!("Could not make the contact sheet: {e}")),
+            },
+            Ok(FileDialogResult::PngExport(result)) => {
+                if let Err(e) = result {
+                    self.notify(ctx, format!("Could not export the board: {e}"));
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                // Recreate channel if disconnected
+                let (tx, rx) = channel();
+                self.file_dialog_tx = tx;
+                self.file_dialog_rx = rx;
+            }
+        }
+
+        // Poll for loaded image data
+        while let Ok(data) = self.image_rx.try_recv() {
+            match data {
+                ImageLoadData::Complete {
+                    frames,
+                    frame_delays,
+                    aspect_ratio,
+                    path,
+                    target_block_id,
+                } => {
+                    if frames.is_empty() {
+                        continue;
+                    }
+
+                    let adjust = self.image_adjust(target_block_id);
+                    let texture_frames: Vec<_> = frames
+                        .iter()
+                        .enumerate()
+                        .map(|(i, img)| {
+                            ctx.load_texture(
+                                format!("img-{}-{i}", Uuid::new_v4()),
+                                adjusted(img, adjust),
+                                egui::TextureOptions::default(),
+                            )
+                        })
+                        .collect();
+
+                    if let Some(target_id) = target_block_id {
+                        self.svg_rerender_pending.remove(&target_id);
+                        self.svg_rerender_failed.remove(&target_id);
+                        let mut resumed = false;
+                        if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
+                            if let BlockContent::Image {
+                                frames: f,
+                                frame_delays: fd,
+                                aspect_ratio: ar,
+                                animation_state,
+                                playing,
+                                playing_start_time,
+                                crop,
+                                ..
+                            } = &mut block.content
+                            {
+                                *f = texture_frames;
+                                *fd = frame_delays;
+                                *ar = cropped_aspect_ratio(aspect_ratio, *crop);
+                                *animation_state = AnimationState::Ready;
+                                // Keep the saved playing flag unless there is nothing to play
+                                if f.len() <= 1 {
+                                    *playing = false;
+                                    *playing_start_time = None;
+                                } else if *playing {
+                                    *playing_start_time = Some(time_now);
+                                    resumed = true;
+                                }
+                            }
+                            if self.pending_fit.remove(&target_id) {
+                                block.rect = fit_rect_to_aspect(block.rect, aspect_ratio);
+                            }
+                        }
+                        if resumed {
+                            self.limit_concurrent_animations();
+                        }
+                    } else {
+                        let id = self.id_generator.next_id();
+                        let width = IMAGE_BLOCK_WIDTH;
+                        let height = width / aspect_ratio;
+                        let size = Vec2::new(width, height);
+                        let center_world = -self.viewport.pan;
+                        let pos = self.find_free_rect(center_world, size);
+
+                        self.blocks.push(Block {
+                            id,
+                            rect: Rect::from_min_size(pos.to_pos2(), size),
+                            content: BlockContent::Image {
+                                frames: texture_frames,
+                                frame_delays,
+                                aspect_ratio,
+                                playing: frames.len() > 1,
                                 current_frame_idx: 0,
                                 last_frame_time: 0.0,
                                 counter: 0,
                                 path,
-                                animation_state,
-                                first_frame: Some(first_frame),
+                                animation_state: AnimationState::Ready,
+                                first_frame: frames.first().cloned(),
                                 playing_start_time: None,
+                                crop: FULL_CROP,
+                                adjust: ImageAdjust::default(),
+                                looping: Looping::default(),
+                                direction: Direction::default(),
+                                progress: Progress::default(),
+                                delay_override: None,
                             },
                             chained: false,
                             selected: false,
-                         });
-                     }
-                 }
-                ImageLoadData::GifPreview {
+                            opacity: u8::MAX,
+                            pinned: false,
+                            created_at: now_millis(),
+                            slide: None,
+                            note: None,
+                            lock_aspect: false,
+                            visible: true,
+                        });
+                    }
+                }
+                ImageLoadData::AnimatedPreview {
+                    format,
                     first_frame,
                     frame_durations,
                     aspect_ratio,
@@ -933,1267 +3075,7567 @@ impl eframe::App for CanvasApp {
                     path,
                     target_block_id,
                 } => {
-                     let texture = ctx.load_texture(
-                         format!("gif-preview-{}", Uuid::new_v4()),
-                         first_frame,
-                         egui::TextureOptions::default(),
-                     );
-
-                     let animation_state = if total_frame_count > 1 {
-                         AnimationState::NotLoaded {
-                             path: path.clone().unwrap(),
-                             format: image_decoder::ImageFormat::Gif,
-                             total_frame_count,
-                             frame_durations: frame_durations.clone(),
-                         }
-                     } else {
-                         AnimationState::Ready
-                     };
-
-                     if let Some(target_id) = target_block_id {
-                         if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
-                             if let BlockContent::Image {
-                                 frames,
-                                 frame_delays,
-                                 aspect_ratio: ar,
-                                 animation_state: anim_state,
-                                 ..
-                             } = &mut block.content
-                             {
-                                 *frames = vec![texture];
-                                 *frame_delays = frame_durations;
-                                 *ar = aspect_ratio;
-                                 *anim_state = animation_state;
-                             }
-                         }
-                     } else {
-                         let id = Uuid::new_v4();
-                         let width = 300.0;
-                         let height = width / aspect_ratio;
-                         let size = Vec2::new(width, height);
-                         let center_world = -self.viewport.pan;
-                         let pos = self.find_free_rect(center_world, size);
-
-                         self.blocks.push(Block {
-                             id,
-                             rect: Rect::from_min_size(pos.to_pos2(), size),
-                             content: BlockContent::Image {
-                                 frames: vec![texture],
-                                 frame_delays: frame_durations,
-                                 aspect_ratio,
-                                 playing: false,
-                                 current_frame_idx: 0,
-                                 last_frame_time: 0.0,
-                                 counter: 0,
-                                 path,
-                                 animation_state,
-                             },
-                             chained: false,
-                             selected: false,
-                         });
-                     }
-                 }
-                ImageLoadData::WebpPreview {
-                    first_frame,
-                    frame_durations,
-                    aspect_ratio,
-                    total_frame_count,
+                    let texture = ctx.load_texture(
+                        format!("preview-{}", Uuid::new_v4()),
+                        adjusted(&first_frame, self.image_adjust(target_block_id)),
+                        egui::TextureOptions::default(),
+                    );
+
+                    let animation_state = match &path {
+                        Some(path) if total_frame_count > 1 => AnimationState::NotLoaded {
+                            path: path.clone(),
+                            format,
+                            total_frame_count,
+                            frame_durations: frame_durations.clone(),
+                        },
+                        _ => AnimationState::Ready,
+                    };
+
+                    if let Some(target_id) = target_block_id {
+                        let mut resume = false;
+                        if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
+                            if let BlockContent::Image {
+                                frames,
+                                frame_delays,
+                                aspect_ratio: ar,
+                                playing,
+                                playing_start_time,
+                                animation_state: anim_state,
+                                first_frame: ff,
+                                crop,
+                                ..
+                            } = &mut block.content
+                            {
+                                *frames = vec![texture];
+                                *frame_delays = frame_durations;
+                                *ar = cropped_aspect_ratio(aspect_ratio, *crop);
+                                *anim_state = animation_state;
+                                *ff = Some(first_frame);
+                                // Nothing more will be decoded, so the preview is all there is
+                                if matches!(anim_state, AnimationState::Ready) {
+                                    *playing = false;
+                                    *playing_start_time = None;
+                                }
+                                // A block saved while playing picks up where it left off
+                                resume = *playing
+                                    && matches!(anim_state, AnimationState::NotLoaded { .. });
+                                if resume {
+                                    *playing_start_time = Some(time_now);
+                                }
+                            }
+                            if self.pending_fit.remove(&target_id) {
+                                block.rect = fit_rect_to_aspect(block.rect, aspect_ratio);
+                            }
+                        }
+                        if resume {
+                            self.start_animation_load(target_id, ctx);
+                            self.limit_concurrent_animations();
+                        }
+                    } else {
+                        let id = self.id_generator.next_id();
+                        let width = IMAGE_BLOCK_WIDTH;
+                        let height = width / aspect_ratio;
+                        let size = Vec2::new(width, height);
+                        let center_world = -self.viewport.pan;
+                        let pos = self.find_free_rect(center_world, size);
+
+                        self.blocks.push(Block {
+                            id,
+                            rect: Rect::from_min_size(pos.to_pos2(), size),
+                            content: BlockContent::Image {
+                                frames: vec![texture],
+                                frame_delays: frame_durations,
+                                aspect_ratio,
+                                playing: false, // Don't auto-play, wait for user click
+                                current_frame_idx: 0,
+                                last_frame_time: 0.0,
+                                counter: 0,
+                                path,
+                                animation_state,
+                                first_frame: Some(first_frame),
+                                playing_start_time: None,
+                                crop: FULL_CROP,
+                                adjust: ImageAdjust::default(),
+                                looping: Looping::default(),
+                                direction: Direction::default(),
+                                progress: Progress::default(),
+                                delay_override: None,
+                            },
+                            chained: false,
+                            selected: false,
+                            opacity: u8::MAX,
+                            pinned: false,
+                            created_at: now_millis(),
+                            slide: None,
+                            note: None,
+                            lock_aspect: false,
+                            visible: true,
+                        });
+                    }
+                }
+                ImageLoadData::Thumbnail {
+                    target_block_id,
+                    image,
+                } => {
+                    if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_block_id) {
+                        if let BlockContent::Image {
+                            frames,
+                            aspect_ratio,
+                            crop,
+                            adjust,
+                            ..
+                        } = &mut block.content
+                        {
+                            // Never replace frames that already came from a real decode
+                            if frames.is_empty() && image.height() > 0 {
+                                let full = image.width() as f32 / image.height() as f32;
+                                *aspect_ratio = cropped_aspect_ratio(full, *crop);
+                                *frames = vec![ctx.load_texture(
+                                    format!("thumb-{target_block_id}"),
+                                    adjusted(&image, *adjust),
+                                    egui::TextureOptions::default(),
+                                )];
+                            }
+                        }
+                    }
+                }
+                 ImageLoadData::AnimationLoaded {
+                    target_block_id,
+                    frames,
+                    frame_delays,
+                } => {
+                    let mut started = false;
+                    if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_block_id) {
+                        if let BlockContent::Image {
+                            frames: existing_frames,
+                            frame_delays: existing_delays,
+                            animation_state,
+                            playing,
+                            last_frame_time,
+                            playing_start_time,
+                            adjust,
+                            ..
+                        } = &mut block.content
+                        {
+                            // Convert all frames to textures
+                            let texture_frames: Vec<_> = frames
+                                .iter()
+                                .enumerate()
+                                .map(|(i, img)| {
+                                    ctx.load_texture(
+                                        format!("avif-anim-{target_block_id}-{i}"),
+                                        adjusted(img, *adjust),
+                                        egui::TextureOptions::default(),
+                                    )
+                                })
+                                .collect();
+
+                            *existing_frames = texture_frames;
+                            *existing_delays = frame_delays;
+                            *animation_state = AnimationState::Ready;
+                            *playing = existing_frames.len() > 1;
+                            if *playing {
+                                playing_start_time.get_or_insert(time_now);
+                            } else {
+                                *playing_start_time = None;
+                            }
+                            *last_frame_time = time_now;
+                            started = *playing;
+                        }
+                    }
+                    if started {
+                        self.limit_concurrent_animations();
+                    }
+                }
+                ImageLoadData::AnimationFailed {
+                    target_block_id,
+                    path,
+                } => {
+                    if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_block_id) {
+                        if let BlockContent::Image {
+                            animation_state,
+                            playing,
+                            ..
+                        } = &mut block.content
+                        {
+                            *animation_state = AnimationState::Ready;
+                            *playing = false;
+                        }
+                    }
+                    let name = Path::new(&path)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or(path);
+                    self.notify(ctx, format!("Could not decode the animation in {name}"));
+                }
+                ImageLoadData::SvgFailed { target_block_id } => {
+                    self.svg_rerender_pending.remove(&target_block_id);
+                    self.svg_rerender_failed.insert(target_block_id);
+                }
+                ImageLoadData::Failed {
+                    target_block_id,
+                    path,
+                } => self.image_failed(target_block_id, &path, ctx),
+                ImageLoadData::LinkPreview {
+                    target_block_id,
+                    url: fetched_url,
+                    preview,
+                    thumbnail: image,
+                } => {
+                    if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_block_id) {
+                        // A preview of a page the link no longer points at is dropped
+                        if let BlockContent::Link {
+                            url,
+                            title,
+                            description,
+                            image_url,
+                            thumbnail,
+                        } = &mut block.content
+                        {
+                            if *url != fetched_url {
+                                continue;
+                            }
+                            // A title typed in by hand wins over the page's
+                            if let (true, Some(page_title)) = (title.is_empty(), preview.title) {
+                                *title = page_title;
+                            }
+                            if let Some(page_description) = preview.description {
+                                *description = page_description;
+                            }
+                            *image_url = preview.image.or(image_url.take());
+                            *thumbnail = image.map(|image| {
+                                ctx.load_texture(
+                                    format!("link-{target_block_id}"),
+                                    image,
+                                    egui::TextureOptions::default(),
+                                )
+                            });
+                        }
+                    }
+                }
+                ImageLoadData::EyedropperStill {
+                    target_block_id,
+                    image,
+                } => {
+                    // The pointer may have moved on to another image in the meantime
+                    if let Some((id, still)) = &mut self.eyedropper_still {
+                        if *id == target_block_id {
+                            *still = Some(image);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Only repaint continuously while an animation is actually advancing.
+        // Everything else is driven by input events or by background loaders
+        // waking the UI up when their results arrive.
+        let any_animating = self.blocks.iter().any(|b| {
+            matches!(
+                &b.content,
+                BlockContent::Image { playing, frames, .. } if *playing && frames.len() > 1
+            )
+        });
+        if any_animating {
+            ctx.request_repaint();
+        }
+
+        // 1. Update Animation State (restored sophisticated timing)
+        let time_now = ctx.input(|i| i.time);
+        for block in &mut self.blocks {
+            if let BlockContent::Image {
+                frames,
+                frame_delays,
+                playing,
+                current_frame_idx,
+                last_frame_time,
+                looping,
+                direction,
+                progress,
+                delay_override,
+                ..
+            } = &mut block.content
+            {
+                if *playing && frames.len() > 1 {
+                    // Skip frames if we've fallen behind to maintain correct animation speed
+                    let mut elapsed = time_now - *last_frame_time;
+                    while elapsed > 0.0 {
+                        let delay = delay_override.unwrap_or_else(|| {
+                            frame_delays.get(*current_frame_idx).copied().unwrap_or(0.1)
+                        });
+                        if elapsed >= delay {
+                            elapsed -= delay;
+                            let (next, keep_playing) = next_frame(
+                                *current_frame_idx,
+                                frames.len(),
+                                *looping,
+                                *direction,
+                                progress,
+                            );
+                            *current_frame_idx = next;
+                            if !keep_playing {
+                                *playing = false;
+                                break;
+                            }
+                        } else {
+                            break;
+                        }
+                    }
+                    // Update last_frame_time, accounting for partial frame time remaining
+                    *last_frame_time = time_now - elapsed;
+                }
+            }
+        }
+
+        // Update concurrent animation count
+        self.current_concurrent_animations = self.blocks.iter().filter(|b| {
+            if let BlockContent::Image { playing, .. } = &b.content {
+                *playing
+            } else {
+                false
+            }
+        }).count();
+
+        // 2. Global Inputs
+        let input = ctx.input(|i| i.clone());
+        // A locked view ignores everything that would pan or zoom it
+        if !self.view_locked {
+            let screen_center = ctx.screen_rect().center();
+            // Scrolling a window or side panel scrolls just that, not the canvas
+            let over_canvas = input
+                .pointer
+                .hover_pos()
+                .is_some_and(|p| self.canvas_rect.contains(p));
+            let mut actions = if ctx.is_pointer_over_area() || !over_canvas {
+                Vec::new()
+            } else {
+                pointer_view_actions(&input, self.settings.scroll_mode, screen_center)
+            };
+            // The keys are free for the view unless something is being typed
+            if self.editing_id.is_none() && ctx.memory(|m| m.focused().is_none()) {
+                let held = |action| self.keymap.held(&input, action);
+                let keys = key_view_actions(held, input.stable_dt, screen_center);
+                if !keys.is_empty() {
+                    self.pan_coast = None;
+                    ctx.request_repaint();
+                }
+                actions.extend(keys);
+            }
+            for action in actions {
+                self.viewport.apply(screen_center, action);
+            }
+
+            let panning = self.pan_gesture(&input);
+            if panning {
+                self.viewport
+                    .apply(screen_center, ViewAction::Pan(input.pointer.delta()));
+            } else if self.was_panning && self.settings.pan_inertia {
+                self.pan_coast = Some(input.pointer.velocity());
+            }
+            self.was_panning = panning;
+            // Any click or scroll catches a coasting view
+            if panning || input.pointer.any_pressed() || input.raw_scroll_delta != Vec2::ZERO {
+                self.pan_coast = None;
+            }
+            if let Some(velocity) = self.pan_coast {
+                let (delta, slower) = coast(velocity, input.stable_dt);
+                self.viewport.pan_by(delta);
+                self.pan_coast = slower;
+                ctx.request_repaint();
+            }
+        }
+
+        // F5 presents the board slide by slide, Esc goes back to editing it
+        if ctx.input_mut(|i| self.keymap.consume(i, Action::Present)) {
+            self.toggle_presentation(ctx);
+        }
+        if self.presentation.is_some() {
+            if ctx.input_mut(|i| self.keymap.consume(i, Action::Cancel)) {
+                self.toggle_presentation(ctx);
+            }
+            // Presentation remotes send Page Down/Up, which work whatever the keymap says
+            let keymap = &self.keymap;
+            let pressed = |action, remote_key| {
+                ctx.input_mut(|i| {
+                    keymap.consume(i, action) || i.consume_key(egui::Modifiers::NONE, remote_key)
+                })
+            };
+            let next = pressed(Action::NextSlide, egui::Key::PageDown);
+            let previous = pressed(Action::PreviousSlide, egui::Key::PageUp);
+            if next {
+                self.step_slide(true);
+            }
+            if previous {
+                self.step_slide(false);
+            }
+        }
+
+        if self.presentation.is_none() && ctx.input_mut(|i| self.keymap.consume(i, Action::Save)) {
+            self.save_session(ctx);
+        }
+
+        if ctx.input_mut(|i| self.keymap.consume(i, Action::Diagnostics)) {
+            self.show_diagnostics = !self.show_diagnostics;
+            self.frame_times.clear();
+        }
+
+        if self.presentation.is_none()
+            && ctx.input_mut(|i| self.keymap.consume(i, Action::FindImage))
+        {
+            ctx.memory_mut(|m| m.request_focus(egui::Id::new(FILENAME_SEARCH_ID)));
+        }
+
+        // Text pasted onto the canvas rather than into a text field becomes a block: a
+        // link card for a web address, a text block for anything else. The event is
+        // taken so the new block's editor doesn't paste it a second time.
+        if self.presentation.is_none() && !ctx.wants_keyboard_input() {
+            let pasted = ctx.input_mut(|i| {
+                let text = i.events.iter().find_map(|e| match e {
+                    egui::Event::Paste(text) => Some(text.trim().to_string()),
+                    _ => None,
+                })?;
+                i.events.retain(|e| !matches!(e, egui::Event::Paste(_)));
+                Some(text)
+            });
+            if let Some(text) = pasted.filter(|text| !text.is_empty()) {
+                if link_preview::is_web_url(&text) {
+                    self.add_link(text, ctx);
+                } else {
+                    let at = ctx
+                        .input(|i| i.pointer.hover_pos())
+                        .filter(|p| self.canvas_rect.contains(*p))
+                        .map(|p| self.viewport.screen_to_world(self.canvas_rect.center(), p));
+                    self.add_pasted_text(text, at);
+                }
+            }
+        }
+
+        if self.editing_id.is_none()
+            && self.presentation.is_none()
+            && ctx.input_mut(|i| self.keymap.consume(i, Action::Duplicate))
+        {
+            let selected: Vec<Uuid> = self
+                .blocks
+                .iter()
+                .filter(|b| b.selected)
+                .map(|b| b.id)
+                .collect();
+            self.duplicate_blocks(&selected, ctx);
+        }
+
+        // Tab/Shift+Tab walk the selection through the blocks, unless a widget has the
+        // keyboard focus (text editing included, so Ctrl+A there selects text)
+        let mut tab_navigated = false;
+        if self.editing_id.is_none()
+            && self.presentation.is_none()
+            && ctx.memory(|m| m.focused().is_none())
+        {
+            let backwards =
+                ctx.input_mut(|i| i.consume_key(egui::Modifiers::SHIFT, egui::Key::Tab));
+            if backwards || ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab))
+            {
+                self.select_next_block(backwards);
+                tab_navigated = true;
+            }
+            if ctx.input_mut(|i| self.keymap.consume(i, Action::SelectAll)) {
+                self.select_all();
+            }
+            if ctx.input_mut(|i| self.keymap.consume(i, Action::InvertSelection)) {
+                self.invert_selection();
+            }
+            // F brings the (topmost) selected block up close
+            if !self.view_locked && ctx.input_mut(|i| self.keymap.consume(i, Action::FitBlock)) {
+                if let Some(idx) = self.blocks.iter().rposition(|b| b.selected) {
+                    self.fit_block_to_view(idx);
+                }
+            }
+            // Ctrl+arrows resize the selection a step at a time, keeping the top-left corner
+            let resize = ctx.input_mut(|i| {
+                [
+                    (egui::Key::ArrowLeft, Vec2::new(-KEY_RESIZE_STEP, 0.0)),
+                    (egui::Key::ArrowRight, Vec2::new(KEY_RESIZE_STEP, 0.0)),
+                    (egui::Key::ArrowUp, Vec2::new(0.0, -KEY_RESIZE_STEP)),
+                    (egui::Key::ArrowDown, Vec2::new(0.0, KEY_RESIZE_STEP)),
+                ]
+                .into_iter()
+                .filter(|&(key, _)| i.consume_key(egui::Modifiers::COMMAND, key))
+                .fold(Vec2::ZERO, |sum, (_, delta)| sum + delta)
+            });
+            if resize != Vec2::ZERO {
+                self.resize_selected_by(resize);
+            }
+        }
+
+        // 3. Toolbar
+        let presenting = self.presentation.is_some();
+        egui::TopBottomPanel::top("toolbar")
+            .frame(
+                egui::Frame::default()
+                    .fill(Palette::for_theme(self.settings.theme).canvas)
+                    .inner_margin(0.0)
+                    .outer_margin(0.0),
+            )
+            .show_animated(ctx, !presenting, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add_space(8.0); // Add gap from window edge
+                    let save_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("💾").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Save Session (right-click to export the selection)");
+                    if save_btn.clicked() {
+                        self.save_session(ctx);
+                    }
+                    save_btn.context_menu(|ui| {
+                        if ui.button("Export Selection…").clicked() {
+                            self.export_selection(ctx);
+                            ui.close_menu();
+                        }
+                        ui.checkbox(&mut self.export_to_origin, "Move selection to origin");
+                        ui.separator();
+                        if ui.button("Export PNG…").clicked() {
+                            self.export_png(ctx);
+                            ui.close_menu();
+                        }
+                        if ui.button("Export SVG…").clicked() {
+                            self.export_svg(ctx);
+                            ui.close_menu();
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Scale");
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.export_scale)
+                                    .range(0.25..=8.0)
+                                    .speed(0.05)
+                                    .suffix(" px/unit"),
+                            )
+                            .on_hover_text("Pixels per canvas unit, whatever the zoom");
+                        });
+                        ui.separator();
+                        if ui.button("Export Markdown…").clicked() {
+                            self.export_markdown(ctx);
+                            ui.close_menu();
+                        }
+                        let reading_order = &mut self.markdown_reading_order;
+                        ui.checkbox(reading_order, "In reading order")
+                            .on_hover_text("Top to bottom, left to right instead of back to front");
+                    });
+                    let load_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("📂").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Load Session (right-click to import into this canvas)");
+                    if load_btn.clicked() {
+                        self.replace_canvas(Replacement::Load, ui.ctx());
+                    }
+                    load_btn.context_menu(|ui| {
+                        if ui.button("New Board").clicked() {
+                            self.replace_canvas(Replacement::New, ctx);
+                            ui.close_menu();
+                        }
+                        if ui.button("Import Into Canvas…").clicked() {
+                            self.import_session_file(ctx);
+                            ui.close_menu();
+                        }
+                    });
+
+                    let text_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("🔤").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text(
+                            "Add Text (right-click to import Markdown, add a link or a color swatch)",
+                        );
+                    if text_btn.clicked() {
+                        self.spawn_text_block(ui.ctx());
+                    }
+                    text_btn.context_menu(|ui| {
+                        if ui.button("Import Markdown…").clicked() {
+                            self.import_markdown(ui.ctx());
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.new_link_url)
+                                .hint_text("https://…"),
+                        );
+                        let url = self.new_link_url.trim().to_string();
+                        if ui
+                            .add_enabled(!url.is_empty(), egui::Button::new("Add Link"))
+                            .clicked()
+                        {
+                            self.add_link(url, ui.ctx());
+                            self.new_link_url.clear();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        if ui.button("Add Color Swatch").clicked() {
+                            let swatch = BlockContent::Color {
+                                color: DEFAULT_SWATCH_COLOR,
+                                label: true,
+                            };
+                            let center = -self.viewport.pan;
+                            self.spawn_block(swatch, center, Vec2::splat(SWATCH_SIZE));
+                            ui.close_menu();
+                        }
+                    });
+                    let image_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("🖼").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Add Images (right-click for PDF options)");
+                    if image_btn.clicked() {
+                        self.spawn_image_block(ui.ctx());
+                    }
+                    image_btn.context_menu(|ui| {
+                        ui.checkbox(&mut self.settings.pdf_all_pages, "Import all PDF pages");
+                    });
+                    let folder_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("📁").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Import Folder (right-click for grid columns)");
+                    if folder_btn.clicked() {
+                        self.import_folder(ui.ctx());
+                    }
+                    folder_btn.context_menu(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.settings.import_columns, IMPORT_COLUMNS_RANGE)
+                                .text("Columns"),
+                        );
+                    });
+
+                    let mut btn = egui::Button::new(RichText::new("🔢").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.counter_tool_active {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    let counter_btn = ui
+                        .add(btn)
+                        .on_hover_text("Counter Tool (right-click for options)");
+                    if counter_btn.clicked() {
+                        self.counter_tool_active = !self.counter_tool_active;
+                        self.ruler_active = false;
+                        self.editing_slide_path = false;
+                        self.eyedropper_active = false;
+                    }
+                    counter_btn.context_menu(|ui| {
+                        ui.checkbox(&mut self.settings.negative_counters, "Count below zero");
+                    });
+
+                    let mut btn = egui::Button::new(RichText::new("🎬").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.editing_slide_path {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    let path_btn = ui
+                        .add(btn)
+                        .on_hover_text("Edit Slide Path (right-click to clear it)");
+                    if path_btn.clicked() {
+                        self.editing_slide_path = !self.editing_slide_path;
+                        self.counter_tool_active = false;
+                        self.ruler_active = false;
+                        self.eyedropper_active = false;
+                    }
+                    path_btn.context_menu(|ui| {
+                        if ui.button("Clear Slide Path").clicked() {
+                            for b in &mut self.blocks {
+                                b.slide = None;
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
+                    let mut btn = egui::Button::new(RichText::new("📏").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.ruler_active {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    if ui.add(btn).on_hover_text("Ruler").clicked() {
+                        self.ruler_active = !self.ruler_active;
+                        self.ruler_points.clear();
+                        self.counter_tool_active = false;
+                        self.editing_slide_path = false;
+                        self.eyedropper_active = false;
+                    }
+
+                    let mut btn = egui::Button::new(RichText::new("💧").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.eyedropper_active {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    let hint = "Eyedropper (click an image to copy a color, Shift-click to add a swatch)";
+                    if ui.add(btn).on_hover_text(hint).clicked() {
+                        self.eyedropper_active = !self.eyedropper_active;
+                        self.ruler_active = false;
+                        self.counter_tool_active = false;
+                        self.editing_slide_path = false;
+                    }
+
+                    let mut btn = egui::Button::new(RichText::new("🧲").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.settings.collision_mode == CollisionMode::Push {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    let push_btn = ui
+                        .add(btn)
+                        .on_hover_text("Push Neighbors While Dragging (right-click for options)");
+                    if push_btn.clicked() {
+                        self.settings.collision_mode = match self.settings.collision_mode {
+                            CollisionMode::Push => CollisionMode::SettleOnDrop,
+                            CollisionMode::SettleOnDrop | CollisionMode::Overlap => {
+                                CollisionMode::Push
+                            }
+                        };
+                    }
+                    push_btn.context_menu(|ui| {
+                        let mode = &mut self.settings.collision_mode;
+                        ui.radio_value(mode, CollisionMode::Push, "Push neighbors while dragging");
+                        ui.radio_value(mode, CollisionMode::SettleOnDrop, "Make room on drop");
+                        ui.radio_value(mode, CollisionMode::Overlap, "Allow overlap");
+                        ui.separator();
+                        ui.checkbox(&mut self.settings.rigid_chains, "Keep chained groups rigid on drop");
+                        ui.checkbox(&mut self.settings.snap_to_guides, "Snap to other blocks' edges");
+                    });
+
+                    let tidy_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("🧹").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Tidy Up (right-click for options)");
+                    if tidy_btn.clicked() {
+                        self.tidy_up();
+                    }
+                    tidy_btn.context_menu(|ui| {
+                        ui.radio_value(&mut self.settings.tidy_masonry, false, "Rows");
+                        ui.radio_value(&mut self.settings.tidy_masonry, true, "Masonry columns");
+                        ui.checkbox(&mut self.settings.tidy_keep_groups, "Keep chained blocks together");
+                    });
+
+                    let age_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("🕑").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Arrange by Age (right-click for newest first)");
+                    if age_btn.clicked() {
+                        self.arrange_by_age(false);
+                    }
+                    age_btn.context_menu(|ui| {
+                        if ui.button("Newest First").clicked() {
+                            self.arrange_by_age(true);
+                            ui.close_menu();
+                        }
+                    });
+
+                    if ui
+                        .add(
+                            egui::Button::new(RichText::new("🔄").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Reset All Counters")
+                        .clicked()
+                    {
+                        self.reset_all_counters();
+                    }
+
+                    let mut btn = egui::Button::new(RichText::new("📑").size(24.0))
+                        .min_size(Vec2::new(32.0, 32.0))
+                        .frame(false);
+                    if self.show_layers {
+                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    }
+                    if ui.add(btn).on_hover_text("Layers").clicked() {
+                        self.show_layers = !self.show_layers;
+                    }
+
+                    let theme_icon = match self.settings.theme {
+                        egui::Theme::Dark => "☀",
+                        egui::Theme::Light => "🌙",
+                    };
+                    let theme_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new(theme_icon).size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Toggle Dark/Light Theme (right-click for background)");
+                    if theme_btn.clicked() {
+                        self.settings.theme = match self.settings.theme {
+                            egui::Theme::Dark => egui::Theme::Light,
+                            egui::Theme::Light => egui::Theme::Dark,
+                        };
+                        self.apply_settings(ctx);
+                    }
+                    theme_btn.context_menu(|ui| {
+                        let theme_color = Palette::for_theme(self.settings.theme).canvas;
+                        let mut rgb = self.background.color.unwrap_or([
+                            theme_color.r(),
+                            theme_color.g(),
+                            theme_color.b(),
+                        ]);
+                        ui.horizontal(|ui| {
+                            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                                self.background.color = Some(rgb);
+                            }
+                            if ui
+                                .add_enabled(
+                                    self.background.color.is_some(),
+                                    egui::Button::new("Use theme color"),
+                                )
+                                .clicked()
+                            {
+                                self.background.color = None;
+                            }
+                        });
+                        let pattern = &mut self.background.pattern;
+                        ui.radio_value(pattern, BackgroundPattern::Solid, "Solid");
+                        ui.radio_value(pattern, BackgroundPattern::Dots, "Dotted grid");
+                        ui.radio_value(pattern, BackgroundPattern::Lines, "Lined grid");
+                        ui.separator();
+                        ui.checkbox(&mut self.settings.fade_in_blocks, "Fade in new blocks");
+                    });
+
+                    if ui
+                        .add(
+                            egui::Button::new(RichText::new("⚙").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Preferences")
+                        .clicked()
+                    {
+                        self.show_settings = !self.show_settings;
+                    }
+
+                    let help_btn = ui
+                        .add(
+                            egui::Button::new(RichText::new("❓").size(24.0))
+                                .min_size(Vec2::new(32.0, 32.0))
+                                .frame(false),
+                        )
+                        .on_hover_text("Help (right-click for keyboard shortcuts)");
+                    if help_btn.clicked() {
+                        self.show_help = !self.show_help;
+                        help_toggled = true;
+                    }
+                    help_btn.context_menu(|ui| {
+                        if ui.button("Keyboard Shortcuts…").clicked() {
+                            self.show_keymap = true;
+                            ui.close_menu();
+                        }
+                    });
+                });
+            });
+
+        // 4. Status Bar
+        egui::TopBottomPanel::bottom("status_bar").show_animated(ctx, !presenting, |ui| {
+            ui.horizontal(|ui| {
+                let pointer = input
+                    .pointer
+                    .hover_pos()
+                    .filter(|p| self.canvas_rect.contains(*p));
+                if let Some(pos) = pointer {
+                    let world = self
+                        .viewport
+                        .screen_to_world(self.canvas_rect.center(), pos);
+                    ui.monospace(format!("x {:>7.1}  y {:>7.1}", world.x, world.y));
+                } else {
+                    ui.monospace(format!("x {:>7}  y {:>7}", "-", "-"));
+                }
+                ui.separator();
+                let zoom_label = ui
+                    .add(
+                        egui::Label::new(
+                            RichText::new(format!("{:.0}%", self.viewport.zoom * 100.0))
+                                .monospace(),
+                        )
+                        .sense(egui::Sense::click()),
+                    )
+                    .on_hover_text("Zoom (right-click for scrolling options)");
+                zoom_label.context_menu(|ui| {
+                    let mode = &mut self.settings.scroll_mode;
+                    ui.radio_value(mode, ScrollMode::Zoom, "Scroll to zoom (mouse wheel)");
+                    ui.radio_value(
+                        mode,
+                        ScrollMode::Pan,
+                        "Scroll to pan, pinch or Ctrl+scroll to zoom (trackpad)",
+                    );
+                    ui.separator();
+                    ui.checkbox(
+                        &mut self.settings.pan_inertia,
+                        "Keep panning after a fast drag",
+                    );
+                    ui.separator();
+                    let changed = ui
+                        .horizontal(|ui| {
+                            ui.label("Zoom range");
+                            zoom_limits_edit(ui, &mut self.settings.zoom_limits)
+                        })
+                        .inner;
+                    if changed {
+                        self.apply_settings(ctx);
+                    }
+                });
+                let lock_icon = if self.view_locked { "🔒" } else { "🔓" };
+                let lock_label = ui
+                    .selectable_label(self.view_locked, lock_icon)
+                    .on_hover_text("Lock the view so it can't be panned or zoomed");
+                if lock_label.clicked() {
+                    self.view_locked = !self.view_locked;
+                    self.pan_coast = None;
+                }
+                ui.separator();
+                ui.label(match self.blocks.len() {
+                    1 => "1 block".to_string(),
+                    n => format!("{n} blocks"),
+                });
+                ui.separator();
+                let search = ui.add(
+                    egui::TextEdit::singleline(&mut self.filename_query)
+                        .id(egui::Id::new(FILENAME_SEARCH_ID))
+                        .hint_text("🔍 Image file name")
+                        .desired_width(160.0),
+                );
+                if search.changed() {
+                    self.find_image(false);
+                }
+                // Enter moves on to the next match and keeps typing in the box
+                if search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    self.find_image(true);
+                    search.request_focus();
+                }
+                if !self.filename_query.trim().is_empty() {
+                    match self.image_matches(&self.filename_query).len() {
+                        0 => ui.colored_label(Color32::LIGHT_RED, "no match"),
+                        1 => ui.label("1 match"),
+                        n => ui.label(format!("{n} matches")),
+                    };
+                }
+                let (total, of_selection) = self.counter_total();
+                if total != 0 || self.counter_tool_active {
+                    ui.separator();
+                    let hover = if of_selection {
+                        "Total of the counters on the selected blocks"
+                    } else {
+                        "Total of all counters"
+                    };
+                    ui.label(format!("Σ {total}")).on_hover_text(hover);
+                }
+                if !self.trash.is_empty() {
+                    let trash_label = ui
+                        .selectable_label(self.show_trash, format!("🗑 {}", self.trash.len()))
+                        .on_hover_text("Recently deleted blocks");
+                    if trash_label.clicked() {
+                        self.show_trash = !self.show_trash;
+                    }
+                }
+            });
+        });
+
+        // 5. Inspector and layers
+        self.show_inspector(ctx);
+        if self.show_layers {
+            self.show_layers(ctx);
+        }
+
+        // 6. Main Canvas
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.paint_background(ui);
+            self.process_canvas(ui);
+        });
+
+        if let Some((message, posted_at)) = &self.notification {
+            let remaining = NOTIFICATION_DURATION - (time_now - posted_at);
+            if remaining > 0.0 {
+                egui::Area::new(egui::Id::new("notification"))
+                    .constrain_to(self.canvas_rect)
+                    .anchor(Align2::CENTER_BOTTOM, Vec2::new(0.0, -16.0))
+                    .interactable(false)
+                    .show(ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.label(message.as_str());
+                        });
+                    });
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(remaining));
+            } else {
+                self.notification = None;
+            }
+        }
+
+        if self.show_diagnostics {
+            self.show_diagnostics(ctx);
+        }
+
+        if !self.missing_images.is_empty() {
+            self.show_missing_images(ctx);
+        }
+
+        let mut help_layer_id = None;
+        if self.show_help {
+            let mut open = true;
+            egui::Window::new("Help")
+                .open(&mut open)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    help_layer_id = Some(ui.layer_id());
+
+                    // Increase text sizes by 1.5x
+                    let mut style = egui::Style::default();
+                    for (_text_style, font_id) in style.text_styles.iter_mut() {
+                        font_id.size *= 1.5;
+                    }
+                    ui.set_style(style);
+
+                    ui.heading("Controls");
+                    ui.label("• ✋ Pan: Middle Mouse Drag OR ⌨️ Space + Left Mouse Drag, a fast drag keeps gliding (right-click the zoom level in the status bar to turn off)");
+                    ui.label("• 🔍 Zoom: Mouse Wheel or pinch (right-click the zoom level in the status bar to pan with two-finger scrolling instead)");
+                    ui.label("• ⌨️ W A S D / Q E: Pan and zoom out/in with one hand while nothing is being typed");
+                    ui.label("• 🔒 Lock View: Click the lock in the status bar to stop panning and zooming while arranging blocks");
+                    ui.label("• ✥ Move Block: Left Mouse Drag");
+                    ui.label("• 👆 Select Block: Click (Shift/Ctrl+Click to add), a single selection shows its properties, where exact position and size can be typed in");
+                    ui.label("• ⌨️ Tab / Shift+Tab: Select the next/previous block and center the view on it, a ring marks it until the mouse is used");
+                    ui.label("• ⌨️ F: Zoom until the selected block fills the view (or double-click a block's 'o' handle)");
+                    ui.label("• ⌨️ F5 Present: Show the board full screen without any controls, one block at a time along the slide path (or in reading order without one), ⌨️ Left/Right Arrows or Page Up/Down to step through, Esc to leave");
+                    ui.label("• ➰ Lasso Select: Alt + Left Mouse Drag on empty canvas around block centers (Shift to add)");
+                    ui.label("• ↘️ Resize Block: Right Mouse Drag (corners), or ⌨️ Ctrl+Arrows for the selection in small steps");
+                    ui.label("• ⌨️ Shift + Drag: Move the block only horizontally or only vertically");
+                    ui.label("• ⌨️ Alt + Drag/Resize: Place the block exactly where it is dropped, even over others");
+                    ui.label("• 📝 Edit Text: Double Click (the block grows to fit what you type), the buttons above the block make the selection bold, italic, a heading or a list, 👁 or ⌨️ Ctrl+P shows the rendered text alongside");
+                    ui.label("• abc Spell Check: Underline unknown words while editing, + accepts the one at the cursor on this board");
+                    ui.label("• ⏯️ Toggle GIF: Click");
+                    ui.label("• ⏱ Fixed Rate: Under Playback, show every frame of an animation for the same time instead of the file's delays");
+                    ui.label("• 🧅 Onion Skin: Turn it on under Playback to see a paused animation's previous and next frames faintly");
+                    ui.label("• 🔁 Playback: Right-click an animation to play it forward, reversed or back and forth, forever or a set number of times");
+                    ui.label("• 🎞 Extract Frame: Right-click a paused animation to copy the shown frame into a still image (not saved with the session)");
+                    ui.label("• ❌ Delete Block: Click 'x' handle, or ⌨️ Delete for the selection");
+                    ui.label("• ⌨️ Ctrl+A / Ctrl+Shift+I: Select all blocks / invert the selection");
+                    ui.label("• ⎘ Duplicate: Right-click a block, or ⌨️ Ctrl+D for the selection");
+                    ui.label("• 🂠 Stack / Spread Out: Right-click a selected block to fan the selection into a pile (click one to bring it to the front) or deal it out into a grid");
+                    ui.label("• 🌓 Opacity: Right-click a block to fade it");
+                    ui.label("• 🔗 Lock Aspect Ratio: Right-click a text block to keep its shape while resizing");
+                    ui.label("• 📑 Layers: List the blocks front to back, click one to select it and bring it into view, drag it onto another to put it in that one's place, click its eye to hide or show it");
+                    ui.label("• ⚙ Preferences: Theme, grid, scrolling, zoom range, editing, animation and export settings in one place, with a reset to the defaults");
+                    ui.label("• 🗑 Recently Deleted: Click the trash in the status bar to bring back deleted blocks");
+                    ui.label("• ⌨️ F12: Show frame times and memory use, e.g. to see whether the app repaints while idle");
+                    ui.label("• 🔍 Find Image: Type part of a file name in the status bar (⌨️ Ctrl+F), Enter for the next match");
+                    ui.label("• 🔤 Import Markdown: Right-click Add Text to turn a Markdown file into blocks, one per heading or rule");
+                    ui.label("• 📋 Paste: Text pasted onto the canvas becomes a new text block, ready to edit");
+                    ui.label("• 🔗 Link: Paste a web address or right-click 🔤 to add a link card with the page's preview, click it to open the page");
+                    ui.label("• 🎨 Swatch: Right-click 🔤 to add a color swatch, click it to copy its hex code");
+                    ui.label("• 💧 Eyedropper: Click an image to copy the color under the pointer, Shift-click to also add a swatch");
+                    ui.label("• 💬 Note: Right-click a block to attach a note, hover its speech bubble to read it");
+                    ui.label("• ✂ Crop: Right-click an image, choose Crop… and drag over the part to keep");
+                    ui.label("• 🎨 Adjust: Right-click an image for brightness, contrast and grayscale");
+                    ui.label("• 🗂 Contact Sheet: Select images, right-click one and combine them into a single image");
+                    ui.label("• 📌 Pin: Right-click a block to keep it in place on screen while panning and zooming");
+                    ui.label("• 🔗 Chain Block: Click 'o' handle (moves together, keeping its layout)");
+                    ui.separator();
+                    ui.heading("Tools");
+                    ui.label("• 💾 Save: Save current session to JSON, Right-click to export only the selected blocks, the board as a PNG or SVG at a fixed scale, or its text as Markdown");
+                    ui.label("• 📂 Load: Load session from JSON, Right-click to start a new board or add its blocks to the current canvas");
+                    ui.label("• 🔤 Text: Add new markdown text block, ![alt](path) shows an image (relative to the session file), click the box of a - [ ] task to tick it");
+                    ui.label("• 🖼 Image: Add images (PNG, APNG, JPG, GIF, AVIF, WEBP, SVG, PDF page), several are laid out in a grid");
+                    ui.label("• 📁 Folder: Import every image in a folder as a grid");
+                    ui.label("• 🔢 Counter: Click image to count, Right-click to decrement, Middle-click to reset (right-click 🔢 to count below zero)");
+                    ui.label("• Σ Total: The status bar adds up the counters of the selected blocks, or of all blocks");
+                    ui.label("• 🎬 Slide Path: Click blocks in the order to present them, click again to take one off, Right-click to clear the path");
+                    ui.label("• 📏 Ruler: Click two points to measure their distance and angle, Esc to leave");
+                    ui.label("• 🧹 Tidy Up: Pack all blocks at the view with even gaps, Right-click for rows or masonry columns and chained groups");
+                    ui.label("• 🕑 Arrange by Age: Lay the blocks out in rows across the view, oldest first (Right-click for newest first)");
+                    ui.label("• 🔄 Reset: Reset all image counters to zero");
+                    ui.label("• 🧲 Push: Dragged blocks shove their neighbors aside (off: they only settle on release), Right-click to let blocks overlap freely or turn off snapping to other blocks' edges");
+                    ui.label("• ⌨️ Keyboard Shortcuts: Right-click ❓ to change the keys for saving (Ctrl+S), deleting, duplicating, panning, looking around, stepping through slides and more");
+                    ui.label("• ☀ Theme: Switch between dark and light, Right-click for background color and grid");
+                });
+            if !open {
+                self.show_help = false;
+            }
+        }
+
+        if let Some(replacement) = self.confirm_replace {
+            self.show_confirm_replace(replacement, ctx);
+        }
+
+        if self.show_settings {
+            self.show_settings(ctx);
+        }
+        if self.show_keymap {
+            self.show_keymap(ctx);
+        }
+
+        if self.show_trash {
+            self.show_trash(ctx);
+        }
+
+        // egui also moves its widget focus on Tab; keep it on the canvas instead
+        if tab_navigated {
+            ctx.memory_mut(|m| {
+                if let Some(id) = m.focused() {
+                    m.surrender_focus(id);
+                }
+            });
+        }
+
+        // Hashing every block is too slow to redo each frame on large boards
+        let now = ctx.input(|i| i.time);
+        let since_check = now - self.dirty_check.1;
+        if since_check >= DIRTY_CHECK_INTERVAL {
+            self.dirty_check = (self.is_dirty(), now);
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(
+                DIRTY_CHECK_INTERVAL - since_check,
+            ));
+        }
+        let title = self.title(self.dirty_check.0);
+        if self.window_title != title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+
+        if self.show_help && !help_toggled && ctx.input(|i| i.pointer.any_click()) {
+            if let Some(pos) = ctx.input(|i| i.pointer.hover_pos()) {
+                if let Some(layer_id) = ctx.layer_id_at(pos) {
+                    if let Some(help_id) = help_layer_id {
+                        if layer_id != help_id {
+                            self.show_help = false;
+                        }
+                    }
+                } else {
+                    self.show_help = false;
+                }
+            }
+        }
+    }
+}
+
+impl CanvasApp {
+    /// Fill the canvas and draw its grid pattern, which lives in world space so it pans
+    /// and zooms along with the blocks
+    fn paint_background(&self, ui: &egui::Ui) {
+        let canvas_rect = ui.max_rect();
+        let fill = match self.background.color {
+            Some([r, g, b]) => Color32::from_rgb(r, g, b),
+            None => Palette::for_theme(self.settings.theme).canvas,
+        };
+        ui.painter().rect_filled(canvas_rect, 0.0, fill);
+        if self.background.pattern == BackgroundPattern::Solid {
+            return;
+        }
+
+        // Faint marks that stay visible on light and dark fills alike
+        let brightness = (fill.r() as u16 + fill.g() as u16 + fill.b() as u16) / 3;
+        let ink = if brightness > 127 {
+            Color32::from_black_alpha(40)
+        } else {
+            Color32::from_white_alpha(30)
+        };
+
+        let screen_center = canvas_rect.center();
+        let spacing = self
+            .viewport
+            .grid_spacing(self.settings.grid_spacing, GRID_MIN_SCREEN_SPACING);
+        let world_min = self
+            .viewport
+            .screen_to_world(screen_center, canvas_rect.min);
+        let world_max = self
+            .viewport
+            .screen_to_world(screen_center, canvas_rect.max);
+        let xs = (world_min.x / spacing).ceil() as i64..=(world_max.x / spacing).floor() as i64;
+        let ys = (world_min.y / spacing).ceil() as i64..=(world_max.y / spacing).floor() as i64;
+        let to_screen = |x: i64, y: i64| {
+            self.viewport.world_to_screen(
+                screen_center,
+                Pos2::new(x as f32 * spacing, y as f32 * spacing),
+            )
+        };
+
+        match self.background.pattern {
+            BackgroundPattern::Solid => {}
+            BackgroundPattern::Dots => {
+                for x in xs {
+                    for y in ys.clone() {
+                        ui.painter().circle_filled(to_screen(x, y), 1.5, ink);
+                    }
+                }
+            }
+            BackgroundPattern::Lines => {
+                let stroke = Stroke::new(1.0, ink);
+                for x in xs {
+                    let sx = to_screen(x, 0).x;
+                    ui.painter().vline(sx, canvas_rect.y_range(), stroke);
+                }
+                for y in ys {
+                    let sy = to_screen(0, y).y;
+                    ui.painter().hline(canvas_rect.x_range(), sy, stroke);
+                }
+            }
+        }
+    }
+
+    /// Line between two world points, labeled with its length in world units and its
+    /// angle in degrees, counter-clockwise from the x axis
+    fn paint_ruler(&self, ui: &egui::Ui, start: Pos2, end: Pos2) {
+        let screen_center = self.canvas_rect.center();
+        let a = self.viewport.world_to_screen(screen_center, start);
+        let b = self.viewport.world_to_screen(screen_center, end);
+        let color = Color32::from_rgb(255, 140, 0);
+        let painter = ui.painter();
+        painter.line_segment([a, b], Stroke::new(2.0, color));
+        painter.circle_filled(a, 3.0, color);
+        painter.circle_filled(b, 3.0, color);
+
+        let d = end - start;
+        let angle = (-d.y).atan2(d.x).to_degrees();
+        let galley = painter.layout_no_wrap(
+            format!("{:.1}  {:.1}°", d.length(), angle),
+            egui::FontId::proportional(14.0),
+            Color32::WHITE,
+        );
+        let label =
+            Align2::CENTER_BOTTOM.anchor_size(a.lerp(b, 0.5) - Vec2::new(0.0, 6.0), galley.size());
+        painter.rect_filled(label.expand(3.0), 3.0, Color32::from_black_alpha(180));
+        painter.galley(label.min, galley, Color32::WHITE);
+    }
+
+    fn process_canvas(&mut self, ui: &mut egui::Ui) {
+        let canvas_rect = ui.max_rect();
+        self.canvas_rect = canvas_rect;
+        let screen_center = canvas_rect.center();
+        // Present mode glides to the current slide until it fills the screen, and hides
+        // the chrome
+        let presenting = self.presentation.is_some();
+        if let Some(idx) = self.current_slide() {
+            let mut target = Viewport::default();
+            target.set_zoom_limits(self.viewport.min_zoom, self.viewport.max_zoom);
+            target.fit(self.blocks[idx].rect, canvas_rect.size(), FIT_BLOCK_FILL);
+            let dt = ui.input(|i| i.stable_dt);
+            if self.viewport.glide_toward(&target, dt) {
+                ui.ctx().request_repaint();
+            }
+        }
+        let zoom = self.viewport.zoom;
+        let palette = Palette::for_theme(self.settings.theme);
+
+        // Much of the canvas reads the pointer directly rather than through widget
+        // responses, so it has to be told about windows, menus and popups over it itself
+        let pointer_over_window = ui.ctx().is_pointer_over_area();
+        let mouse_pos = ui
+            .input(|i| i.pointer.hover_pos())
+            .filter(|_| !pointer_over_window);
+        if ui.input(|i| i.pointer.any_pressed()) {
+            self.keyboard_focus = None;
+        }
+        let secondary_down = ui.input(|i| i.pointer.secondary_down());
+        let secondary_pressed =
+            ui.input(|i| i.pointer.button_pressed(egui::PointerButton::Secondary));
+        // Holding Alt places the block exactly where it is dropped, overlapping or not
+        let free_placement = ui.input(|i| i.modifiers.alt);
+    let secondary_released =
+        ui.input(|i| i.pointer.button_released(egui::PointerButton::Secondary));
+
+    let time_now = ui.input(|i| i.time);
+
+        // --- Resize Logic ---
+        if secondary_pressed
+            && !presenting
+            && !self.counter_tool_active
+            && !self.overlay_tool_active()
+        {
+            if let Some(m_pos) = mouse_pos.filter(|p| canvas_rect.contains(*p)) {
+                // Pinned blocks are drawn over the others, so they are hit first
+                let hit = self
+                    .blocks
+                    .iter()
+                    .rev()
+                    .filter(|b| b.pinned)
+                    .chain(self.blocks.iter().rev().filter(|b| !b.pinned))
+                    .find(|b| b.visible && b.rect.contains(self.to_block_space(b, m_pos)));
+                if let Some(block) = hit {
+                    self.resizing_state = Some(InteractionState {
+                        id: block.id,
+                        handle: ResizeHandle::nearest(
+                            block.rect,
+                            self.to_block_space(block, m_pos),
+                        ),
+                        initial_mouse_pos: m_pos,
+                        initial_block_rect: block.rect,
+                    });
+                    self.last_dragged_id = Some(block.id);
+                }
+            }
+        }
+
+        if secondary_released {
+            self.resizing_state = None;
+        }
+
+        if let Some(state) = &self.resizing_state {
+            if let Some(curr_mouse_pos) = mouse_pos {
+                if let Some(idx) = self.blocks.iter().position(|b| b.id == state.id) {
+                    let scale = if self.blocks[idx].pinned { 1.0 } else { zoom };
+                    let delta_world = (curr_mouse_pos - state.initial_mouse_pos) / scale;
+                    let aspect_ratio = self.blocks[idx].kept_aspect_ratio();
+                    let new_rect = resized_rect(
+                        state.initial_block_rect,
+                        state.handle,
+                        delta_world,
+                        aspect_ratio,
+                    );
+                    self.blocks[idx].rect = new_rect;
+                    if self.settings.collision_mode == CollisionMode::Push && !free_placement {
+                        self.push_neighbors(idx);
+                    }
+                }
+            }
+        }
+
+        // --- Lasso Selection ---
+        let (primary_pressed, primary_down, alt_held) = ui.input(|i| {
+            (
+                i.pointer.primary_pressed(),
+                i.pointer.primary_down(),
+                i.modifiers.alt,
+            )
+        });
+        if primary_pressed
+            && alt_held
+            && !presenting
+            && !self.counter_tool_active
+            && !self.overlay_tool_active()
+        {
+            if let Some(m_pos) = mouse_pos.filter(|p| canvas_rect.contains(*p)) {
+                let world_mouse = self.viewport.screen_to_world(screen_center, m_pos);
+                if !self
+                    .blocks
+                    .iter()
+                    .any(|b| b.visible && self.block_screen_rect(b).contains(m_pos))
+                {
+                    self.lasso = Some(vec![world_mouse]);
+                }
+            }
+        }
+        if primary_down {
+            if let (Some(points), Some(m_pos)) = (&mut self.lasso, mouse_pos) {
+                let world_mouse = self.viewport.screen_to_world(screen_center, m_pos);
+                // Skip points closer than a few pixels to keep the outline short
+                if points
+                    .last()
+                    .is_some_and(|last| last.distance(world_mouse) * zoom > 3.0)
+                {
+                    points.push(world_mouse);
+                }
+            }
+        } else if let Some(points) = self.lasso.take() {
+            // Blocks are picked by their center; Shift adds to the current selection
+            let additive = ui.input(|i| i.modifiers.shift);
+            for b in &mut self.blocks {
+                let inside = point_in_polygon(b.rect.center(), &points);
+                if points.len() >= 3 && b.visible && !b.pinned && inside {
+                    b.selected = true;
+                } else if !additive {
+                    b.selected = false;
+                }
+            }
+        }
+
+        // --- Render & Interaction ---
+        let mut ids_to_delete = HashSet::new();
+        let mut chain_handle_hovered = false;
+        let mut interact_captured = false;
+        let mut pending_move = None;
+        let mut clicked_block = None;
+        let mut slide_toggle = None;
+        let mut pile_request = None;
+        let multi_selection = self.blocks.iter().filter(|b| b.selected).count() > 1;
+        let mut duplicate_requests = Vec::new();
+        let mut reveal_requests = Vec::new();
+        let mut pin_requests = Vec::new();
+        let mut hide_requests = Vec::new();
+        let mut uncrop_requests = Vec::new();
+        let mut extract_requests = Vec::new();
+        let mut reload_requests = Vec::new();
+        let mut contact_sheet_requested = false;
+        let mut fit_request = None;
+        let mut grow_request = None;
+        let mut animation_load_requests = Vec::new(); // Collect animation load requests
+        let mut svg_rerender_requests = Vec::new();
+
+        let now = now_millis();
+        let mut fading = false;
+
+        // Pinned blocks float above the world, hidden ones aren't there at all
+        let draw_order: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned)
+            .chain((0..self.blocks.len()).filter(|&i| self.blocks[i].pinned))
+            .filter(|&i| self.blocks[i].visible)
+            .collect();
+
+        for i in draw_order {
+            let b_id = self.blocks[i].id;
+            let b_selected = self.blocks[i].selected;
+            let b_chained = self.blocks[i].chained;
+            let b_pinned = self.blocks[i].pinned;
+            let is_editing = self.editing_id == Some(b_id);
+            // Pinned blocks keep their size whatever the zoom
+            let zoom = if b_pinned { 1.0 } else { zoom };
+
+            let screen_rect = self.block_screen_rect(&self.blocks[i]);
+
+            if !canvas_rect.intersects(screen_rect) {
+                continue;
+            }
+
+            let fade = if self.settings.fade_in_blocks {
+                fade_in(now, self.blocks[i].created_at)
+            } else {
+                1.0
+            };
+            fading |= fade < 1.0;
+            let alpha = self.blocks[i].opacity as f32 / 255.0 * fade;
+            // The selection outline stays opaque so faded blocks can still be found
+            let border_color = if b_selected {
+                Color32::YELLOW
+            } else if b_chained {
+                Color32::GREEN.gamma_multiply(alpha)
+            } else {
+                Color32::BLACK.gamma_multiply(alpha)
+            };
+            let bg_color = palette.block.gamma_multiply(alpha);
+            let text_color = palette.block_text.gamma_multiply(alpha);
+
+            ui.painter().rect_filled(screen_rect, 5.0, bg_color);
+            if !presenting {
+                ui.painter()
+                    .rect_stroke(screen_rect, 5.0, Stroke::new(2.0, border_color));
+                if is_editing || self.keyboard_focus == Some(b_id) {
+                    ui.painter().rect_stroke(
+                        screen_rect.expand(4.0),
+                        7.0,
+                        Stroke::new(3.0, palette.focus),
+                    );
+                }
+            }
+
+            let interact_id = ui.make_persistent_id(b_id);
+            let sense = if is_editing || presenting {
+                egui::Sense::hover()
+            } else if self.counter_tool_active || self.editing_slide_path {
+                egui::Sense::click()
+            } else {
+                egui::Sense::click_and_drag()
+            };
+            let response = ui.interact(screen_rect, interact_id, sense);
+            response.widget_info(|| {
+                let block = &self.blocks[i];
+                let mut label = block.content.accessible_label();
+                if let Some(note) = &block.note {
+                    label += &format!(", note: {note}");
+                }
+                let mut info = egui::WidgetInfo::labeled(egui::WidgetType::Other, true, label);
+                info.selected = Some(b_selected);
+                info
+            });
+            // Hovering an image shows its size and file details, unless it is being moved
+            let content = &self.blocks[i].content;
+            let response = match content {
+                BlockContent::Image { .. } if !presenting && !response.dragged() => response
+                    .on_hover_ui(|ui| {
+                        ui.label(content.image_details().unwrap_or_default());
+                    }),
+                _ => response,
+            };
+
+            if response.hovered() || response.dragged() {
+                interact_captured = true;
+            }
+
+            let btn_size = 16.0 * zoom;
+            let padding = 4.0 * zoom;
+            let top_right = screen_rect.right_top();
+            let close_rect = Rect::from_center_size(
+                top_right + Vec2::new(-btn_size / 2.0 - padding, btn_size / 2.0 + padding),
+                Vec2::splat(btn_size),
+            );
+            let chain_rect = Rect::from_center_size(
+                close_rect.center() - Vec2::new(btn_size + padding, 0.0),
+                Vec2::splat(btn_size),
+            );
+
+            let close_hovered = mouse_pos.is_some_and(|p| close_rect.contains(p));
+            let chain_hovered = mouse_pos.is_some_and(|p| chain_rect.contains(p));
+
+            if response.clicked() && !close_hovered && !chain_hovered && !self.counter_tool_active {
+                if self.editing_slide_path {
+                    slide_toggle = Some(b_id);
+                } else {
+                    clicked_block = Some(b_id);
+                }
+            }
+
+            // Right-click without dragging; the counter tool uses it to decrement instead
+            if !self.counter_tool_active && !is_editing {
+                let image = match &self.blocks[i].content {
+                    BlockContent::Image {
+                        path,
+                        crop,
+                        frames,
+                        adjust,
+                        frame_delays,
+                        looping,
+                        direction,
+                        playing,
+                        delay_override,
+                        ..
+                    } => Some((
+                        path.as_deref(),
+                        *crop,
+                        !frames.is_empty(),
+                        *adjust,
+                        (frame_delays.len() > 1).then_some((
+                            *looping,
+                            *direction,
+                            *playing,
+                            *delay_override,
+                        )),
+                    )),
+                    BlockContent::Text { .. }
+                    | BlockContent::Link { .. }
+                    | BlockContent::Color { .. } => None,
+                };
+                let mut link = match &self.blocks[i].content {
+                    BlockContent::Link { url, title, .. } => Some((url.clone(), title.clone())),
+                    _ => None,
+                };
+                let mut swatch = match self.blocks[i].content {
+                    BlockContent::Color { color, label } => Some((color, label)),
+                    _ => None,
+                };
+                let mut opacity = self.blocks[i].opacity;
+                let mut note = self.blocks[i].note.clone().unwrap_or_default();
+                let mut lock_aspect = self.blocks[i].lock_aspect;
+                let (mut start_crop, mut reset_crop) = (false, false);
+                let mut adjust_changed = None;
+                let mut playback_changed = None;
+                let mut delay_changed = None;
+                let mut onion_skin = self.settings.onion_skin;
+                let counted = match self.blocks[i].content {
+                    BlockContent::Image { counter, .. } => counter != 0,
+                    BlockContent::Text { .. }
+                    | BlockContent::Link { .. }
+                    | BlockContent::Color { .. } => false,
+                };
+                let mut reset_counter = false;
+                response.context_menu(|ui| {
+                    if ui.button("Duplicate").clicked() {
+                        duplicate_requests.push(b_id);
+                        ui.close_menu();
+                    }
+                    ui.add(egui::Slider::new(&mut opacity, 0..=255).text("Opacity"));
+                    ui.add(egui::TextEdit::singleline(&mut note).hint_text("Note"));
+                    if let Some((url, title)) = &mut link {
+                        ui.add(egui::TextEdit::singleline(url).hint_text("URL"));
+                        ui.add(egui::TextEdit::singleline(title).hint_text("Title"));
+                    }
+                    if let Some((color, label)) = &mut swatch {
+                        ui.horizontal(|ui| {
+                            ui.label("Color");
+                            egui::color_picker::color_edit_button_srgba(
+                                ui,
+                                color,
+                                egui::color_picker::Alpha::OnlyBlend,
+                            );
+                        });
+                        ui.checkbox(label, "Show Hex Code");
+                    }
+                    if image.is_none() {
+                        ui.checkbox(&mut lock_aspect, "Lock Aspect Ratio");
+                    }
+                    let pin_label = if b_pinned {
+                        "Unpin from Screen"
+                    } else {
+                        "Pin to Screen"
+                    };
+                    if ui.button(pin_label).clicked() {
+                        pin_requests.push(i);
+                        ui.close_menu();
+                    }
+                    if ui.button("Hide").clicked() {
+                        hide_requests.push(i);
+                        ui.close_menu();
+                    }
+                    if b_selected && multi_selection {
+                        if ui.button("Stack Selection").clicked() {
+                            pile_request = Some(true);
+                            ui.close_menu();
+                        }
+                        if ui.button("Spread Out Selection").clicked() {
+                            pile_request = Some(false);
+                            ui.close_menu();
+                        }
+                    }
+                    if let Some((path, crop, loaded, mut adjust, playback)) = image {
+                        start_crop = ui.add_enabled(loaded, egui::Button::new("Crop…")).clicked();
+                        reset_crop = ui
+                            .add_enabled(crop != FULL_CROP, egui::Button::new("Reset Crop"))
+                            .clicked();
+                        if start_crop || reset_crop {
+                            ui.close_menu();
+                        }
+                        if counted && ui.button("Reset Counter").clicked() {
+                            reset_counter = true;
+                            ui.close_menu();
+                        }
+                        // Checked only while the menu is open, not every frame
+                        let source = existing_source_file(path);
+                        let reveal = ui
+                            .add_enabled(
+                                source.is_some(),
+                                egui::Button::new("Open Containing Folder"),
+                            )
+                            .on_disabled_hover_text("The image file is not on disk");
+                        if let (true, Some(source)) = (reveal.clicked(), source.clone()) {
+                            reveal_requests.push(source);
+                            ui.close_menu();
+                        }
+                        // Adjustments are baked in by decoding the file again
+                        ui.add_enabled_ui(source.is_some(), |ui| {
+                            ui.menu_button("Adjust", |ui| {
+                                let brightness = ui.add(
+                                    egui::Slider::new(&mut adjust.brightness, -1.0..=1.0)
+                                        .text("Brightness"),
+                                );
+                                let contrast = ui.add(
+                                    egui::Slider::new(&mut adjust.contrast, -1.0..=1.0)
+                                        .text("Contrast"),
+                                );
+                                let grayscale = ui.checkbox(&mut adjust.grayscale, "Grayscale");
+                                let reset = ui
+                                    .add_enabled(!adjust.is_identity(), egui::Button::new("Reset"));
+                                if reset.clicked() {
+                                    adjust = ImageAdjust::default();
+                                }
+                                // Sliders apply once let go, not on every step
+                                let settled = |r: &egui::Response| {
+                                    r.drag_stopped() || (r.changed() && !r.dragged())
+                                };
+                                let settled = settled(&brightness)
+                                    || settled(&contrast)
+                                    || grayscale.changed()
+                                    || reset.clicked();
+                                if settled || brightness.changed() || contrast.changed() {
+                                    adjust_changed = Some((adjust, settled));
+                                }
+                            });
+                        });
+                        // Only animations have a playback to control
+                        if let Some((looping, mut direction, playing, delay_override)) = playback {
+                            let extract = ui
+                                .add_enabled(!playing, egui::Button::new("Extract Frame"))
+                                .on_disabled_hover_text("Pause the animation on the frame first");
+                            if extract.clicked() {
+                                extract_requests.push(i);
+                                ui.close_menu();
+                            }
+                            ui.menu_button("Playback", |ui| {
+                                ui.checkbox(&mut onion_skin, "Onion Skin When Paused")
+                                    .on_hover_text("Show the frames before and after faintly");
+                                ui.separator();
+                                for (mode, label) in [
+                                    (Direction::Forward, "Forward"),
+                                    (Direction::Reverse, "Reverse"),
+                                    (Direction::PingPong, "Ping-Pong"),
+                                ] {
+                                    if ui.radio_value(&mut direction, mode, label).clicked() {
+                                        playback_changed = Some((looping, direction));
+                                    }
+                                }
+                                ui.separator();
+                                let mut times = match looping {
+                                    Looping::Times(times) => times,
+                                    Looping::Forever => 1,
+                                };
+                                let forever = ui.radio(looping == Looping::Forever, "Loop Forever");
+                                if forever.clicked() {
+                                    playback_changed = Some((Looping::Forever, direction));
+                                }
+                                ui.horizontal(|ui| {
+                                    let limited = ui.radio(looping != Looping::Forever, "Play");
+                                    let count = ui.add(
+                                        egui::DragValue::new(&mut times)
+                                            .range(1..=99)
+                                            .suffix(" times"),
+                                    );
+                                    if limited.clicked() || count.changed() {
+                                        playback_changed = Some((Looping::Times(times), direction));
+                                    }
+                                });
+                                ui.separator();
+                                // Some GIFs have all over the place delays; one steady rate
+                                // can stand in for them
+                                ui.horizontal(|ui| {
+                                    let mut fixed = delay_override.is_some();
+                                    let mut fps =
+                                        delay_override.map_or(DEFAULT_FIXED_FPS, |d| 1.0 / d);
+                                    let toggled = ui.checkbox(&mut fixed, "Fixed rate").changed();
+                                    let rate = ui.add_enabled(
+                                        fixed,
+                                        egui::DragValue::new(&mut fps)
+                                            .range(1.0..=60.0)
+                                            .max_decimals(1)
+                                            .suffix(" fps"),
+                                    );
+                                    if toggled || rate.changed() {
+                                        delay_changed = Some(fixed.then(|| 1.0 / fps));
+                                    }
+                                });
+                            });
+                        }
+                        if b_selected && ui.button("Make Contact Sheet…").clicked() {
+                            contact_sheet_requested = true;
+                            ui.close_menu();
+                        }
+                    }
+                });
+                self.blocks[i].opacity = opacity;
+                self.blocks[i].lock_aspect = lock_aspect;
+                if let (Some(edited), BlockContent::Color { color, label }) =
+                    (swatch, &mut self.blocks[i].content)
+                {
+                    (*color, *label) = edited;
+                }
+                let mut refetch = None;
+                if let (Some((new_url, new_title)), BlockContent::Link { url, title, .. }) =
+                    (link, &mut self.blocks[i].content)
+                {
+                    let new_url = new_url.trim();
+                    if !new_url.is_empty() && new_url != url {
+                        *url = new_url.to_string();
+                        refetch = Some(url.clone());
+                    }
+                    *title = new_title;
+                }
+                // A new address gets its own preview
+                if let Some(url) = refetch {
+                    if let BlockContent::Link {
+                        description,
+                        image_url,
+                        thumbnail,
+                        ..
+                    } = &mut self.blocks[i].content
+                    {
+                        description.clear();
+                        *image_url = None;
+                        *thumbnail = None;
+                    }
+                    self.fetch_link_preview(b_id, url, None, ui.ctx());
+                }
+                if self.blocks[i].note.as_deref().unwrap_or_default() != note {
+                    self.blocks[i].note = (!note.trim().is_empty()).then_some(note);
+                }
+                if start_crop {
+                    self.crop_target = Some(b_id);
+                    self.crop_drag = None;
+                    self.ruler_active = false;
+                }
+                if reset_crop {
+                    uncrop_requests.push(i);
+                }
+                self.settings.onion_skin = onion_skin;
+                if reset_counter {
+                    if let BlockContent::Image { counter, .. } = &mut self.blocks[i].content {
+                        *counter = 0;
+                    }
+                }
+                if let Some(new_delay) = delay_changed {
+                    if let BlockContent::Image { delay_override, .. } = &mut self.blocks[i].content
+                    {
+                        *delay_override = new_delay;
+                    }
+                }
+                if let Some((new_looping, new_direction)) = playback_changed {
+                    if let BlockContent::Image {
+                        looping,
+                        direction,
+                        progress,
+                        ..
+                    } = &mut self.blocks[i].content
+                    {
+                        *looping = new_looping;
+                        *direction = new_direction;
+                        *progress = Progress::default();
+                    }
+                }
+                if let Some((adjust, settled)) = adjust_changed {
+                    if let BlockContent::Image {
+                        adjust: current, ..
+                    } = &mut self.blocks[i].content
+                    {
+                        *current = adjust;
+                    }
+                    if settled {
+                        reload_requests.push(b_id);
+                    }
+                }
+            }
+
+            // Checked whether or not the view is locked, so a pan never turns into a move
+            if response.dragged() && !secondary_down && !ui.input(|i| self.pan_gesture(i)) {
+                let delta = response.drag_delta() / zoom;
+                pending_move = Some((i, delta));
+                self.last_dragged_id = Some(b_id);
+            }
+
+            if is_editing {
+                if ui.input_mut(|inp| self.keymap.consume(inp, Action::Preview)) {
+                    self.settings.edit_preview = !self.settings.edit_preview;
+                }
+                // With the preview on, the editor takes the left half and the
+                // rendered text the right one
+                let inner = screen_rect.shrink(4.0);
+                let (editor_rect, preview_rect) = if self.settings.edit_preview {
+                    let (left, right) = inner.split_left_right_at_fraction(0.5);
+                    let gap = egui::vec2(2.0, 0.0);
+                    (left.shrink2(gap), Some(right.shrink2(gap)))
+                } else {
+                    (inner, None)
+                };
+                let mut child_ui = ui.new_child(
+                    egui::UiBuilder::new()
+                        .max_rect(editor_rect)
+                        .layout(egui::Layout::left_to_right(egui::Align::Min)),
+                );
+                child_ui.visuals_mut().override_text_color = Some(text_color);
+                if let Some(text_mut) = self.blocks[i].content.as_text_mut() {
+                    let output = egui::TextEdit::multiline(text_mut)
+                        .font(egui::FontId::proportional(16.0 * zoom))
+                        .frame(false)
+                        .desired_width(f32::INFINITY)
+                        .show(&mut child_ui);
+
+                    // Grow downward instead of letting the text run out of the block
+                    let needed = output.galley.rect.height() + 8.0;
+                    if needed > screen_rect.height() {
+                        grow_request = Some((i, needed / zoom));
+                    }
+
+                    // Squiggles under unknown words; the one at the cursor can be learned
+                    let mut unknown_word = None;
+                    if self.settings.spell_check {
+                        let cursor = output.state.cursor.char_range().map(|r| r.primary.index);
+                        for range in spelling::misspelled(text_mut, &self.custom_words) {
+                            let start = text_mut[..range.start].chars().count();
+                            let end = start + text_mut[range.clone()].chars().count();
+                            draw_squiggle(child_ui.painter(), &output, start..end);
+                            if cursor.is_some_and(|c| (start..=end).contains(&c)) {
+                                unknown_word = Some(text_mut[range].to_lowercase());
+                            }
+                        }
+                    }
+
+                    // Formatting toolbar floating above the block
+                    let mut format = None;
+                    let mut toggle_preview = false;
+                    let mut toggle_spell_check = false;
+                    let mut learned_word = None;
+                    egui::Area::new(egui::Id::new(("format_toolbar", b_id)))
+                        .fixed_pos(screen_rect.left_top() - egui::vec2(0.0, 30.0))
+                        .order(egui::Order::Foreground)
+                        .show(ui.ctx(), |ui| {
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    let buttons = [
+                                        ("B", Format::Bold, "Bold"),
+                                        ("I", Format::Italic, "Italic"),
+                                        ("H", Format::Heading, "Heading"),
+                                        ("•", Format::List, "List"),
+                                    ];
+                                    for (label, f, hover) in buttons {
+                                        let label = match f {
+                                            Format::Bold => egui::RichText::new(label).strong(),
+                                            Format::Italic => egui::RichText::new(label).italics(),
+                                            _ => egui::RichText::new(label),
+                                        };
+                                        if ui.small_button(label).on_hover_text(hover).clicked() {
+                                            format = Some(f);
+                                        }
+                                    }
+                                    ui.separator();
+                                    toggle_preview = ui
+                                        .selectable_label(preview_rect.is_some(), "👁")
+                                        .on_hover_text("Preview (Ctrl+P)")
+                                        .clicked();
+                                    let spell_check = ui
+                                        .selectable_label(self.settings.spell_check, "abc")
+                                        .on_hover_text("Spell check (right-click: added words)");
+                                    toggle_spell_check = spell_check.clicked();
+                                    spell_check.context_menu(|ui| {
+                                        if self.custom_words.is_empty() {
+                                            ui.label("No words added yet");
+                                        }
+                                        self.custom_words.retain(|word| {
+                                            !ui.button(format!("🗑 {word}")).clicked()
+                                        });
+                                    });
+                                    if let Some(word) = &unknown_word {
+                                        if ui
+                                            .small_button(format!("+ {word}"))
+                                            .on_hover_text("Accept this word on this board")
+                                            .clicked()
+                                        {
+                                            learned_word = Some(word.clone());
+                                        }
+                                    }
+                                });
+                            });
+                        });
+                    if let Some(format) = format {
+                        let mut state = output.state;
+                        let selection = match state.cursor.char_range() {
+                            Some(range) => {
+                                let [min, max] = range.sorted();
+                                min.index..max.index
+                            }
+                            None => {
+                                let end = text_mut.chars().count();
+                                end..end
+                            }
+                        };
+                        let selection = format.apply(text_mut, selection);
+                        let (start, end) = (
+                            egui::text::CCursor::new(selection.start),
+                            egui::text::CCursor::new(selection.end),
+                        );
+                        let range = egui::text::CCursorRange::two(start, end);
+                        state.cursor.set_char_range(Some(range));
+                        state.store(ui.ctx(), output.response.id);
+                        self.focus_request = Some(b_id);
+                    } else if toggle_preview || toggle_spell_check || learned_word.is_some() {
+                        self.settings.edit_preview ^= toggle_preview;
+                        self.settings.spell_check ^= toggle_spell_check;
+                        self.custom_words.extend(learned_word);
+                        self.focus_request = Some(b_id);
+                    } else if self.focus_request == Some(b_id) {
+                        output.response.request_focus();
+                        self.focus_request = None;
+                    }
+                    if ui.input(|inp| self.keymap.pressed(inp, Action::Cancel)) {
+                        self.editing_id = None;
+                    }
+
+                    if let Some(preview_rect) = preview_rect {
+                        let x = preview_rect.left() - 2.0;
+                        ui.painter().vline(
+                            x,
+                            preview_rect.y_range(),
+                            egui::Stroke::new(1.0, text_color.gamma_multiply(0.3)),
+                        );
+                        let mut preview_ui = ui.new_child(
+                            egui::UiBuilder::new()
+                                .max_rect(preview_rect)
+                                .layout(egui::Layout::left_to_right(egui::Align::Min)),
+                        );
+                        for font_id in preview_ui.style_mut().text_styles.values_mut() {
+                            font_id.size *= zoom;
+                        }
+                        preview_ui.visuals_mut().override_text_color = Some(text_color);
+                        show_markdown(
+                            &mut preview_ui,
+                            &mut self.common_mark_cache,
+                            &mut self.resolved_markdown,
+                            text_mut,
+                            self.session_path.as_deref(),
+                        );
+                    }
+                }
+            } else {
+                match &mut self.blocks[i].content {
+                    BlockContent::Text { text } => {
+                        let mut child_ui = ui.new_child(
+                            egui::UiBuilder::new()
+                                .max_rect(screen_rect.shrink(5.0 * zoom))
+                                .layout(egui::Layout::left_to_right(egui::Align::Min)),
+                        );
+                        for (_text_style, font_id) in child_ui.style_mut().text_styles.iter_mut() {
+                            font_id.size *= zoom;
+                        }
+                        child_ui.visuals_mut().override_text_color = Some(text_color);
+                        show_markdown(
+                            &mut child_ui,
+                            &mut self.common_mark_cache,
+                            &mut self.resolved_markdown,
+                            text,
+                            self.session_path.as_deref(),
+                        );
+                        if response.double_clicked()
+                            && !close_hovered
+                            && !chain_hovered
+                            && !self.editing_slide_path
+                        {
+                            self.editing_id = Some(b_id);
+                            self.focus_request = Some(b_id);
+                        }
+                    }
+                    BlockContent::Image {
+                        frames,
+                        current_frame_idx,
+                        playing,
+                        counter,
+                        animation_state,
+                        playing_start_time,
+                        path,
+                        crop,
+                        looping,
+                        direction,
+                        progress,
+                        ..
+                    } => {
+                        // Vector images get re-rasterized once the block is shown
+                        // noticeably larger than the current texture
+                        if let (Some(tex), Some(p)) = (frames.first(), path.as_deref()) {
+                            let wanted = (screen_rect.width() / crop.width()
+                                * ui.ctx().pixels_per_point())
+                            .ceil() as u32;
+                            if is_svg(Path::new(p))
+                                && wanted as f32 > tex.size()[0] as f32 * 1.25
+                                && tex.size()[0] < SVG_MAX_RASTER_WIDTH as usize
+                            {
+                                svg_rerender_requests.push((
+                                    b_id,
+                                    p.to_string(),
+                                    wanted.next_power_of_two().min(SVG_MAX_RASTER_WIDTH),
+                                ));
+                            }
+                        }
+
+                        if let Some(tex) = frames.get(*current_frame_idx) {
+                            ui.painter().image(
+                                tex.id(),
+                                screen_rect,
+                                *crop,
+                                Color32::WHITE.gamma_multiply(alpha),
+                            );
+                        }
+                        // Previous frame in red, next one in blue, to see the motion
+                        if self.settings.onion_skin && !*playing && !presenting {
+                            let [previous, next] = onion_frames(*current_frame_idx, frames.len());
+                            let ghosts = [
+                                (previous, Color32::from_rgb(255, 110, 110)),
+                                (next, Color32::from_rgb(110, 160, 255)),
+                            ];
+                            for (idx, tint) in ghosts {
+                                if let Some(tex) = idx.and_then(|idx| frames.get(idx)) {
+                                    ui.painter().image(
+                                        tex.id(),
+                                        screen_rect,
+                                        *crop,
+                                        tint.gamma_multiply(alpha * ONION_SKIN_ALPHA),
+                                    );
+                                }
+                            }
+                        }
+
+                        // Show loading indicator for animation frames
+                        if let AnimationState::Loading { total_frame_count } = animation_state {
+                            let loading_text =
+                                format!("Loading {}/{}", frames.len(), total_frame_count);
+                            ui.painter().text(
+                                screen_rect.center(),
+                                Align2::CENTER_CENTER,
+                                loading_text,
+                                egui::FontId::proportional(16.0 * zoom),
+                                Color32::WHITE,
+                            );
+                        }
+
+                        if *counter != 0 && !presenting {
+                            let circle_radius = 15.0 * zoom;
+                            let circle_center = screen_rect.min
+                                + Vec2::new(circle_radius + 5.0, circle_radius + 5.0);
+                            ui.painter().circle_filled(
+                                circle_center,
+                                circle_radius,
+                                if *counter > 0 {
+                                    Color32::GREEN
+                                } else {
+                                    Color32::ORANGE
+                                },
+                            );
+                            ui.painter().text(
+                                circle_center,
+                                Align2::CENTER_CENTER,
+                                counter.to_string(),
+                                egui::FontId::proportional(20.0 * zoom),
+                                Color32::BLACK,
+                            );
+                        }
+
+                        if self.counter_tool_active {
+                            if response.clicked() {
+                                *counter += 1;
+                            } else if response.secondary_clicked() {
+                                *counter =
+                                    decremented_counter(*counter, self.settings.negative_counters);
+                            } else if response.middle_clicked() {
+                                *counter = 0;
+                            }
+                        } else if response.clicked()
+                            && !close_hovered
+                            && !chain_hovered
+                            && !self.editing_slide_path
+                        {
+                            // An animation that played its loops starts over
+                            if !*playing && looping.finished(progress.loops_played) {
+                                *progress = Progress::default();
+                                *current_frame_idx = direction.first_frame(frames.len());
+                            }
+                            // Handle animation state transitions
+                            match animation_state {
+                                AnimationState::NotLoaded { .. } => {
+                                    // Collect request to start loading animation frames
+                                    animation_load_requests.push((b_id,));
+                                }
+                                AnimationState::Paused => {
+                                    *playing = true;
+                                    *animation_state = AnimationState::Ready;
+                                    *playing_start_time = Some(time_now);
+                                    self.current_concurrent_animations += 1;
+                                    if self.current_concurrent_animations
+                                        > self.settings.max_concurrent_animations
+                                    {
+                                        self.pause_oldest_animation();
+                                    }
+                                }
+                                AnimationState::Ready => {
+                                    if *playing {
+                                        *playing = false;
+                                        self.current_concurrent_animations -= 1;
+                                    } else {
+                                        *playing = true;
+                                        *playing_start_time = Some(time_now);
+                                        self.current_concurrent_animations += 1;
+                                        if self.current_concurrent_animations
+                                            > self.settings.max_concurrent_animations
+                                        {
+                                            self.pause_oldest_animation();
+                                        }
+                                    }
+                                }
+                                AnimationState::Loading { .. } => {
+                                    // Do nothing, wait for loading
+                                }
+                            }
+                        }
+                    }
+                    BlockContent::Link {
+                        url,
+                        title,
+                        description,
+                        thumbnail,
+                        ..
+                    } => {
+                        let painter = ui.painter().with_clip_rect(screen_rect);
+                        let inner = screen_rect.shrink(8.0 * zoom);
+                        let mut top_left = inner.left_top();
+                        // The page's image sits on the left, the text next to it
+                        if let Some(texture) = thumbnail {
+                            let cell = Rect::from_min_size(inner.min, Vec2::splat(inner.height()));
+                            painter.image(
+                                texture.id(),
+                                fit_rect_to_aspect(cell, texture.aspect_ratio()),
+                                FULL_CROP,
+                                Color32::WHITE.gamma_multiply(alpha),
+                            );
+                            top_left.x = cell.right() + 8.0 * zoom;
+                        }
+                        painter.text(
+                            top_left,
+                            Align2::LEFT_TOP,
+                            "🔗",
+                            egui::FontId::proportional(20.0 * zoom),
+                            text_color,
+                        );
+                        painter.text(
+                            top_left + Vec2::new(28.0 * zoom, 0.0),
+                            Align2::LEFT_TOP,
+                            link_caption(url, title),
+                            egui::FontId::proportional(18.0 * zoom),
+                            text_color,
+                        );
+                        let weak = text_color.gamma_multiply(0.6);
+                        let mut line_top = top_left + Vec2::new(0.0, 30.0 * zoom);
+                        if !description.is_empty() {
+                            painter.text(
+                                line_top,
+                                Align2::LEFT_TOP,
+                                description.as_str(),
+                                egui::FontId::proportional(14.0 * zoom),
+                                text_color,
+                            );
+                            line_top.y += 20.0 * zoom;
+                        }
+                        painter.text(
+                            line_top,
+                            Align2::LEFT_TOP,
+                            url.as_str(),
+                            egui::FontId::proportional(13.0 * zoom),
+                            weak,
+                        );
+                        // Modified clicks only select, so a link can be picked without
+                        // opening it
+                        let plain_click = ui.input(|inp| inp.modifiers.is_none());
+                        if response.clicked()
+                            && plain_click
+                            && !close_hovered
+                            && !chain_hovered
+                            && !self.counter_tool_active
+                            && !self.editing_slide_path
+                        {
+                            ui.ctx().open_url(egui::OpenUrl::new_tab(url.as_str()));
+                        }
+                    }
+                    BlockContent::Color { color, label } => {
+                        let (color, label) = (*color, *label);
+                        ui.painter()
+                            .rect_filled(screen_rect, 5.0, color.gamma_multiply(alpha));
+                        let hex = color_hex(color);
+                        if label {
+                            ui.painter().text(
+                                screen_rect.left_bottom() + Vec2::new(8.0, -8.0) * zoom,
+                                Align2::LEFT_BOTTOM,
+                                &hex,
+                                egui::FontId::monospace(14.0 * zoom),
+                                contrasting_text(color).gamma_multiply(alpha),
+                            );
+                        }
+                        let plain_click = ui.input(|inp| inp.modifiers.is_none());
+                        if response.clicked()
+                            && plain_click
+                            && !close_hovered
+                            && !chain_hovered
+                            && !self.counter_tool_active
+                            && !self.editing_slide_path
+                        {
+                            ui.ctx().copy_text(hex.clone());
+                            self.notify(ui.ctx(), format!("Copied {hex}"));
+                        }
+                    }
+                }
+            }
+
+            // A speech bubble in the bottom-right corner holds the note
+            if let (false, Some(note)) = (presenting, &self.blocks[i].note) {
+                let size = 20.0 * zoom;
+                let bubble_rect = Rect::from_min_size(
+                    screen_rect.right_bottom() - Vec2::splat(size + padding),
+                    Vec2::splat(size),
+                );
+                ui.painter().text(
+                    bubble_rect.center(),
+                    Align2::CENTER_CENTER,
+                    "💬",
+                    egui::FontId::proportional(16.0 * zoom),
+                    text_color,
+                );
+                ui.interact(bubble_rect, interact_id.with("note"), egui::Sense::hover())
+                    .on_hover_text(note);
+            }
+
+            // Numbered badges show the slide path while it is being edited
+            if let (true, Some(slide)) = (self.editing_slide_path, self.blocks[i].slide) {
+                let radius = 12.0 * zoom;
+                let center = screen_rect.left_bottom() + Vec2::new(radius + 5.0, -radius - 5.0);
+                ui.painter()
+                    .circle_filled(center, radius, Color32::LIGHT_BLUE);
+                ui.painter().text(
+                    center,
+                    Align2::CENTER_CENTER,
+                    (slide + 1).to_string(),
+                    egui::FontId::proportional(16.0 * zoom),
+                    Color32::BLACK,
+                );
+            }
+
+            if !presenting && (response.hovered() || response.dragged() || b_chained) {
+                chain_handle_hovered |= chain_hovered;
+                ui.painter().circle_filled(
+                    close_rect.center(),
+                    btn_size / 2.0,
+                    if close_hovered {
+                        Color32::from_rgb(255, 100, 100)
+                    } else {
+                        Color32::RED
+                    },
+                );
+                ui.painter().text(
+                    close_rect.center(),
+                    Align2::CENTER_CENTER,
+                    "x",
+                    egui::FontId::monospace(12.0 * zoom),
+                    Color32::WHITE,
+                );
+                ui.painter().circle_filled(
+                    chain_rect.center(),
+                    btn_size / 2.0,
+                    if b_chained {
+                        Color32::GREEN
+                    } else if chain_hovered {
+                        Color32::LIGHT_GRAY
+                    } else {
+                        Color32::GRAY
+                    },
+                );
+                ui.painter().text(
+                    chain_rect.center(),
+                    Align2::CENTER_CENTER,
+                    "o",
+                    egui::FontId::monospace(12.0 * zoom),
+                    Color32::WHITE,
+                );
+
+                if response.clicked() {
+                    if close_hovered {
+                        ids_to_delete.insert(b_id);
+                    } else if chain_hovered {
+                        self.blocks[i].chained = !self.blocks[i].chained;
+                        self.last_chain_interaction = ui.input(|i| i.time);
+                    }
+                }
+                // Both clicks toggled the chain, so it ends up as it was
+                if response.double_clicked() && chain_hovered {
+                    fit_request = Some(i);
+                }
+            }
+        }
+
+        if fading {
+            ui.ctx().request_repaint();
+        }
+
+        // Hovering a chain handle shows which blocks are chained together so far
+        if chain_handle_hovered {
+            let strength = pulse(time_now, CHAIN_PULSE_PERIOD);
+            let color = Color32::GREEN.gamma_multiply(0.3 + 0.7 * strength);
+            for block in self.blocks.iter().filter(|b| b.chained && !b.pinned) {
+                ui.painter().rect_stroke(
+                    self.block_screen_rect(block).expand(6.0),
+                    8.0,
+                    Stroke::new(3.0, color),
+                );
+            }
+            ui.ctx().request_repaint();
+        }
+
+        if let Some(points) = &self.lasso {
+            let screen_points = points
+                .iter()
+                .map(|p| self.viewport.world_to_screen(screen_center, *p))
+                .collect();
+            ui.painter().add(egui::Shape::closed_line(
+                screen_points,
+                Stroke::new(1.5, Color32::YELLOW),
+            ));
+        }
+
+        // --- Ruler ---
+        if self.ruler_active {
+            // Laid over the blocks so they can be measured without being moved
+            let response = ui.interact(
+                canvas_rect,
+                ui.id().with("ruler"),
+                egui::Sense::click_and_drag(),
+            );
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+            }
+            if let (true, Some(pos)) = (response.clicked(), response.interact_pointer_pos()) {
+                if self.ruler_points.len() >= 2 {
+                    self.ruler_points.clear();
+                }
+                self.ruler_points
+                    .push(self.viewport.screen_to_world(screen_center, pos));
+                interact_captured = true;
+            }
+            if self.editing_id.is_none() && ui.input(|i| self.keymap.pressed(i, Action::Cancel)) {
+                self.ruler_active = false;
+                self.ruler_points.clear();
+            }
+
+            let hover = mouse_pos
+                .filter(|p| canvas_rect.contains(*p))
+                .map(|p| self.viewport.screen_to_world(screen_center, p));
+            if let (Some(&start), Some(end)) = (
+                self.ruler_points.first(),
+                self.ruler_points.get(1).copied().or(hover),
+            ) {
+                self.paint_ruler(ui, start, end);
+            }
+        }
+
+        // --- Eyedropper ---
+        if self.eyedropper_active {
+            let response = ui.interact(
+                canvas_rect,
+                ui.id().with("eyedropper"),
+                egui::Sense::click(),
+            );
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+            }
+            if let Some(pos) = mouse_pos.filter(|p| canvas_rect.contains(*p)) {
+                self.request_eyedropper_still(pos, ui.ctx());
+            }
+            let sampled = mouse_pos
+                .filter(|p| canvas_rect.contains(*p))
+                .and_then(|p| Some((p, self.color_at(p)?)));
+            if let Some((pos, color)) = sampled {
+                // A swatch of the color under the pointer, with its hex code
+                let hex = color_hex(color);
+                let swatch = Rect::from_min_size(pos + Vec2::new(16.0, 16.0), Vec2::splat(32.0));
+                ui.painter().rect_filled(swatch, 4.0, color);
+                ui.painter()
+                    .rect_stroke(swatch, 4.0, Stroke::new(1.0, Color32::BLACK));
+                ui.painter().text(
+                    swatch.right_center() + Vec2::new(6.0, 0.0),
+                    Align2::LEFT_CENTER,
+                    &hex,
+                    egui::FontId::monospace(14.0),
+                    palette.block_text,
+                );
+                if response.clicked() {
+                    ui.ctx().copy_text(hex.clone());
+                    if ui.input(|i| i.modifiers.shift) {
+                        let world = self.viewport.screen_to_world(screen_center, pos);
+                        let swatch = BlockContent::Color { color, label: true };
+                        self.spawn_block(swatch, world.to_vec2(), Vec2::splat(SWATCH_SIZE));
+                    }
+                    self.notify(ui.ctx(), format!("Copied {hex}"));
+                }
+            }
+            if response.clicked() {
+                interact_captured = true;
+            }
+            if self.editing_id.is_none() && ui.input(|i| self.keymap.pressed(i, Action::Cancel)) {
+                self.eyedropper_active = false;
+            }
+        } else {
+            self.eyedropper_still = None;
+        }
+
+        // --- Crop ---
+        let crop_idx = self
+            .crop_target
+            .and_then(|id| self.blocks.iter().position(|b| b.id == id));
+        if let Some(idx) = crop_idx {
+            let response = ui.interact(
+                canvas_rect,
+                ui.id().with("crop"),
+                egui::Sense::click_and_drag(),
+            );
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+            }
+            let block = &self.blocks[idx];
+            if response.drag_started() {
+                if let Some(origin) = ui.input(|i| i.pointer.press_origin()) {
+                    let start = self.to_block_space(block, origin);
+                    self.crop_drag = Some((start, start));
+                }
+            }
+            let pointer = response
+                .interact_pointer_pos()
+                .map(|p| self.to_block_space(block, p));
+            if let (Some((_, end)), Some(pointer)) = (&mut self.crop_drag, pointer) {
+                *end = pointer;
+            }
+
+            // Outline the block, and the part of it that will be kept
+            let block_rect = block.rect;
+            let screen_block = self.block_screen_rect(block);
+            ui.painter()
+                .rect_stroke(screen_block, 5.0, Stroke::new(2.0, Color32::YELLOW));
+            if let Some((a, b)) = self.crop_drag {
+                let part = Rect::from_two_pos(a, b).intersect(block_rect);
+                let scale = screen_block.size() / block_rect.size();
+                let to_screen = |p: Pos2| screen_block.min + (p - block_rect.min) * scale;
+                let screen_part = Rect::from_min_max(to_screen(part.min), to_screen(part.max));
+                ui.painter().rect(
+                    screen_part,
+                    0.0,
+                    Color32::from_white_alpha(30),
+                    Stroke::new(1.5, Color32::WHITE),
+                );
+            }
+
+            if response.drag_stopped() {
+                if let Some((a, b)) = self.crop_drag.take() {
+                    self.crop_block(idx, Rect::from_two_pos(a, b));
+                }
+                self.crop_target = None;
+            } else if response.clicked()
+                || (self.editing_id.is_none()
+                    && ui.input(|i| self.keymap.pressed(i, Action::Cancel)))
+            {
+                self.crop_target = None;
+                self.crop_drag = None;
+            }
+            interact_captured |= response.clicked();
+        } else {
+            self.crop_target = None;
+        }
+
+        if !duplicate_requests.is_empty() {
+            self.duplicate_blocks(&duplicate_requests, ui.ctx());
+        }
+        for idx in uncrop_requests {
+            self.uncrop_block(idx);
+        }
+        for idx in extract_requests {
+            self.extract_frame(idx);
+        }
+        // Like F, the chain handle's double-click leaves a locked view alone
+        if let Some(idx) = fit_request.filter(|_| !self.view_locked) {
+            self.fit_block_to_view(idx);
+        }
+        if let Some((idx, height)) = grow_request {
+            let aspect_ratio = self.blocks[idx].kept_aspect_ratio();
+            let rect = &mut self.blocks[idx].rect;
+            rect.max.y = rect.min.y + height;
+            // A locked block widens along with it
+            if let Some(aspect) = aspect_ratio {
+                rect.max.x = rect.min.x + height * aspect;
+            }
+            self.push_neighbors(idx);
+        }
+        for id in reload_requests {
+            self.reload_image(id, ui.ctx());
+        }
+        if contact_sheet_requested {
+            self.make_contact_sheet(ui.ctx());
+        }
+        for idx in pin_requests {
+            self.toggle_pinned(idx);
+        }
+        for idx in hide_requests {
+            self.toggle_visible(idx);
+        }
+        for file in reveal_requests {
+            if let Err(e) = reveal_in_file_manager(&file) {
+                self.notify(ui.ctx(), format!("Could not open the file manager: {e}"));
+            }
+        }
+
+        if let Some(id) = slide_toggle {
+            self.toggle_slide(id);
+        }
+
+        if let Some(clicked_id) = clicked_block {
+            // Shift/Ctrl-click adds to or removes from the selection
+            let additive = ui.input(|i| i.modifiers.shift || i.modifiers.command);
+            for b in &mut self.blocks {
+                if b.id == clicked_id {
+                    b.selected = !(additive && b.selected);
+                } else if !additive {
+                    b.selected = false;
+                }
+            }
+        }
+
+        if let Some((idx, delta)) = pending_move {
+            if self.blocks[idx].chained {
+                self.last_chain_interaction = ui.input(|i| i.time);
+            }
+            let group = self.group_indices(idx);
+            // Placed from where the drag started, so Shift can straighten it at any time
+            let unsnapped_min = self.blocks[idx].rect.min - self.drag_snap;
+            let (start, travel) = self.drag_start.get_or_insert((unsnapped_min, Vec2::ZERO));
+            *travel += delta;
+            let travel = if ui.input(|i| i.modifiers.shift) {
+                constrain_to_axis(*travel)
+            } else {
+                *travel
+            };
+            let mut delta = *start + travel - unsnapped_min;
+            if self.settings.snap_to_guides && !self.blocks[idx].pinned {
+                let others: Vec<Rect> = (0..self.blocks.len())
+                    .filter(|i| !group.contains(i) && !self.blocks[*i].pinned)
+                    .map(|i| self.blocks[i].rect)
+                    .collect();
+                let unsnapped = self.blocks[idx].rect.translate(delta - self.drag_snap);
+                let snap = layout::snap_to_edges(unsnapped, &others, SNAP_DISTANCE / zoom);
+                delta += snap.offset - self.drag_snap;
+                self.drag_snap = snap.offset;
+                self.snap_guides = snap.guides;
+            }
+            for i in group {
+                self.blocks[i].rect = self.blocks[i].rect.translate(delta);
+            }
+            if self.settings.collision_mode == CollisionMode::Push && !free_placement {
+                self.push_neighbors(idx);
+            }
+        }
+        for [a, b] in &self.snap_guides {
+            ui.painter().line_segment(
+                [
+                    self.viewport.world_to_screen(screen_center, *a),
+                    self.viewport.world_to_screen(screen_center, *b),
+                ],
+                Stroke::new(1.0, Color32::from_rgb(255, 0, 255)),
+            );
+        }
+
+        if ui.input(|i| {
+            i.pointer.button_released(egui::PointerButton::Primary)
+                || i.pointer.button_released(egui::PointerButton::Secondary)
+        }) {
+            self.drag_snap = Vec2::ZERO;
+            self.drag_start = None;
+            self.snap_guides.clear();
+            let dropped = self.last_dragged_id.take().filter(|_| !free_placement);
+            if let Some(dragged_id) = dropped {
+                let idx = self
+                    .blocks
+                    .iter()
+                    .position(|b| b.id == dragged_id && !b.pinned);
+                if let Some(idx) = idx {
+                    self.settle_drop(idx);
+                }
+            }
+        }
+        // Process animation load requests
+        for (block_id,) in animation_load_requests {
+            self.start_animation_load(block_id, ui.ctx());
+        }
+
+        for (block_id, path, width) in svg_rerender_requests {
+            if self.svg_rerender_failed.contains(&block_id) {
+                continue;
+            }
+            if self.svg_rerender_pending.insert(block_id) {
+                self.load_svg_file(PathBuf::from(path), ui.ctx().clone(), Some(block_id), width);
+            }
+        }
+
+        // The delete key takes the whole selection, unless something is being typed
+        if self.editing_id.is_none()
+            && !presenting
+            && ui.memory(|m| m.focused().is_none())
+            && ui.input_mut(|i| self.keymap.consume(i, Action::Delete))
+        {
+            ids_to_delete.extend(self.blocks.iter().filter(|b| b.selected).map(|b| b.id));
+        }
+
+        let deletes_text = self.blocks.iter().any(|b| {
+            ids_to_delete.contains(&b.id) && matches!(b.content, BlockContent::Text { .. })
+        });
+        self.move_to_trash(&ids_to_delete);
+        if deletes_text {
+            self.clear_markdown_cache(ui.ctx());
+        }
+        // Indices change from here on
+        if let Some(id) = clicked_block {
+            self.raise_if_covered(id);
+        }
+        match pile_request {
+            Some(true) => self.stack_selection(),
+            Some(false) => self.spread_selection(),
+            None => {}
+        }
+
+        // Only clicks on the canvas itself, not on panels or menus over it
+        let on_canvas = mouse_pos.is_some_and(|p| canvas_rect.contains(p));
+        if ui.input(|i| i.pointer.any_click()) && !interact_captured && !secondary_down && on_canvas
+        {
+            self.editing_id = None;
+            for b in &mut self.blocks {
+                b.selected = false;
+            }
+        }
+
+        let time_now = ui.input(|i| i.time);
+        if self.blocks.iter().any(|b| b.chained) {
+            if time_now - self.last_chain_interaction > 10.0 {
+                for b in &mut self.blocks {
+                    b.chained = false;
+                }
+            } else {
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_secs_f64(
+                        (10.0 - (time_now - self.last_chain_interaction)).max(0.0),
+                    ));
+            }
+        }
+    }
+
+    /// Side panel with the properties of the selected block, when exactly one is selected
+    fn show_inspector(&mut self, ctx: &egui::Context) {
+        if self.presentation.is_some() {
+            return;
+        }
+        let mut selected = (0..self.blocks.len()).filter(|&i| self.blocks[i].selected);
+        let (Some(idx), None) = (selected.next(), selected.next()) else {
+            return;
+        };
+
+        let mut rect_changed = false;
+        let mut moved_to = self.blocks[idx].rect.min;
+        let mut chain_toggled = false;
+        egui::SidePanel::right("inspector")
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.heading("Block");
+                let lowest_count = if self.settings.negative_counters {
+                    i32::MIN
+                } else {
+                    0
+                };
+                let block = &mut self.blocks[idx];
+                egui::Grid::new("inspector_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Id");
+                        ui.add(
+                            egui::Label::new(RichText::new(block.id.to_string()).monospace())
+                                .truncate(),
+                        )
+                        .on_hover_text(block.id.to_string());
+                        ui.end_row();
+
+                        ui.label("Type");
+                        ui.label(match block.content {
+                            BlockContent::Text { .. } => "Text",
+                            BlockContent::Image { .. } => "Image",
+                            BlockContent::Link { .. } => "Link",
+                            BlockContent::Color { .. } => "Color",
+                        });
+                        ui.end_row();
+
+                        let mut min = block.rect.min;
+                        ui.label(if block.pinned {
+                            "Position (screen)"
+                        } else {
+                            "Position"
+                        });
+                        ui.horizontal(|ui| {
+                            rect_changed |= ui.add(geometry_value(&mut min.x, "x ")).changed();
+                            rect_changed |= ui.add(geometry_value(&mut min.y, "y ")).changed();
+                        });
+                        ui.end_row();
+
+                        let aspect_ratio = block.kept_aspect_ratio();
+                        let mut size = block.rect.size();
+                        ui.label("Size");
+                        ui.horizontal(|ui| {
+                            let range = MIN_BLOCK_SIZE..=f32::MAX;
+                            let width_changed = ui
+                                .add(geometry_value(&mut size.x, "w ").range(range.clone()))
+                                .changed();
+                            let height_changed = ui
+                                .add(geometry_value(&mut size.y, "h ").range(range))
+                                .changed();
+                            // Images and locked blocks keep their aspect ratio, as when resizing
+                            // with the mouse
+                            if let Some(aspect) = aspect_ratio {
+                                if width_changed {
+                                    size.y = size.x / aspect;
+                                } else if height_changed {
+                                    size.x = size.y * aspect;
+                                }
+                                if size.min_elem() < MIN_BLOCK_SIZE {
+                                    size *= MIN_BLOCK_SIZE / size.min_elem();
+                                }
+                            }
+                            rect_changed |= width_changed || height_changed;
+                        });
+                        ui.end_row();
+                        block.rect = Rect::from_min_size(block.rect.min, size);
+                        moved_to = min;
+
+                        if let BlockContent::Image { path, counter, .. } = &mut block.content {
+                            let path = path.as_deref().unwrap_or("-");
+                            ui.label("Path");
+                            ui.add(egui::Label::new(path).truncate())
+                                .on_hover_text(path);
+                            ui.end_row();
+
+                            ui.label("Counter");
+                            ui.add(egui::DragValue::new(counter).range(lowest_count..=i32::MAX));
+                            ui.end_row();
+                        }
+
+                        ui.label("Chained");
+                        chain_toggled = ui.checkbox(&mut block.chained, "").changed();
+                        ui.end_row();
+
+                        ui.label("Opacity");
+                        ui.add(egui::Slider::new(&mut block.opacity, 0..=255));
+                        ui.end_row();
+                    });
+            });
+
+        self.move_block_to(idx, moved_to);
+        if rect_changed && self.settings.collision_mode != CollisionMode::Overlap {
+            // The edited block keeps its exact place, neighbors make room
+            self.push_neighbors(idx);
+        }
+        if chain_toggled {
+            self.last_chain_interaction = ctx.input(|i| i.time);
+        }
+    }
+
+    /// Indices of the blocks front to back as they are drawn: pinned blocks first, since
+    /// they stay over all others
+    fn layer_order(&self) -> Vec<usize> {
+        let (pinned, unpinned): (Vec<usize>, Vec<usize>) = (0..self.blocks.len())
+            .rev()
+            .partition(|&i| self.blocks[i].pinned);
+        pinned.into_iter().chain(unpinned).collect()
+    }
+
+    /// Side panel listing the blocks front to back. Clicking one selects it and brings
+    /// it into view, dragging one onto another moves it to that place in the z-order,
+    /// and the eye next to it hides or shows it.
+    fn show_layers(&mut self, ctx: &egui::Context) {
+        if self.presentation.is_some() {
+            return;
+        }
+        let mut focus = None;
+        let mut reorder = None;
+        let mut toggle = None;
+        egui::SidePanel::left("layers")
+            .default_width(200.0)
+            .show(ctx, |ui| {
+                ui.heading("Layers");
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for idx in self.layer_order() {
+                        let block = &self.blocks[idx];
+                        let name = block.content.accessible_label();
+                        let mut label = if block.pinned {
+                            RichText::new(format!("📌 {name}"))
+                        } else {
+                            RichText::new(name)
+                        };
+                        if !block.visible {
+                            label = label.weak();
+                        }
+                        let row = ui
+                            .horizontal(|ui| {
+                                let (eye, hover) = if block.visible {
+                                    ("👁", "Hide")
+                                } else {
+                                    ("—", "Show")
+                                };
+                                let eye = ui.add(egui::Button::new(eye).frame(false));
+                                if eye.on_hover_text(hover).clicked() {
+                                    toggle = Some(idx);
+                                }
+                                ui.add(egui::SelectableLabel::new(block.selected, label))
+                                    .interact(egui::Sense::drag())
+                            })
+                            .inner;
+                        row.dnd_set_drag_payload(idx);
+                        if let Some(from) = row.dnd_hover_payload::<usize>() {
+                            // The dragged block takes this one's place, so it lands above
+                            // it when coming from behind and below it otherwise
+                            let y = if *from < idx {
+                                row.rect.top()
+                            } else {
+                                row.rect.bottom()
+                            };
+                            ui.painter().hline(
+                                row.rect.x_range(),
+                                y,
+                                ui.visuals().selection.stroke,
+                            );
+                        }
+                        if let Some(from) = row.dnd_release_payload::<usize>() {
+                            reorder = Some((*from, idx));
+                        }
+                        if row.clicked() && block.visible {
+                            focus = Some(idx);
+                        }
+                    }
+                });
+            });
+
+        if let Some((from, to)) = reorder {
+            self.move_block(from, to);
+        } else if let Some(idx) = focus {
+            self.focus_block(idx);
+        }
+        if let Some(idx) = toggle {
+            self.toggle_visible(idx);
+        }
+    }
+
+    /// Move the block at `from` to `to` in the z-order, shifting the ones in between
+    fn move_block(&mut self, from: usize, to: usize) {
+        let block = self.blocks.remove(from);
+        self.blocks.insert(to, block);
+    }
+
+    fn spawn_text_block(&mut self, _ctx: &egui::Context) {
+        let center_world = -self.viewport.pan;
+        let size = Vec2::new(200.0, 100.0);
+        let pos = self.find_free_rect(center_world, size);
+        self.blocks.push(Block {
+            id: self.id_generator.next_id(),
+            rect: Rect::from_min_size(pos.to_pos2(), size),
+            content: BlockContent::Text {
+                text: "Double click to edit...".to_string(),
+            },
+            chained: false,
+            selected: false,
+            opacity: u8::MAX,
+            pinned: false,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
+        });
+    }
+
+    /// Add a text block holding `text` near the world point `at`, or the middle of the
+    /// view, and start editing it
+    fn add_pasted_text(&mut self, text: String, at: Option<Pos2>) {
+        let size = Vec2::new(MARKDOWN_IMPORT_WIDTH, estimated_text_height(&text));
+        let start = at.map_or(-self.viewport.pan, |p| p.to_vec2());
+        let id = self.spawn_block(BlockContent::Text { text }, start, size);
+        self.editing_id = Some(id);
+        self.focus_request = Some(id);
+    }
+
+    /// Add a card for `url` and start fetching its preview
+    fn add_link(&mut self, url: String, ctx: &egui::Context) {
+        let id = self.spawn_link_block(url.clone());
+        self.fetch_link_preview(id, url, None, ctx);
+    }
+
+    /// Add a card for `url` in the first free spot near the middle of the view. Returns
+    /// its id.
+    fn spawn_link_block(&mut self, url: String) -> Uuid {
+        let content = BlockContent::Link {
+            url,
+            title: String::new(),
+            description: String::new(),
+            image_url: None,
+            thumbnail: None,
+        };
+        let size = Vec2::new(LINK_BLOCK_WIDTH, LINK_BLOCK_HEIGHT);
+        self.spawn_block(content, -self.viewport.pan, size)
+    }
+
+    /// Add a block of `content` and `size` in the first free spot near `start` (in world
+    /// coordinates). Returns its id.
+    fn spawn_block(&mut self, content: BlockContent, start: Vec2, size: Vec2) -> Uuid {
+        let pos = self.find_free_rect(start, size);
+        let id = self.id_generator.next_id();
+        self.blocks.push(Block {
+            id,
+            rect: Rect::from_min_size(pos.to_pos2(), size),
+            content,
+            chained: false,
+            selected: false,
+            opacity: u8::MAX,
+            pinned: false,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
+        });
+        id
+    }
+
+    /// Fetch the page at `url` in the background and send its preview to the link block
+    /// `id`, or with `known_image` just fetch that image again. Nothing is sent if the
+    /// page can't be fetched, so the card stays a plain link. At most
+    /// `LINK_FETCH_WORKERS` fetches run at once, however many links a session has.
+    fn fetch_link_preview(
+        &self,
+        id: Uuid,
+        url: String,
+        known_image: Option<String>,
+        ctx: &egui::Context,
+    ) {
+        static LINK_FETCHES: WorkQueue = WorkQueue::new(LINK_FETCH_WORKERS);
+        let tx = self.image_tx.clone();
+        let ctx = ctx.clone();
+        LINK_FETCHES.spawn(move || {
+            let preview = match known_image {
+                Some(image) => PagePreview {
+                    image: Some(image),
+                    ..PagePreview::default()
+                },
+                None => match fetch_page(&url) {
+                    Ok(html) => link_preview::parse(&html, &url),
+                    Err(e) => {
+                        eprintln!("Link preview error: {url}: {e}");
+                        return;
+                    }
+                },
+            };
+            let thumbnail = preview.image.as_deref().and_then(fetch_thumbnail);
+            let _ = tx.send(ImageLoadData::LinkPreview {
+                target_block_id: id,
+                url,
+                preview,
+                thumbnail,
+            });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Ask for a Markdown file to turn into text blocks, one per section
+    fn import_markdown(&self, ctx: &egui::Context) {
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let Some(path) = FileDialog::new()
+                .add_filter("Markdown", &["md", "markdown", "txt"])
+                .pick_file()
+            else {
+                return;
+            };
+            let text = std::fs::read_to_string(&path).map_err(|e| e.to_string());
+            let _ = tx.send(FileDialogResult::Markdown(text));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Add a text block for each section of a Markdown document (see
+    /// `markdown::split_sections`), in a column in the first free spot near the middle
+    /// of the view. Returns how many were added.
+    fn add_markdown_sections(&mut self, text: &str) -> usize {
+        let sections = markdown::split_sections(text);
+        let added = sections.len();
+        if added == 0 {
+            return 0;
+        }
+        let sizes: Vec<Vec2> = sections
+            .iter()
+            .map(|section| Vec2::new(MARKDOWN_IMPORT_WIDTH, estimated_text_height(section)))
+            .collect();
+        let height: f32 = sizes.iter().map(|size| size.y + IMPORT_GRID_GAP).sum();
+        let column = Vec2::new(MARKDOWN_IMPORT_WIDTH, height - IMPORT_GRID_GAP);
+        let mut pos = self.find_free_rect(-self.viewport.pan, column).to_pos2();
+
+        for (text, size) in sections.into_iter().zip(sizes) {
+            self.blocks.push(Block {
+                id: self.id_generator.next_id(),
+                rect: Rect::from_min_size(pos, size),
+                content: BlockContent::Text { text },
+                chained: false,
+                selected: false,
+                opacity: u8::MAX,
+                pinned: false,
+                created_at: now_millis(),
+                slide: None,
+                note: None,
+                lock_aspect: false,
+                visible: true,
+            });
+            pos.y += size.y + IMPORT_GRID_GAP;
+        }
+        added
+    }
+
+    fn spawn_image_block(&mut self, ctx: &egui::Context) {
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        let pdf_all_pages = self.settings.pdf_all_pages;
+        thread::spawn(move || {
+            if let Some(mut paths) = FileDialog::new()
+                .add_filter("Image", IMAGE_EXTENSIONS)
+                .pick_files()
+            {
+                if pdf_all_pages {
+                    paths = expand_pdf_pages(paths);
+                }
+                let _ = tx.send(FileDialogResult::Images(paths));
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    fn import_folder(&mut self, ctx: &egui::Context) {
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let Some(dir) = FileDialog::new().pick_folder() else {
+                return;
+            };
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("Folder read error: {e}");
+                    return;
+                }
+            };
+
+            let mut images = Vec::new();
+            let mut skipped = 0;
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if !path.is_file() {
+                    continue;
+                }
+                if is_supported_image(&path) {
+                    images.push(path);
+                } else {
+                    skipped += 1;
+                }
+            }
+            images.sort();
+
+            let _ = tx.send(FileDialogResult::Folder { images, skipped });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Window listing images the session refers to but that aren't on disk, with ways
+    /// to point at where they went
+    fn show_missing_images(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut ignore = false;
+        egui::Window::new("Missing Images")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} images in this session could not be found.",
+                    self.missing_images.len()
+                ));
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for (id, path) in &self.missing_images {
+                            ui.horizontal(|ui| {
+                                if ui.button("Locate…").clicked() {
+                                    self.pick_replacement_file(*id, path);
+                                }
+                                ui.add(egui::Label::new(path.as_str()).truncate());
+                            });
+                        }
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Search Folder…")
+                        .on_hover_text(
+                            "Look for files with the same names in a folder and its subfolders",
+                        )
+                        .clicked()
+                    {
+                        self.search_folder_for_missing(ctx);
+                    }
+                    if ui.button("Ignore").clicked() {
+                        ignore = true;
+                    }
+                });
+            });
+        if !open || ignore {
+            self.missing_images.clear();
+        }
+    }
+
+    /// Ask for a single file to stand in for the missing image of block `id`
+    fn pick_replacement_file(&self, id: Uuid, missing: &str) {
+        let tx = self.file_dialog_tx.clone();
+        let (file, _) = split_pdf_page(Path::new(missing));
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string());
+        thread::spawn(move || {
+            let mut dialog = FileDialog::new().set_title("Locate missing image");
+            if let Some(name) = name {
+                dialog = dialog.set_file_name(name);
+            }
+            if let Some(path) = dialog.pick_file() {
+                let _ = tx.send(FileDialogResult::Relink(vec![(id, path)]));
+            }
+        });
+    }
+
+    /// Ask for a folder and match every missing image by file name against the files
+    /// in it and its subfolders
+    fn search_folder_for_missing(&self, ctx: &egui::Context) {
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        let missing: Vec<(Uuid, PathBuf)> = self
+            .missing_images
+            .iter()
+            .map(|(id, path)| (*id, split_pdf_page(Path::new(path)).0))
+            .collect();
+        thread::spawn(move || {
+            let Some(dir) = FileDialog::new().pick_folder() else {
+                return;
+            };
+            let mut found = std::collections::HashMap::new();
+            let mut dirs = vec![dir];
+            while let Some(dir) = dirs.pop() {
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    match entry.file_type() {
+                        // Symlinked folders are skipped so loops can't trap the search
+                        Ok(t) if t.is_dir() => dirs.push(entry.path()),
+                        Ok(t) if t.is_file() => {
+                            // The first match wins, like a search in the file manager
+                            found
+                                .entry(entry.file_name())
+                                .or_insert_with(|| entry.path());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            let replacements = missing
+                .into_iter()
+                .filter_map(|(id, file)| {
+                    let name = file.file_name()?;
+                    Some((id, found.get(name)?.clone()))
+                })
+                .collect();
+            let _ = tx.send(FileDialogResult::Relink(replacements));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Point image block `id` at `file` instead of its missing one (keeping the page of a
+    /// PDF) and load it from there. Returns false if the block is gone.
+    fn relink_image(&mut self, id: Uuid, file: &Path, ctx: &egui::Context) -> bool {
+        let Some(block) = self.blocks.iter_mut().find(|b| b.id == id) else {
+            return false;
+        };
+        let BlockContent::Image { path, .. } = &mut block.content else {
+            return false;
+        };
+        let mut new_path = file.to_string_lossy().to_string();
+        if let Some((_, page)) = path.as_deref().and_then(|p| p.rsplit_once("#page=")) {
+            new_path = format!("{new_path}#page={page}");
+        }
+        *path = Some(new_path.clone());
+        self.missing_images
+            .retain(|(missing_id, _)| *missing_id != id);
+        self.load_image_file(PathBuf::from(new_path), ctx.clone(), Some(id));
+        true
+    }
+
+    /// Adjustments of the image block `id`, for frames decoded for it
+    fn image_adjust(&self, id: Option<Uuid>) -> ImageAdjust {
+        let block = id.and_then(|id| self.blocks.iter().find(|b| b.id == id));
+        match block.map(|b| &b.content) {
+            Some(BlockContent::Image { adjust, .. }) => *adjust,
+            _ => ImageAdjust::default(),
+        }
+    }
+
+    /// Decode the image block `id` again from its file, e.g. to bake in new adjustments.
+    /// The current frames stay on screen until the new ones arrive.
+    fn reload_image(&mut self, id: Uuid, ctx: &egui::Context) {
+        let path = self
+            .blocks
+            .iter()
+            .find(|b| b.id == id)
+            .and_then(|b| match &b.content {
+                BlockContent::Image { path, .. } => path.clone(),
+                BlockContent::Text { .. }
+                | BlockContent::Link { .. }
+                | BlockContent::Color { .. } => None,
+            });
+        if let Some(path) = path {
+            self.load_image_file(PathBuf::from(path), ctx.clone(), Some(id));
+        }
+    }
+
+    /// Combine the selected images into one contact sheet image, saved to a file the user
+    /// picks, that takes their place on the canvas
+    fn make_contact_sheet(&mut self, ctx: &egui::Context) {
+        let mut picked: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| {
+                let b = &self.blocks[i];
+                let on_disk = match &b.content {
+                    BlockContent::Image { path, .. } => {
+                        existing_source_file(path.as_deref()).is_some()
+                    }
+                    BlockContent::Text { .. }
+                    | BlockContent::Link { .. }
+                    | BlockContent::Color { .. } => false,
+                };
+                b.selected && !b.pinned && on_disk
+            })
+            .collect();
+        if picked.len() < 2 {
+            self.notify(ctx, "Select at least two images to make a contact sheet");
+            return;
+        }
+        self.sort_reading_order(&mut picked);
+
+        let mut sources = Vec::new();
+        for &i in &picked {
+            if let BlockContent::Image {
+                path: Some(path),
+                crop,
+                adjust,
+                ..
+            } = &self.blocks[i].content
+            {
+                sources.push((path.clone(), *crop, *adjust));
+            }
+        }
+        let replaces: Vec<Uuid> = picked.iter().map(|&i| self.blocks[i].id).collect();
+        let bounds = picked
+            .iter()
+            .map(|&i| self.blocks[i].rect)
+            .fold(Rect::NOTHING, |a, b| a.union(b));
+
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let Some(mut file) = FileDialog::new()
+                .add_filter("PNG", &["png"])
+                .set_file_name("contact-sheet.png")
+                .save_file()
+            else {
+                return;
+            };
+            if file.extension().is_none() {
+                file.set_extension("png");
+            }
+            let (file, rect) = match export::contact_sheet(&sources) {
+                Some(sheet) => {
+                    let aspect_ratio = sheet.width() as f32 / sheet.height() as f32;
+                    let written = sheet.save(&file).map(|_| file).map_err(|e| e.to_string());
+                    (written, fit_rect_to_aspect(bounds, aspect_ratio))
+                }
+                None => (Err("none of the images could be read".to_string()), bounds),
+            };
+            let _ = tx.send(FileDialogResult::ContactSheet {
+                file,
+                replaces,
+                rect,
+            });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Swap the blocks `replaces` for a selected image block showing `file`
+    fn place_contact_sheet(
+        &mut self,
+        file: PathBuf,
+        replaces: &[Uuid],
+        rect: Rect,
+        ctx: &egui::Context,
+    ) {
+        let before = self.blocks.len();
+        // Trashed like a delete, so the originals can still be restored
+        self.move_to_trash(&replaces.iter().copied().collect());
+        let combined = before - self.blocks.len();
+        for b in &mut self.blocks {
+            b.selected = false;
+        }
+
+        let id = self.id_generator.next_id();
+        self.blocks.push(Block {
+            id,
+            rect,
+            content: BlockContent::image_placeholder(file.to_string_lossy().to_string()),
+            chained: false,
+            selected: true,
+            opacity: u8::MAX,
+            pinned: false,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
+        });
+        self.push_neighbors(self.blocks.len() - 1);
+        self.load_image_file(file, ctx.clone(), Some(id));
+        self.notify(
+            ctx,
+            format!("Combined {combined} images into a contact sheet"),
+        );
+    }
+
+    fn notify(&mut self, ctx: &egui::Context, message: impl Into<String>) {
+        self.notification = Some((message.into(), ctx.input(|i| i.time)));
+    }
+
+    /// The image of block `id` could not be decoded. A placeholder still waiting to be
+    /// fitted to it never showed anything, so it is taken off the canvas again.
+    fn image_failed(&mut self, id: Uuid, path: &str, ctx: &egui::Context) {
+        if self.pending_fit.remove(&id) {
+            self.blocks.retain(|b| b.id != id);
+        }
+        let name = Path::new(path)
+            .file_name()
+            .map_or(path.into(), |n| n.to_string_lossy());
+        self.notify(ctx, format!("Could not load {name}"));
+    }
+
+    /// Create placeholder image blocks for `paths` in a grid of `columns` at the view
+    /// center and start decoding each image into its placeholder.
+    fn import_images_grid(&mut self, paths: Vec<PathBuf>, ctx: &egui::Context, columns: usize) {
+        if paths.is_empty() {
+            return;
+        }
+        let columns = columns.clamp(1, paths.len());
+        let rows = paths.len().div_ceil(columns);
+        let cell = Vec2::splat(IMAGE_BLOCK_WIDTH);
+        let pitch = cell + Vec2::splat(IMPORT_GRID_GAP);
+        let grid_size =
+            Vec2::new(columns as f32, rows as f32) * pitch - Vec2::splat(IMPORT_GRID_GAP);
+        let origin = self.find_free_rect(-self.viewport.pan, grid_size);
+
+        for (i, path) in paths.into_iter().enumerate() {
+            let offset = Vec2::new((i % columns) as f32, (i / columns) as f32) * pitch;
+            let id = self.id_generator.next_id();
+            self.blocks.push(Block {
+                id,
+                rect: Rect::from_min_size((origin + offset).to_pos2(), cell),
+                content: BlockContent::image_placeholder(path.to_string_lossy().to_string()),
+                chained: false,
+                selected: false,
+                opacity: u8::MAX,
+                pinned: false,
+                created_at: now_millis(),
+                slide: None,
+                note: None,
+                lock_aspect: false,
+                visible: true,
+            });
+            self.pending_fit.insert(id);
+            self.load_image_file(path, ctx.clone(), Some(id));
+        }
+    }
+
+    /// Rasterize an SVG at `width` pixels on a background thread. With a target block
+    /// the new texture replaces its frames, otherwise a new block is created.
+    fn load_svg_file(
+        &self,
+        path: PathBuf,
+        ctx: egui::Context,
+        target_block_id: Option<Uuid>,
+        width: u32,
+    ) {
+        let tx = self.image_tx.clone();
+        thread::spawn(move || {
+            // Re-renders replace a raster that is already on screen
+            if width == SVG_INITIAL_RASTER_WIDTH {
+                send_cached_thumbnail(&tx, &ctx, &path, target_block_id);
+            }
+
+            let raster = match std::fs::read(&path) {
+                Ok(data) => image_decoder::rasterize_svg(&data, width),
+                Err(e) => {
+                    eprintln!("SVG open error: {e}");
+                    None
+                }
+            };
+            if let Some(raster) = raster {
+                let size = [raster.width as usize, raster.height as usize];
+                let image = egui::ColorImage::from_rgba_premultiplied(size, &raster.pixels);
+                thumbnail_cache::store(&path, &image);
+                let _ = tx.send(ImageLoadData::Complete {
+                    frames: vec![image],
+                    frame_delays: vec![0.0],
+                    aspect_ratio: raster.aspect_ratio,
+                    path: Some(path.to_string_lossy().to_string()),
+                    target_block_id,
+                });
+                ctx.request_repaint();
+            } else if let Some(target_block_id) = target_block_id {
+                // Without its first raster the block has no image at all
+                let _ = tx.send(if width == SVG_INITIAL_RASTER_WIDTH {
+                    ImageLoadData::Failed {
+                        target_block_id,
+                        path: path.to_string_lossy().to_string(),
+                    }
+                } else {
+                    ImageLoadData::SvgFailed { target_block_id }
+                });
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Render a PDF page on a background thread. `path` may carry a `#page=N`
+    /// suffix, otherwise the first page is used.
+    fn load_pdf_file(&self, path: PathBuf, ctx: egui::Context, target_block_id: Option<Uuid>) {
+        let tx = self.image_tx.clone();
+        thread::spawn(move || {
+            send_cached_thumbnail(&tx, &ctx, &path, target_block_id);
+
+            let (file, page) = split_pdf_page(&path);
+            let raster = image_decoder::render_pdf_page(&file, page, PDF_RASTER_WIDTH)
+                .filter(|r| r.width > 0 && r.height > 0);
+            if let Some(raster) = raster {
+                let size = [raster.width as usize, raster.height as usize];
+                let page_image = egui::ColorImage::from_rgba_unmultiplied(size, &raster.pixels);
+                thumbnail_cache::store(&path, &page_image);
+                let _ = tx.send(ImageLoadData::Complete {
+                    frames: vec![page_image],
+                    frame_delays: vec![0.0],
+                    aspect_ratio: raster.width as f32 / raster.height as f32,
+                    path: Some(path.to_string_lossy().to_string()),
+                    target_block_id,
+                });
+                ctx.request_repaint();
+            } else {
+                eprintln!("PDF render error: {}", path.display());
+                if let Some(target_block_id) = target_block_id {
+                    let _ = tx.send(ImageLoadData::Failed {
+                        target_block_id,
+                        path: path.to_string_lossy().to_string(),
+                    });
+                    ctx.request_repaint();
+                }
+            }
+        });
+    }
+
+    fn load_image_file(&self, path: PathBuf, ctx: egui::Context, target_block_id: Option<Uuid>) {
+        if is_svg(&path) {
+            self.load_svg_file(path, ctx, target_block_id, SVG_INITIAL_RASTER_WIDTH);
+            return;
+        }
+        if is_pdf(&path) {
+            self.load_pdf_file(path, ctx, target_block_id);
+            return;
+        }
+
+        let tx = self.image_tx.clone();
+        let path_str = path.to_string_lossy().to_string();
+
+        thread::spawn(move || {
+            send_cached_thumbnail(&tx, &ctx, &path, target_block_id);
+
+            let is_gif = path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "gif");
+            let is_avif = path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "avif");
+            let is_webp = path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "webp");
+            let is_png = path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "png");
+            let mut frames_data = vec![];
+            let mut delays = vec![];
+            let mut aspect = 1.0;
+
+            if is_gif {
+                match File::open(&path) {
+                    Ok(mut file) => {
+                        let mut buffer = Vec::new();
+                        if file.read_to_end(&mut buffer).is_ok() {
+                            if let Some(preview) = image_decoder::decode_gif_first_frame(&buffer) {
+                                if preview.total_frame_count > 1 {
+                                    // Animated GIF - send preview only
+                                    let first_frame = egui::ColorImage::from_rgba_unmultiplied(
+                                        [preview.frame.width as usize, preview.frame.height as usize],
+                                        &preview.frame.pixels,
+                                    );
+                                    thumbnail_cache::store(&path, &first_frame);
+                                    let _ = tx.send(ImageLoadData::AnimatedPreview {
+                                        format: image_decoder::ImageFormat::Gif,
+                                        first_frame,
+                                        frame_durations: preview.frame_durations,
+                                        aspect_ratio: preview.aspect_ratio,
+                                        total_frame_count: preview.total_frame_count,
+                                        path: Some(path_str),
+                                        target_block_id,
+                                    });
+                                    ctx.request_repaint();
+                                    return;
+                                } else {
+                                    // Static GIF - just one frame
+                                    let size = [preview.frame.width as usize, preview.frame.height as usize];
+                                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
+                                        size,
+                                        &preview.frame.pixels,
+                                    ));
+                                    delays.push(preview.frame.duration);
+                                    aspect = preview.aspect_ratio;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("GIF open error: {e}"),
+                }
+            } else if is_avif {
+                match File::open(&path) {
+                    Ok(mut file) => {
+                        let mut buffer = Vec::new();
+                        if file.read_to_end(&mut buffer).is_ok() {
+                            // For animated AVIFs (>1 frame), use lazy loading
+                            // Load only first frame immediately, rest on demand
+                            if let Some(preview) = image_decoder::decode_avif_first_frame(&buffer) {
+                                if preview.total_frame_count > 1 {
+                                    // Animated AVIF - send preview only
+                                    let first_frame = egui::ColorImage::from_rgba_unmultiplied(
+                                        [
+                                            preview.frame.width as usize,
+                                            preview.frame.height as usize,
+                                        ],
+                                        &preview.frame.pixels,
+                                    );
+                                    thumbnail_cache::store(&path, &first_frame);
+                                    let _ = tx.send(ImageLoadData::AnimatedPreview {
+                                        format: image_decoder::ImageFormat::Avif,
+                                        first_frame,
+                                        frame_durations: preview.frame_durations,
+                                        aspect_ratio: preview.aspect_ratio,
+                                        total_frame_count: preview.total_frame_count,
+                                        path: Some(path_str),
+                                        target_block_id,
+                                    });
+                                    ctx.request_repaint();
+                                    return;
+                                } else {
+                                    // Static AVIF - just one frame
+                                    let size = [
+                                        preview.frame.width as usize,
+                                        preview.frame.height as usize,
+                                    ];
+                                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
+                                        size,
+                                        &preview.frame.pixels,
+                                    ));
+                                    delays.push(preview.frame.duration);
+                                    aspect = preview.aspect_ratio;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("AVIF open error: {e}"),
+                }
+            } else if is_webp {
+                match File::open(&path) {
+                    Ok(mut file) => {
+                        let mut buffer = Vec::new();
+                        if file.read_to_end(&mut buffer).is_ok() {
+                            if let Some(preview) = image_decoder::decode_webp_first_frame(&buffer) {
+                                if preview.total_frame_count > 1 {
+                                    // Animated WebP - send preview only
+                                    let first_frame = egui::ColorImage::from_rgba_unmultiplied(
+                                        [
+                                            preview.frame.width as usize,
+                                            preview.frame.height as usize,
+                                        ],
+                                        &preview.frame.pixels,
+                                    );
+                                    thumbnail_cache::store(&path, &first_frame);
+                                    let _ = tx.send(ImageLoadData::AnimatedPreview {
+                                        format: image_decoder::ImageFormat::Webp,
+                                        first_frame,
+                                        frame_durations: preview.frame_durations,
+                                        aspect_ratio: preview.aspect_ratio,
+                                        total_frame_count: preview.total_frame_count,
+                                        path: Some(path_str),
+                                        target_block_id,
+                                    });
+                                    ctx.request_repaint();
+                                    return;
+                                } else {
+                                    // Static WebP - just one frame
+                                    let size = [
+                                        preview.frame.width as usize,
+                                        preview.frame.height as usize,
+                                    ];
+                                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
+                                        size,
+                                        &preview.frame.pixels,
+                                    ));
+                                    delays.push(preview.frame.duration);
+                                    aspect = preview.aspect_ratio;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("WebP open error: {e}"),
+                }
+            } else if is_png {
+                // Only animated PNGs are handled here, regular ones fall through
+                // to the `image` crate below
+                if let Ok(buffer) = std::fs::read(&path) {
+                    if let Some(preview) = image_decoder::decode_apng_first_frame(&buffer) {
+                        if preview.total_frame_count > 1 {
+                            let first_frame = egui::ColorImage::from_rgba_unmultiplied(
+                                [preview.frame.width as usize, preview.frame.height as usize],
+                                &preview.frame.pixels,
+                            );
+                            thumbnail_cache::store(&path, &first_frame);
+                            let _ = tx.send(ImageLoadData::AnimatedPreview {
+                                format: image_decoder::ImageFormat::Apng,
+                                first_frame,
+                                frame_durations: preview.frame_durations,
+                                aspect_ratio: preview.aspect_ratio,
+                                total_frame_count: preview.total_frame_count,
+                                path: Some(path_str),
+                                target_block_id,
+                            });
+                            ctx.request_repaint();
+                            return;
+                        } else {
+                            // Animation with a single frame
+                            let size =
+                                [preview.frame.width as usize, preview.frame.height as usize];
+                            frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
+                                size,
+                                &preview.frame.pixels,
+                            ));
+                            delays.push(preview.frame.duration);
+                            aspect = preview.aspect_ratio;
+                        }
+                    }
+                }
+            }
+
+            // Everything else, plus WebP files the animation decoder rejected,
+            // goes through the `image` crate as a single still frame
+            if frames_data.is_empty() && (is_webp || (!is_gif && !is_avif)) {
+                if let Ok(img) = image::open(&path) {
+                    let buffer = img.to_rgba8();
+                    let size = [buffer.width() as usize, buffer.height() as usize];
+                    if size[0] > 0 && size[1] > 0 {
+                        aspect = size[0] as f32 / size[1] as f32;
+                        frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
+                            size,
+                            buffer.as_raw(),
+                        ));
+                        delays.push(0.0);
+                    }
+                }
+            }
+
+            if let Some(first_frame) = frames_data.first() {
+                thumbnail_cache::store(&path, first_frame);
+                let _ = tx.send(ImageLoadData::Complete {
+                    frames: frames_data,
+                    frame_delays: delays,
+                    aspect_ratio: aspect,
+                    path: Some(path_str),
+                    target_block_id,
+                });
+                ctx.request_repaint();
+            } else if let Some(target_block_id) = target_block_id {
+                let _ = tx.send(ImageLoadData::Failed {
+                    target_block_id,
+                    path: path_str,
+                });
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Copy the blocks with the given ids next to the originals, on top of everything.
+    /// The copies end up as the selection.
+    fn duplicate_blocks(&mut self, ids: &[Uuid], ctx: &egui::Context) {
+        let originals: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| ids.contains(&self.blocks[i].id))
+            .collect();
+        if originals.is_empty() {
+            return;
+        }
+        for b in &mut self.blocks {
+            b.selected = false;
+        }
+
+        for i in originals {
+            let original = &self.blocks[i];
+            let size = original.rect.size();
+            let start = original.rect.min.to_vec2() + Vec2::splat(DUPLICATE_OFFSET);
+            let content = original.content.duplicate();
+            let (opacity, pinned) = (original.opacity, original.pinned);
+            let (note, lock_aspect) = (original.note.clone(), original.lock_aspect);
+            let still_decoding = self.pending_fit.contains(&original.id);
+            // Pinned copies stay on the screen, where nothing collides
+            let pos = if pinned {
+                start
+            } else {
+                self.find_free_rect(start, size)
+            };
+            let id = self.id_generator.next_id();
+
+            // Only a block whose image hasn't arrived yet has to be loaded again
+            if let BlockContent::Image {
+                frames,
+                path: Some(path),
+                ..
+            } = &content
+            {
+                if frames.is_empty() {
+                    self.load_image_file(PathBuf::from(path), ctx.clone(), Some(id));
+                }
+            }
+            if still_decoding {
+                self.pending_fit.insert(id);
+            }
+
+            self.blocks.push(Block {
+                id,
+                rect: Rect::from_min_size(pos.to_pos2(), size),
+                content,
+                chained: false,
+                selected: true,
+                opacity,
+                pinned,
+                created_at: now_millis(),
+                slide: None,
+                note,
+                lock_aspect,
+                visible: true,
+            });
+        }
+    }
+
+    /// Add the frame an animation is paused on as a new still image block next to it,
+    /// sharing its texture. The new block has no file, so it isn't saved with the session.
+    fn extract_frame(&mut self, idx: usize) {
+        let original = &self.blocks[idx];
+        let BlockContent::Image {
+            frames,
+            current_frame_idx,
+            aspect_ratio,
+            crop,
+            adjust,
+            ..
+        } = &original.content
+        else {
+            return;
+        };
+        let Some(frame) = frames.get(*current_frame_idx) else {
+            return;
+        };
+        let mut content = BlockContent::image_placeholder(String::new());
+        if let BlockContent::Image {
+            frames,
+            frame_delays,
+            aspect_ratio: still_aspect,
+            path,
+            crop: still_crop,
+            adjust: still_adjust,
+            ..
+        } = &mut content
+        {
+            *frames = vec![frame.clone()];
+            *frame_delays = vec![0.0];
+            *still_aspect = *aspect_ratio;
+            *path = None;
+            *still_crop = *crop;
+            *still_adjust = *adjust;
+        }
+        let size = original.rect.size();
+        let start = original.rect.min.to_vec2() + Vec2::splat(DUPLICATE_OFFSET);
+        let (opacity, pinned) = (original.opacity, original.pinned);
+        let pos = if pinned {
+            start
+        } else {
+            self.find_free_rect(start, size)
+        };
+
+        for b in &mut self.blocks {
+            b.selected = false;
+        }
+        self.blocks.push(Block {
+            id: self.id_generator.next_id(),
+            rect: Rect::from_min_size(pos.to_pos2(), size),
+            content,
+            chained: false,
+            selected: true,
+            opacity,
+            pinned,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
+        });
+    }
+
+    /// Bring the block with `id` to the front if a block drawn over it overlaps it. The
+    /// z-order is saved, so it is left alone for blocks that aren't covered.
+    fn raise_if_covered(&mut self, id: Uuid) {
+        let Some(idx) = self.blocks.iter().position(|b| b.id == id) else {
+            return;
+        };
+        let (rect, pinned) = (self.blocks[idx].rect, self.blocks[idx].pinned);
+        let covered = self.blocks[idx + 1..]
+            .iter()
+            .any(|b| b.pinned == pinned && b.rect.intersects(rect));
+        if covered {
+            let block = self.blocks.remove(idx);
+            self.blocks.push(block);
+        }
+    }
+
+    /// Indices of the selected blocks in the world (not pinned), back to front
+    fn selected_world_blocks(&self) -> Vec<usize> {
+        (0..self.blocks.len())
+            .filter(|&i| self.blocks[i].selected && !self.blocks[i].pinned)
+            .collect()
+    }
+
+    /// Pile the selected blocks up like a fanned hand of cards where the backmost one
+    /// is, each `STACK_OFFSET` down and right of the one below, keeping their z-order
+    /// but on top of everything else. A click brings any of them to the front.
+    fn stack_selection(&mut self) {
+        let pile = self.selected_world_blocks();
+        if pile.len() < 2 {
+            return;
+        }
+        let anchor = self.blocks[pile[0]].rect.min;
+        let mut blocks = std::mem::take(&mut self.blocks);
+        let mut stacked = Vec::with_capacity(pile.len());
+        for (k, &i) in pile.iter().enumerate().rev() {
+            let mut block = blocks.remove(i);
+            let pos = anchor + Vec2::splat(STACK_OFFSET) * k as f32;
+            block.rect = block.rect.translate(pos - block.rect.min);
+            stacked.push(block);
+        }
+        stacked.reverse();
+        blocks.extend(stacked);
+        self.blocks = blocks;
+    }
+
+    /// Deal a pile of selected blocks back out into a roughly square grid from the
+    /// top-left of their bounds, pushing other blocks out of the way
+    fn spread_selection(&mut self) {
+        let selected = self.selected_world_blocks();
+        if selected.len() < 2 {
+            return;
+        }
+        let rects: Vec<Rect> = selected.iter().map(|&i| self.blocks[i].rect).collect();
+        let bounds = rects.iter().fold(Rect::NOTHING, |a, b| a.union(*b));
+        let sizes: Vec<Vec2> = rects.iter().map(Rect::size).collect();
+        let widest = sizes.iter().map(|s| s.x).fold(0.0, f32::max);
+        let columns = (selected.len() as f32).sqrt().ceil();
+        let width = columns * (widest + IMPORT_GRID_GAP) - IMPORT_GRID_GAP;
+        let positions = layout::arrange_in_rows(&sizes, bounds.min, width, IMPORT_GRID_GAP);
+        for ((&i, rect), pos) in selected.iter().zip(rects).zip(positions) {
+            self.blocks[i].rect = rect.translate(pos - rect.min);
+        }
+
+        let world: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned)
+            .collect();
+        let fixed: Vec<usize> = (0..world.len())
+            .filter(|&w| self.blocks[world[w]].selected)
+            .collect();
+        let mut rects: Vec<Rect> = world.iter().map(|&i| self.blocks[i].rect).collect();
+        for j in layout::push_neighbors(&mut rects, &fixed) {
+            self.blocks[world[j]].rect = rects[j];
+        }
+    }
+
+    /// Lay the blocks out in rows across the view in the order they were created, oldest
+    /// or newest first. Pinned blocks stay where they are.
+    fn arrange_by_age(&mut self, newest_first: bool) {
+        let mut order: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned)
+            .collect();
+        // Stable, so blocks of the same age keep their z-order
+        order.sort_by_key(|&i| self.blocks[i].created_at);
+        if newest_first {
+            order.reverse();
+        }
+        let units: Vec<Vec<usize>> = order.into_iter().map(|i| vec![i]).collect();
+        self.lay_out_in_view(&units, false);
+    }
+
+    /// Pack every block (but pinned ones) at the view with even gaps, in rows or
+    /// masonry columns, keeping their reading order. With `tidy_keep_groups` a chained
+    /// group is packed as one piece that keeps its own layout.
+    fn tidy_up(&mut self) {
+        let mut order: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned)
+            .collect();
+        self.sort_reading_order(&mut order);
+        let mut units: Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            if units.iter().any(|unit| unit.contains(&i)) {
+                continue;
+            }
+            if self.settings.tidy_keep_groups {
+                units.push(self.group_indices(i));
+            } else {
+                units.push(vec![i]);
+            }
+        }
+        self.lay_out_in_view(&units, self.settings.tidy_masonry);
+    }
+
+    /// Move each unit of block indices as a whole so the units are laid out in rows (or
+    /// masonry columns) across the view, in order. Positions are set directly, so
+    /// nothing gets pushed around on the way.
+    fn lay_out_in_view(&mut self, units: &[Vec<usize>], masonry: bool) {
+        let view = self.canvas_rect;
+        let origin =
+            self.viewport.screen_to_world(view.center(), view.min) + Vec2::splat(IMPORT_GRID_GAP);
+        let width = view.width() / self.viewport.zoom - 2.0 * IMPORT_GRID_GAP;
+        let bounds: Vec<Rect> = units
+            .iter()
+            .map(|unit| {
+                unit.iter()
+                    .map(|&i| self.blocks[i].rect)
+                    .fold(Rect::NOTHING, Rect::union)
+            })
+            .collect();
+        let sizes: Vec<Vec2> = bounds.iter().map(Rect::size).collect();
+        let positions = if masonry {
+            layout::masonry(&sizes, origin, width, IMPORT_GRID_GAP)
+        } else {
+            layout::arrange_in_rows(&sizes, origin, width, IMPORT_GRID_GAP)
+        };
+        for ((unit, bounds), pos) in units.iter().zip(bounds).zip(positions) {
+            for &i in unit {
+                self.blocks[i].rect = self.blocks[i].rect.translate(pos - bounds.min);
+            }
+        }
+    }
+
+    /// Sort block indices by position: top to bottom, then left to right
+    fn sort_reading_order(&self, indices: &mut [usize]) {
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (self.blocks[a].rect.min, self.blocks[b].rect.min);
+            a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+        });
+    }
+
+    /// Select the next block in reading order (top to bottom, then left to right), or
+    /// the previous one, and center the view on it unless it is locked. Pinned blocks
+    /// are skipped since they are always in view, hidden ones since they can't be.
+    fn select_next_block(&mut self, backwards: bool) {
+        let mut order: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| !self.blocks[i].pinned && self.blocks[i].visible)
+            .collect();
+        if order.is_empty() {
+            return;
+        }
+        self.sort_reading_order(&mut order);
+
+        // With several blocks selected, carry on from the last of them
+        let current = order.iter().rposition(|&i| self.blocks[i].selected);
+        let next = match (current, backwards) {
+            (Some(pos), false) => (pos + 1) % order.len(),
+            (Some(pos), true) => (pos + order.len() - 1) % order.len(),
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+        };
+        self.focus_block(order[next]);
+    }
+
+    /// Select every block that isn't hidden
+    fn select_all(&mut self) {
+        for b in &mut self.blocks {
+            b.selected = b.visible;
+        }
+    }
+
+    /// Select the blocks that aren't selected and deselect the ones that are. Hidden
+    /// blocks stay out of it.
+    fn invert_selection(&mut self) {
+        for b in &mut self.blocks {
+            b.selected = b.visible && !b.selected;
+        }
+    }
+
+    /// Select only the block at `idx`, ring it and center the view on it. A hidden block
+    /// is left alone, as selecting it would let Delete remove something unseen.
+    fn focus_block(&mut self, idx: usize) {
+        if !self.blocks[idx].visible {
+            return;
+        }
+        for (i, b) in self.blocks.iter_mut().enumerate() {
+            b.selected = i == idx;
+        }
+        self.keyboard_focus = Some(self.blocks[idx].id);
+        if !self.view_locked && !self.blocks[idx].pinned {
+            self.viewport.pan = -self.blocks[idx].rect.center().to_vec2();
+        }
+    }
+
+    /// Image blocks whose file name contains `query` (ignoring case), in reading order.
+    /// Pinned blocks are left out as they are always in view, hidden ones as they can't be.
+    fn image_matches(&self, query: &str) -> Vec<usize> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut matches: Vec<usize> = (0..self.blocks.len())
+            .filter(|&i| match &self.blocks[i].content {
+                BlockContent::Image {
+                    path: Some(path), ..
+                } => {
+                    !self.blocks[i].pinned
+                        && self.blocks[i].visible
+                        && Path::new(path)
+                            .file_name()
+                            .is_some_and(|n| n.to_string_lossy().to_lowercase().contains(&query))
+                }
+                _ => false,
+            })
+            .collect();
+        self.sort_reading_order(&mut matches);
+        matches
+    }
+
+    /// Select and center the first image matching `filename_query`, or with `next` the
+    /// one after the currently selected match, wrapping around
+    fn find_image(&mut self, next: bool) {
+        let matches = self.image_matches(&self.filename_query);
+        if matches.is_empty() {
+            return;
+        }
+        let current = matches.iter().rposition(|&i| self.blocks[i].selected);
+        let pos = match (current, next) {
+            (Some(pos), true) => (pos + 1) % matches.len(),
+            _ => 0,
+        };
+        self.focus_block(matches[pos]);
+    }
+
+    /// Blocks in the order present mode shows them: the slide path, or without one the
+    /// board's reading order with pinned blocks left out. Hidden blocks are skipped either
+    /// way.
+    fn slide_order(&self) -> Vec<usize> {
+        let shown = |i: &usize| self.blocks[*i].visible && !self.blocks[*i].pinned;
+        let mut order: Vec<usize> = (0..self.blocks.len())
+            .filter(|i| self.blocks[*i].slide.is_some() && shown(i))
+            .collect();
+        if order.is_empty() {
+            order = (0..self.blocks.len()).filter(shown).collect();
+            self.sort_reading_order(&mut order);
+        } else {
+            order.sort_by_key(|&i| self.blocks[i].slide);
+        }
+        order
+    }
+
+    /// Put the block `id` at the end of the slide path, or take it off and close the gap
+    fn toggle_slide(&mut self, id: Uuid) {
+        let Some(idx) = self.blocks.iter().position(|b| b.id == id && !b.pinned) else {
+            return;
+        };
+        if let Some(removed) = self.blocks[idx].slide.take() {
+            for b in &mut self.blocks {
+                if let Some(slide) = b.slide.as_mut().filter(|s| **s > removed) {
+                    *slide -= 1;
+                }
+            }
+        } else {
+            self.blocks[idx].slide = Some(self.slide_path_len());
+        }
+    }
+
+    /// Slot after the last block on the slide path
+    fn slide_path_len(&self) -> u32 {
+        self.blocks
+            .iter()
+            .filter_map(|b| b.slide)
+            .max()
+            .map_or(0, |last| last + 1)
+    }
+
+    /// Index of the block shown while presenting
+    fn current_slide(&self) -> Option<usize> {
+        let slide = self.presentation?;
+        self.slide_order().get(slide).copied()
+    }
+
+    /// Enter present mode full screen on the first slide, or leave it
+    fn toggle_presentation(&mut self, ctx: &egui::Context) {
+        if self.presentation.is_some() {
+            self.presentation = None;
+        } else if !self.slide_order().is_empty() {
+            self.presentation = Some(0);
+            self.editing_id = None;
+            self.ruler_active = false;
+            self.eyedropper_active = false;
+            self.crop_target = None;
+            self.show_help = false;
+        } else {
+            self.notify(ctx, "Add some blocks to present first");
+            return;
+        }
+        let fullscreen = self.presentation.is_some();
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(fullscreen));
+    }
+
+    /// Show the next or previous slide, stopping at either end
+    fn step_slide(&mut self, forward: bool) {
+        let last = self.slide_order().len().saturating_sub(1);
+        if let Some(slide) = &mut self.presentation {
+            *slide = if forward {
+                (*slide + 1).min(last)
+            } else {
+                slide.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Zoom and pan so the block fills most of the canvas. Pinned blocks are always in
+    /// view already.
+    fn fit_block_to_view(&mut self, idx: usize) {
+        let block = &self.blocks[idx];
+        if !block.pinned {
+            self.viewport
+                .fit(block.rect, self.canvas_rect.size(), FIT_BLOCK_FILL);
+            self.pan_coast = None;
+        }
+    }
+
+    /// Grow or shrink the selected blocks by `delta` (world units) from their top-left
+    /// corner. Images keep their aspect ratio, following whichever side the keys change.
+    fn resize_selected_by(&mut self, delta: Vec2) {
+        for idx in 0..self.blocks.len() {
+            if !self.blocks[idx].selected {
+                continue;
+            }
+            let block = &mut self.blocks[idx];
+            let aspect_ratio = block.kept_aspect_ratio();
+            let delta = match aspect_ratio {
+                Some(aspect) if delta.x == 0.0 => Vec2::new(delta.y * aspect, 0.0),
+                _ => delta,
+            };
+            block.rect = resized_rect(block.rect, ResizeHandle::BottomRight, delta, aspect_ratio);
+            self.push_neighbors(idx);
+        }
+    }
+
+    /// Top-left corner near `start_pos` where a block of `size` overlaps no other block
+    fn find_free_rect(&self, start_pos: Vec2, size: Vec2) -> Vec2 {
+        let occupied: Vec<Rect> = self
+            .blocks
+            .iter()
+            .filter(|b| !b.pinned)
+            .map(|b| b.rect)
+            .collect();
+        layout::find_free_rect(&occupied, start_pos, size)
+    }
+
+    /// Decodes the remaining frames of a lazily loaded animation in the background.
+    /// Does nothing unless the block is still waiting for them.
+    fn start_animation_load(&mut self, block_id: Uuid, ctx: &egui::Context) {
+        if let Some(block) = self.blocks.iter_mut().find(|b| b.id == block_id) {
+            if let BlockContent::Image {
+                animation_state, ..
+            } = &mut block.content
+            {
+                let not_loaded = std::mem::replace(animation_state, AnimationState::Ready);
+                if let AnimationState::NotLoaded {
                     path,
-                    target_block_id,
-                } => {
-                     let texture = ctx.load_texture(
-                         format!("webp-preview-{}", Uuid::new_v4()),
-                         first_frame,
-                         egui::TextureOptions::default(),
-                     );
-
-                     let animation_state = if total_frame_count > 1 {
-                         AnimationState::NotLoaded {
-                             path: path.clone().unwrap(),
-                             format: image_decoder::ImageFormat::Webp,
-                             total_frame_count,
-                             frame_durations: frame_durations.clone(),
-                         }
-                     } else {
-                         AnimationState::Ready
-                     };
-
-                     if let Some(target_id) = target_block_id {
-                         if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_id) {
-                             if let BlockContent::Image {
-                                 frames,
-                                 frame_delays,
-                                 aspect_ratio: ar,
-                                 animation_state: anim_state,
-                                 ..
-                             } = &mut block.content
-                             {
-                                 *frames = vec![texture];
-                                 *frame_delays = frame_durations;
-                                 *ar = aspect_ratio;
-                                 *anim_state = animation_state;
-                             }
-                         }
-                     } else {
-                         let id = Uuid::new_v4();
-                         let width = 300.0;
-                         let height = width / aspect_ratio;
-                         let size = Vec2::new(width, height);
-                         let center_world = -self.viewport.pan;
-                         let pos = self.find_free_rect(center_world, size);
-
-                         self.blocks.push(Block {
-                             id,
-                             rect: Rect::from_min_size(pos.to_pos2(), size),
-                             content: BlockContent::Image {
-                                 frames: vec![texture],
-                                 frame_delays: frame_durations,
-                                 aspect_ratio,
-                                 playing: false,
-                                 current_frame_idx: 0,
-                                 last_frame_time: 0.0,
-                                 counter: 0,
-                                 path,
-                                 animation_state,
-                             },
-                             chained: false,
-                             selected: false,
-                         });
-                     }
-                 }
-                 ImageLoadData::AnimationLoaded {
-                    target_block_id,
-                    frames,
-                    frame_delays,
-                } => {
-                    if let Some(block) = self.blocks.iter_mut().find(|b| b.id == target_block_id) {
-                        if let BlockContent::Image {
-                            frames: existing_frames,
-                            frame_delays: existing_delays,
-                            animation_state,
-                            playing,
-                            last_frame_time,
-                            ..
-                        } = &mut block.content
-                        {
-                            // Convert all frames to textures
-                            let texture_frames: Vec<_> = frames
-                                .iter()
-                                .enumerate()
-                                .map(|(i, img)| {
-                                    ctx.load_texture(
-                                        format!("avif-anim-{target_block_id}-{i}"),
-                                        img.clone(),
-                                        egui::TextureOptions::default(),
-                                    )
-                                })
-                                .collect();
+                    format,
+                    total_frame_count,
+                    frame_durations,
+                } = not_loaded
+                {
+                    let tx = self.image_tx.clone();
+                    let ctx = ctx.clone();
+
+                    // Update state to loading
+                    *animation_state = AnimationState::Loading { total_frame_count };
+
+                    thread::spawn(move || {
+                        // Read the file again to decode
+                        let frames = if let Ok(mut file) = std::fs::File::open(&path) {
+                            let mut buffer = Vec::new();
+                            if file.read_to_end(&mut buffer).is_ok() {
+                                match format {
+                                    image_decoder::ImageFormat::Avif => {
+                                        if let Some(result) = image_decoder::decode_avif(&buffer) {
+                                            result
+                                                .frames
+                                                .into_iter()
+                                                .map(|frame| {
+                                                    let size = [
+                                                        frame.width as usize,
+                                                        frame.height as usize,
+                                                    ];
+                                                    egui::ColorImage::from_rgba_unmultiplied(
+                                                        size,
+                                                        &frame.pixels,
+                                                    )
+                                                })
+                                                .collect()
+                                        } else {
+                                            Vec::new()
+                                        }
+                                    }
+                                    image_decoder::ImageFormat::Gif => {
+                                        if let Some(result) = image_decoder::decode_gif(&buffer) {
+                                            result
+                                                .frames
+                                                .into_iter()
+                                                .map(|frame| {
+                                                    let size = [
+                                                        frame.width as usize,
+                                                        frame.height as usize,
+                                                    ];
+                                                    egui::ColorImage::from_rgba_unmultiplied(
+                                                        size,
+                                                        &frame.pixels,
+                                                    )
+                                                })
+                                                .collect()
+                                        } else {
+                                            Vec::new()
+                                        }
+                                    }
+                                    image_decoder::ImageFormat::Webp => {
+                                        if let Some(result) = image_decoder::decode_webp(&buffer) {
+                                            result
+                                                .frames
+                                                .into_iter()
+                                                .map(|frame| {
+                                                    let size = [
+                                                        frame.width as usize,
+                                                        frame.height as usize,
+                                                    ];
+                                                    egui::ColorImage::from_rgba_unmultiplied(
+                                                        size,
+                                                        &frame.pixels,
+                                                    )
+                                                })
+                                                .collect()
+                                        } else {
+                                            Vec::new()
+                                        }
+                                    }
+                                    image_decoder::ImageFormat::Apng => {
+                                        if let Some(result) = image_decoder::decode_apng(&buffer) {
+                                            result
+                                                .frames
+                                                .into_iter()
+                                                .map(|frame| {
+                                                    let size = [
+                                                        frame.width as usize,
+                                                        frame.height as usize,
+                                                    ];
+                                                    egui::ColorImage::from_rgba_unmultiplied(
+                                                        size,
+                                                        &frame.pixels,
+                                                    )
+                                                })
+                                                .collect()
+                                        } else {
+                                            Vec::new()
+                                        }
+                                    }
+                                }
+                            } else {
+                                Vec::new()
+                            }
+                        } else {
+                            Vec::new()
+                        };
 
-                            *existing_frames = texture_frames;
-                            *existing_delays = frame_delays;
-                            *animation_state = AnimationState::Ready;
-                            *playing = true;
-                            *last_frame_time = time_now;
-                        }
-                    }
+                        let _ = if frames.is_empty() {
+                            tx.send(ImageLoadData::AnimationFailed {
+                                target_block_id: block_id,
+                                path,
+                            })
+                        } else {
+                            tx.send(ImageLoadData::AnimationLoaded {
+                                target_block_id: block_id,
+                                frames,
+                                frame_delays: frame_durations,
+                            })
+                        };
+                        ctx.request_repaint();
+                    });
+                } else {
+                    *animation_state = not_loaded;
                 }
             }
         }
+    }
 
-        if !self.blocks.is_empty() {
-            ctx.request_repaint();
+    /// Recount the playing animations after one starts without going through the play
+    /// button (a session block resuming, or frames finishing loading) and pause the
+    /// oldest if that goes over the limit
+    fn limit_concurrent_animations(&mut self) {
+        self.current_concurrent_animations = self
+            .blocks
+            .iter()
+            .filter(|b| matches!(b.content, BlockContent::Image { playing: true, .. }))
+            .count();
+        if self.current_concurrent_animations > self.settings.max_concurrent_animations {
+            self.pause_oldest_animation();
         }
+    }
 
-        // 1. Update Animation State (restored sophisticated timing)
-        let time_now = ctx.input(|i| i.time);
-        for block in &mut self.blocks {
+    /// Pause the animation that has been playing longest. Animations still loading
+    /// their frames are left alone; they are counted again once they arrive.
+    fn pause_oldest_animation(&mut self) {
+        let mut oldest_idx = None;
+        let mut oldest_time = f64::INFINITY;
+        for (i, block) in self.blocks.iter().enumerate() {
             if let BlockContent::Image {
-                frames,
-                frame_delays,
                 playing,
-                current_frame_idx,
-                last_frame_time,
+                playing_start_time,
+                animation_state,
                 ..
-            } = &mut block.content
+            } = &block.content
             {
-                if *playing && frames.len() > 1 {
-                    // Skip frames if we've fallen behind to maintain correct animation speed
-                    let mut elapsed = time_now - *last_frame_time;
-                    while elapsed > 0.0 {
-                        let delay = frame_delays.get(*current_frame_idx).copied().unwrap_or(0.1);
-                        if elapsed >= delay {
-                            elapsed -= delay;
-                            *current_frame_idx = (*current_frame_idx + 1) % frames.len();
-                        } else {
-                            break;
+                if *playing && !matches!(animation_state, AnimationState::Loading { .. }) {
+                    if let Some(start) = playing_start_time {
+                        if *start < oldest_time {
+                            oldest_time = *start;
+                            oldest_idx = Some(i);
                         }
                     }
-                    // Update last_frame_time, accounting for partial frame time remaining
-                    *last_frame_time = time_now - elapsed;
                 }
             }
         }
+        if let Some(idx) = oldest_idx {
+            if let BlockContent::Image {
+                playing,
+                animation_state,
+                ..
+            } = &mut self.blocks[idx].content
+            {
+                *playing = false;
+                // A block that never got its frames can still load them on a click
+                if matches!(animation_state, AnimationState::Ready) {
+                    *animation_state = AnimationState::Paused;
+                }
+                self.current_concurrent_animations -= 1;
+            }
+        }
+    }
+
+    fn save_session(&mut self, ctx: &egui::Context) -> bool {
+        let Some(path) = save_session_as(&self.to_session(), self.session_path.as_deref()) else {
+            return false;
+        };
+        self.session_path = Some(path);
+        self.mark_saved();
+        // Only the file path of an image is saved, so images without one come back empty
+        let unsaved = self.unsaved_image_count();
+        if unsaved > 0 {
+            self.notify(
+                ctx,
+                format!("Saved without {unsaved} images that have no file, like extracted frames"),
+            );
+        }
+        true
+    }
+
+    /// Image blocks with no file behind them, such as extracted frames
+    fn unsaved_image_count(&self) -> usize {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b.content, BlockContent::Image { path: None, .. }))
+            .count()
+    }
+
+    /// Hash of everything a save would write except the view and animation playback, to
+    /// tell whether there are unsaved changes. Hashing the saved form means a field added
+    /// to the session is covered without being listed here.
+    fn content_fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut session = self.to_session();
+        session.viewport = ViewportData {
+            pan: [0.0; 2],
+            zoom: 1.0,
+        };
+        for block in &mut session.blocks {
+            if let BlockContentData::Image { playing, .. } = &mut block.content {
+                *playing = false;
+            }
+        }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        serde_json::to_vec(&session)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Window title: the session's file name, with `*` for unsaved changes
+    fn title(&self, dirty: bool) -> String {
+        let name = self
+            .session_path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map_or("Untitled".into(), |n| n.to_string_lossy());
+        let dirty = if dirty { "*" } else { "" };
+        format!("{name}{dirty} - MA Blocks")
+    }
+
+    /// Take the canvas as it is now as saved, and the title to be brought up to date
+    fn mark_saved(&mut self) {
+        self.saved_fingerprint = Some(self.content_fingerprint());
+        self.dirty_check = (false, f64::NEG_INFINITY);
+    }
+
+    /// Whether the canvas changed since it was last saved or loaded
+    fn is_dirty(&self) -> bool {
+        match self.saved_fingerprint {
+            Some(saved) => saved != self.content_fingerprint(),
+            None => !self.blocks.is_empty(),
+        }
+    }
+
+    /// Take the blocks in `ids` off the canvas and into the trash, dropping the oldest
+    /// trashed blocks beyond `TRASH_LIMIT`. Images with a file let go of their textures
+    /// until they are restored; ones without keep them, as they couldn't come back.
+    fn move_to_trash(&mut self, ids: &HashSet<Uuid>) {
+        let deleted_at = now_millis();
+        let (trashed, kept): (Vec<Block>, Vec<Block>) = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .partition(|b| ids.contains(&b.id));
+        self.blocks = kept;
+        for mut block in trashed {
+            block.selected = false;
+            block.content.unload_frames();
+            self.trash.push((block, deleted_at));
+        }
+        let excess = self.trash.len().saturating_sub(TRASH_LIMIT);
+        self.trash.drain(..excess);
+    }
+
+    /// Put trashed block `idx` back on the canvas with its id, as close to where it was as
+    /// the blocks placed since then allow, decoding its image again if it was unloaded
+    fn restore_from_trash(&mut self, idx: usize, ctx: &egui::Context) {
+        let (mut block, _) = self.trash.remove(idx);
+        if !block.pinned {
+            let size = block.rect.size();
+            let pos = self.find_free_rect(block.rect.min.to_vec2(), size);
+            block.rect = Rect::from_min_size(pos.to_pos2(), size);
+        }
+        let unloaded =
+            matches!(&block.content, BlockContent::Image { frames, .. } if frames.is_empty());
+        let id = block.id;
+        self.blocks.push(block);
+        if unloaded {
+            self.reload_image(id, ctx);
+        }
+    }
+
+    /// Textures the blocks hold and their size in bytes, counting the ones duplicates
+    /// share only once
+    fn texture_usage(&self) -> (usize, usize) {
+        let mut seen = HashSet::new();
+        let mut bytes = 0;
+        for block in &self.blocks {
+            if let BlockContent::Image { frames, .. } = &block.content {
+                for tex in frames.iter().filter(|t| seen.insert(t.id())) {
+                    let [w, h] = tex.size();
+                    bytes += w * h * 4;
+                }
+            }
+        }
+        (seen.len(), bytes)
+    }
+
+    /// Corner overlay graphing recent frame times, with what the board holds in memory.
+    /// It doesn't ask for repaints itself, so an idle app shows a frozen graph.
+    fn show_diagnostics(&self, ctx: &egui::Context) {
+        egui::Area::new(egui::Id::new("diagnostics"))
+            .constrain_to(self.canvas_rect)
+            .anchor(Align2::LEFT_TOP, Vec2::splat(8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let count = self.frame_times.len().max(1) as f32;
+                    let mean = self.frame_times.iter().sum::<f32>() / count;
+                    ui.monospace(format!(
+                        "{:>5.1} fps {:>6.1} ms",
+                        1.0 / mean.max(f32::EPSILON),
+                        mean * 1000.0
+                    ));
+
+                    let size = Vec2::new(FRAME_HISTORY as f32, 40.0);
+                    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                    let painter = ui.painter_at(rect);
+                    painter.rect_filled(rect, 2.0, Color32::from_black_alpha(120));
+                    // Taller bars for slower frames, up to `FRAME_GRAPH_MAX`; the line
+                    // marks 60 fps
+                    let y =
+                        |dt: f32| rect.bottom() - (dt / FRAME_GRAPH_MAX).min(1.0) * rect.height();
+                    let target = Stroke::new(1.0, Color32::DARK_GREEN);
+                    painter.hline(rect.x_range(), y(1.0 / 60.0), target);
+                    let bar = Stroke::new(1.0, Color32::YELLOW);
+                    let x0 = rect.right() - self.frame_times.len() as f32;
+                    for (i, dt) in self.frame_times.iter().enumerate() {
+                        painter.vline(x0 + i as f32 + 0.5, y(*dt)..=rect.bottom(), bar);
+                    }
+
+                    let (textures, bytes) = self.texture_usage();
+                    ui.monospace(format!("blocks      {}", self.blocks.len()));
+                    ui.monospace(format!("textures    {textures}"));
+                    ui.monospace(format!("tex memory  {}", format_file_size(bytes as u64)));
+                    ui.monospace(format!(
+                        "animating   {}",
+                        self.current_concurrent_animations
+                    ));
+                    ui.monospace(format!("in trash    {}", self.trash.len()));
+                });
+            });
+    }
+
+    /// Window listing the blocks deleted in this session, newest first, to bring back
+    fn show_trash(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut restore = None;
+        let mut empty = false;
+        let now = now_millis();
+        egui::Window::new("Recently Deleted")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for (idx, (block, deleted_at)) in self.trash.iter().enumerate().rev() {
+                            ui.horizontal(|ui| {
+                                if ui.button("Restore").clicked() {
+                                    restore = Some(idx);
+                                }
+                                ui.weak(format_age(now.saturating_sub(*deleted_at)));
+                                ui.add(
+                                    egui::Label::new(block.content.accessible_label()).truncate(),
+                                );
+                            });
+                        }
+                    });
+                ui.separator();
+                if ui.button("Empty Trash").clicked() {
+                    empty = true;
+                }
+            });
+        if let Some(idx) = restore {
+            self.restore_from_trash(idx, ctx);
+        }
+        if empty {
+            self.trash.clear();
+        }
+        if !open || self.trash.is_empty() {
+            self.show_trash = false;
+        }
+    }
+
+    /// Window with everything kept in `Settings`, grouped by what it affects
+    fn show_settings(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let before = self.settings.clone();
+        egui::Window::new("Preferences")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let settings = &mut self.settings;
+                egui::Grid::new("settings_grid")
+                    .num_columns(2)
+                    .spacing([24.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.strong("Appearance");
+                        ui.end_row();
+                        ui.label("Theme");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut settings.theme, egui::Theme::Dark, "Dark");
+                            ui.radio_value(&mut settings.theme, egui::Theme::Light, "Light");
+                        });
+                        ui.end_row();
+                        ui.label("Grid spacing");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.grid_spacing)
+                                .range(GRID_SPACING_RANGE)
+                                .speed(1.0)
+                                .suffix(" units"),
+                        );
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.fade_in_blocks, "Fade in new blocks");
+                        ui.end_row();
+
+                        ui.strong("View");
+                        ui.end_row();
+                        ui.label("Scrolling");
+                        ui.vertical(|ui| {
+                            let mode = &mut settings.scroll_mode;
+                            ui.radio_value(mode, ScrollMode::Zoom, "Zooms (mouse wheel)");
+                            ui.radio_value(mode, ScrollMode::Pan, "Pans (trackpad)");
+                        });
+                        ui.end_row();
+                        ui.label("Zoom range");
+                        ui.horizontal(|ui| zoom_limits_edit(ui, &mut settings.zoom_limits));
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.pan_inertia, "Keep panning after a fast drag");
+                        ui.end_row();
+
+                        ui.strong("Text");
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.edit_preview, "Preview while editing");
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.spell_check, "Check spelling");
+                        ui.end_row();
+
+                        ui.strong("Images");
+                        ui.end_row();
+                        ui.label("Playing at once");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.max_concurrent_animations)
+                                .range(1..=100),
+                        )
+                        .on_hover_text("Starting one more animation pauses the oldest");
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.onion_skin, "Onion skin when paused");
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.negative_counters, "Counters go below zero");
+                        ui.end_row();
+
+                        ui.label("Import folders in");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.import_columns)
+                                .range(IMPORT_COLUMNS_RANGE)
+                                .suffix(" columns"),
+                        );
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.pdf_all_pages, "Import all PDF pages");
+                        ui.end_row();
+
+                        ui.strong("Arranging");
+                        ui.end_row();
+                        ui.label("Dropped blocks");
+                        ui.vertical(|ui| {
+                            let mode = &mut settings.collision_mode;
+                            let push = CollisionMode::Push;
+                            ui.radio_value(mode, push, "Push neighbors while dragging");
+                            ui.radio_value(mode, CollisionMode::SettleOnDrop, "Make room on drop");
+                            ui.radio_value(mode, CollisionMode::Overlap, "Allow overlap");
+                        });
+                        ui.end_row();
+                        ui.label("");
+                        let rigid = &mut settings.rigid_chains;
+                        ui.checkbox(rigid, "Keep chained groups rigid on drop");
+                        ui.end_row();
+                        ui.label("");
+                        ui.checkbox(&mut settings.snap_to_guides, "Snap to other blocks' edges");
+                        ui.end_row();
+                        ui.label("Tidy up into");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut settings.tidy_masonry, false, "Rows");
+                            ui.radio_value(&mut settings.tidy_masonry, true, "Masonry columns");
+                        });
+                        ui.end_row();
+                        ui.label("");
+                        let keep_groups = &mut settings.tidy_keep_groups;
+                        ui.checkbox(keep_groups, "Keep chained blocks together");
+                        ui.end_row();
+
+                        ui.strong("Export");
+                        ui.end_row();
+                        ui.label("Image scale");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.export_scale)
+                                .range(0.25..=8.0)
+                                .speed(0.05)
+                                .suffix(" px/unit"),
+                        );
+                        ui.end_row();
+                    });
+                ui.separator();
+                if ui.button("Reset to Defaults").clicked() {
+                    *settings = Settings::default();
+                }
+            });
+        if self.settings != before {
+            self.apply_settings(ctx);
+        }
+        if !open {
+            self.show_settings = false;
+        }
+    }
 
-        // Update concurrent animation count
-        self.current_concurrent_animations = self.blocks.iter().filter(|b| {
-            if let BlockContent::Image { playing, .. } = &b.content {
-                *playing
-            } else {
-                false
-            }
-        }).count();
-
-        // 2. Global Inputs
-        let input = ctx.input(|i| i.clone());
-        if input.raw_scroll_delta.y.abs() > 0.0 {
-            let factor = 1.0 + input.raw_scroll_delta.y * 0.001;
-            let old_zoom = self.viewport.zoom;
-            self.viewport.zoom = (self.viewport.zoom * factor).clamp(0.1, 5.0);
-
-            if let Some(mouse_pos) = input.pointer.hover_pos() {
-                let screen_center = ctx.screen_rect().center().to_vec2();
-                let mouse_offset = mouse_pos.to_vec2() - screen_center;
-                let world_point_under_mouse = (mouse_offset / old_zoom) - self.viewport.pan;
-                self.viewport.pan = (mouse_offset / self.viewport.zoom) - world_point_under_mouse;
-            }
+    /// Put the settings that live elsewhere into effect: the theme in egui and the zoom
+    /// limits in the viewport, which also keeps them in bounds
+    fn apply_settings(&mut self, ctx: &egui::Context) {
+        ctx.set_theme(self.settings.theme);
+        let (min, max) = self.settings.zoom_limits;
+        self.viewport.set_zoom_limits(min, max);
+        self.settings.zoom_limits = (self.viewport.min_zoom, self.viewport.max_zoom);
+        // Whatever was stored, the grid must not get too fine to draw
+        if !GRID_SPACING_RANGE.contains(&self.settings.grid_spacing) {
+            self.settings.grid_spacing = GRID_SPACING;
         }
+        self.settings.max_concurrent_animations = self.settings.max_concurrent_animations.max(1);
+        let columns = self.settings.import_columns;
+        self.settings.import_columns =
+            columns.clamp(*IMPORT_COLUMNS_RANGE.start(), *IMPORT_COLUMNS_RANGE.end());
+    }
 
-        if input.pointer.middle_down()
-            || (input.key_down(egui::Key::Space) && input.pointer.primary_down())
-        {
-            self.viewport.pan += input.pointer.delta() / self.viewport.zoom;
+    /// Window listing the rebindable shortcuts. Clicking one waits for the next key
+    /// press, which `update` picks up before anything else sees it.
+    fn show_keymap(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Keyboard Shortcuts")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("keymap_grid")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.label());
+                            let text = if self.rebinding == Some(action) {
+                                "Press a key (Esc to cancel)…".to_string()
+                            } else {
+                                ctx.format_shortcut(&self.keymap.shortcut(action))
+                            };
+                            if ui.button(text).clicked() {
+                                self.rebinding = Some(action);
+                            }
+                            let conflicts = self.keymap.conflicts(action);
+                            if let Some(other) = conflicts.first() {
+                                ui.colored_label(Color32::YELLOW, "⚠")
+                                    .on_hover_text(format!("Also bound to: {}", other.label()));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                if ui.button("Reset to Defaults").clicked() {
+                    self.keymap = Keymap::default();
+                    self.rebinding = None;
+                }
+            });
+        if !open {
+            self.show_keymap = false;
+            self.rebinding = None;
         }
+    }
 
-        // 3. Toolbar
-        egui::TopBottomPanel::top("toolbar")
-            .frame(
-                egui::Frame::default()
-                    .fill(Color32::from_rgb(30, 30, 30))
-                    .inner_margin(0.0)
-                    .outer_margin(0.0),
-            )
+    /// Modal asking what to do with unsaved changes before the canvas is replaced
+    fn show_confirm_replace(&mut self, replacement: Replacement, ctx: &egui::Context) {
+        let mut choice = None;
+        egui::Window::new("Unsaved Changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
             .show(ctx, |ui| {
+                ui.label("The canvas has changes that haven't been saved.");
                 ui.horizontal(|ui| {
-                    ui.add_space(8.0); // Add gap from window edge
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("💾").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Save Session")
-                        .clicked()
-                    {
-                        self.save_session();
-                    }
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("📂").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Load Session")
-                        .clicked()
-                    {
-                        self.load_session();
-                    }
-
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("🔤").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Add Text")
-                        .clicked()
-                    {
-                        self.spawn_text_block(ui.ctx());
-                    }
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("🖼").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Add Image")
-                        .clicked()
-                    {
-                        self.spawn_image_block(ui.ctx());
-                    }
-
-                    let mut btn = egui::Button::new(RichText::new("🔢").size(24.0))
-                        .min_size(Vec2::new(32.0, 32.0))
-                        .frame(false);
-                    if self.counter_tool_active {
-                        btn = btn.fill(Color32::LIGHT_GREEN);
+                    if ui.button("Save…").clicked() {
+                        choice = Some(true);
                     }
-                    if ui.add(btn).on_hover_text("Counter Tool").clicked() {
-                        self.counter_tool_active = !self.counter_tool_active;
-                    }
-
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("🔄").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Reset All Counters")
-                        .clicked()
-                    {
-                        self.reset_all_counters();
+                    if ui.button("Discard").clicked() {
+                        choice = Some(false);
                     }
-
-                    if ui
-                        .add(
-                            egui::Button::new(RichText::new("❓").size(24.0))
-                                .min_size(Vec2::new(32.0, 32.0))
-                                .frame(false),
-                        )
-                        .on_hover_text("Help")
-                        .clicked()
-                    {
-                        self.show_help = !self.show_help;
-                        help_toggled = true;
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_replace = None;
                     }
                 });
             });
 
-        // 4. Main Canvas
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.painter()
-                .rect_filled(ui.max_rect(), 0.0, Color32::from_rgb(30, 30, 30));
-            self.process_canvas(ui);
-        });
-
-        let mut help_layer_id = None;
-        if self.show_help {
-            let mut open = true;
-            egui::Window::new("Help")
-                .open(&mut open)
-                .collapsible(false)
-                .show(ctx, |ui| {
-                    help_layer_id = Some(ui.layer_id());
-
-                    // Increase text sizes by 1.5x
-                    let mut style = egui::Style::default();
-                    for (_text_style, font_id) in style.text_styles.iter_mut() {
-                        font_id.size *= 1.5;
-                    }
-                    ui.set_style(style);
-
-                    ui.heading("Controls");
-                    ui.label("• ✋ Pan: Middle Mouse Drag OR ⌨️ Space + Left Mouse Drag");
-                    ui.label("• 🔍 Zoom: Mouse Wheel");
-                    ui.label("• ✥ Move Block: Left Mouse Drag");
-                    ui.label("• ↘️ Resize Block: Right Mouse Drag (corners)");
-                    ui.label("• 📝 Edit Text: Double Click");
-                    ui.label("• ⏯️ Toggle GIF: Click");
-                    ui.label("• ❌ Delete Block: Click 'x' handle");
-                    ui.label("• 🔗 Chain Block: Click 'o' handle (moves together)");
-                    ui.separator();
-                    ui.heading("Tools");
-                    ui.label("• 💾 Save: Save current session to JSON");
-                    ui.label("• 📂 Load: Load session from JSON");
-                    ui.label("• 🔤 Text: Add new markdown text block");
-                    ui.label("• 🖼 Image: Add image (PNG, JPG, GIF, AVIF, WEBP)");
-                    ui.label("• 🔢 Counter: Click image to count, Right-click to decrement");
-                    ui.label("• 🔄 Reset: Reset all image counters to zero");
-                });
-            if !open {
-                self.show_help = false;
+        if let Some(save_first) = choice {
+            self.confirm_replace = None;
+            // Backing out of the save dialog cancels the replacement as well
+            if !save_first || self.save_session(ctx) {
+                self.replace_canvas_unchecked(replacement, ctx);
             }
         }
+    }
 
-        if self.show_help && !help_toggled && ctx.input(|i| i.pointer.any_click()) {
-            if let Some(pos) = ctx.input(|i| i.pointer.hover_pos()) {
-                if let Some(layer_id) = ctx.layer_id_at(pos) {
-                    if let Some(help_id) = help_layer_id {
-                        if layer_id != help_id {
-                            self.show_help = false;
-                        }
-                    }
-                } else {
-                    self.show_help = false;
-                }
+    /// Save only the selected blocks, e.g. as a snippet to import into other boards
+    fn export_selection(&mut self, ctx: &egui::Context) {
+        match self.selection_to_session(self.export_to_origin) {
+            Some(session) => {
+                save_session_as(&session, None);
             }
+            None => self.notify(ctx, "Select the blocks to export first"),
         }
     }
-}
 
-impl CanvasApp {
-    fn process_canvas(&mut self, ui: &mut egui::Ui) {
-        let screen_rect = ui.max_rect();
-        let screen_center = screen_rect.center().to_vec2();
-        let zoom = self.viewport.zoom;
-        let pan = self.viewport.pan;
+    /// Render the board to a PNG file at `export_scale`, regardless of the view. Decoding
+    /// every image takes a while, so it happens on a background thread.
+    fn export_png(&self, ctx: &egui::Context) {
+        let session = self.to_session();
+        let scale = self.settings.export_scale;
+        let tx = self.file_dialog_tx.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let Some(path) = ask_export_path("PNG", "png") else {
+                return;
+            };
+            let _ = tx.send(FileDialogResult::PngExport(export::render_png(
+                &session, &path, scale,
+            )));
+            ctx.request_repaint();
+        });
+    }
 
-        let mouse_pos = ui.input(|i| i.pointer.hover_pos());
-        let secondary_down = ui.input(|i| i.pointer.secondary_down());
-        let secondary_pressed =
-            ui.input(|i| i.pointer.button_pressed(egui::PointerButton::Secondary));
-    let secondary_released =
-        ui.input(|i| i.pointer.button_released(egui::PointerButton::Secondary));
+    /// Write the board to an SVG file that is `export_scale` pixels per canvas unit in size
+    fn export_svg(&mut self, ctx: &egui::Context) {
+        let Some(path) = ask_export_path("SVG", "svg") else {
+            return;
+        };
+        if let Err(e) = export::write_svg(&self.to_session(), &path, self.settings.export_scale) {
+            self.notify(ctx, format!("Could not export the board: {e}"));
+        }
+    }
 
-    let time_now = ui.input(|i| i.time);
+    /// Write the text of all blocks to one Markdown file, with images as links
+    fn export_markdown(&mut self, ctx: &egui::Context) {
+        let Some(path) = ask_export_path("Markdown", "md") else {
+            return;
+        };
+        if let Err(e) = std::fs::write(&path, self.board_markdown()) {
+            self.notify(ctx, format!("Could not export the board: {e}"));
+        }
+    }
 
-        // --- Resize Logic ---
-        if secondary_pressed && !self.counter_tool_active {
-            if let Some(m_pos) = mouse_pos {
-                let world_mouse = (m_pos.to_vec2() - screen_center) / zoom - pan;
-                if let Some(block) = self
-                    .blocks
-                    .iter()
-                    .rev()
-                    .find(|b| b.rect.contains(world_mouse.to_pos2()))
-                {
-                    let center = block.rect.center();
-                    let handle = match (world_mouse.x < center.x, world_mouse.y < center.y) {
-                        (true, true) => ResizeHandle::TopLeft,
-                        (false, true) => ResizeHandle::TopRight,
-                        (true, false) => ResizeHandle::BottomLeft,
-                        (false, false) => ResizeHandle::BottomRight,
-                    };
-                    self.resizing_state = Some(InteractionState {
-                        id: block.id,
-                        handle,
-                        initial_mouse_pos: m_pos,
-                        initial_block_rect: block.rect,
-                    });
-                    self.last_dragged_id = Some(block.id);
-                }
-            }
+    /// The board as one Markdown document: each block's text, or a link for an image,
+    /// separated by horizontal rules. Pasted images have no file to link to and are left
+    /// out.
+    fn board_markdown(&self) -> String {
+        let mut order: Vec<usize> = (0..self.blocks.len()).collect();
+        if self.markdown_reading_order {
+            self.sort_reading_order(&mut order);
         }
+        let parts: Vec<String> = order
+            .into_iter()
+            .filter_map(|i| match &self.blocks[i].content {
+                BlockContent::Text { text } => Some(text.trim().to_string()),
+                BlockContent::Image { path, .. } => {
+                    let path = path.as_deref()?;
+                    let name = Path::new(path).file_name()?.to_string_lossy();
+                    Some(format!("![{name}](<{path}>)"))
+                }
+                BlockContent::Link { url, title, .. } => {
+                    Some(format!("[{}](<{url}>)", link_caption(url, title)))
+                }
+                BlockContent::Color { color, .. } => Some(format!("`{}`", color_hex(*color))),
+            })
+            .collect();
+        let mut markdown = parts.join("\n\n---\n\n");
+        markdown.push('\n');
+        markdown
+    }
 
-        if secondary_released {
-            self.resizing_state = None;
+    /// Snapshot of just the selected blocks, keeping their positions relative to each
+    /// other. With `to_origin` they are moved so the selection's top-left corner is at
+    /// the world origin, and the view is centered on them. `None` if nothing is selected.
+    fn selection_to_session(&self, to_origin: bool) -> Option<Session> {
+        let mut session = self.to_session();
+        let selected: HashSet<Uuid> = self
+            .blocks
+            .iter()
+            .filter(|b| b.selected)
+            .map(|b| b.id)
+            .collect();
+        session.blocks.retain(|b| selected.contains(&b.id));
+
+        let bounds = self
+            .blocks
+            .iter()
+            .filter(|b| b.selected && !b.pinned)
+            .map(|b| b.rect)
+            .reduce(|a, b| a.union(b))?;
+        if to_origin {
+            let offset = -bounds.min.to_vec2();
+            for b in session.blocks.iter_mut().filter(|b| !b.pinned) {
+                let [x0, y0, x1, y1] = b.rect;
+                b.rect = [x0 + offset.x, y0 + offset.y, x1 + offset.x, y1 + offset.y];
+            }
+            let center = bounds.translate(offset).center();
+            session.viewport = ViewportData {
+                pan: [-center.x, -center.y],
+                zoom: 1.0,
+            };
         }
+        Some(session)
+    }
 
-        if let Some(state) = &self.resizing_state {
-            if let Some(curr_mouse_pos) = mouse_pos {
-                if let Some(idx) = self.blocks.iter().position(|b| b.id == state.id) {
-                    let delta_screen = curr_mouse_pos - state.initial_mouse_pos;
-                    let delta_world = delta_screen / zoom;
-                    let mut new_rect = state.initial_block_rect;
-                    let min_size = MIN_BLOCK_SIZE;
-
-                    match state.handle {
-                        ResizeHandle::BottomRight => {
-                            new_rect.max.x += delta_world.x;
-                            new_rect.max.y += delta_world.y;
-                        }
-                        ResizeHandle::BottomLeft => {
-                            new_rect.min.x += delta_world.x;
-                            new_rect.max.y += delta_world.y;
-                        }
-                        ResizeHandle::TopRight => {
-                            new_rect.max.x += delta_world.x;
-                            new_rect.min.y += delta_world.y;
-                        }
-                        ResizeHandle::TopLeft => {
-                            new_rect.min.x += delta_world.x;
-                            new_rect.min.y += delta_world.y;
+    /// Snapshot the canvas for saving. Blocks keep their `Vec` order, which is the z-order.
+    fn to_session(&self) -> Session {
+        Session {
+            viewport: ViewportData {
+                pan: [self.viewport.pan.x, self.viewport.pan.y],
+                zoom: self.viewport.zoom,
+            },
+            background: self.background,
+            custom_words: self.custom_words.clone(),
+            blocks: self
+                .blocks
+                .iter()
+                .map(|b| BlockData {
+                    id: b.id,
+                    rect: rect_to_session(b.rect),
+                    chained: b.chained,
+                    opacity: b.opacity,
+                    pinned: b.pinned,
+                    created_at: b.created_at,
+                    slide: b.slide,
+                    note: b.note.clone(),
+                    lock_aspect: b.lock_aspect,
+                    visible: b.visible,
+                    content: match &b.content {
+                        BlockContent::Text { text } => {
+                            BlockContentData::Text { text: text.clone() }
                         }
-                    }
+                        BlockContent::Link {
+                            url,
+                            title,
+                            description,
+                            image_url,
+                            ..
+                        } => BlockContentData::Link {
+                            url: url.clone(),
+                            title: title.clone(),
+                            description: description.clone(),
+                            image: image_url.clone(),
+                        },
+                        BlockContent::Color { color, label } => BlockContentData::Color {
+                            rgba: color.to_srgba_unmultiplied(),
+                            label: *label,
+                        },
+                        BlockContent::Image {
+                            path,
+                            counter,
+                            playing,
+                            crop,
+                            adjust,
+                            looping,
+                            direction,
+                            delay_override,
+                            ..
+                        } => BlockContentData::Image {
+                            path: path.clone().unwrap_or_default(),
+                            counter: *counter,
+                            playing: *playing && !b.content.is_still(),
+                            crop: rect_to_session(*crop),
+                            adjust: *adjust,
+                            looping: *looping,
+                            direction: *direction,
+                            delay_override: *delay_override,
+                        },
+                    },
+                })
+                .collect(),
+        }
+    }
 
-                    if new_rect.width() < min_size {
-                        if state.handle == ResizeHandle::TopLeft
-                            || state.handle == ResizeHandle::BottomLeft
-                        {
-                            new_rect.min.x = new_rect.max.x - min_size;
-                        } else {
-                            new_rect.max.x = new_rect.min.x + min_size;
-                        }
-                    }
-                    if new_rect.height() < min_size {
-                        if state.handle == ResizeHandle::TopLeft
-                            || state.handle == ResizeHandle::TopRight
-                        {
-                            new_rect.min.y = new_rect.max.y - min_size;
-                        } else {
-                            new_rect.max.y = new_rect.min.y + min_size;
-                        }
-                    }
+    /// Sum of the counters on the selected blocks, or on all blocks when none are
+    /// selected, and whether it is the selection's
+    fn counter_total(&self) -> (i64, bool) {
+        let of_selection = self.blocks.iter().any(|b| b.selected);
+        let total = self
+            .blocks
+            .iter()
+            .filter(|b| b.selected || !of_selection)
+            .map(|b| match b.content {
+                BlockContent::Image { counter, .. } => i64::from(counter),
+                BlockContent::Text { .. }
+                | BlockContent::Link { .. }
+                | BlockContent::Color { .. } => 0,
+            })
+            .sum();
+        (total, of_selection)
+    }
 
-                    if let BlockContent::Image { aspect_ratio, .. } = self.blocks[idx].content {
-                        let w = new_rect.width();
-                        new_rect.set_height(w / aspect_ratio);
-                    }
-                    self.blocks[idx].rect = new_rect;
-                }
+    fn reset_all_counters(&mut self) {
+        for block in &mut self.blocks {
+            if let BlockContent::Image { counter, .. } = &mut block.content {
+                *counter = 0;
             }
         }
+    }
 
-        // --- Render & Interaction ---
-        let mut ids_to_delete = HashSet::new();
-        let mut interact_captured = false;
-        let mut pending_move = None;
-        let mut animation_load_requests = Vec::new(); // Collect animation load requests
+    /// Load a session or start a new board, asking first if there are unsaved changes
+    fn replace_canvas(&mut self, replacement: Replacement, ctx: &egui::Context) {
+        if self.is_dirty() {
+            self.confirm_replace = Some(replacement);
+        } else {
+            self.replace_canvas_unchecked(replacement, ctx);
+        }
+    }
 
-        for i in 0..self.blocks.len() {
-            let b_id = self.blocks[i].id;
-            let b_rect = self.blocks[i].rect;
-            let b_selected = self.blocks[i].selected;
-            let b_chained = self.blocks[i].chained;
-            let is_editing = self.editing_id == Some(b_id);
+    /// Load a session or start a new board, dropping whatever is on the canvas
+    fn replace_canvas_unchecked(&mut self, replacement: Replacement, ctx: &egui::Context) {
+        match replacement {
+            Replacement::Load => {
+                if let Some((session, path)) = self.pick_session(ctx) {
+                    self.apply_session(session, ctx);
+                    self.session_path = Some(path);
+                }
+            }
+            Replacement::New => {
+                let empty = Session {
+                    viewport: ViewportData {
+                        pan: [0.0; 2],
+                        zoom: 1.0,
+                    },
+                    background: BackgroundData::default(),
+                    blocks: Vec::new(),
+                    custom_words: Vec::new(),
+                };
+                self.apply_session(empty, ctx);
+                self.session_path = None;
+                self.saved_fingerprint = None;
+            }
+        }
+    }
 
-            let screen_pos_min = screen_center + (b_rect.min.to_vec2() + pan) * zoom;
-            let screen_size = b_rect.size() * zoom;
-            let screen_rect = Rect::from_min_size(screen_pos_min.to_pos2(), screen_size);
+    fn import_session_file(&mut self, ctx: &egui::Context) {
+        if let Some((session, _)) = self.pick_session(ctx) {
+            self.import_session(session, ctx);
+        }
+    }
 
-            if !screen_rect.intersects(screen_rect) {
-                continue;
+    /// Ask for a session file and read it, reporting files that can't be parsed
+    fn pick_session(&mut self, ctx: &egui::Context) -> Option<(Session, PathBuf)> {
+        let path = FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()?;
+        let file = File::open(&path).ok()?;
+        match serde_json::from_reader::<_, Session>(BufReader::new(file)) {
+            Ok(session) => Some((session, path)),
+            Err(e) => {
+                self.notify(ctx, format!("Could not read session: {e}"));
+                None
             }
+        }
+    }
 
-            let border_color = if b_selected {
-                Color32::YELLOW
-            } else if b_chained {
-                Color32::GREEN
-            } else {
-                Color32::BLACK
-            };
-            let bg_color = Color32::from_rgb(240, 240, 240);
-
-            ui.painter().rect_filled(screen_rect, 5.0, bg_color);
-            ui.painter()
-                .rect_stroke(screen_rect, 5.0, Stroke::new(2.0, border_color));
+    /// Replace the canvas with `session`, recreating blocks in saved (z-)order and
+    /// starting the async loads for their images.
+    fn apply_session(&mut self, session: Session, ctx: &egui::Context) {
+        let pan = Vec2::new(session.viewport.pan[0], session.viewport.pan[1]);
+        self.viewport.pan = if pan.is_finite() { pan } else { Vec2::ZERO };
+        self.viewport.zoom = if session.viewport.zoom.is_finite() {
+            self.viewport.clamp_zoom(session.viewport.zoom)
+        } else {
+            self.viewport.clamp_zoom(1.0)
+        };
+        self.background = session.background;
+        self.custom_words = session.custom_words;
+        self.blocks.clear();
+        self.clear_markdown_cache(ctx);
+        self.missing_images.clear();
+        // Deleted blocks belong to the board being replaced
+        self.trash.clear();
+
+        let (skipped, repaired) = self.add_session_blocks(session.blocks, Vec2::ZERO, false, ctx);
+        self.mark_saved();
+        if skipped > 0 || repaired > 0 {
+            self.notify(
+                ctx,
+                format!("Session had invalid blocks: {skipped} skipped, {repaired} repaired"),
+            );
+        }
+    }
 
-            let interact_id = ui.make_persistent_id(b_id);
-            let sense = if is_editing {
-                egui::Sense::hover()
-            } else if self.counter_tool_active {
-                egui::Sense::click()
-            } else {
-                egui::Sense::click_and_drag()
-            };
-            let response = ui.interact(screen_rect, interact_id, sense);
+    /// Merge the blocks of `session` into the canvas, keeping their layout but moving
+    /// them as a whole into free space near the middle of the view. They get new ids and
+    /// become the selection, and its spell-check words are added to the canvas' own; the
+    /// session's viewport and background are ignored.
+    fn import_session(&mut self, mut session: Session, ctx: &egui::Context) {
+        // Pinned blocks land in the world like the rest, and the session's slides
+        // follow the ones already on the path
+        let path_len = self.slide_path_len();
+        for b in &mut session.blocks {
+            b.pinned = false;
+            b.slide = b.slide.map(|s| s + path_len);
+        }
+        let bounds = session
+            .blocks
+            .iter()
+            .filter_map(|b| rect_from_session(b.rect))
+            .map(|(rect, _)| rect)
+            .reduce(|a, b| a.union(b));
+        let Some(bounds) = bounds else {
+            self.notify(ctx, "The session has no blocks to import");
+            return;
+        };
+        let pos = self.find_free_rect(-self.viewport.pan - bounds.size() / 2.0, bounds.size());
+        let offset = pos - bounds.min.to_vec2();
 
-            if response.hovered() || response.dragged() {
-                interact_captured = true;
+        for b in &mut self.blocks {
+            b.selected = false;
+        }
+        for word in session.custom_words {
+            if !self.custom_words.contains(&word) {
+                self.custom_words.push(word);
             }
+        }
+        let first_new = self.blocks.len();
+        let (skipped, _) = self.add_session_blocks(session.blocks, offset, true, ctx);
+        for b in &mut self.blocks[first_new..] {
+            b.selected = true;
+            // Otherwise they would join the chain already on the canvas
+            b.chained = false;
+        }
 
-            let btn_size = 16.0 * zoom;
-            let padding = 4.0 * zoom;
-            let top_right = screen_rect.right_top();
-            let close_rect = Rect::from_center_size(
-                top_right + Vec2::new(-btn_size / 2.0 - padding, btn_size / 2.0 + padding),
-                Vec2::splat(btn_size),
-            );
-            let chain_rect = Rect::from_center_size(
-                close_rect.center() - Vec2::new(btn_size + padding, 0.0),
-                Vec2::splat(btn_size),
-            );
+        let mut message = format!("Imported {} blocks", self.blocks.len() - first_new);
+        if skipped > 0 {
+            message += &format!(" ({skipped} invalid blocks skipped)");
+        }
+        self.notify(ctx, message);
+    }
 
-            let close_hovered = mouse_pos.is_some_and(|p| close_rect.contains(p));
-            let chain_hovered = mouse_pos.is_some_and(|p| chain_rect.contains(p));
+    /// Append saved blocks in order, moved by `offset`, and start loading their images.
+    /// With `fresh_ids` they get new ids instead of their saved ones. Returns how many
+    /// blocks had to be skipped and how many repaired because of invalid rects.
+    fn add_session_blocks(
+        &mut self,
+        blocks: Vec<BlockData>,
+        offset: Vec2,
+        fresh_ids: bool,
+        ctx: &egui::Context,
+    ) -> (usize, usize) {
+        let time_now = ctx.input(|i| i.time);
+        let mut skipped = 0;
+        let mut repaired = 0;
 
-            if response.dragged() && !secondary_down && !ui.input(|i| i.pointer.middle_down()) {
-                let delta = response.drag_delta() / zoom;
-                pending_move = Some((i, delta));
-                self.last_dragged_id = Some(b_id);
+        for b_data in blocks {
+            let Some((rect, was_repaired)) = rect_from_session(b_data.rect) else {
+                skipped += 1;
+                continue;
+            };
+            if was_repaired {
+                repaired += 1;
             }
+            let id = if fresh_ids {
+                self.id_generator.next_id()
+            } else {
+                self.id_generator.observe(b_data.id);
+                b_data.id
+            };
 
-            if is_editing {
-                let mut child_ui = ui.new_child(
-                    egui::UiBuilder::new()
-                        .max_rect(screen_rect.shrink(4.0))
-                        .layout(egui::Layout::left_to_right(egui::Align::Min)),
-                );
-                if let Some(text_mut) = self.blocks[i].content.as_text_mut() {
-                    let output = egui::TextEdit::multiline(text_mut)
-                        .font(egui::FontId::proportional(16.0 * zoom))
-                        .frame(false)
-                        .desired_width(f32::INFINITY)
-                        .show(&mut child_ui);
-                    if self.focus_request == Some(b_id) {
-                        output.response.request_focus();
-                        self.focus_request = None;
+            let content = match b_data.content {
+                BlockContentData::Text { text } => BlockContent::Text { text },
+                BlockContentData::Link {
+                    url,
+                    title,
+                    description,
+                    image,
+                } => {
+                    // Only the image is fetched again; the text was saved with the block
+                    if let Some(image) = &image {
+                        self.fetch_link_preview(id, url.clone(), Some(image.clone()), ctx);
                     }
-                    if ui.input(|inp| inp.key_pressed(egui::Key::Escape)) {
-                        self.editing_id = None;
+                    BlockContent::Link {
+                        url,
+                        title,
+                        description,
+                        image_url: image,
+                        thumbnail: None,
                     }
                 }
-            } else {
-                match &mut self.blocks[i].content {
-                    BlockContent::Text { text } => {
-                        let mut child_ui = ui.new_child(
-                            egui::UiBuilder::new()
-                                .max_rect(screen_rect.shrink(5.0 * zoom))
-                                .layout(egui::Layout::left_to_right(egui::Align::Min)),
-                        );
-                        for (_text_style, font_id) in child_ui.style_mut().text_styles.iter_mut() {
-                            font_id.size *= zoom;
-                        }
-                        CommonMarkViewer::new().show(
-                            &mut child_ui,
-                            &mut self.common_mark_cache,
-                            text,
-                        );
-                        if response.double_clicked() && !close_hovered && !chain_hovered {
-                            self.editing_id = Some(b_id);
-                            self.focus_request = Some(b_id);
-                        }
+                BlockContentData::Color {
+                    rgba: [r, g, b, a],
+                    label,
+                } => BlockContent::Color {
+                    color: Color32::from_rgba_unmultiplied(r, g, b, a),
+                    label,
+                },
+                BlockContentData::Image {
+                    path,
+                    counter,
+                    playing,
+                    crop,
+                    adjust,
+                    looping,
+                    direction,
+                    delay_override,
+                } => {
+                    // Trigger async load; missing files are offered for relinking instead
+                    if existing_source_file(Some(&path)).is_some() {
+                        self.load_image_file(PathBuf::from(&path), ctx.clone(), Some(id));
+                    } else if !path.is_empty() {
+                        self.missing_images.push((id, path.clone()));
                     }
+                    // Create placeholder
                     BlockContent::Image {
-                        frames,
-                        current_frame_idx,
+                        frames: vec![],
+                        frame_delays: vec![],
+                        aspect_ratio: 1.0,
                         playing,
+                        current_frame_idx: 0,
+                        last_frame_time: 0.0,
                         counter,
-                        animation_state,
-                        playing_start_time,
-                        frame_delays,
-                        aspect_ratio,
-                        path,
-                        ..
-                    } => {
-                        if let Some(tex) = frames.get(*current_frame_idx) {
-                            ui.painter().image(
-                                tex.id(),
-                                screen_rect,
-                                Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
-                                Color32::WHITE,
-                            );
-                        }
+                        path: Some(path),
+                        animation_state: AnimationState::Ready,
+                        first_frame: None,
+                        playing_start_time: playing.then_some(time_now),
+                        crop: crop_from_session(crop),
+                        adjust,
+                        looping,
+                        direction,
+                        progress: Progress::default(),
+                        // A rate that is zero or not a number would never move on
+                        delay_override: delay_override.filter(|d| d.is_finite() && *d > 0.0),
+                    }
+                }
+            };
 
-                        // Show loading indicator for animation frames
-                        if let AnimationState::Loading { total_frame_count } = animation_state {
-                            let loading_text =
-                                format!("Loading {}/{}", frames.len(), total_frame_count);
-                            ui.painter().text(
-                                screen_rect.center(),
-                                Align2::CENTER_CENTER,
-                                loading_text,
-                                egui::FontId::proportional(16.0 * zoom),
-                                Color32::WHITE,
-                            );
-                        }
+            self.blocks.push(Block {
+                id,
+                rect: rect.translate(offset),
+                content,
+                chained: b_data.chained,
+                selected: false,
+                opacity: b_data.opacity,
+                pinned: b_data.pinned,
+                created_at: b_data.created_at,
+                slide: b_data.slide,
+                note: b_data.note,
+                lock_aspect: b_data.lock_aspect,
+                visible: b_data.visible,
+            });
+        }
+        (skipped, repaired)
+    }
+}
 
-                        if *counter > 0 {
-                            let circle_radius = 15.0 * zoom;
-                            let circle_center = screen_rect.min
-                                + Vec2::new(circle_radius + 5.0, circle_radius + 5.0);
-                            ui.painter().circle_filled(
-                                circle_center,
-                                circle_radius,
-                                Color32::GREEN,
-                            );
-                            ui.painter().text(
-                                circle_center,
-                                Align2::CENTER_CENTER,
-                                counter.to_string(),
-                                egui::FontId::proportional(20.0 * zoom),
-                                Color32::BLACK,
-                            );
-                        }
+impl BlockContent {
+    /// Empty image content for a block whose frames are still being decoded
+    fn image_placeholder(path: String) -> Self {
+        BlockContent::Image {
+            frames: vec![],
+            frame_delays: vec![],
+            aspect_ratio: 1.0,
+            playing: false,
+            current_frame_idx: 0,
+            last_frame_time: 0.0,
+            counter: 0,
+            path: Some(path),
+            animation_state: AnimationState::Ready,
+            first_frame: None,
+            playing_start_time: None,
+            crop: FULL_CROP,
+            adjust: ImageAdjust::default(),
+            looping: Looping::default(),
+            direction: Direction::default(),
+            progress: Progress::default(),
+            delay_override: None,
+        }
+    }
 
-                        if self.counter_tool_active {
-                            if response.clicked() {
-                                *counter += 1;
-                            } else if response.secondary_clicked() {
-                                *counter = (*counter - 1).max(0);
-                            }
-                        } else if response.clicked() && !close_hovered && !chain_hovered {
-                            // Handle animation state transitions
-                            match animation_state {
-                                AnimationState::NotLoaded { .. } => {
-                                    // Collect request to start loading animation frames
-                                    animation_load_requests.push((b_id,));
-                                }
-                                AnimationState::Paused { .. } => {
-                                    *playing = true;
-                                    *animation_state = AnimationState::Ready;
-                                    *playing_start_time = Some(time_now);
-                                    self.current_concurrent_animations += 1;
-                                    if self.current_concurrent_animations > self.max_concurrent_animations {
-                                        self.pause_oldest_animation();
-                                    }
-                                }
-                                AnimationState::Ready => {
-                                    if *playing {
-                                        *playing = false;
-                                        self.current_concurrent_animations -= 1;
-                                    } else {
-                                        *playing = true;
-                                        *playing_start_time = Some(time_now);
-                                        self.current_concurrent_animations += 1;
-                                        if self.current_concurrent_animations > self.max_concurrent_animations {
-                                            self.pause_oldest_animation();
-                                        }
-                                    }
-                                }
-                                AnimationState::Loading { .. } => {
-                                    // Do nothing, wait for loading
-                                }
-                            }
-                        }
-                    }
-                }
+    /// Whether this is an image known to have a single frame. Placeholders whose
+    /// frames are still being decoded don't count, as they may yet turn out animated.
+    fn is_still(&self) -> bool {
+        matches!(self, BlockContent::Image { frame_delays, .. } if frame_delays.len() == 1)
+    }
+
+    /// Drop the decoded frames of an image that can be read from its file again, freeing
+    /// their textures. It is left as a paused placeholder, to be decoded anew.
+    fn unload_frames(&mut self) {
+        let BlockContent::Image {
+            path,
+            frames,
+            frame_delays,
+            playing,
+            current_frame_idx,
+            animation_state,
+            first_frame,
+            playing_start_time,
+            progress,
+            ..
+        } = self
+        else {
+            return;
+        };
+        if existing_source_file(path.as_deref()).is_none() {
+            return;
+        }
+        frames.clear();
+        frame_delays.clear();
+        *playing = false;
+        *current_frame_idx = 0;
+        *animation_state = AnimationState::Ready;
+        *first_frame = None;
+        *playing_start_time = None;
+        *progress = Progress::default();
+    }
+
+    /// Copy for a duplicated or pasted block. Frames are reference-counted textures, so
+    /// nothing is read from disk again and images without a `path` copy just as well.
+    /// The copy starts paused; one whose animation is still streaming in keeps only the
+    /// first frame, as the remaining ones are delivered to the original block.
+    fn duplicate(&self) -> Self {
+        let mut copy = self.clone();
+        if let BlockContent::Image {
+            frames,
+            frame_delays,
+            playing,
+            current_frame_idx,
+            animation_state,
+            playing_start_time,
+            direction,
+            progress,
+            ..
+        } = &mut copy
+        {
+            if matches!(animation_state, AnimationState::Loading { .. }) {
+                frames.truncate(1);
+                frame_delays.truncate(1);
+                *animation_state = AnimationState::Ready;
             }
+            *playing = false;
+            *playing_start_time = None;
+            *current_frame_idx = direction.first_frame(frames.len());
+            *progress = Progress::default();
+        }
+        copy
+    }
 
-            if response.hovered() || response.dragged() || b_chained {
-                ui.painter().circle_filled(
-                    close_rect.center(),
-                    btn_size / 2.0,
-                    if close_hovered {
-                        Color32::from_rgb(255, 100, 100)
-                    } else {
-                        Color32::RED
-                    },
-                );
-                ui.painter().text(
-                    close_rect.center(),
-                    Align2::CENTER_CENTER,
-                    "x",
-                    egui::FontId::monospace(12.0 * zoom),
-                    Color32::WHITE,
-                );
-                ui.painter().circle_filled(
-                    chain_rect.center(),
-                    btn_size / 2.0,
-                    if b_chained {
-                        Color32::GREEN
-                    } else if chain_hovered {
-                        Color32::LIGHT_GRAY
-                    } else {
-                        Color32::GRAY
-                    },
-                );
-                ui.painter().text(
-                    chain_rect.center(),
-                    Align2::CENTER_CENTER,
-                    "o",
-                    egui::FontId::monospace(12.0 * zoom),
-                    Color32::WHITE,
-                );
+    fn as_text_mut(&mut self) -> Option<&mut String> {
+        if let BlockContent::Text { text } = self {
+            Some(text)
+        } else {
+            None
+        }
+    }
 
-                if response.clicked() {
-                    if close_hovered {
-                        ids_to_delete.insert(b_id);
-                    } else if chain_hovered {
-                        self.blocks[i].chained = !self.blocks[i].chained;
-                        self.last_chain_interaction = ui.input(|i| i.time);
-                    }
+    /// What a screen reader announces for the block: its text on one line, cut short,
+    /// the image's file name, the link's title or the swatch's hex code
+    fn accessible_label(&self) -> String {
+        match self {
+            BlockContent::Text { text } => {
+                let words: Vec<&str> = text.split_whitespace().collect();
+                let mut label = words.join(" ");
+                if let Some((cut, _)) = label.char_indices().nth(ACCESSIBLE_TEXT_CHARS) {
+                    label.truncate(cut);
+                    label.push('…');
                 }
+                format!("Text block: {label}")
+            }
+            BlockContent::Image { path, .. } => {
+                let name = path
+                    .as_deref()
+                    .and_then(|p| Path::new(p).file_name())
+                    .map(|n| n.to_string_lossy());
+                match name {
+                    Some(name) => format!("Image block: {name}"),
+                    None => "Image block".to_string(),
+                }
+            }
+            BlockContent::Link { url, title, .. } => {
+                format!("Link block: {}", link_caption(url, title))
+            }
+            BlockContent::Color { color, .. } => format!("Color swatch: {}", color_hex(*color)),
+        }
+    }
+
+    /// Pixel size, aspect ratio, frame count and file size of an image, one per line,
+    /// for its hover tooltip. SVGs and PDF pages have no pixel size of their own, so
+    /// theirs is marked as the size they were rendered at. `None` for text blocks.
+    fn image_details(&self) -> Option<String> {
+        let BlockContent::Image {
+            frames,
+            aspect_ratio,
+            path,
+            animation_state,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        // A PDF page's path names the page after the file
+        let file = path.as_deref().map(|p| split_pdf_page(Path::new(p)).0);
+        let mut lines = Vec::new();
+        if let Some(frame) = frames.first() {
+            let [width, height] = frame.size();
+            let vector = file.as_deref().is_some_and(|f| is_svg(f) || is_pdf(f));
+            let rendered = if vector { " as rendered" } else { "" };
+            lines.push(format!("{width} × {height} px{rendered}"));
+        }
+        lines.push(format!("Aspect ratio {aspect_ratio:.2}"));
+        let frame_count = match animation_state {
+            AnimationState::NotLoaded {
+                total_frame_count, ..
+            }
+            | AnimationState::Loading { total_frame_count } => *total_frame_count,
+            _ => frames.len(),
+        };
+        if frame_count > 1 {
+            lines.push(format!("{frame_count} frames"));
+        }
+        if let Some(Ok(metadata)) = file.map(std::fs::metadata) {
+            lines.push(format_file_size(metadata.len()));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// Ask where to save `session`, suggesting `current` (the file it came from), and write
+/// it there as JSON. Returns the path it was saved to.
+fn save_session_as(session: &Session, current: Option<&Path>) -> Option<PathBuf> {
+    let mut dialog = FileDialog::new().add_filter("JSON", &["json"]);
+    if let Some(current) = current {
+        if let Some(dir) = current.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(name) = current.file_name() {
+            dialog = dialog.set_file_name(name.to_string_lossy());
+        }
+    }
+    let mut path = dialog.save_file()?;
+    if path.extension().is_none() {
+        path.set_extension("json");
+    }
+
+    let file = File::create(&path).ok()?;
+    serde_json::to_writer_pretty(file, session).ok()?;
+    Some(path)
+}
+
+/// Ask where to export the board as `format`, adding the `extension` if none is typed
+fn ask_export_path(format: &str, extension: &str) -> Option<PathBuf> {
+    let mut path = FileDialog::new()
+        .add_filter(format, &[extension])
+        .set_file_name(format!("board.{extension}"))
+        .save_file()?;
+    if path.extension().is_none() {
+        path.set_extension(extension);
+    }
+    Some(path)
+}
+
+/// `--render SESSION OUT [--scale N]`: draw a saved session to a PNG without opening a
+/// window, at N pixels per canvas unit (1 by default)
+fn render_from_args(args: &[String]) -> Result<(), String> {
+    let usage = || "usage: ma_blocks --render <session.json> <out.png> [--scale N]".to_string();
+    let (session_path, out_path, scale) = match args {
+        [session_path, out_path] => (session_path, out_path, 1.0),
+        [session_path, out_path, flag, scale] if flag == "--scale" => {
+            let scale: f32 = scale.parse().map_err(|_| usage())?;
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(usage());
             }
+            (session_path, out_path, scale)
         }
+        _ => return Err(usage()),
+    };
+    let file = File::open(session_path).map_err(|e| format!("{session_path}: {e}"))?;
+    let session: Session = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("{session_path}: {e}"))?;
+    export::render_png(&session, Path::new(out_path), scale)
+}
 
-        if let Some((idx, delta)) = pending_move {
-            let mut moved_indices = vec![idx];
-            if self.blocks[idx].chained {
-                self.last_chain_interaction = ui.input(|i| i.time);
-                for (i, b) in self.blocks.iter().enumerate() {
-                    if i != idx && b.chained {
-                        moved_indices.push(i);
-                    }
-                }
+fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--render") {
+        if let Err(e) = render_from_args(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // With the `persistence` feature eframe saves the window size and position on exit
+    // and restores them on the next launch; this size only applies to the first one
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1200.0, 800.0]),
+        ..Default::default()
+    };
+    let mut app = CanvasApp::default();
+    // Reproducible ids keep sessions that are kept under version control diffable
+    if std::env::var_os("MA_BLOCKS_SEQUENTIAL_IDS").is_some() {
+        app.id_generator = IdGenerator::Sequential(0);
+    }
+    eframe::run_native(
+        "MA Blocks",
+        options,
+        Box::new(|cc| {
+            cc.egui_ctx
+                .add_image_loader(std::sync::Arc::new(markdown_images::Loader::default()));
+            if let Some(storage) = cc.storage {
+                app.settings = Settings::load(storage);
             }
-            for &i in &moved_indices {
-                self.blocks[i].rect = self.blocks[i].rect.translate(delta);
+            if let Some(keymap) = cc.storage.and_then(|s| eframe::get_value(s, KEYMAP_KEY)) {
+                app.keymap = keymap;
+            }
+            if let Some(active) = cc
+                .storage
+                .and_then(|s| eframe::get_value(s, COUNTER_TOOL_KEY))
+            {
+                app.counter_tool_active = active;
+            }
+            if let Some(show) = cc.storage.and_then(|s| eframe::get_value(s, SHOW_HELP_KEY)) {
+                app.show_help = show;
+            }
+            if let Some(show) = cc
+                .storage
+                .and_then(|s| eframe::get_value(s, SHOW_LAYERS_KEY))
+            {
+                app.show_layers = show;
             }
+            app.apply_settings(&cc.egui_ctx);
+            Ok(Box::new(app) as Box<dyn eframe::App>)
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_block(text: &str, min: (f32, f32)) -> Block {
+        Block {
+            id: Uuid::new_v4(),
+            rect: Rect::from_min_size(Pos2::new(min.0, min.1), Vec2::new(200.0, 100.0)),
+            content: BlockContent::Text {
+                text: text.to_string(),
+            },
+            chained: false,
+            selected: false,
+            opacity: u8::MAX,
+            pinned: false,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
         }
+    }
 
-        if ui.input(|i| {
-            i.pointer.button_released(egui::PointerButton::Primary)
-                || i.pointer.button_released(egui::PointerButton::Secondary)
-        }) {
-            if let Some(dragged_id) = self.last_dragged_id {
-                if let Some(idx) = self.blocks.iter().position(|b| b.id == dragged_id) {
-                    let others = self.blocks.clone();
-                    self.blocks[idx].resolve_collision(&others);
-                    if self.blocks[idx].chained {
-                        for i in 0..self.blocks.len() {
-                            if self.blocks[i].chained && i != idx {
-                                self.blocks[i].resolve_collision(&others);
-                            }
-                        }
-                    }
-                }
+    #[test]
+    fn resumed_animations_pause_the_oldest_one_over_the_limit() {
+        let mut app = CanvasApp::default();
+        app.settings.max_concurrent_animations = 2;
+        let states = [
+            AnimationState::Loading {
+                total_frame_count: 10,
+            },
+            AnimationState::Ready,
+            AnimationState::Ready,
+        ];
+        for (start, state) in states.into_iter().enumerate() {
+            let mut content = BlockContent::image_placeholder("/gone/anim.gif".into());
+            if let BlockContent::Image {
+                playing,
+                playing_start_time,
+                animation_state,
+                ..
+            } = &mut content
+            {
+                *playing = true;
+                *playing_start_time = Some(start as f64);
+                *animation_state = state;
             }
+            app.blocks.push(Block {
+                content,
+                ..text_block("", (0.0, 0.0))
+            });
         }
-        // Process animation load requests
-        for (block_id,) in animation_load_requests {
-            if let Some(block) = self.blocks.iter_mut().find(|b| b.id == block_id) {
-                if let BlockContent::Image {
-                    animation_state, ..
-                } = &mut block.content
-                {
-                    let not_loaded = std::mem::replace(animation_state, AnimationState::Ready);
-                    if let AnimationState::NotLoaded {
-                        path,
-                        format,
-                        total_frame_count,
-                        frame_durations,
-                    } = not_loaded
-                    {
-                        let path = path;
-                        let frame_durations = frame_durations;
-                        let tx = self.image_tx.clone();
 
-                        // Update state to loading
-                        *animation_state = AnimationState::Loading {
-                            total_frame_count,
-                        };
+        app.limit_concurrent_animations();
+
+        // The oldest is still loading, so the next oldest gives way
+        let states: Vec<(bool, bool)> = app
+            .blocks
+            .iter()
+            .map(|b| match &b.content {
+                BlockContent::Image {
+                    playing,
+                    animation_state,
+                    ..
+                } => (*playing, matches!(animation_state, AnimationState::Paused)),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(states, [(true, false), (false, true), (true, false)]);
+        assert_eq!(app.current_concurrent_animations, 2);
+    }
 
-                        thread::spawn(move || {
-                            // Read the file again to decode
-                            let frames = if let Ok(mut file) = std::fs::File::open(&path) {
-                                let mut buffer = Vec::new();
-                                if file.read_to_end(&mut buffer).is_ok() {
-                                    match format {
-                                        image_decoder::ImageFormat::Avif => {
-                                            if let Some(result) = image_decoder::decode_avif(&buffer) {
-                                                result.frames.into_iter().map(|frame| {
-                                                    let size = [frame.width as usize, frame.height as usize];
-                                                    egui::ColorImage::from_rgba_unmultiplied(size, &frame.pixels)
-                                                }).collect()
-                                            } else {
-                                                Vec::new()
-                                            }
-                                        }
-                                        image_decoder::ImageFormat::Gif => {
-                                            if let Some(result) = image_decoder::decode_gif(&buffer) {
-                                                result.frames.into_iter().map(|frame| {
-                                                    let size = [frame.width as usize, frame.height as usize];
-                                                    egui::ColorImage::from_rgba_unmultiplied(size, &frame.pixels)
-                                                }).collect()
-                                            } else {
-                                                Vec::new()
-                                            }
-                                        }
-                                        image_decoder::ImageFormat::Webp => {
-                                            if let Some(result) = image_decoder::decode_webp(&buffer) {
-                                                result.frames.into_iter().map(|frame| {
-                                                    let size = [frame.width as usize, frame.height as usize];
-                                                    egui::ColorImage::from_rgba_unmultiplied(size, &frame.pixels)
-                                                }).collect()
-                                            } else {
-                                                Vec::new()
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    Vec::new()
-                                }
-                            } else {
-                                Vec::new()
-                            };
+    #[test]
+    fn session_round_trip_preserves_block_order() {
+        let ctx = egui::Context::default();
+        let mut app = CanvasApp::default();
+        // Overlapping blocks, so the order decides which one is on top
+        app.blocks.push(text_block("bottom", (0.0, 0.0)));
+        app.blocks.push(text_block("middle", (50.0, 50.0)));
+        app.blocks.push(text_block("top", (100.0, 100.0)));
+        app.blocks.swap(0, 2);
+        app.blocks[1].opacity = 80;
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        let session: Session = serde_json::from_str(&json).unwrap();
+        let mut loaded = CanvasApp::default();
+        loaded.apply_session(session, &ctx);
+
+        let ids = |app: &CanvasApp| app.blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids(&app), ids(&loaded));
+        let rects = |app: &CanvasApp| app.blocks.iter().map(|b| b.rect).collect::<Vec<_>>();
+        assert_eq!(rects(&app), rects(&loaded));
+        let opacities = |app: &CanvasApp| app.blocks.iter().map(|b| b.opacity).collect::<Vec<_>>();
+        assert_eq!(opacities(&loaded), vec![u8::MAX, 80, u8::MAX]);
+    }
 
-                            if !frames.is_empty() {
-                                let _ = tx.send(ImageLoadData::AnimationLoaded {
-                                    target_block_id: block_id,
-                                    frames,
-                                    frame_delays: frame_durations,
-                                });
-                            }
-                        });
-                    } else {
-                        *animation_state = not_loaded;
-                    }
+    #[test]
+    fn arranging_by_age_lays_blocks_out_in_creation_order() {
+        let mut saved = CanvasApp::default();
+        for (text, created_at) in [("second", 20), ("first", 10), ("third", 30)] {
+            saved.blocks.push(Block {
+                created_at,
+                ..text_block(text, (created_at as f32 * 7.0, 0.0))
+            });
+        }
+        // Ages survive a reload
+        let mut app = load_json(&serde_json::to_string(&saved.to_session()).unwrap());
+        app.canvas_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(500.0, 400.0));
+
+        let texts = |app: &CanvasApp| {
+            let mut blocks: Vec<&Block> = app.blocks.iter().collect();
+            blocks.sort_by(|a, b| {
+                let (a, b) = (a.rect.min, b.rect.min);
+                a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+            });
+            blocks
+                .iter()
+                .map(|b| match &b.content {
+                    BlockContent::Text { text } => text.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        app.arrange_by_age(false);
+        assert_eq!(texts(&app), ["first", "second", "third"]);
+        // Two 200-wide blocks fit across the 500-wide view, the third wraps
+        assert_eq!(app.blocks[0].rect.min.y, app.blocks[1].rect.min.y);
+        assert!(app.blocks[2].rect.min.y > app.blocks[0].rect.max.y);
+
+        app.arrange_by_age(true);
+        assert_eq!(texts(&app), ["third", "second", "first"]);
+    }
+
+    #[test]
+    fn tidying_packs_blocks_without_overlaps_and_keeps_chains_together() {
+        let mut app = CanvasApp {
+            canvas_rect: Rect::from_min_size(Pos2::ZERO, Vec2::new(700.0, 500.0)),
+            ..Default::default()
+        };
+        app.blocks.push(text_block("a", (-900.0, 400.0)));
+        app.blocks.push(text_block("b", (1000.0, -300.0)));
+        app.blocks.push(text_block("c", (1100.0, -100.0)));
+        app.blocks[1].chained = true;
+        app.blocks[2].chained = true;
+        let chain_offset = app.blocks[2].rect.min - app.blocks[1].rect.min;
+
+        for masonry in [false, true] {
+            app.settings.tidy_masonry = masonry;
+            app.tidy_up();
+            let center = app.canvas_rect.center();
+            let top_left = app.viewport.screen_to_world(center, Pos2::ZERO);
+            for (i, a) in app.blocks.iter().enumerate() {
+                assert!(a.rect.min.x >= top_left.x && a.rect.min.y >= top_left.y);
+                for b in &app.blocks[i + 1..] {
+                    assert!(!a.rect.intersects(b.rect));
                 }
             }
+            let offset = app.blocks[2].rect.min - app.blocks[1].rect.min;
+            assert_eq!(offset, chain_offset);
+        }
+    }
+
+    #[test]
+    fn stacked_blocks_fan_out_on_top_and_spread_back_without_overlaps() {
+        let mut app = CanvasApp::default();
+        for (i, x) in [0.0, 300.0, 600.0, 900.0].into_iter().enumerate() {
+            app.blocks.push(Block {
+                selected: i != 1,
+                ..text_block(&i.to_string(), (x, 0.0))
+            });
+        }
+        let ids: Vec<Uuid> = app.blocks.iter().map(|b| b.id).collect();
+
+        app.stack_selection();
+        // The unselected block is now at the back, the pile in its old order on top
+        let order: Vec<Uuid> = app.blocks.iter().map(|b| b.id).collect();
+        assert_eq!(order, [ids[1], ids[0], ids[2], ids[3]]);
+        for k in 1..3 {
+            let step = app.blocks[k + 1].rect.min - app.blocks[k].rect.min;
+            assert_eq!(step, Vec2::splat(STACK_OFFSET));
+        }
+        assert_eq!(app.blocks[1].rect.min, Pos2::ZERO);
+
+        // Clicking a covered card brings it up; the top one stays where it is
+        app.raise_if_covered(ids[0]);
+        assert_eq!(app.blocks.last().unwrap().id, ids[0]);
+        app.raise_if_covered(ids[1]);
+        assert_eq!(app.blocks[0].id, ids[1]);
+
+        app.spread_selection();
+        for (i, a) in app.blocks.iter().enumerate() {
+            for b in &app.blocks[i + 1..] {
+                assert!(!a.rect.intersects(b.rect));
+            }
         }
+    }
 
-        self.blocks.retain(|b| !ids_to_delete.contains(&b.id));
+    #[test]
+    fn key_resize_keeps_the_top_left_corner_and_image_aspect_ratio() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            selected: true,
+            ..text_block("text", (0.0, 0.0))
+        });
+        let mut content = BlockContent::image_placeholder("a.png".into());
+        if let BlockContent::Image { aspect_ratio, .. } = &mut content {
+            *aspect_ratio = 2.0;
+        }
+        app.blocks.push(Block {
+            rect: Rect::from_min_size(Pos2::new(0.0, 200.0), Vec2::new(200.0, 100.0)),
+            content,
+            selected: true,
+            ..text_block("", (0.0, 0.0))
+        });
 
-        if ui.input(|i| i.pointer.any_click()) && !interact_captured && !secondary_down {
-            self.editing_id = None;
-            for b in &mut self.blocks {
-                b.selected = false;
+        app.resize_selected_by(Vec2::new(0.0, KEY_RESIZE_STEP));
+        assert_eq!(app.blocks[0].rect.min, Pos2::ZERO);
+        assert_eq!(app.blocks[0].rect.size(), Vec2::new(200.0, 110.0));
+        // The image follows the height change with its width
+        let image = app.blocks[1].rect;
+        assert_eq!(image.min, Pos2::new(0.0, 200.0));
+        assert_eq!(image.size(), Vec2::new(220.0, 110.0));
+
+        app.resize_selected_by(Vec2::new(-1000.0, 0.0));
+        assert_eq!(app.blocks[0].rect.width(), MIN_BLOCK_SIZE);
+        let image = app.blocks[1].rect;
+        assert_eq!(image.width(), image.height() * 2.0);
+    }
+
+    #[test]
+    fn typed_geometry_must_be_a_finite_number() {
+        assert_eq!(parse_finite(" 1 250.5 "), Some(1250.5));
+        assert_eq!(parse_finite("\u{2212}40"), Some(-40.0));
+        assert_eq!(parse_finite("inf"), None);
+        assert_eq!(parse_finite("NaN"), None);
+        assert_eq!(parse_finite("wide"), None);
+    }
+
+    #[test]
+    fn present_mode_steps_through_blocks_in_reading_order() {
+        let ctx = egui::Context::default();
+        let mut app = CanvasApp::default();
+        app.toggle_presentation(&ctx);
+        assert_eq!(app.presentation, None);
+
+        app.blocks.push(text_block("second", (300.0, 0.0)));
+        app.blocks.push(text_block("third", (0.0, 300.0)));
+        app.blocks.push(text_block("first", (0.0, 0.0)));
+        app.blocks.push(Block {
+            pinned: true,
+            ..text_block("pinned", (-500.0, -500.0))
+        });
+        app.toggle_presentation(&ctx);
+        assert_eq!(app.current_slide(), Some(2));
+
+        app.step_slide(false);
+        assert_eq!(app.current_slide(), Some(2));
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            app.step_slide(true);
+            shown.push(app.current_slide().unwrap());
+        }
+        // The last slide stays up
+        assert_eq!(shown, [0, 1, 1, 1]);
+
+        app.toggle_presentation(&ctx);
+        assert_eq!(app.current_slide(), None);
+    }
+
+    #[test]
+    fn slide_path_sets_the_presentation_order_and_is_saved() {
+        let mut app = CanvasApp::default();
+        for x in [0.0, 300.0, 600.0, 900.0] {
+            app.blocks.push(text_block("slide", (x, 0.0)));
+        }
+        let ids: Vec<Uuid> = app.blocks.iter().map(|b| b.id).collect();
+        for &i in &[2, 0, 3] {
+            app.toggle_slide(ids[i]);
+        }
+        assert_eq!(app.slide_order(), [2, 0, 3]);
+
+        // Taking a block off closes the gap; it goes back on at the end
+        app.toggle_slide(ids[0]);
+        assert_eq!(app.slide_order(), [2, 3]);
+        assert_eq!(app.blocks[3].slide, Some(1));
+        app.toggle_slide(ids[0]);
+        assert_eq!(app.slide_order(), [2, 3, 0]);
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        assert_eq!(load_json(&json).slide_order(), [2, 3, 0]);
+    }
+
+    #[test]
+    fn notes_are_saved_and_copied_with_their_block() {
+        let ctx = egui::Context::default();
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            note: Some("check the license".to_string()),
+            ..text_block("image credits", (0.0, 0.0))
+        });
+        app.duplicate_blocks(&[app.blocks[0].id], &ctx);
+        assert_eq!(app.blocks[1].note, app.blocks[0].note);
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        let loaded = load_json(&json);
+        assert_eq!(loaded.blocks[0].note.as_deref(), Some("check the license"));
+    }
+
+    #[test]
+    fn locked_text_blocks_keep_their_aspect_ratio_and_save_the_lock() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            selected: true,
+            lock_aspect: true,
+            ..text_block("text", (0.0, 0.0))
+        });
+
+        app.resize_selected_by(Vec2::new(0.0, KEY_RESIZE_STEP));
+        assert_eq!(app.blocks[0].rect.size(), Vec2::new(220.0, 110.0));
+        app.resize_selected_by(Vec2::new(KEY_RESIZE_STEP * 2.0, 0.0));
+        assert_eq!(app.blocks[0].rect.size(), Vec2::new(240.0, 120.0));
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        assert!(loaded.blocks[0].lock_aspect);
+    }
+
+    #[test]
+    fn hidden_blocks_drop_out_of_the_selection_and_stay_hidden_when_saved() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("shown", (0.0, 0.0)));
+        app.blocks.push(Block {
+            selected: true,
+            ..text_block("hidden", (300.0, 0.0))
+        });
+        app.editing_id = Some(app.blocks[1].id);
+
+        app.toggle_visible(1);
+        assert!(!app.blocks[1].visible && !app.blocks[1].selected);
+        assert_eq!(app.editing_id, None);
+        app.select_all();
+        let selected: Vec<bool> = app.blocks.iter().map(|b| b.selected).collect();
+        assert_eq!(selected, [true, false]);
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        let visible: Vec<bool> = loaded.blocks.iter().map(|b| b.visible).collect();
+        assert_eq!(visible, [true, false]);
+
+        // Sessions from before blocks could be hidden show everything
+        let old = load_json(&format!(
+            r#"{{"viewport":{{"pan":[0.0,0.0],"zoom":1.0}},"blocks":[{}]}}"#,
+            text_block_json("[0.0,0.0,100.0,100.0]")
+        ));
+        assert!(old.blocks[0].visible);
+    }
+
+    #[test]
+    fn hidden_blocks_are_skipped_by_present_mode_find_and_pushing() {
+        let mut app = CanvasApp::default();
+        for (x, name) in [(0.0, "a.png"), (300.0, "b.png")] {
+            app.blocks.push(Block {
+                content: BlockContent::image_placeholder(format!("/photos/{name}")),
+                ..text_block("", (x, 0.0))
+            });
+        }
+        app.toggle_slide(app.blocks[1].id);
+        app.toggle_slide(app.blocks[0].id);
+        assert_eq!(app.slide_order(), [1, 0]);
+        app.toggle_visible(1);
+        assert_eq!(app.slide_order(), [0]);
+        assert_eq!(app.image_matches("png"), [0]);
+
+        // Find can't select it, nor can anything else that focuses a block
+        app.focus_block(1);
+        assert!(!app.blocks[1].selected);
+
+        // Dropping a block over a hidden one leaves both where they are
+        let hidden = app.blocks[1].rect;
+        app.blocks[0].rect = hidden;
+        app.push_neighbors(0);
+        assert_eq!(app.blocks[0].rect, hidden);
+        assert_eq!(app.blocks[1].rect, hidden);
+    }
+
+    #[test]
+    fn deleted_blocks_wait_in_the_trash_until_restored() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("keep", (0.0, 0.0)));
+        app.blocks.push(Block {
+            selected: true,
+            ..text_block("gone", (300.0, 0.0))
+        });
+        let id = app.blocks[1].id;
+
+        app.move_to_trash(&HashSet::from([id]));
+        assert_eq!(app.blocks.len(), 1);
+        assert_eq!(app.trash.len(), 1);
+        assert!(!app.trash[0].0.selected);
+
+        // Its old spot is taken now, so it comes back next to it
+        app.blocks.push(text_block("new", (300.0, 0.0)));
+        app.restore_from_trash(0, &egui::Context::default());
+        assert!(app.trash.is_empty());
+        let restored = app.blocks.last().unwrap();
+        assert_eq!(restored.id, id);
+        assert!(!restored.rect.intersects(app.blocks[1].rect));
+
+        let ids: HashSet<Uuid> = (0..TRASH_LIMIT + 5)
+            .map(|i| {
+                let block = text_block("", (0.0, 200.0 * (i + 1) as f32));
+                let id = block.id;
+                app.blocks.push(block);
+                id
+            })
+            .collect();
+        app.move_to_trash(&ids);
+        assert_eq!(app.trash.len(), TRASH_LIMIT);
+    }
+
+    #[test]
+    fn images_combined_into_a_contact_sheet_can_be_restored() {
+        let mut app = CanvasApp::default();
+        for x in [0.0, 300.0] {
+            app.blocks.push(Block {
+                content: BlockContent::image_placeholder("/gone/photo.png".into()),
+                note: Some("the good one".into()),
+                ..text_block("", (x, 0.0))
+            });
+        }
+        let sources: Vec<Uuid> = app.blocks.iter().map(|b| b.id).collect();
+
+        let sheet = Rect::from_min_size(Pos2::ZERO, Vec2::splat(200.0));
+        let ctx = egui::Context::default();
+        app.place_contact_sheet(PathBuf::from("/gone/sheet.png"), &sources, sheet, &ctx);
+        assert_eq!(app.blocks.len(), 1);
+        let trashed: Vec<Uuid> = app.trash.iter().map(|(b, _)| b.id).collect();
+        assert_eq!(trashed, sources);
+        assert!(app.trash.iter().all(|(b, _)| b.note.is_some()));
+    }
+
+    #[test]
+    fn eyedropper_samples_the_still_decoded_for_it() {
+        let mut app = CanvasApp {
+            canvas_rect: Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.0)),
+            ..CanvasApp::default()
+        };
+        app.blocks.push(Block {
+            pinned: true,
+            content: BlockContent::image_placeholder("/gone/photo.png".into()),
+            ..text_block("", (0.0, 0.0))
+        });
+        app.blocks[0].rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.0));
+        let (left, right) = (Pos2::new(25.0, 50.0), Pos2::new(75.0, 50.0));
+        assert_eq!(app.color_at(left), None);
+
+        let mut still = egui::ColorImage::new([2, 1], Color32::RED);
+        still.pixels[1] = Color32::BLUE;
+        app.eyedropper_still = Some((Uuid::new_v4(), Some(still.clone())));
+        assert_eq!(app.color_at(left), None);
+        app.eyedropper_still = Some((app.blocks[0].id, Some(still)));
+        assert_eq!(app.color_at(left), Some(Color32::RED));
+        assert_eq!(app.color_at(right), Some(Color32::BLUE));
+    }
+
+    #[test]
+    fn trashed_images_with_a_file_let_go_of_their_textures() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_trash_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("photo.png");
+        std::fs::write(&file, [0]).unwrap();
+
+        let ctx = egui::Context::default();
+        let texture = ctx.load_texture(
+            "photo",
+            egui::ColorImage::new([4, 4], Color32::RED),
+            Default::default(),
+        );
+        let mut app = CanvasApp::default();
+        for path in [Some(file.to_string_lossy().into_owned()), None] {
+            let mut content = BlockContent::image_placeholder(String::new());
+            if let BlockContent::Image {
+                path: p,
+                frames,
+                frame_delays,
+                ..
+            } = &mut content
+            {
+                *p = path;
+                *frames = vec![texture.clone()];
+                *frame_delays = vec![0.0];
             }
+            app.blocks.push(Block {
+                content,
+                ..text_block("", (0.0, 0.0))
+            });
         }
+        let ids: HashSet<Uuid> = app.blocks.iter().map(|b| b.id).collect();
+        app.move_to_trash(&ids);
+
+        let frame_counts: Vec<usize> = app
+            .trash
+            .iter()
+            .map(|(b, _)| match &b.content {
+                BlockContent::Image { frames, .. } => frames.len(),
+                _ => unreachable!(),
+            })
+            .collect();
+        // An image without a file couldn't be decoded again, so it keeps its frames
+        assert_eq!(frame_counts, [0, 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-        let time_now = ui.input(|i| i.time);
-        if self.blocks.iter().any(|b| b.chained) {
-            if time_now - self.last_chain_interaction > 10.0 {
-                for b in &mut self.blocks {
-                    b.chained = false;
-                }
-            } else {
-                ui.ctx()
-                    .request_repaint_after(std::time::Duration::from_secs_f64(
-                        (10.0 - (time_now - self.last_chain_interaction)).max(0.0),
-                    ));
+    #[test]
+    fn image_search_cycles_through_matching_file_names() {
+        let mut app = CanvasApp::default();
+        for (i, path) in ["/a/Beach.png", "/b/notes.txt.png", "/c/beach-2.jpg"]
+            .into_iter()
+            .enumerate()
+        {
+            app.blocks.push(Block {
+                content: BlockContent::image_placeholder(path.into()),
+                ..text_block("", (300.0 * i as f32, 0.0))
+            });
+        }
+        app.blocks.push(text_block("beach trip", (0.0, 200.0)));
+
+        app.filename_query = "BEACH".to_string();
+        assert_eq!(app.image_matches(&app.filename_query), [0, 2]);
+        app.find_image(false);
+        assert!(app.blocks[0].selected);
+        assert_eq!(app.viewport.pan, -app.blocks[0].rect.center().to_vec2());
+        app.find_image(true);
+        assert!(app.blocks[2].selected && !app.blocks[0].selected);
+        app.find_image(true);
+        assert!(app.blocks[0].selected);
+
+        // Only the file name counts, not the folders above it
+        assert!(app.image_matches("b/").is_empty());
+    }
+
+    #[test]
+    fn image_details_list_size_frames_and_file_size() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_details_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("anim.gif");
+        std::fs::write(&file, vec![0; 2048]).unwrap();
+
+        let mut content = BlockContent::image_placeholder(file.to_string_lossy().into());
+        if let BlockContent::Image {
+            aspect_ratio,
+            animation_state,
+            ..
+        } = &mut content
+        {
+            *aspect_ratio = 1.5;
+            *animation_state = AnimationState::Loading {
+                total_frame_count: 12,
+            };
+        }
+        assert_eq!(
+            content.image_details().as_deref(),
+            Some("Aspect ratio 1.50\n12 frames\n2.0 KB")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // A missing file just has no size
+        let gone = BlockContent::image_placeholder(file.to_string_lossy().into());
+        assert_eq!(gone.image_details().as_deref(), Some("Aspect ratio 1.00"));
+        let text = BlockContent::Text { text: "x".into() };
+        assert_eq!(text.image_details(), None);
+
+        let ctx = egui::Context::default();
+        let raster = ctx.load_texture(
+            "raster",
+            egui::ColorImage::new([600, 300], Color32::WHITE),
+            Default::default(),
+        );
+        for (path, size) in [
+            ("/gone/logo.svg", "600 × 300 px as rendered"),
+            ("/gone/photo.png", "600 × 300 px"),
+        ] {
+            let mut content = BlockContent::image_placeholder(path.into());
+            if let BlockContent::Image { frames, .. } = &mut content {
+                *frames = vec![raster.clone()];
             }
+            let details = content.image_details().unwrap();
+            assert_eq!(details.lines().next(), Some(size));
         }
+        assert_eq!(format_file_size(900), "900 bytes");
+        assert_eq!(format_file_size(3 * 1024 * 1024), "3.0 MB");
     }
 
-    fn spawn_text_block(&mut self, _ctx: &egui::Context) {
-        let center_world = -self.viewport.pan;
-        let size = Vec2::new(200.0, 100.0);
-        let pos = self.find_free_rect(center_world, size);
-        self.blocks.push(Block {
-            id: Uuid::new_v4(),
-            rect: Rect::from_min_size(pos.to_pos2(), size),
-            content: BlockContent::Text {
-                text: "Double click to edit...".to_string(),
-            },
-            chained: false,
-            selected: false,
-        });
+    #[test]
+    fn onion_skin_shows_the_frames_that_exist_either_side() {
+        assert_eq!(onion_frames(0, 5), [None, Some(1)]);
+        assert_eq!(onion_frames(2, 5), [Some(1), Some(3)]);
+        assert_eq!(onion_frames(4, 5), [Some(3), None]);
+        assert_eq!(onion_frames(0, 1), [None, None]);
     }
 
-    fn spawn_image_block(&mut self, _ctx: &egui::Context) {
-        let tx = self.file_dialog_tx.clone();
-        thread::spawn(move || {
-            if let Some(paths) = FileDialog::new()
-                .add_filter("Image", &["png", "jpg", "jpeg", "gif", "avif", "webp"])
-                .pick_files()
+    #[test]
+    fn fixed_frame_rates_are_saved_and_unusable_ones_dropped() {
+        let mut app = CanvasApp::default();
+        for delay in [Some(0.1), Some(0.0), None] {
+            let mut content = BlockContent::image_placeholder("/gone/anim.gif".into());
+            if let BlockContent::Image { delay_override, .. } = &mut content {
+                *delay_override = delay;
+            }
+            app.blocks.push(Block {
+                content,
+                ..text_block("", (0.0, 0.0))
+            });
+        }
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        let delays: Vec<Option<f64>> = loaded
+            .blocks
+            .iter()
+            .map(|b| match b.content {
+                BlockContent::Image { delay_override, .. } => delay_override,
+                BlockContent::Text { .. }
+                | BlockContent::Link { .. }
+                | BlockContent::Color { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(delays, [Some(0.1), None, None]);
+    }
+
+    #[test]
+    fn still_images_are_never_saved_or_loaded_as_playing() {
+        let mut app = CanvasApp::default();
+        for delays in [vec![0.0], vec![], vec![0.1, 0.1]] {
+            let mut content = BlockContent::image_placeholder("/gone/image.png".into());
+            if let BlockContent::Image {
+                frame_delays,
+                playing,
+                ..
+            } = &mut content
             {
-                let _ = tx.send(paths);
+                *frame_delays = delays;
+                *playing = true;
+            }
+            app.blocks.push(Block {
+                content,
+                ..text_block("", (0.0, 0.0))
+            });
+        }
+        assert!(app.blocks[0].content.is_still());
+        assert!(!app.blocks[1].content.is_still());
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        let playing: Vec<bool> = loaded
+            .blocks
+            .iter()
+            .map(|b| matches!(b.content, BlockContent::Image { playing, .. } if playing))
+            .collect();
+        // Only the still drops its flag; an undecoded image may yet turn out animated
+        assert_eq!(playing, [false, true, true]);
+    }
+
+    #[test]
+    fn dropped_chains_settle_as_one_piece_while_pushing_is_on() {
+        let mut app = CanvasApp::default();
+        assert!(app.settings.collision_mode == CollisionMode::Push && app.settings.rigid_chains);
+        for x in [0.0, 250.0] {
+            app.blocks.push(Block {
+                chained: true,
+                ..text_block("chained", (x, 0.0))
+            });
+        }
+        app.blocks.push(text_block("in the way", (100.0, 50.0)));
+        let obstacle = app.blocks[2].rect;
+
+        app.settle_drop(0);
+        assert_eq!(app.blocks[2].rect, obstacle);
+        assert!(!app.blocks[0].rect.intersects(obstacle));
+        assert!(!app.blocks[1].rect.intersects(obstacle));
+        let offset = app.blocks[1].rect.min - app.blocks[0].rect.min;
+        assert_eq!(offset, Vec2::new(250.0, 0.0));
+    }
+
+    #[test]
+    fn typed_positions_move_chained_blocks_together() {
+        let mut app = CanvasApp::default();
+        for (x, chained) in [(0.0, true), (250.0, true), (500.0, false)] {
+            app.blocks.push(Block {
+                chained,
+                ..text_block("", (x, 0.0))
+            });
+        }
+
+        app.move_block_to(0, Pos2::new(10.0, 300.0));
+        let corners: Vec<Pos2> = app.blocks.iter().map(|b| b.rect.min).collect();
+        assert_eq!(
+            corners,
+            [
+                Pos2::new(10.0, 300.0),
+                Pos2::new(260.0, 300.0),
+                Pos2::new(500.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn overlap_mode_leaves_dropped_blocks_where_they_land() {
+        let mut app = CanvasApp::default();
+        app.settings.collision_mode = CollisionMode::Overlap;
+        app.blocks.push(text_block("dropped", (0.0, 0.0)));
+        app.blocks.push(text_block("underneath", (50.0, 20.0)));
+        let rects: Vec<Rect> = app.blocks.iter().map(|b| b.rect).collect();
+
+        app.settle_drop(0);
+        let settled: Vec<Rect> = app.blocks.iter().map(|b| b.rect).collect();
+        assert_eq!(settled, rects);
+        assert!(settled[0].intersects(settled[1]));
+    }
+
+    #[test]
+    fn moving_a_block_shifts_the_ones_in_between() {
+        let mut app = CanvasApp::default();
+        for text in ["a", "b", "c", "d"] {
+            app.blocks.push(text_block(text, (0.0, 0.0)));
+        }
+        let order = |app: &CanvasApp| -> String {
+            app.blocks
+                .iter()
+                .filter_map(|b| match &b.content {
+                    BlockContent::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        app.move_block(0, 2);
+        assert_eq!(order(&app), "bcad");
+        app.move_block(3, 0);
+        assert_eq!(order(&app), "dbca");
+        app.move_block(1, 1);
+        assert_eq!(order(&app), "dbca");
+    }
+
+    #[test]
+    fn layers_list_pinned_blocks_first_as_they_are_drawn_on_top() {
+        let mut app = CanvasApp::default();
+        for pinned in [true, false, false, true] {
+            app.blocks.push(Block {
+                pinned,
+                ..text_block("block", (0.0, 0.0))
+            });
+        }
+        assert_eq!(app.layer_order(), [3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn chain_highlight_pulses_between_full_and_none() {
+        assert_eq!(pulse(0.0, 2.0), 1.0);
+        assert!(pulse(1.0, 2.0).abs() < 1e-6);
+        assert!((pulse(0.5, 2.0) - 0.5).abs() < 1e-6);
+        assert_eq!(pulse(4.0, 2.0), 1.0);
+    }
+
+    #[test]
+    fn new_blocks_fade_in_and_old_or_future_ones_are_opaque() {
+        assert_eq!(fade_in(1_000, 1_000), 0.0);
+        assert_eq!(fade_in(1_075, 1_000), 0.5);
+        assert_eq!(fade_in(5_000, 1_000), 1.0);
+        assert_eq!(fade_in(1_000, 2_000), 1.0);
+    }
+
+    #[test]
+    fn counters_stop_at_zero_unless_negative_counts_are_allowed() {
+        assert_eq!(decremented_counter(2, false), 1);
+        assert_eq!(decremented_counter(0, false), 0);
+        assert_eq!(decremented_counter(-3, false), 0);
+        assert_eq!(decremented_counter(0, true), -1);
+        assert_eq!(decremented_counter(i32::MIN, true), i32::MIN);
+    }
+
+    #[test]
+    fn counter_total_sums_the_selection_or_everything() {
+        let mut app = CanvasApp::default();
+        for (i, count) in [3, -1, i32::MAX].into_iter().enumerate() {
+            let mut content = BlockContent::image_placeholder("a.png".into());
+            if let BlockContent::Image { counter, .. } = &mut content {
+                *counter = count;
             }
+            app.blocks.push(Block {
+                content,
+                ..text_block("", (300.0 * i as f32, 0.0))
+            });
+        }
+        app.blocks.push(text_block("no counter", (0.0, 200.0)));
+
+        assert_eq!(app.counter_total(), (i64::from(i32::MAX) + 2, false));
+        app.blocks[0].selected = true;
+        app.blocks[1].selected = true;
+        assert_eq!(app.counter_total(), (2, true));
+    }
+
+    #[test]
+    fn shift_drags_keep_to_the_longer_axis() {
+        let mostly_across = Vec2::new(30.0, -10.0);
+        assert_eq!(constrain_to_axis(mostly_across), Vec2::new(30.0, 0.0));
+        let mostly_up = Vec2::new(5.0, -12.0);
+        assert_eq!(constrain_to_axis(mostly_up), Vec2::new(0.0, -12.0));
+        assert_eq!(constrain_to_axis(Vec2::ZERO), Vec2::ZERO);
+    }
+
+    #[test]
+    fn markdown_export_joins_blocks_in_reading_order() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("Second\n", (300.0, 0.0)));
+        app.blocks.push(Block {
+            content: BlockContent::image_placeholder("/shots/my cat.png".into()),
+            ..text_block("", (0.0, 200.0))
+        });
+        app.blocks.push(text_block("# First", (0.0, 0.0)));
+        // Pasted, so there is no file to link to
+        let mut pasted = BlockContent::image_placeholder(String::new());
+        if let BlockContent::Image { path, .. } = &mut pasted {
+            *path = None;
+        }
+        app.blocks.push(Block {
+            content: pasted,
+            ..text_block("", (300.0, 200.0))
         });
+
+        assert_eq!(
+            app.board_markdown(),
+            "# First\n\n---\n\nSecond\n\n---\n\n![my cat.png](</shots/my cat.png>)\n"
+        );
+        app.markdown_reading_order = false;
+        let z_order = app.board_markdown();
+        assert!(z_order.starts_with("Second\n\n---\n\n![my cat.png]"));
     }
 
-    fn load_image_file(&self, path: PathBuf, _ctx: egui::Context, target_block_id: Option<Uuid>) {
-        let tx = self.image_tx.clone();
-        let path_str = path.to_string_lossy().to_string();
+    #[test]
+    fn markdown_sections_become_a_column_of_text_blocks() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("in the way", (0.0, 0.0)));
+        let long = "word ".repeat(200);
+        let added = app.add_markdown_sections(&format!("# A\nshort\n\n---\n# B\n{long}"));
+
+        assert_eq!(added, 2);
+        let (a, b) = (&app.blocks[1], &app.blocks[2]);
+        assert!(matches!(&a.content, BlockContent::Text { text } if text == "# A\nshort"));
+        assert!(!a.rect.intersects(app.blocks[0].rect));
+        assert_eq!(a.rect.width(), MARKDOWN_IMPORT_WIDTH);
+        assert_eq!(b.rect.left(), a.rect.left());
+        assert_eq!(b.rect.top(), a.rect.bottom() + IMPORT_GRID_GAP);
+        // A long section gets a taller block
+        assert!(b.rect.height() > a.rect.height());
+    }
 
-        thread::spawn(move || {
-            let is_gif = path
-                .extension()
-                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "gif");
-            let is_avif = path
-                .extension()
-                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "avif");
-            let is_webp = path
-                .extension()
-                .is_some_and(|e| e.to_string_lossy().to_lowercase() == "webp");
-            let mut frames_data = vec![];
-            let mut delays = vec![];
-            let mut aspect = 1.0;
+    #[test]
+    fn link_blocks_are_saved_and_exported_under_their_title() {
+        let mut app = CanvasApp::default();
+        app.spawn_link_block("https://example.com".to_string());
+        assert_eq!(
+            app.blocks[0].content.accessible_label(),
+            "Link block: https://example.com"
+        );
+        if let BlockContent::Link {
+            title, description, ..
+        } = &mut app.blocks[0].content
+        {
+            *title = "Example".to_string();
+            *description = "For use in examples".to_string();
+        }
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        assert!(matches!(
+            &loaded.blocks[0].content,
+            BlockContent::Link { url, title, .. } if url == "https://example.com" && title == "Example"
+        ));
+        assert!(matches!(
+            &loaded.blocks[0].content,
+            BlockContent::Link { description, .. } if description == "For use in examples"
+        ));
+        let markdown = loaded.board_markdown();
+        assert_eq!(markdown, "[Example](<https://example.com>)\n");
+    }
+
+    #[test]
+    fn color_swatches_keep_their_color_and_name_it_in_hex() {
+        let mut app = CanvasApp::default();
+        let color = Color32::from_rgba_unmultiplied(0x12, 0xab, 0xff, 0x80);
+        let swatch = BlockContent::Color { color, label: true };
+        app.spawn_block(swatch, Vec2::ZERO, Vec2::splat(SWATCH_SIZE));
+        assert_eq!(
+            app.blocks[0].content.accessible_label(),
+            "Color swatch: #12abff80"
+        );
+
+        let loaded = load_json(&serde_json::to_string(&app.to_session()).unwrap());
+        assert!(matches!(
+            loaded.blocks[0].content,
+            BlockContent::Color { color: c, label: true } if c == color
+        ));
+        assert_eq!(color_hex(Color32::from_rgb(255, 0, 16)), "#ff0010");
+        assert_eq!(contrasting_text(Color32::YELLOW), Color32::BLACK);
+        assert_eq!(contrasting_text(Color32::DARK_BLUE), Color32::WHITE);
+    }
+
+    #[test]
+    fn pasted_text_lands_under_the_pointer_ready_to_edit() {
+        let mut app = CanvasApp::default();
+        let text = "first line\nsecond";
+        app.add_pasted_text(text.to_string(), Some(Pos2::new(30.0, 40.0)));
+        let block = &app.blocks[0];
+        assert_eq!(block.rect.min, Pos2::new(30.0, 40.0));
+        assert_eq!(block.rect.width(), MARKDOWN_IMPORT_WIDTH);
+        assert!(matches!(&block.content, BlockContent::Text { text: t } if t == text));
+        assert_eq!(app.editing_id, Some(block.id));
+
+        // Without a pointer over the canvas it goes to the middle of the view
+        app.add_pasted_text("more".to_string(), None);
+        assert!(!app.blocks[1].rect.intersects(app.blocks[0].rect));
+        assert_eq!(app.editing_id, Some(app.blocks[1].id));
+    }
+
+    #[test]
+    fn exported_selection_keeps_relative_positions_at_origin() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("left", (100.0, 300.0)));
+        app.blocks.push(text_block("skipped", (0.0, 0.0)));
+        app.blocks.push(text_block("right", (500.0, 250.0)));
+        app.blocks[0].selected = true;
+        app.blocks[2].selected = true;
+
+        let session = app.selection_to_session(true).unwrap();
+        let rects: Vec<[f32; 4]> = session.blocks.iter().map(|b| b.rect).collect();
+        let size = app.blocks[0].rect.size();
+        assert_eq!(
+            rects,
+            vec![
+                [0.0, 50.0, size.x, 50.0 + size.y],
+                [400.0, 0.0, 400.0 + size.x, size.y],
+            ]
+        );
+
+        let in_place = app.selection_to_session(false).unwrap();
+        assert_eq!(in_place.blocks[1].rect, rect_to_session(app.blocks[2].rect));
+
+        for b in &mut app.blocks {
+            b.selected = false;
+        }
+        assert!(app.selection_to_session(true).is_none());
+    }
 
-            if is_gif {
-                match File::open(&path) {
-                    Ok(mut file) => {
-                        let mut buffer = Vec::new();
-                        if file.read_to_end(&mut buffer).is_ok() {
-                            if let Some(preview) = image_decoder::decode_gif_first_frame(&buffer) {
-                                if preview.total_frame_count > 1 {
-                                    // Animated GIF - send preview only
-                                    let first_frame = egui::ColorImage::from_rgba_unmultiplied(
-                                        [preview.frame.width as usize, preview.frame.height as usize],
-                                        &preview.frame.pixels,
-                                    );
-                                    let _ = tx.send(ImageLoadData::GifPreview {
-                                        first_frame,
-                                        frame_durations: preview.frame_durations,
-                                        aspect_ratio: preview.aspect_ratio,
-                                        total_frame_count: preview.total_frame_count,
-                                        path: Some(path_str),
-                                        target_block_id,
-                                    });
-                                    return;
-                                } else {
-                                    // Static GIF - just one frame
-                                    let size = [preview.frame.width as usize, preview.frame.height as usize];
-                                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
-                                        size,
-                                        &preview.frame.pixels,
-                                    ));
-                                    delays.push(preview.frame.duration);
-                                    aspect = preview.aspect_ratio;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => eprintln!("GIF open error: {e}"),
-                }
-            } else if is_avif {
-                match File::open(&path) {
-                    Ok(mut file) => {
-                        let mut buffer = Vec::new();
-                        if file.read_to_end(&mut buffer).is_ok() {
-                            // For animated AVIFs (>1 frame), use lazy loading
-                            // Load only first frame immediately, rest on demand
-                            if let Some(preview) = image_decoder::decode_avif_first_frame(&buffer) {
-                                if preview.total_frame_count > 1 {
-                                    // Animated AVIF - send preview only
-                                    let first_frame = egui::ColorImage::from_rgba_unmultiplied(
-                                        [
-                                            preview.frame.width as usize,
-                                            preview.frame.height as usize,
-                                        ],
-                                        &preview.frame.pixels,
-                                    );
-                                     let _ = tx.send(ImageLoadData::AvifPreview {
-                                         first_frame,
-                                         frame_durations: preview.frame_durations,
-                                         aspect_ratio: preview.aspect_ratio,
-                                         total_frame_count: preview.total_frame_count,
-                                         path: Some(path_str),
-                                         target_block_id,
-                                     });
-                                    return;
-                                } else {
-                                    // Static AVIF - just one frame
-                                    let size = [
-                                        preview.frame.width as usize,
-                                        preview.frame.height as usize,
-                                    ];
-                                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
-                                        size,
-                                        &preview.frame.pixels,
-                                    ));
-                                    delays.push(preview.frame.duration);
-                                    aspect = preview.aspect_ratio;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => eprintln!("AVIF open error: {e}"),
+    #[test]
+    fn import_appends_blocks_with_new_ids_in_free_space() {
+        let ctx = egui::Context::default();
+        let mut source = CanvasApp::default();
+        source.blocks.push(text_block("a", (0.0, 0.0)));
+        source.blocks.push(text_block("b", (300.0, 40.0)));
+        source.custom_words = vec!["egui".to_string(), "rustfmt".to_string()];
+        let session = source.to_session();
+
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("existing", (-100.0, -50.0)));
+        app.custom_words = vec!["rustfmt".to_string()];
+        app.viewport.zoom = 2.0;
+        app.import_session(session, &ctx);
+
+        assert_eq!(app.blocks.len(), 3);
+        assert_eq!(app.viewport.zoom, 2.0);
+        let imported = &app.blocks[1..];
+        assert!(imported.iter().all(|b| b.selected));
+        assert!(imported
+            .iter()
+            .all(|b| source.blocks.iter().all(|s| s.id != b.id)));
+        // Moved as a whole, clear of what was already there
+        assert_eq!(
+            imported[1].rect.min - imported[0].rect.min,
+            source.blocks[1].rect.min - source.blocks[0].rect.min
+        );
+        assert!(imported
+            .iter()
+            .all(|b| !b.rect.intersects(app.blocks[0].rect)));
+        assert_eq!(app.custom_words, ["rustfmt", "egui"]);
+    }
+
+    #[test]
+    fn images_that_fail_to_decode_leave_no_placeholder_behind() {
+        let ctx = egui::Context::default();
+        let dir = std::env::temp_dir().join(format!("ma_blocks_broken_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not a png").unwrap();
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("kept", (0.0, 0.0)));
+
+        app.import_images_grid(vec![broken], &ctx, 1);
+        assert_eq!(app.blocks.len(), 2);
+        let message = app
+            .image_rx
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let ImageLoadData::Failed {
+            target_block_id,
+            path,
+        } = message
+        else {
+            panic!("expected the decode to fail");
+        };
+        app.image_failed(target_block_id, &path, &ctx);
+
+        assert_eq!(app.blocks.len(), 1);
+        assert!(app.pending_fit.is_empty());
+        let (message, _) = app.notification.unwrap();
+        assert_eq!(message, "Could not load broken.png");
+    }
+
+    #[test]
+    fn edits_mark_the_canvas_dirty_in_the_title() {
+        let mut app = CanvasApp::default();
+        assert!(!app.is_dirty());
+        app.blocks.push(text_block("note", (0.0, 0.0)));
+        assert!(app.is_dirty());
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        let mut loaded = load_json(&json);
+        assert!(!loaded.is_dirty());
+
+        loaded.blocks[0].rect = loaded.blocks[0].rect.translate(Vec2::new(5.0, 0.0));
+        assert!(loaded.is_dirty());
+        loaded.blocks[0].rect = loaded.blocks[0].rect.translate(Vec2::new(-5.0, 0.0));
+        assert!(!loaded.is_dirty());
+
+        assert_eq!(loaded.title(loaded.is_dirty()), "Untitled - MA Blocks");
+        loaded.session_path = Some(PathBuf::from("/boards/board.json"));
+        // Selecting isn't an edit
+        loaded.blocks[0].selected = true;
+        assert_eq!(loaded.title(loaded.is_dirty()), "board.json - MA Blocks");
+
+        // Looking around isn't an edit
+        loaded.viewport.zoom = 3.0;
+        assert!(!loaded.is_dirty());
+        loaded.background.pattern = BackgroundPattern::Lines;
+        assert!(loaded.is_dirty());
+        assert_eq!(loaded.title(loaded.is_dirty()), "board.json* - MA Blocks");
+    }
+
+    #[test]
+    fn new_boards_wait_for_unsaved_changes_to_be_dealt_with() {
+        let ctx = egui::Context::default();
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("note", (0.0, 0.0)));
+        app.replace_canvas(Replacement::New, &ctx);
+        assert_eq!(app.confirm_replace, Some(Replacement::New));
+        assert_eq!(app.blocks.len(), 1);
+
+        app.replace_canvas_unchecked(Replacement::New, &ctx);
+        assert!(app.blocks.is_empty());
+        assert!(!app.is_dirty());
+        app.confirm_replace = None;
+        app.replace_canvas(Replacement::New, &ctx);
+        assert_eq!(app.confirm_replace, None);
+    }
+
+    #[test]
+    fn every_saved_change_marks_the_canvas_dirty() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            content: BlockContent::image_placeholder("/gone/photo.png".into()),
+            ..text_block("", (0.0, 0.0))
+        });
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        let edits: [fn(&mut Block); 6] = [
+            |b| b.note = Some("later".into()),
+            |b| b.opacity = 128,
+            |b| b.slide = Some(1),
+            |b| {
+                if let BlockContent::Image { crop, .. } = &mut b.content {
+                    *crop = Rect::from_min_max(Pos2::new(0.25, 0.0), Pos2::new(1.0, 0.5));
                 }
-            } else if is_webp {
-                if let Ok(mut file) = File::open(&path) {
-                    let mut buffer = Vec::new();
-                    if file.read_to_end(&mut buffer).is_ok() {
-                        if let Some(preview) = image_decoder::decode_webp_first_frame(&buffer) {
-                            if preview.total_frame_count > 1 {
-                                // Animated WebP - send preview only
-                                let first_frame = egui::ColorImage::from_rgba_unmultiplied(
-                                    [preview.frame.width as usize, preview.frame.height as usize],
-                                    &preview.frame.pixels,
-                                );
-                                let _ = tx.send(ImageLoadData::WebpPreview {
-                                    first_frame,
-                                    frame_durations: preview.frame_durations,
-                                    aspect_ratio: preview.aspect_ratio,
-                                    total_frame_count: preview.total_frame_count,
-                                    path: Some(path_str),
-                                    target_block_id,
-                                });
-                                return;
-                            } else {
-                                // Static WebP - just one frame
-                                let size = [preview.frame.width as usize, preview.frame.height as usize];
-                                frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
-                                    size,
-                                    &preview.frame.pixels,
-                                ));
-                                delays.push(preview.frame.duration);
-                                aspect = preview.aspect_ratio;
-                            }
-                        }
-                    }
+            },
+            |b| {
+                if let BlockContent::Image { adjust, .. } = &mut b.content {
+                    adjust.contrast = -0.5;
                 }
-            } else if let Ok(img) = image::open(&path) {
-                let buffer = img.to_rgba8();
-                let size = [buffer.width() as usize, buffer.height() as usize];
-                if size[0] > 0 && size[1] > 0 {
-                    aspect = size[0] as f32 / size[1] as f32;
-                    frames_data.push(egui::ColorImage::from_rgba_unmultiplied(
-                        size,
-                        buffer.as_raw(),
-                    ));
-                    delays.push(0.0);
+            },
+            |b| {
+                if let BlockContent::Image { direction, .. } = &mut b.content {
+                    *direction = Direction::PingPong;
                 }
-            }
+            },
+        ];
+        for edit in edits {
+            let mut loaded = load_json(&json);
+            assert!(!loaded.is_dirty());
+            edit(&mut loaded.blocks[0]);
+            assert!(loaded.is_dirty());
+        }
+    }
 
-            if !frames_data.is_empty() {
-                let _ = tx.send(ImageLoadData::Complete {
-                    frames: frames_data,
-                    frame_delays: delays,
-                    aspect_ratio: aspect,
-                    path: Some(path_str),
-                    target_block_id,
-                });
+    #[test]
+    fn sequential_ids_are_reproducible_and_skip_loaded_ones() {
+        let ctx = egui::Context::default();
+        let spawn_three = || {
+            let mut app = CanvasApp {
+                id_generator: IdGenerator::Sequential(0),
+                ..CanvasApp::default()
+            };
+            for _ in 0..3 {
+                app.spawn_text_block(&ctx);
             }
+            app
+        };
+        let ids = |app: &CanvasApp| app.blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        let app = spawn_three();
+        assert_eq!(ids(&app), ids(&spawn_three()));
+        assert_eq!(app.blocks[0].id, Uuid::from_u128(1));
+
+        let mut loaded = CanvasApp {
+            id_generator: IdGenerator::Sequential(0),
+            ..CanvasApp::default()
+        };
+        loaded.apply_session(app.to_session(), &ctx);
+        loaded.spawn_text_block(&ctx);
+        assert_eq!(loaded.blocks[3].id, Uuid::from_u128(4));
+
+        // Nothing is left to count up to past the largest id
+        let mut ids = IdGenerator::Sequential(0);
+        ids.observe(Uuid::from_u128(u128::MAX));
+        let (a, b) = (ids.next_id(), ids.next_id());
+        assert!(a != b && a.as_u128() != u128::MAX && b.as_u128() != u128::MAX);
+    }
+
+    #[test]
+    fn render_png_draws_blocks_inside_the_margin() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_export_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("red.png");
+        image::RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255]))
+            .save(&image_path)
+            .unwrap();
+
+        let mut app = CanvasApp::default();
+        app.blocks
+            .push(text_block("# Title\nsome <text> & more", (0.0, 0.0)));
+        app.blocks.push(Block {
+            rect: Rect::from_min_size(Pos2::new(300.0, 0.0), Vec2::new(200.0, 100.0)),
+            content: BlockContent::image_placeholder(image_path.to_string_lossy().to_string()),
+            ..text_block("", (0.0, 0.0))
         });
+        let out = dir.join("board.png");
+
+        export::render_png(&app.to_session(), &out, 1.0).unwrap();
+
+        let png = image::open(&out).unwrap().to_rgba8();
+        // 500x100 of blocks plus a 20 unit margin on every side
+        assert_eq!(png.dimensions(), (540, 140));
+        assert_eq!(png.get_pixel(5, 5).0, [30, 30, 30, 255]);
+        assert_eq!(png.get_pixel(420, 70).0, [255, 0, 0, 255]);
+
+        // The export scale applies whatever the view's zoom is
+        app.viewport.zoom = 0.5;
+        export::render_png(&app.to_session(), &out, 2.0).unwrap();
+        let png = image::open(&out).unwrap().to_rgba8();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(png.dimensions(), (1080, 280));
+        assert_eq!(png.get_pixel(840, 140).0, [255, 0, 0, 255]);
     }
 
-    fn find_free_rect(&self, start_pos: Vec2, size: Vec2) -> Vec2 {
-        let spacing = 20.0;
-        let step_x = size.x + spacing;
-        let step_y = size.y + spacing;
-
-        // Try positions in a spiral pattern around start_pos
-        // First try the start position itself
-        let candidate = Rect::from_min_size(start_pos.to_pos2(), size);
-        if !self.blocks.iter().any(|b| b.rect.intersects(candidate)) {
-            return start_pos;
-        }
-
-        // Then try in expanding rings around start position
-        for ring in 1..20 {
-            let ring_f = ring as f32;
-            // Try positions along each ring (right, down, left, up pattern)
-            for i in 0..(ring * 8) {
-                let (dx, dy) = match i / (ring * 2) {
-                    0 => (i % (ring * 2), 0),                     // top edge, going right
-                    1 => (ring * 2, i % (ring * 2)),              // right edge, going down
-                    2 => (ring * 2 - (i % (ring * 2)), ring * 2), // bottom edge, going left
-                    _ => (0, ring * 2 - (i % (ring * 2))),        // left edge, going up
-                };
+    #[test]
+    fn svg_export_keeps_text_and_embeds_images() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_svg_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("red.png");
+        image::RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255]))
+            .save(&image_path)
+            .unwrap();
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("a < b", (0.0, 0.0)));
+        app.blocks.push(Block {
+            content: BlockContent::image_placeholder(image_path.to_string_lossy().to_string()),
+            ..text_block("", (300.0, 0.0))
+        });
+        let out = dir.join("board.svg");
 
-                let offset =
-                    Vec2::new((dx as f32 - ring_f) * step_x, (dy as f32 - ring_f) * step_y);
-                let pos = start_pos + offset;
-                let candidate = Rect::from_min_size(pos.to_pos2(), size);
+        export::write_svg(&app.to_session(), &out, 2.0).unwrap();
 
-                if !self.blocks.iter().any(|b| b.rect.intersects(candidate)) {
-                    return pos;
-                }
-            }
+        let svg = std::fs::read_to_string(&out).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(svg.contains(r#"viewBox="-20 -20 540 140" width="1080" height="280""#));
+        assert!(svg.contains(r#"rx="5""#));
+        assert!(svg.contains(">a &lt; b</tspan>"));
+        assert!(svg.contains(r#"<image x="300" y="0" width="200" height="100""#));
+        assert!(svg.contains("data:image/png;base64,"));
+    }
+
+    #[test]
+    fn exported_images_are_cropped_and_adjusted_like_on_the_canvas() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_crop_export_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("red_blue.png");
+        // Red left half, blue right half
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        image::RgbaImage::from_fn(4, 2, |x, _| image::Rgba(if x < 2 { red } else { blue }))
+            .save(&image_path)
+            .unwrap();
+
+        let mut app = CanvasApp::default();
+        let mut content = BlockContent::image_placeholder(image_path.to_string_lossy().into());
+        if let BlockContent::Image { crop, .. } = &mut content {
+            *crop = Rect::from_min_max(Pos2::new(0.5, 0.0), Pos2::new(1.0, 1.0));
         }
+        app.blocks.push(Block {
+            content,
+            ..text_block("", (0.0, 0.0))
+        });
+        let out = dir.join("board.png");
 
-        // Fallback: place with simple offset
-        start_pos + Vec2::new(step_x, 0.0)
+        // Only the kept blue half fills the block
+        export::render_png(&app.to_session(), &out, 1.0).unwrap();
+        let png = image::open(&out).unwrap().to_rgba8();
+        assert_eq!(png.get_pixel(40, 70).0, [0, 0, 255, 255]);
+        assert_eq!(png.get_pixel(200, 70).0, [0, 0, 255, 255]);
+
+        if let BlockContent::Image { adjust, .. } = &mut app.blocks[0].content {
+            adjust.grayscale = true;
+        }
+        export::render_png(&app.to_session(), &out, 1.0).unwrap();
+        let png = image::open(&out).unwrap().to_rgba8();
+        let _ = std::fs::remove_dir_all(&dir);
+        let [r, g, b, _] = png.get_pixel(120, 70).0;
+        assert!(r == g && g == b && r > 0);
     }
 
-    fn pause_oldest_animation(&mut self) {
-        let mut oldest_idx = None;
-        let mut oldest_time = f64::INFINITY;
-        for (i, block) in self.blocks.iter().enumerate() {
-            if let BlockContent::Image { playing, playing_start_time, .. } = &block.content {
-                if *playing {
-                    if let Some(start) = playing_start_time {
-                        if *start < oldest_time {
-                            oldest_time = *start;
-                            oldest_idx = Some(i);
-                        }
-                    }
+    #[test]
+    fn render_png_rejects_an_empty_session() {
+        let out = std::env::temp_dir().join("ma_blocks_empty_export.png");
+        assert!(export::render_png(&CanvasApp::default().to_session(), &out, 1.0).is_err());
+    }
+
+    /// Two-frame APNG: a red frame shown for 1/10 s, then a blue one for 1/4 s
+    fn two_frame_apng() -> Vec<u8> {
+        fn crc32(bytes: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for &byte in bytes {
+                crc ^= byte as u32;
+                for _ in 0..8 {
+                    crc = if crc & 1 == 1 {
+                        (crc >> 1) ^ 0xEDB8_8320
+                    } else {
+                        crc >> 1
+                    };
                 }
             }
+            !crc
         }
-        if let Some(idx) = oldest_idx {
-            if let BlockContent::Image { playing, animation_state, frame_delays, aspect_ratio, path, .. } = &mut self.blocks[idx].content {
-                *playing = false;
-                *animation_state = AnimationState::Paused {
-                    total_frame_count: frame_delays.len(),
-                    frame_durations: frame_delays.clone(),
-                    aspect_ratio: *aspect_ratio,
-                    path: path.clone().unwrap_or_default(),
-                    format: image_decoder::ImageFormat::Avif, // dummy
-                };
-                self.current_concurrent_animations -= 1;
+        fn chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+            out.extend((data.len() as u32).to_be_bytes());
+            let start = out.len();
+            out.extend(kind);
+            out.extend(data);
+            let crc = crc32(&out[start..]);
+            out.extend(crc.to_be_bytes());
+        }
+        // Pixel data of a 2x1 single color PNG
+        fn idat(color: [u8; 4]) -> Vec<u8> {
+            let mut png = Vec::new();
+            image::RgbaImage::from_pixel(2, 1, image::Rgba(color))
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            let at = png.windows(4).position(|w| w == b"IDAT").unwrap();
+            let len = u32::from_be_bytes(png[at - 4..at].try_into().unwrap()) as usize;
+            png[at + 4..at + 4 + len].to_vec()
+        }
+        fn fctl(sequence: u32, delay: (u16, u16)) -> Vec<u8> {
+            let mut data = Vec::new();
+            for value in [sequence, 2, 1, 0, 0] {
+                data.extend(value.to_be_bytes());
             }
+            data.extend(delay.0.to_be_bytes());
+            data.extend(delay.1.to_be_bytes());
+            data.extend([0, 0]);
+            data
         }
+
+        let mut apng = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend(2u32.to_be_bytes());
+        ihdr.extend(1u32.to_be_bytes());
+        ihdr.extend([8, 6, 0, 0, 0]);
+        chunk(&mut apng, b"IHDR", &ihdr);
+        chunk(&mut apng, b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+        chunk(&mut apng, b"fcTL", &fctl(0, (1, 10)));
+        chunk(&mut apng, b"IDAT", &idat([255, 0, 0, 255]));
+        chunk(&mut apng, b"fcTL", &fctl(1, (25, 0)));
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend(idat([0, 0, 255, 255]));
+        chunk(&mut apng, b"fdAT", &fdat);
+        chunk(&mut apng, b"IEND", &[]);
+        apng
     }
 
-    fn save_session(&self) {
-        if let Some(mut path) = FileDialog::new().add_filter("JSON", &["json"]).save_file() {
-            if path.extension().is_none() {
-                path.set_extension("json");
-            }
+    #[test]
+    fn animated_pngs_decode_every_frame_and_regular_ones_are_left_alone() {
+        let apng = two_frame_apng();
+        let preview = image_decoder::decode_apng_first_frame(&apng).unwrap();
+        assert_eq!(preview.total_frame_count, 2);
+        assert_eq!(preview.frame_durations, vec![0.1, 0.25]);
+        assert_eq!(&preview.frame.pixels[..4], &[255, 0, 0, 255]);
+
+        let frames = image_decoder::decode_apng(&apng).unwrap().frames;
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[1].width, frames[1].height), (2, 1));
+        assert_eq!(&frames[1].pixels[..4], &[0, 0, 255, 255]);
+        assert!((frames[1].duration - 0.25).abs() < 1e-9);
+
+        let mut png = Vec::new();
+        image::RgbaImage::new(2, 1)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        assert!(image_decoder::decode_apng_first_frame(&png).is_none());
+        assert!(image_decoder::decode_apng(&png).is_none());
+    }
 
-            let session = Session {
-                viewport: ViewportData {
-                    pan: [self.viewport.pan.x, self.viewport.pan.y],
-                    zoom: self.viewport.zoom,
-                },
-                blocks: self
-                    .blocks
-                    .iter()
-                    .map(|b| BlockData {
-                        id: b.id,
-                        rect: [b.rect.min.x, b.rect.min.y, b.rect.max.x, b.rect.max.y],
-                        chained: b.chained,
-                        content: match &b.content {
-                            BlockContent::Text { text } => {
-                                BlockContentData::Text { text: text.clone() }
-                            }
-                            BlockContent::Image {
-                                path,
-                                counter,
-                                playing,
-                                ..
-                            } => BlockContentData::Image {
-                                path: path.clone().unwrap_or_default(),
-                                counter: *counter,
-                                playing: *playing,
-                            },
-                        },
-                    })
-                    .collect(),
-            };
+    #[test]
+    fn dragging_with_the_middle_button_or_the_pan_key_pans_instead_of_moving() {
+        // (middle, primary, pan key)
+        assert!(is_pan_gesture(true, false, false));
+        assert!(is_pan_gesture(true, true, false));
+        assert!(is_pan_gesture(false, true, true));
+        // A plain primary drag moves the block, holding the pan key alone does nothing
+        assert!(!is_pan_gesture(false, true, false));
+        assert!(!is_pan_gesture(false, false, true));
+        assert!(!is_pan_gesture(false, false, false));
+    }
 
-            if let Ok(file) = File::create(path) {
-                let _ = serde_json::to_writer_pretty(file, &session);
-            }
+    /// Storage kept in memory, for the settings tests
+    #[derive(Default)]
+    struct MemoryStorage(BTreeMap<String, String>);
+
+    impl eframe::Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
         }
+
+        fn flush(&mut self) {}
     }
 
-    fn reset_all_counters(&mut self) {
-        for block in &mut self.blocks {
-            if let BlockContent::Image { counter, .. } = &mut block.content {
-                *counter = 0;
+    #[test]
+    fn settings_are_carried_over_from_the_keys_older_versions_saved() {
+        let mut storage = MemoryStorage::default();
+        eframe::set_value(&mut storage, THEME_KEY, &egui::Theme::Light);
+        eframe::set_value(&mut storage, ZOOM_LIMITS_KEY, &(0.5f32, 2.0f32));
+        eframe::set_value(&mut storage, PAN_INERTIA_KEY, &false);
+        let settings = Settings::load(&storage);
+        assert_eq!(
+            settings,
+            Settings {
+                theme: egui::Theme::Light,
+                zoom_limits: (0.5, 2.0),
+                pan_inertia: false,
+                ..Default::default()
             }
+        );
+
+        // Once saved together, the old keys are ignored and missing fields get defaults
+        let saved = "(grid_spacing: 80.0, collision_mode: Overlap)";
+        eframe::Storage::set_string(&mut storage, SETTINGS_KEY, saved.into());
+        let settings = Settings::load(&storage);
+        assert_eq!(settings.grid_spacing, 80.0);
+        assert_eq!(settings.collision_mode, CollisionMode::Overlap);
+        assert_eq!(settings.theme, egui::Theme::Dark);
+    }
+
+    #[test]
+    fn applied_settings_are_kept_in_bounds() {
+        let mut app = CanvasApp::default();
+        app.settings.zoom_limits = (3.0, 0.5);
+        app.settings.grid_spacing = 0.0;
+        app.settings.max_concurrent_animations = 0;
+        app.settings.import_columns = 0;
+        app.apply_settings(&egui::Context::default());
+        assert_eq!(app.settings.zoom_limits, (3.0, 3.0));
+        assert_eq!(app.viewport.min_zoom, 3.0);
+        assert_eq!(app.settings.grid_spacing, GRID_SPACING);
+        assert_eq!(app.settings.max_concurrent_animations, 1);
+        assert_eq!(app.settings.import_columns, 1);
+    }
+
+    #[test]
+    fn held_keys_pan_and_zoom_at_a_steady_rate() {
+        let center = Pos2::new(400.0, 300.0);
+        let held = |actions: &'static [Action]| move |a| actions.contains(&a);
+        assert!(key_view_actions(held(&[Action::Save]), 0.5, center).is_empty());
+
+        // Looking up and left moves the world down and right
+        let actions = key_view_actions(held(&[Action::LookUp, Action::LookLeft]), 0.5, center);
+        let mut viewport = Viewport::default();
+        for action in actions {
+            viewport.apply(center, action);
         }
+        assert_eq!(viewport.pan, Vec2::splat(KEY_PAN_SPEED * 0.5));
+
+        let zoom_in = key_view_actions(held(&[Action::ZoomIn]), 1.0, center);
+        assert_eq!(
+            zoom_in,
+            [ViewAction::Zoom {
+                anchor: center,
+                factor: KEY_ZOOM_SPEED
+            }]
+        );
     }
 
-    fn load_session(&mut self) {
-        if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
-            if let Ok(file) = File::open(path) {
-                if let Ok(session) = serde_json::from_reader::<_, Session>(BufReader::new(file)) {
-                    self.viewport.pan = Vec2::new(session.viewport.pan[0], session.viewport.pan[1]);
-                    self.viewport.zoom = session.viewport.zoom;
-                    self.blocks.clear();
+    #[test]
+    fn blocks_have_accessible_labels() {
+        let text = BlockContent::Text {
+            text: "# Plan\n\n- [ ] call   back".to_string(),
+        };
+        let label = text.accessible_label();
+        assert_eq!(label, "Text block: # Plan - [ ] call back");
+        let long = BlockContent::Text {
+            text: "a".repeat(ACCESSIBLE_TEXT_CHARS + 10),
+        };
+        assert!(long.accessible_label().ends_with("a…"));
 
-                    for b_data in session.blocks {
-                        let rect = Rect::from_min_max(
-                            Pos2::new(b_data.rect[0], b_data.rect[1]),
-                            Pos2::new(b_data.rect[2], b_data.rect[3]),
-                        );
+        let image = BlockContent::image_placeholder("/photos/cat.png".to_string());
+        assert_eq!(image.accessible_label(), "Image block: cat.png");
+    }
 
-                        let content = match b_data.content {
-                            BlockContentData::Text { text } => BlockContent::Text { text },
-                            BlockContentData::Image {
-                                path,
-                                counter,
-                                playing,
-                            } => {
-                                // Trigger async load
-                                if !path.is_empty() {
-                                    self.load_image_file(
-                                        PathBuf::from(&path),
-                                        egui::Context::default(),
-                                        Some(b_data.id),
-                                    );
-                                }
-                                // Create placeholder
-                                BlockContent::Image {
-                                    frames: vec![],
-                                    frame_delays: vec![],
-                                    aspect_ratio: 1.0,
-                                    playing,
-                                    current_frame_idx: 0,
-                                    last_frame_time: 0.0,
-                                counter,
-                                path: Some(path),
-                                animation_state: AnimationState::Ready,
-                                first_frame: None,
-                                playing_start_time: None,
-                            }
-                            }
-                        };
+    #[test]
+    fn keymap_keeps_only_changed_shortcuts() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            keymap.shortcut(Action::Duplicate),
+            egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::D)
+        );
+
+        let f = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F);
+        keymap.bind(Action::Delete, f);
+        assert_eq!(keymap.shortcut(Action::Delete), f);
+        assert_eq!(keymap.conflicts(Action::Delete), [Action::FitBlock]);
+
+        let json = serde_json::to_string(&keymap).unwrap();
+        let loaded: Keymap = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.shortcut(Action::Delete), f);
+
+        // Binding the default again forgets the change
+        keymap.bind(Action::Delete, Action::Delete.default_shortcut());
+        assert!(keymap.changed.is_empty());
+    }
 
-                        self.blocks.push(Block {
-                            id: b_data.id,
-                            rect,
-                            content,
-                            chained: b_data.chained,
-                            selected: false,
-                        });
-                    }
-                }
+    #[test]
+    fn select_all_then_invert_leaves_nothing_selected() {
+        let mut app = CanvasApp::default();
+        for x in [0.0, 300.0, 600.0] {
+            app.blocks.push(text_block("block", (x, 0.0)));
+        }
+        app.blocks[1].selected = true;
+        app.invert_selection();
+        let selected: Vec<bool> = app.blocks.iter().map(|b| b.selected).collect();
+        assert_eq!(selected, [true, false, true]);
+
+        app.select_all();
+        assert!(app.blocks.iter().all(|b| b.selected));
+        app.invert_selection();
+        assert!(app.blocks.iter().all(|b| !b.selected));
+    }
+
+    #[test]
+    fn tab_walks_the_blocks_in_reading_order_and_wraps() {
+        let mut app = CanvasApp::default();
+        app.blocks.push(text_block("second", (300.0, 0.0)));
+        app.blocks.push(text_block("third", (0.0, 200.0)));
+        app.blocks.push(text_block("first", (0.0, 0.0)));
+        let selected = |app: &CanvasApp| -> Vec<usize> {
+            (0..app.blocks.len())
+                .filter(|&i| app.blocks[i].selected)
+                .collect()
+        };
+
+        for expected in [2, 0, 1, 2] {
+            app.select_next_block(false);
+            assert_eq!(selected(&app), vec![expected]);
+        }
+        assert_eq!(app.viewport.pan, Vec2::new(-100.0, -50.0));
+
+        app.select_next_block(true);
+        assert_eq!(selected(&app), vec![1]);
+        assert_eq!(app.keyboard_focus, Some(app.blocks[1].id));
+
+        // A locked view stays put
+        app.view_locked = true;
+        app.select_next_block(true);
+        assert_eq!(selected(&app), vec![0]);
+        assert_eq!(app.viewport.pan, Vec2::new(-100.0, -250.0));
+    }
+
+    #[test]
+    fn pinned_blocks_stay_on_screen_and_out_of_the_way() {
+        let mut app = CanvasApp {
+            viewport: Viewport {
+                pan: Vec2::new(30.0, -10.0),
+                zoom: 2.0,
+                ..Default::default()
+            },
+            canvas_rect: Rect::from_min_size(Pos2::new(0.0, 40.0), Vec2::new(800.0, 600.0)),
+            ..Default::default()
+        };
+        app.blocks.push(text_block("pinned", (0.0, 0.0)));
+        app.blocks.push(text_block("world", (300.0, 0.0)));
+        let on_screen = app.block_screen_rect(&app.blocks[0]);
+
+        app.toggle_pinned(0);
+        assert!(app.blocks[0].pinned);
+        assert_eq!(app.block_screen_rect(&app.blocks[0]), on_screen);
+        app.viewport.pan += Vec2::new(100.0, 50.0);
+        app.viewport.zoom = 0.5;
+        assert_eq!(app.block_screen_rect(&app.blocks[0]), on_screen);
+
+        // Sitting on top of a world block pushes nothing
+        let world = app.blocks[1].rect;
+        app.blocks[0].rect = app
+            .block_screen_rect(&app.blocks[1])
+            .translate(-app.canvas_rect.min.to_vec2());
+        app.push_neighbors(0);
+        app.push_neighbors(1);
+        assert_eq!(app.blocks[1].rect, world);
+
+        // Unpinning puts it back in the world under the same spot
+        let on_screen = app.block_screen_rect(&app.blocks[0]);
+        app.toggle_pinned(0);
+        assert!(!app.blocks[0].pinned);
+        let back = app.block_screen_rect(&app.blocks[0]);
+        assert!((back.min - on_screen.min).length() < 1e-3);
+        assert!(!app.blocks[0].rect.intersects(app.blocks[1].rect));
+    }
+
+    #[test]
+    fn cropping_keeps_the_part_in_place_and_survives_a_reload() {
+        let mut content = BlockContent::image_placeholder("missing.png".to_string());
+        if let BlockContent::Image { aspect_ratio, .. } = &mut content {
+            *aspect_ratio = 2.0;
+        }
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            content,
+            ..text_block("", (0.0, 0.0))
+        });
+        let image_crop = |app: &CanvasApp| match &app.blocks[0].content {
+            BlockContent::Image {
+                crop, aspect_ratio, ..
+            } => (*crop, *aspect_ratio),
+            BlockContent::Text { .. } | BlockContent::Link { .. } | BlockContent::Color { .. } => {
+                unreachable!()
             }
+        };
+
+        // The selection sticks out of the block and is cut to its right half
+        app.crop_block(
+            0,
+            Rect::from_min_max(Pos2::new(100.0, 0.0), Pos2::new(300.0, 100.0)),
+        );
+        let right_half = Rect::from_min_max(Pos2::new(0.5, 0.0), Pos2::new(1.0, 1.0));
+        assert_eq!(
+            app.blocks[0].rect,
+            Rect::from_min_max(Pos2::new(100.0, 0.0), Pos2::new(200.0, 100.0))
+        );
+        assert_eq!(image_crop(&app), (right_half, 1.0));
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        assert_eq!(image_crop(&load_json(&json)).0, right_half);
+
+        app.uncrop_block(0);
+        assert_eq!(
+            app.blocks[0].rect,
+            Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 100.0))
+        );
+        assert_eq!(image_crop(&app), (FULL_CROP, 2.0));
+    }
+
+    #[test]
+    fn image_adjustments_are_saved_and_baked_into_new_frames() {
+        let adjust = ImageAdjust {
+            brightness: 0.25,
+            contrast: -0.5,
+            grayscale: true,
+        };
+        let mut content = BlockContent::image_placeholder("missing.png".to_string());
+        if let BlockContent::Image { adjust: a, .. } = &mut content {
+            *a = adjust;
         }
+        let mut app = CanvasApp::default();
+        app.blocks.push(Block {
+            content,
+            ..text_block("", (0.0, 0.0))
+        });
+
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        let loaded = load_json(&json);
+        assert_eq!(loaded.image_adjust(Some(loaded.blocks[0].id)), adjust);
+
+        let frame = egui::ColorImage::new([2, 2], Color32::from_rgb(200, 40, 40));
+        let baked = adjusted(&frame, adjust);
+        assert!(baked
+            .pixels
+            .iter()
+            .all(|p| *p == adjust.apply_to_pixel(frame.pixels[0])));
+        assert_eq!(adjusted(&frame, ImageAdjust::default()), frame);
     }
-}
 
-impl BlockContent {
-    fn as_text_mut(&mut self) -> Option<&mut String> {
-        if let BlockContent::Text { text } = self {
-            Some(text)
-        } else {
-            None
+    #[test]
+    fn contact_sheet_lays_images_out_in_a_grid_as_shown() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_sheet_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let red = dir.join("red.png");
+        image::RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255]))
+            .save(&red)
+            .unwrap();
+        let blue = dir.join("blue.png");
+        image::RgbaImage::from_pixel(2, 4, image::Rgba([0, 0, 255, 255]))
+            .save(&blue)
+            .unwrap();
+
+        let gray = ImageAdjust {
+            grayscale: true,
+            ..Default::default()
+        };
+        let sheet = export::contact_sheet(&[
+            (red.to_string_lossy().to_string(), FULL_CROP, gray),
+            (
+                blue.to_string_lossy().to_string(),
+                FULL_CROP,
+                ImageAdjust::default(),
+            ),
+            (
+                dir.join("gone.png").to_string_lossy().to_string(),
+                FULL_CROP,
+                gray,
+            ),
+        ])
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Two cells side by side; the missing image is left out
+        assert_eq!(sheet.dimensions(), (2 * 256 + 3 * 8, 256 + 2 * 8));
+        let [r, g, b, _] = sheet.get_pixel(8 + 128, 8 + 128).0;
+        assert!(r == g && g == b && r > 0);
+        assert_eq!(
+            sheet.get_pixel(8 + 256 + 8 + 128, 8 + 128).0,
+            [0, 0, 255, 255]
+        );
+        // Wide and tall images are centered in their cells
+        assert_eq!(sheet.get_pixel(8 + 128, 12).0, [40, 40, 40, 255]);
+        assert_eq!(
+            sheet.get_pixel(8 + 256 + 8 + 4, 8 + 128).0,
+            [40, 40, 40, 255]
+        );
+    }
+
+    #[test]
+    fn contact_sheet_bounds_the_raster_of_tiny_crops() {
+        let dir = std::env::temp_dir().join(format!("ma_blocks_sheet_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let svg = dir.join("red.svg");
+        std::fs::write(
+            &svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100"><rect width="100" height="100" fill="red"/></svg>"#,
+        )
+        .unwrap();
+
+        // A 1% crop would want a raster 25600 pixels wide to fill the cell
+        let crop = Rect::from_min_size(Pos2::new(0.5, 0.5), Vec2::splat(0.01));
+        let sheet = export::contact_sheet(&[(
+            svg.to_string_lossy().to_string(),
+            crop,
+            ImageAdjust::default(),
+        )])
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(sheet.dimensions(), (256 + 2 * 8, 256 + 2 * 8));
+        assert_eq!(sheet.get_pixel(8 + 128, 8 + 128).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn duplicate_shares_textures_and_never_reloads() {
+        let ctx = egui::Context::default();
+        let texture = ctx.load_texture(
+            "pasted",
+            egui::ColorImage::new([4, 4], Color32::RED),
+            Default::default(),
+        );
+        let mut app = CanvasApp::default();
+        let original = Block {
+            id: app.id_generator.next_id(),
+            rect: Rect::from_min_size(Pos2::ZERO, Vec2::splat(100.0)),
+            content: BlockContent::Image {
+                frames: vec![texture.clone(), texture.clone()],
+                frame_delays: vec![0.1, 0.1],
+                aspect_ratio: 1.0,
+                playing: true,
+                current_frame_idx: 1,
+                last_frame_time: 0.0,
+                counter: 3,
+                // Pasted from the clipboard, so there is no file to fall back on
+                path: None,
+                animation_state: AnimationState::Ready,
+                first_frame: None,
+                playing_start_time: Some(0.0),
+                crop: FULL_CROP,
+                adjust: ImageAdjust::default(),
+                looping: Looping::Times(3),
+                direction: Direction::Reverse,
+                progress: Progress {
+                    loops_played: 2,
+                    returning: false,
+                },
+                delay_override: Some(0.25),
+            },
+            chained: true,
+            selected: true,
+            opacity: u8::MAX,
+            pinned: false,
+            created_at: now_millis(),
+            slide: None,
+            note: None,
+            lock_aspect: false,
+            visible: true,
+        };
+        let original_id = original.id;
+        app.blocks.push(original);
+
+        app.duplicate_blocks(&[original_id], &ctx);
+
+        assert_eq!(app.blocks.len(), 2);
+        let copy = &app.blocks[1];
+        assert_ne!(copy.id, original_id);
+        assert!(copy.selected && !app.blocks[0].selected);
+        assert!(!copy.chained);
+        assert!(!copy.rect.intersects(app.blocks[0].rect));
+        let BlockContent::Image {
+            frames,
+            counter,
+            playing,
+            current_frame_idx,
+            looping,
+            progress,
+            delay_override,
+            ..
+        } = &copy.content
+        else {
+            panic!("copy is not an image");
+        };
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].id(), texture.id());
+        assert_eq!(*counter, 3);
+        assert!(!playing);
+        // Back at the start of the reversed animation
+        assert_eq!(*current_frame_idx, 1);
+        assert_eq!(*looping, Looping::Times(3));
+        assert_eq!(*delay_override, Some(0.25));
+        assert_eq!(progress.loops_played, 0);
+        // Nothing was sent off to be decoded
+        assert!(app.image_rx.try_recv().is_err());
+        // ...and the shared texture is only counted once
+        assert_eq!(app.texture_usage(), (1, 4 * 4 * 4));
+    }
+
+    #[test]
+    fn extracted_frames_are_stills_without_a_file() {
+        let ctx = egui::Context::default();
+        let frame = |name: &str, color| {
+            ctx.load_texture(
+                name,
+                egui::ColorImage::new([4, 2], color),
+                Default::default(),
+            )
+        };
+        let (first, second) = (frame("first", Color32::RED), frame("second", Color32::BLUE));
+        let mut app = CanvasApp::default();
+        let mut content = BlockContent::image_placeholder("anim.gif".to_string());
+        if let BlockContent::Image {
+            frames,
+            frame_delays,
+            aspect_ratio,
+            current_frame_idx,
+            ..
+        } = &mut content
+        {
+            *frames = vec![first, second.clone()];
+            *frame_delays = vec![0.1, 0.1];
+            *aspect_ratio = 2.0;
+            *current_frame_idx = 1;
         }
+        app.blocks.push(Block {
+            content,
+            rect: Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 100.0)),
+            selected: true,
+            ..text_block("", (0.0, 0.0))
+        });
+        assert_eq!(app.unsaved_image_count(), 0);
+
+        app.extract_frame(0);
+
+        assert_eq!(app.blocks.len(), 2);
+        let still = &app.blocks[1];
+        assert!(still.selected && !app.blocks[0].selected);
+        assert!(!still.rect.intersects(app.blocks[0].rect));
+        assert_eq!(still.rect.size(), Vec2::new(200.0, 100.0));
+        let BlockContent::Image {
+            frames,
+            path,
+            aspect_ratio,
+            ..
+        } = &still.content
+        else {
+            panic!("the frame is not an image");
+        };
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id(), second.id());
+        assert!(path.is_none());
+        assert_eq!(*aspect_ratio, 2.0);
+        assert_eq!(app.unsaved_image_count(), 1);
     }
-}
 
-fn main() -> eframe::Result<()> {
-    let options = eframe::NativeOptions::default();
-    eframe::run_native(
-        "MA Blocks",
-        options,
-        Box::new(|_cc| Ok(Box::new(CanvasApp::default()) as Box<dyn eframe::App>)),
-    )
+    fn load_json(json: &str) -> CanvasApp {
+        let session: Session = serde_json::from_str(json).unwrap();
+        let mut app = CanvasApp::default();
+        app.apply_session(session, &egui::Context::default());
+        app
+    }
+
+    fn text_block_json(rect: &str) -> String {
+        format!(
+            r#"{{"id":"{}","rect":{rect},"content":{{"Text":{{"text":"x"}}}},"chained":false}}"#,
+            Uuid::new_v4()
+        )
+    }
+
+    #[test]
+    fn session_background_round_trips_and_old_sessions_get_defaults() {
+        let app = CanvasApp {
+            background: BackgroundData {
+                color: Some([10, 20, 30]),
+                pattern: BackgroundPattern::Dots,
+            },
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&app.to_session()).unwrap();
+        assert_eq!(load_json(&json).background, app.background);
+
+        // Sessions from before backgrounds and opacity were saved
+        let old = load_json(&format!(
+            r#"{{"viewport":{{"pan":[0.0,0.0],"zoom":1.0}},"blocks":[{}]}}"#,
+            text_block_json("[0.0,0.0,100.0,100.0]")
+        ));
+        assert_eq!(old.background, BackgroundData::default());
+        assert_eq!(old.background.pattern, BackgroundPattern::Solid);
+        assert_eq!(old.blocks[0].opacity, u8::MAX);
+    }
+
+    #[test]
+    fn missing_images_are_offered_for_relinking() {
+        let id = Uuid::new_v4();
+        let json = format!(
+            r#"{{"viewport":{{"pan":[0.0,0.0],"zoom":1.0}},"blocks":[{{"id":"{id}","rect":[0.0,0.0,100.0,100.0],"content":{{"Image":{{"path":"/gone/slides.pdf#page=3","counter":0,"playing":false}}}},"chained":false}}]}}"#
+        );
+        let mut app = load_json(&json);
+        assert_eq!(
+            app.missing_images,
+            vec![(id, "/gone/slides.pdf#page=3".to_string())]
+        );
+
+        let moved = std::env::temp_dir().join("moved").join("slides.pdf");
+        assert!(app.relink_image(id, &moved, &egui::Context::default()));
+
+        assert!(app.missing_images.is_empty());
+        let BlockContent::Image { path, .. } = &app.blocks[0].content else {
+            panic!("block is not an image");
+        };
+        // Still the same page of the document
+        assert_eq!(
+            path.as_deref(),
+            Some(format!("{}#page=3", moved.display()).as_str())
+        );
+    }
+
+    #[test]
+    fn session_load_repairs_inverted_and_tiny_rects() {
+        let json = format!(
+            r#"{{"viewport":{{"pan":[0.0,0.0],"zoom":1.0}},"blocks":[{},{}]}}"#,
+            text_block_json("[300.0,200.0,100.0,0.0]"),
+            text_block_json("[0.0,0.0,0.0,10.0]"),
+        );
+        let app = load_json(&json);
+
+        assert_eq!(app.blocks.len(), 2);
+        assert_eq!(
+            app.blocks[0].rect,
+            Rect::from_min_max(Pos2::new(100.0, 0.0), Pos2::new(300.0, 200.0))
+        );
+        assert_eq!(app.blocks[1].rect.size(), Vec2::splat(MIN_BLOCK_SIZE));
+        assert!(app.notification.is_some());
+    }
+
+    #[test]
+    fn session_load_skips_non_finite_rects() {
+        // JSON has no NaN/inf literals, so a non-finite rect can only come from a
+        // session built in memory or a float that overflows f32
+        let json = format!(
+            r#"{{"viewport":{{"pan":[0.0,0.0],"zoom":1.0}},"blocks":[{},{}]}}"#,
+            text_block_json("[0.0,0.0,1e39,100.0]"),
+            text_block_json("[0.0,0.0,100.0,100.0]"),
+        );
+        let app = load_json(&json);
+        assert_eq!(app.blocks.len(), 1);
+
+        let mut session: Session = serde_json::from_str(&json).unwrap();
+        session.blocks[1].rect[0] = f32::NAN;
+        session.viewport.zoom = f32::NAN;
+        let mut app = CanvasApp::default();
+        app.apply_session(session, &egui::Context::default());
+        assert!(app.blocks.is_empty());
+        assert_eq!(app.viewport.zoom, 1.0);
+    }
+
+    #[test]
+    fn session_load_rejects_malformed_json() {
+        for json in [
+            r#"{"viewport":{"pan":[0.0,0.0],"zoom":1.0},"blocks":[{"rect":[0,0,1]}]}"#,
+            r#"{"viewport":{"pan":[0.0,0.0],"zoom":1.0},"blocks":"#,
+            r#"{"viewport":{"pan":[null,0.0],"zoom":1.0},"blocks":[]}"#,
+        ] {
+            assert!(serde_json::from_str::<Session>(json).is_err(), "{json}");
+        }
+    }
 }